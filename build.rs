@@ -4,6 +4,10 @@
 use cuda_setup::{build, GpuArchitecture};
 
 fn main() {
+    // The PTX is embedded in the binary; rebuild it when the kernels change.
+    #[cfg(feature = "cuda")]
+    println!("cargo:rerun-if-changed=src/cuda");
+
     #[cfg(feature = "cuda")]
     build(
         GpuArchitecture::Rtx4,
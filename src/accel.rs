@@ -2,16 +2,24 @@
 
 //! This module contains acceleration calculations.
 
+use barnes_hut::{BhConfig, Cube, Tree};
 use lin_alg::f64::Vec3;
 use rayon::prelude::*;
 
 use crate::{
-    grav_shell::{GravShell, AMP_SCALER},
+    grav_shell::{GravShell, ShellAnisotropy, ShellSpeed, AMP_SCALER},
+    shell_geometry::ShellGeometry,
     units::{A0_MOND, C, G},
-    Body,
+    Body, BOUNDING_BOX_PAD,
 };
 
-#[derive(Clone, Copy, PartialEq)]
+/// Tree nodes closer than this many softening lengths to the target are opened. See
+/// `run_bh_softened`.
+const SOFTENING_OPEN_MULT: f64 = 2.;
+/// Half of a unit cube's diagonal: √3 / 2.
+const CUBE_HALF_DIAG: f64 = 0.866_025_403_784_438_6;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum MondFn {
     /// Famaey & Binney. More realistic fits than the standard one. `x` is a_Newton / a_0.
     Simple,
@@ -20,35 +28,159 @@ pub enum MondFn {
 }
 
 impl MondFn {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Simple => "Simple",
+            Self::Standard => "Standard",
+        }
+        .to_owned()
+    }
+
     pub fn μ(&self, x: f64) -> f64 {
         match self {
             Self::Simple => x / (1. + x),
             Self::Standard => x / (1. + x.powi(2)).sqrt(),
         }
     }
+
+    /// The inverse interpolating function: Solves a μ(a / a_0) = a_N for a = ν(y) a_N, where `y` is
+    /// a_Newton / a_0.
+    pub fn nu(&self, y: f64) -> f64 {
+        match self {
+            Self::Simple => (1. + (1. + 4. / y).sqrt()) / 2.,
+            Self::Standard => ((1. + (1. + 4. / y.powi(2)).sqrt()) / 2.).sqrt(),
+        }
+    }
 }
 
 /// The most fundamental part of Newtonian acceleration calculation.
 /// `acc_dir` is a unit vector.
+///
+/// Plummer softening: This is the gradient of the potential -G m / √(d² + ε²), which we use for energy,
+/// so forces and energies are consistent. The same kernel is used for bodies and tree nodes.
 pub fn acc_newton_inner(acc_dir: Vec3, src_mass: f64, dist: f64, softening_factor_sq: f64) -> Vec3 {
-    acc_dir * G * src_mass / (dist.powi(2) + softening_factor_sq)
+    acc_dir * G * src_mass * dist / (dist.powi(2) + softening_factor_sq).powf(1.5)
 }
 
-/// This optionally applies MOND to our basic Newton acceleration.
-pub fn acc_newton_inner_with_mond(
+/// Apply MOND to a (Newtonian-like) acceleration: a = a_N / μ(a_N / a_0). This is what the pairwise
+/// MOND model applies to each source's contribution.
+pub fn apply_mond(acc: Vec3, mond_fn: MondFn) -> Vec3 {
+    let x = acc.magnitude() / A0_MOND;
+    if x < f64::EPSILON {
+        return acc;
+    }
+    acc / mond_fn.μ(x)
+}
+
+/// Apply MOND to the net Newtonian acceleration on a body, AQUAL-like: Solves the algebraic relation
+/// μ(|a| / a_0) a = a_N exactly, using the inverse function ν. MOND is nonlinear, so this is not
+/// equivalent to summing pairwise MOND contributions.
+pub fn apply_mond_net(acc_newton: Vec3, mond_fn: MondFn) -> Vec3 {
+    let y = acc_newton.magnitude() / A0_MOND;
+    if y < f64::EPSILON {
+        return acc_newton;
+    }
+    acc_newton * mond_fn.nu(y)
+}
+
+/// The softened kernel, optionally with MOND applied to this contribution.
+fn acc_inner_mond_opt(
     acc_dir: Vec3,
-    mass: f64,
+    src_mass: f64,
     dist: f64,
     mond: Option<MondFn>,
     softening_factor_sq: f64,
 ) -> Vec3 {
-    let mut acc = acc_newton_inner(acc_dir, mass, dist, softening_factor_sq);
+    let acc = acc_newton_inner(acc_dir, src_mass, dist, softening_factor_sq);
+
+    match mond {
+        Some(mond_fn) => apply_mond(acc, mond_fn),
+        None => acc,
+    }
+}
+
+/// Sum `acc_fn` over the tree, as `barnes_hut::run_bh`, but with an opening criterion that accounts
+/// for softening: Besides the usual width / dist < θ, a node is only accepted if the target is more
+/// than `SOFTENING_OPEN_MULT` softening lengths outside its cube. Within that, the softened kernel
+/// varies strongly across the node, so its monopole is a poor approximation, however small θ is
+/// relative to the node.
+///
+/// Leaves containing the target are skipped, as with `run_bh`.
+pub fn run_bh_softened<F>(
+    posit_target: Vec3,
+    id_target: usize,
+    tree: &Tree,
+    bh_config: &BhConfig,
+    softening_factor_sq: f64,
+    acc_fn: &F,
+) -> Vec3
+where
+    F: Fn(Vec3, f64, f64) -> Vec3,
+{
+    let mut result = Vec3::new_zero();
+    let Some(root) = tree.nodes.first() else {
+        return result;
+    };
+
+    let ε = softening_factor_sq.sqrt();
+    let mut stack = vec![root];
+
+    while let Some(node) = stack.pop() {
+        let acc_diff = node.center_of_mass - posit_target;
+        let dist = acc_diff.magnitude();
+
+        if node.children.is_empty() {
+            if node.body_ids.contains(&id_target) || dist < f64::EPSILON {
+                continue;
+            }
+            result += acc_fn(acc_diff / dist, node.mass, dist);
+            continue;
+        }
+
+        let width = node.bounding_box.width;
+        let dist_cube = (node.bounding_box.center - posit_target).magnitude();
+        let accept = dist > f64::EPSILON
+            && width / dist < bh_config.θ
+            && dist_cube > width * CUBE_HALF_DIAG + SOFTENING_OPEN_MULT * ε;
 
-    if let Some(mond_fn) = mond {
-        let x = acc.magnitude() / A0_MOND;
-        acc /= mond_fn.μ(x);
+        if accept {
+            result += acc_fn(acc_diff / dist, node.mass, dist);
+        } else {
+            stack.extend(node.children.iter().map(|i| &tree.nodes[*i]));
+        }
     }
-    return acc;
+
+    result
+}
+
+/// Acceleration on a target using the Barnes-Hut tree; Newtonian, or with MOND applied pairwise.
+/// Leaf bodies and accepted nodes use the same softened kernel as `acc_newton`, and nodes within
+/// the softening region are opened; see `run_bh_softened`. With `mond = None`, this agrees with
+/// `acc_newton` to within the opening criterion's error.
+///
+/// With MOND, it doesn't: μ is nonlinear, so MOND applied to an accepted node's aggregate
+/// contribution isn't the sum of MOND applied to each of its bodies'. For MOND on the tree, pass
+/// `None`, then use `apply_mond_net`.
+pub fn acc_tree(
+    posit_target: Vec3,
+    id_target: usize,
+    tree: &Tree,
+    bh_config: &BhConfig,
+    mond: Option<MondFn>,
+    softening_factor_sq: f64,
+) -> Vec3 {
+    let acc_fn = |acc_dir, mass_src, dist| {
+        acc_inner_mond_opt(acc_dir, mass_src, dist, mond, softening_factor_sq)
+    };
+
+    run_bh_softened(
+        posit_target,
+        id_target,
+        tree,
+        bh_config,
+        softening_factor_sq,
+        &acc_fn,
+    )
 }
 
 pub fn calc_acc_shell(
@@ -57,6 +189,9 @@ pub fn calc_acc_shell(
     id_target: usize,
     shell_c: f64,
     softening_factor_sq: f64,
+    anisotropy: ShellAnisotropy,
+    speed: &ShellSpeed,
+    geometry: ShellGeometry,
 ) -> Vec3 {
     // todo: Once you have more than one body acting on a target, you need to change this, so you get
     // todo exactly 0 or 1 shells per other body.
@@ -78,7 +213,9 @@ pub fn calc_acc_shell(
 
         Some(acc_newton_inner(
             acc_dir,
-            shell.value(posit, shell_c),
+            shell.value(posit, speed.gauss_c(shell.radius, shell_c), geometry)
+                * shell.anisotropy_factor(posit, anisotropy)
+                * geometry.flux_factor(dist),
             dist,
             softening_factor_sq,
         ))
@@ -88,8 +225,7 @@ pub fn calc_acc_shell(
 }
 
 /// An instantaneous acceleration computation, from all sources, on a single target.
-/// Either Newtonian, or Newtonian modified with MOND.
-/// `mond_params` are `(a, a_0)`.
+/// Either Newtonian, or Newtonian modified with MOND applied pairwise.
 ///
 /// Uses Rayon for parallel execution. The functional approach is required for use with Rayon.
 pub fn acc_newton(
@@ -112,7 +248,7 @@ pub fn acc_newton(
             let dist = acc_diff.magnitude();
             let acc_dir = acc_diff / dist; // Unit vector.
 
-            Some(acc_newton_inner_with_mond(
+            Some(acc_inner_mond_opt(
                 acc_dir,
                 body_source.mass,
                 dist,
@@ -123,8 +259,170 @@ pub fn acc_newton(
         .reduce(Vec3::new_zero, |acc, elem| acc + elem) // Sum the contributions.
 }
 
+/// Newtonian acceleration and jerk (its time derivative) on a target, by direct sum, with the same
+/// softened kernel as `acc_newton`. For r = x_src - x, and v = v_src - v_tgt:
+///
+/// j = Σ G m [v / (r² + ε²)^(3/2) - 3 (r·v) r / (r² + ε²)^(5/2)]
+///
+/// The tree has no velocities, so there's no tree version.
+pub fn acc_jerk_newton(
+    posit_target: Vec3,
+    vel_target: Vec3,
+    id_target: usize,
+    bodies_src: &[Body],
+    softening_factor_sq: f64,
+) -> (Vec3, Vec3) {
+    bodies_src
+        .par_iter()
+        .enumerate()
+        .filter_map(|(i, body_source)| {
+            if i == id_target {
+                return None; // Skip self-interaction.
+            }
+
+            let r = body_source.posit - posit_target;
+            let v = body_source.vel - vel_target;
+
+            let dist_sq = r.magnitude_squared() + softening_factor_sq;
+            let inv_dist_3 = 1. / (dist_sq * dist_sq.sqrt());
+            let gm = G * body_source.mass;
+
+            let acc = r * gm * inv_dist_3;
+            let jerk = (v - r * (3. * r.dot(v) / dist_sq)) * gm * inv_dist_3;
+            Some((acc, jerk))
+        })
+        .reduce(
+            || (Vec3::new_zero(), Vec3::new_zero()),
+            |a, b| (a.0 + b.0, a.1 + b.1),
+        )
+}
+
+/// Relative error of tree accelerations against a softened direct sum, over all bodies.
+/// Returns (RMS, max). For checking that the tree and direct paths agree, e.g. after changing θ
+/// or softening.
+pub fn tree_force_error(
+    bodies: &[Body],
+    bh_config: &BhConfig,
+    mond: Option<MondFn>,
+    softening_factor_sq: f64,
+) -> Option<(f64, f64)> {
+    let bb = Cube::from_bodies(bodies, BOUNDING_BOX_PAD, true)?;
+    let tree = Tree::new(bodies, &bb, bh_config);
+
+    let errors: Vec<f64> = bodies
+        .par_iter()
+        .enumerate()
+        .filter_map(|(id, body)| {
+            let direct = acc_newton(body.posit, id, bodies, mond, softening_factor_sq);
+            let tree = acc_tree(body.posit, id, &tree, bh_config, mond, softening_factor_sq);
+
+            let mag = direct.magnitude();
+            (mag > f64::EPSILON).then(|| (tree - direct).magnitude() / mag)
+        })
+        .collect();
+
+    if errors.is_empty() {
+        return None;
+    }
+
+    let rms = (errors.iter().map(|e| e.powi(2)).sum::<f64>() / errors.len() as f64).sqrt();
+    let max = errors.iter().copied().fold(0., f64::max);
+    Some((rms, max))
+}
+
 /// Finds the gravitomagnetic vector potential, analagous to magnetism in Maxwell's equations for EM.
 pub fn gravitomagnetic_force(bodies: &[Body]) -> Vec3 {
     // todo: Is this from motion of masses, or rotation? A fn for each?
     Vec3::new_zero()
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Tight, so nearly all nodes are opened, and the tree should match the direct sum closely.
+    const THETA_TIGHT: f64 = 0.02;
+    const SOFTENING_SQ: f64 = 0.01;
+
+    /// A small, clumped distribution, so some bodies are within each other's softening length.
+    fn make_bodies() -> Vec<Body> {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        (0..300)
+            .map(|i| {
+                // Half the bodies in a compact clump, so softening matters.
+                let extent = if i % 2 == 0 { 10. } else { 0.5 };
+                Body {
+                    posit: Vec3::new(
+                        rng.random_range(-extent..extent),
+                        rng.random_range(-extent..extent),
+                        rng.random_range(-extent..extent),
+                    ),
+                    vel: Vec3::new_zero(),
+                    accel: Vec3::new_zero(),
+                    mass: rng.random_range(1e6..1e8),
+                    component: Default::default(),
+                }
+            })
+            .collect()
+    }
+
+    /// RMS and max relative error of the tree against the direct sum, over all bodies, with `post`
+    /// applied to both.
+    fn tree_errors(θ: f64, mond: Option<MondFn>, post: impl Fn(Vec3) -> Vec3) -> (f64, f64) {
+        let bodies = make_bodies();
+        let bh_config = BhConfig {
+            θ,
+            ..Default::default()
+        };
+        let bb = Cube::from_bodies(&bodies, BOUNDING_BOX_PAD, true).unwrap();
+        let tree = Tree::new(&bodies, &bb, &bh_config);
+
+        let errors: Vec<f64> = bodies
+            .iter()
+            .enumerate()
+            .map(|(id, body)| {
+                let direct = post(acc_newton(body.posit, id, &bodies, mond, SOFTENING_SQ));
+                let tree = post(acc_tree(
+                    body.posit,
+                    id,
+                    &tree,
+                    &bh_config,
+                    mond,
+                    SOFTENING_SQ,
+                ));
+                (tree - direct).magnitude() / direct.magnitude()
+            })
+            .collect();
+
+        let rms = (errors.iter().map(|e| e.powi(2)).sum::<f64>() / errors.len() as f64).sqrt();
+        (rms, errors.iter().copied().fold(0., f64::max))
+    }
+
+    #[test]
+    fn tree_matches_direct_newton() {
+        let (rms, max) = tree_errors(THETA_TIGHT, None, |a| a);
+        assert!(rms < 1e-3, "RMS error: {rms:e}");
+        assert!(max < 1e-2, "Max error: {max:e}");
+    }
+
+    #[test]
+    fn tree_matches_direct_mond_net() {
+        for mond_fn in [MondFn::Simple, MondFn::Standard] {
+            let (rms, max) = tree_errors(THETA_TIGHT, None, |a| apply_mond_net(a, mond_fn));
+            assert!(rms < 1e-3, "{}: RMS error: {rms:e}", mond_fn.to_str());
+            assert!(max < 1e-2, "{}: Max error: {max:e}", mond_fn.to_str());
+        }
+    }
+
+    #[test]
+    fn tree_matches_direct_mond_pairwise() {
+        // Pairwise MOND on aggregate nodes isn't exact, but at a tight θ, few nodes are accepted.
+        for mond_fn in [MondFn::Simple, MondFn::Standard] {
+            let (rms, _) = tree_errors(THETA_TIGHT, Some(mond_fn), |a| a);
+            assert!(rms < 1e-2, "{}: RMS error: {rms:e}", mond_fn.to_str());
+        }
+    }
+}
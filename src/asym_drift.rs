@@ -0,0 +1,243 @@
+//! Asymmetric drift: Random motions partly support a disk against gravity, so its mean rotation lags
+//! the circular velocity. Observed curves measure the rotation; forces set the circular velocity.
+//! We compute the correction from the simulated disk's dispersions with the radial Jeans equation,
+//! neglecting the vertical term (Binney & Tremaine eq. 4.228):
+//!
+//! v_c² - v_φ² = σ_R² [ -d ln Σ / d ln R - d ln σ_R² / d ln R - (1 - σ_φ² / σ_R²) ]
+//!
+//! For isotropic gas this reduces to the form HI observers use, e.g. Broeils for NGC 1560.
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    body_creation::{Component, GalaxyDescrip},
+    properties::{component_curves, plot_multi, quadrature_sum, PlotOutput},
+    units::KPC_MYR_PER_KM_S,
+    util::interpolate,
+    Body,
+};
+
+const N_ANNULI: usize = 30;
+/// Annuli with fewer bodies than this are too noisy for dispersions, and are skipped.
+const MIN_BODIES: usize = 10;
+
+/// Disk kinematics in a cylindrical annulus. Velocities are in km/s.
+#[derive(Clone, Debug)]
+pub struct Annulus {
+    /// kpc
+    pub r: f64,
+    /// Mean rotation speed.
+    pub v_phi: f64,
+    pub σ_r: f64,
+    pub σ_phi: f64,
+    /// M☉ / kpc²
+    pub surface_density: f64,
+}
+
+/// Mass-weighted mean and standard deviation.
+fn mean_std(vals: &[(f64, f64)]) -> (f64, f64) {
+    let mass: f64 = vals.iter().map(|(_, m)| m).sum();
+    let mean = vals.iter().map(|(v, m)| v * m).sum::<f64>() / mass;
+    let var = vals
+        .iter()
+        .map(|(v, m)| (v - mean).powi(2) * m)
+        .sum::<f64>()
+        / mass;
+
+    (mean, var.sqrt())
+}
+
+/// Kinematics of disk and gas bodies in annuli around `center`, in the disk (XY) plane.
+pub fn annuli(bodies: &[Body], center: Vec3) -> Vec<Annulus> {
+    // Cylindrical radius, and radial and azimuthal velocities. kpc/Myr.
+    let samples: Vec<(f64, f64, f64, f64)> = bodies
+        .iter()
+        .filter(|b| matches!(b.component, Component::Disk | Component::Gas))
+        .filter_map(|b| {
+            let posit = b.posit - center;
+            let r = (posit.x.powi(2) + posit.y.powi(2)).sqrt();
+            if r < f64::EPSILON {
+                return None;
+            }
+            let (cos, sin) = (posit.x / r, posit.y / r);
+
+            let v_r = b.vel.x * cos + b.vel.y * sin;
+            let v_phi = -b.vel.x * sin + b.vel.y * cos;
+            Some((r, v_r, v_phi, b.mass))
+        })
+        .collect();
+
+    let r_max = samples.iter().map(|s| s.0).fold(0., f64::max);
+    let dr = r_max / N_ANNULI as f64;
+    if dr <= 0. {
+        return Vec::new();
+    }
+
+    let mut bins = vec![Vec::new(); N_ANNULI];
+    for s in &samples {
+        let i = ((s.0 / dr) as usize).min(N_ANNULI - 1);
+        bins[i].push(*s);
+    }
+
+    bins.iter()
+        .enumerate()
+        .filter(|(_, bin)| bin.len() >= MIN_BODIES)
+        .map(|(i, bin)| {
+            let v_r: Vec<(f64, f64)> = bin.iter().map(|s| (s.1, s.3)).collect();
+            let v_phi: Vec<(f64, f64)> = bin.iter().map(|s| (s.2, s.3)).collect();
+            let (_, σ_r) = mean_std(&v_r);
+            let (v_phi, σ_phi) = mean_std(&v_phi);
+
+            let (r_inner, r_outer) = (i as f64 * dr, (i + 1) as f64 * dr);
+            let area = std::f64::consts::PI * (r_outer.powi(2) - r_inner.powi(2));
+            let mass: f64 = bin.iter().map(|s| s.3).sum();
+
+            Annulus {
+                r: (r_inner + r_outer) / 2.,
+                // The disk may rotate either way.
+                v_phi: v_phi.abs() / KPC_MYR_PER_KM_S,
+                σ_r: σ_r / KPC_MYR_PER_KM_S,
+                σ_phi: σ_phi / KPC_MYR_PER_KM_S,
+                surface_density: mass / area,
+            }
+        })
+        .collect()
+}
+
+/// d ln y / d ln R at each annulus; central differences, and one-sided at the ends.
+fn log_slope(annuli: &[Annulus], y: impl Fn(&Annulus) -> f64) -> Vec<f64> {
+    let n = annuli.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (&annuli[i.saturating_sub(1)], &annuli[(i + 1).min(n - 1)]);
+            let d_ln_r = (b.r / a.r).ln();
+            if d_ln_r == 0. || y(a) <= 0. || y(b) <= 0. {
+                return 0.;
+            }
+            (y(b) / y(a)).ln() / d_ln_r
+        })
+        .collect()
+}
+
+/// The asymmetric drift correction, v_c² - v_φ². X: r (kpc). Y: km²/s².
+pub fn drift(annuli: &[Annulus]) -> Vec<(f64, f64)> {
+    if annuli.len() < 2 {
+        return Vec::new();
+    }
+
+    let slope_density = log_slope(annuli, |a| a.surface_density);
+    let slope_disp = log_slope(annuli, |a| a.σ_r.powi(2));
+
+    annuli
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let σ_r_sq = a.σ_r.powi(2);
+            let anisotropy = if σ_r_sq > 0. {
+                1. - a.σ_phi.powi(2) / σ_r_sq
+            } else {
+                0.
+            };
+            (
+                a.r,
+                σ_r_sq * (-slope_density[i] - slope_disp[i] - anisotropy),
+            )
+        })
+        .collect()
+}
+
+/// The circular velocity inferred from the mean rotation, corrected for asymmetric drift, as
+/// observers do. X: r (kpc). Y: km/s.
+pub fn circular_velocity(annuli: &[Annulus], drift: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    annuli
+        .iter()
+        .zip(drift)
+        .map(|(a, (r, dv_sq))| (*r, (a.v_phi.powi(2) + dv_sq).max(0.).sqrt()))
+        .collect()
+}
+
+/// The rotation we'd observe for a circular velocity curve: v_φ² = v_c² - drift. Points outside
+/// the range the drift was computed over are omitted. X: r (kpc). Y: km/s.
+pub fn apply_drift(v_circ: &[(f64, f64)], drift: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let (Some(first), Some(last)) = (drift.first(), drift.last()) else {
+        return Vec::new();
+    };
+    if drift.len() < 2 {
+        return Vec::new();
+    }
+
+    v_circ
+        .iter()
+        .filter(|(r, _)| *r >= first.0 && *r <= last.0)
+        .filter_map(|(r, v)| {
+            let dv_sq = interpolate(drift, *r)?;
+            Some((*r, (v.powi(2) - dv_sq).max(0.).sqrt()))
+        })
+        .collect()
+}
+
+/// Compare simulated and observed curves on equal footing: The simulated circular velocity with
+/// drift applied, against the observed rotation; and the simulated rotation, corrected for drift,
+/// against the observed corrected curve, if published. Also plots the dispersions.
+pub fn plot_drift(
+    out: &PlotOutput,
+    bodies: &[Body],
+    descrip: &GalaxyDescrip,
+    softening_factor_sq: f64,
+    desc: &str,
+) {
+    let center = Vec3::new_zero();
+    let annuli = annuli(bodies, center);
+    let drift = drift(&annuli);
+
+    if drift.is_empty() {
+        eprintln!("Not enough disk bodies to compute asymmetric drift.");
+        return;
+    }
+
+    let v_circ = quadrature_sum(&component_curves(bodies, center, softening_factor_sq));
+    let v_circ_drift = apply_drift(&v_circ, &drift);
+    let v_phi: Vec<(f64, f64)> = annuli.iter().map(|a| (a.r, a.v_phi)).collect();
+    let v_phi_corrected = circular_velocity(&annuli, &drift);
+
+    let to_km_s = |curve: &[(f64, f64)]| -> Vec<(f64, f64)> {
+        curve
+            .iter()
+            .map(|(r, v)| (*r, v / KPC_MYR_PER_KM_S))
+            .collect()
+    };
+    let observed = to_km_s(&descrip.rotation_curve_disk);
+    let observed_corrected = to_km_s(&descrip.rotation_curve_corrected);
+
+    let mut series: Vec<(&str, &[(f64, f64)])> = vec![
+        ("Observed", &observed),
+        ("Sim v_c", &v_circ),
+        ("Sim v_c, drift applied", &v_circ_drift),
+        ("Sim v_φ", &v_phi),
+        ("Sim v_φ, drift corrected", &v_phi_corrected),
+    ];
+    if !observed_corrected.is_empty() {
+        series.insert(1, ("Observed, drift corrected", &observed_corrected));
+    }
+
+    plot_multi(
+        out,
+        &series,
+        "r (kpc)",
+        "v (km/s)",
+        &format!("Asymmetric drift of {desc}"),
+        &format!("asym_drift_{desc}"),
+    );
+
+    let σ_r: Vec<(f64, f64)> = annuli.iter().map(|a| (a.r, a.σ_r)).collect();
+    let σ_phi: Vec<(f64, f64)> = annuli.iter().map(|a| (a.r, a.σ_phi)).collect();
+
+    plot_multi(
+        out,
+        &[("σ_R", &σ_r), ("σ_φ", &σ_phi)],
+        "r (kpc)",
+        "σ (km/s)",
+        &format!("Disk velocity dispersion of {desc}"),
+        &format!("dispersion_{desc}"),
+    );
+}
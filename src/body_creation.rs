@@ -2,19 +2,187 @@
 
 //! This model creates distributions of bodies, e.g. ones that coarsely represent galaxies.
 
-use std::f64::consts::TAU;
+use std::{f64::consts::TAU, fmt, fmt::Formatter};
 
+use barnes_hut::{Cube, Tree};
+use bincode::{Decode, Encode};
 use lin_alg::{
     f64::{Quaternion, Vec3},
     linspace,
 };
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
 
 use crate::{
-    util::{interpolate, volume_sphere},
-    Body, DISK_RING_PORTION,
+    accel::{acc_newton, acc_tree},
+    obs_uncertainty::ObsUncertainty,
+    properties::{intensity_from_mag, sersic_fit},
+    rot_model::disk_scale_length,
+    units::G,
+    util::{abel_inversion, interpolate, volume_sphere},
+    Body, Config, BOUNDING_BOX_PAD, DISK_RING_PORTION,
 };
 
+/// The galaxy component a body was generated for. Follows the SPARC decomposition of rotation curves
+/// into disk, bulge, and gas contributions, with a halo for dark matter models.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum Component {
+    #[default]
+    Disk,
+    Bulge,
+    Gas,
+    Halo,
+}
+
+impl Component {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Disk => "Disk",
+            Self::Bulge => "Bulge",
+            Self::Gas => "Gas",
+            Self::Halo => "Halo",
+        }
+        .to_owned()
+    }
+}
+
+/// How we place bodies within each component.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum BodySampling {
+    /// Fill a band around each data radius with bodies. (`make_distrib_data_area`)
+    #[default]
+    Annuli,
+    /// Sample radii from the cumulative mass profile. (`make_distrib_inverse_cdf`)
+    InverseCdf,
+}
+
+impl BodySampling {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Annuli => "Annuli",
+            Self::InverseCdf => "Inverse CDF",
+        }
+        .to_owned()
+    }
+}
+
+/// How we set body speeds when generating them.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum VelocityInit {
+    /// Interpolate the observed rotation curve (scaled by `Config::v_scaler`).
+    #[default]
+    Observed,
+    /// Use the circular velocity from the gravitational field of the bodies actually generated.
+    /// With the Newton force model, this starts the system in equilibrium.
+    SelfConsistent,
+}
+
+impl VelocityInit {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Observed => "Observed",
+            Self::SelfConsistent => "Self-consistent",
+        }
+        .to_owned()
+    }
+}
+
+/// Whether, after generating bodies, we compare their speeds to the circular velocity implied by the
+/// enclosed mass, and whether we correct them.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum VelocityCheck {
+    #[default]
+    Off,
+    Report,
+    /// Report, then set each body's speed to the circular velocity, keeping its direction.
+    Correct,
+}
+
+impl VelocityCheck {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Off => "Off",
+            Self::Report => "Report",
+            Self::Correct => "Correct",
+        }
+        .to_owned()
+    }
+}
+
+/// How we model the innermost region of each component. Core treatment strongly affects stability.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum CentralModel {
+    /// A single body, initially at rest at the center, containing the mass enclosed by the inner
+    /// rings. It's integrated like the other bodies.
+    PointMass,
+    /// A dense Plummer sphere containing the enclosed mass, made of `Config::num_bodies_core` bodies.
+    PlummerCore,
+    /// No special treatment; the inner rings are populated like the rest of the component.
+    Resolved,
+    /// The inner rings are left empty. The component's total mass is kept, so their mass is spread
+    /// over the other bodies.
+    #[default]
+    Omitted,
+}
+
+impl CentralModel {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::PointMass => "Point mass",
+            Self::PlummerCore => "Plummer core",
+            Self::Resolved => "Resolved",
+            Self::Omitted => "Omitted",
+        }
+        .to_owned()
+    }
+}
+
+/// Thresholds for subdividing the data-driven annuli, where the profile changes quickly.
+#[derive(Clone, Copy, PartialEq, Debug, Encode, Decode)]
+pub struct RingRefinement {
+    /// Split an annulus if it contains more than this portion of the component's mass.
+    pub max_mass_portion: f64,
+    /// Split an annulus if the density changes across it by more than this portion of its larger
+    /// edge value.
+    pub max_density_change: f64,
+}
+
+impl Default for RingRefinement {
+    fn default() -> Self {
+        Self {
+            max_mass_portion: 0.05,
+            max_density_change: 0.25,
+        }
+    }
+}
+
+/// For galaxies without a measured disk thickness. kpc.
+pub const DISK_THICKNESS_DEFAULT: f64 = 0.4;
+
+/// Limits the number of subdivision passes; each pass can at most double the annulus count.
+const MAX_REFINE_PASSES: usize = 6;
+
+/// A band of the disk (or bulge plane), which we fill with bodies at random positions.
+#[derive(Clone, Debug)]
+struct Annulus {
+    /// The radius the density value was taken at.
+    r: f64,
+    r_inner: f64,
+    r_outer: f64,
+    /// M☉ / kpc^2
+    density: f64,
+}
+
+impl Annulus {
+    fn area(&self) -> f64 {
+        (self.r_outer.powi(2) - self.r_inner.powi(2)) * TAU / 2.
+    }
+
+    fn mass(&self) -> f64 {
+        self.density * self.area()
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum GalaxyShape {
     GrandDesignSpiral,
@@ -26,6 +194,31 @@ pub enum GalaxyShape {
     LenticularRingSeyfertType2,
 }
 
+/// Where a galaxy's observational data comes from, so results can be traced back to their inputs.
+/// Empty fields are unknown.
+#[derive(Clone, Debug, Default)]
+pub struct Provenance {
+    /// The paper(s) the data, or its reduction, is from.
+    pub paper: String,
+    /// The survey or catalog, e.g. SPARC.
+    pub survey: String,
+    /// The coordinate epoch, e.g. J2000.
+    pub epoch: String,
+    /// The distance the data assumes, and its source.
+    pub distance: String,
+}
+
+impl Provenance {
+    pub fn new(paper: &str, survey: &str, epoch: &str, distance: &str) -> Self {
+        Self {
+            paper: paper.to_owned(),
+            survey: survey.to_owned(),
+            epoch: epoch.to_owned(),
+            distance: distance.to_owned(),
+        }
+    }
+}
+
 /// todo: We assume a spiral galaxy for now
 pub struct GalaxyDescrip {
     pub shape: GalaxyShape,
@@ -34,10 +227,13 @@ pub struct GalaxyDescrip {
     // /// X: r (kpc). Y: km/s. Note: This isn't in our standard units; convert when making bodies.
     /// X: r (kpc). Y: kpc/MYR.
     pub rotation_curve_disk: Vec<(f64, f64)>,
+    /// The disk curve, corrected for asymmetric drift (pressure support), i.e. the circular
+    /// velocity. Empty if not published. X: r (kpc). Y: kpc/MYR.
+    pub rotation_curve_corrected: Vec<(f64, f64)>,
     /// Luminosity brightness profile. r (kpc), mu (mac arcsec^-2) -
     pub luminosity_disk: Vec<(f64, f64)>,
-    /// X: r (kpc). Y:  M☉ / kpc^2. Note that this is only valid in the plane of the bulge; you must
-    /// map to a 3D structure using data not included here.
+    /// X: r (kpc). Y:  M☉ / kpc^2. This is projected; when making bodies, we deproject it to a 3D
+    /// structure, assuming spherical symmetry.
     pub mass_density_bulge: Vec<(f64, f64)>,
     /// X: r (kpc). Y: kpc/MYR.
     pub rotation_curve_bulge: Vec<(f64, f64)>,
@@ -45,6 +241,8 @@ pub struct GalaxyDescrip {
     // todo: More A/R
     /// 0 means a circle. 1 is fully elongated.
     pub eccentricity: f64,
+    /// Disk bodies are placed uniformly within ±half this of the plane. kpc.
+    pub disk_thickness: f64,
     pub arm_count: usize,
     /// For generating a dark matter halo. (core radius, central density)
     pub burkert_params: (f64, f64),
@@ -59,6 +257,9 @@ pub struct GalaxyDescrip {
     pub mass_to_light_ratio: f64,
     /// Kpc
     pub dist_from_earth: f64,
+    /// Uncertainty in the distance and inclination the observed data assumes.
+    pub uncertainty: ObsUncertainty,
+    pub provenance: Provenance,
 }
 
 fn ring_area(r: f64, dr: f64) -> f64 {
@@ -84,12 +285,11 @@ fn ring_volume(r: f64, dr: f64) -> f64 {
 impl GalaxyDescrip {
     /// See the `properties` module for info on distributions
     /// todo: Luminosity A/R
-    pub fn make_bodies(
-        &self,
-        num_bodies_disk: usize,
-        num_bodies_bulge: usize,
-        v_scaler: f64,
-    ) -> Vec<Body> {
+    pub fn make_bodies(&self, cfg: &Config) -> Vec<Body> {
+        let num_bodies_disk = cfg.num_bodies_disk;
+        let num_bodies_bulge = cfg.num_bodies_bulge;
+        let v_scaler = cfg.v_scaler;
+
         //todo temp.
         let p = 3.;
         let m = 1e14;
@@ -105,28 +305,36 @@ impl GalaxyDescrip {
                     vel: Vec3::new(0., v_mag, 0.),
                     accel: Vec3::new_zero(),
                     mass: m,
+                    component: Component::Disk,
                 },
                 Body {
                     posit: Vec3::new(p, 0., 0.),
                     vel: Vec3::new(0., -v_mag, 0.),
                     accel: Vec3::new_zero(),
                     mass: m,
+                    component: Component::Disk,
                 },
             ];
         }
 
+        // A single generator for all components, so a fixed seed reproduces the whole galaxy.
+        let mut rng = match cfg.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
         let mut result = Vec::with_capacity(num_bodies_disk + num_bodies_bulge);
 
         // result.append(&mut self.make_disk(num_bodies_disk, num_rings_disk));
         println!("\nMaking disk bodies...");
-        result.append(&mut make_distrib_data_area(
+        result.append(&mut self.make_component(
             &self.mass_density_disk,
             &self.rotation_curve_disk,
             self.mass_disk,
-            self.eccentricity,
             num_bodies_disk,
-            false,
-            v_scaler,
+            Component::Disk,
+            cfg,
+            &mut rng,
         ));
 
         // println!("Bodies: {:.4?}", &result);
@@ -135,19 +343,185 @@ impl GalaxyDescrip {
 
         println!("\nMaking bulge bodies...");
         if num_bodies_bulge > 0 && !self.mass_density_bulge.is_empty() {
-            result.append(&mut make_distrib_data_area(
+            result.append(&mut self.make_component(
                 &self.mass_density_bulge,
                 &self.rotation_curve_bulge,
                 self.mass_bulge,
-                self.eccentricity,
                 num_bodies_bulge,
-                true,
-                v_scaler,
+                Component::Bulge,
+                cfg,
+                &mut rng,
             ));
         }
 
         result
     }
+
+    /// Create the bodies for a single component (e.g. disk or bulge), using the sampling approach
+    /// set in the config. The bulge and halo are 3D, and their surface density is deprojected;
+    /// other components are disks.
+    fn make_component(
+        &self,
+        mass_density: &[(f64, f64)],
+        vel: &[(f64, f64)],
+        mass_total: f64,
+        num_bodies: usize,
+        component: Component,
+        cfg: &Config,
+        rng: &mut StdRng,
+    ) -> Vec<Body> {
+        let three_d = matches!(component, Component::Bulge | Component::Halo);
+
+        let deprojected;
+        let mass_density = if three_d {
+            deprojected = radial_density_spherical(mass_density);
+            &deprojected
+        } else {
+            mass_density
+        };
+
+        let mut result = match cfg.body_sampling {
+            BodySampling::Annuli => make_distrib_data_area(
+                mass_density,
+                vel,
+                mass_total,
+                self.eccentricity,
+                self.disk_thickness,
+                num_bodies,
+                three_d,
+                cfg.v_scaler,
+                cfg.central_model,
+                cfg.num_bodies_core,
+                cfg.refine_rings.then_some(cfg.ring_refinement),
+                rng,
+            ),
+            BodySampling::InverseCdf => make_distrib_inverse_cdf(
+                mass_density,
+                vel,
+                mass_total,
+                self.eccentricity,
+                self.disk_thickness,
+                num_bodies,
+                three_d,
+                cfg.v_scaler,
+                cfg.central_model,
+                cfg.num_bodies_core,
+                rng,
+            ),
+        };
+
+        for body in &mut result {
+            body.component = component;
+        }
+        result
+    }
+}
+
+/// For spherical components: Deproject surface density to volume density, then express it as the
+/// surface density a disk would need for the same mass between each radius: Σ' = 2rρ, so that
+/// τ r Σ' dr = 2τ r² ρ dr. This lets the samplers, which fill annuli by area, fill spherical shells
+/// instead. If there are too few points to deproject, the data is returned as-is.
+fn radial_density_spherical(surface_density: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let result: Vec<_> = abel_inversion(surface_density)
+        .into_iter()
+        .map(|(r, ρ)| (r, 2. * r * ρ))
+        .collect();
+
+    if result.len() < 2 {
+        eprintln!("Unable to deproject the surface density; using it directly.");
+        return surface_density.to_vec();
+    }
+    result
+}
+
+impl GalaxyDescrip {
+    /// (label, value) rows describing the galaxy: Its data's provenance, component masses, scale
+    /// lengths and Sérsic profiles fit to the density and light profiles, and the ranges of the
+    /// data. For the log, UI, and reports.
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        /// E.g. "0.20 – 15.3 kpc (42 points)".
+        fn data_range(data: &[(f64, f64)]) -> String {
+            match (data.first(), data.last()) {
+                (Some(first), Some(last)) => {
+                    format!("{:.2} – {:.1} kpc ({} points)", first.0, last.0, data.len())
+                }
+                _ => "None".to_owned(),
+            }
+        }
+        let scale_length = |density: &[(f64, f64)]| {
+            disk_scale_length(density).map_or("No fit".to_owned(), |r| format!("{r:.2} kpc"))
+        };
+
+        let mut result = Vec::new();
+        let prov = &self.provenance;
+        for (label, val) in [
+            ("Source", &prov.paper),
+            ("Survey", &prov.survey),
+            ("Epoch", &prov.epoch),
+            ("Distance basis", &prov.distance),
+        ] {
+            if !val.is_empty() {
+                result.push((label, val.clone()));
+            }
+        }
+
+        result.extend([
+            ("Disk mass", format!("{:.3e} M☉", self.mass_disk)),
+            ("Bulge mass", format!("{:.3e} M☉", self.mass_bulge)),
+            ("Disk scale length", scale_length(&self.mass_density_disk)),
+        ]);
+        if !self.mass_density_bulge.is_empty() {
+            result.push(("Bulge scale length", scale_length(&self.mass_density_bulge)));
+        }
+        if let Some(fit) = sersic_fit(&intensity_from_mag(&self.luminosity_disk)) {
+            result.push(("Sérsic fit, light", fit.to_string()));
+        }
+        if let Some(fit) = sersic_fit(&self.mass_density_disk) {
+            result.push(("Sérsic fit, density", fit.to_string()));
+        }
+        let (r_core, rho_0) = self.burkert_params;
+        if r_core > 0. && rho_0 > 0. {
+            result.push((
+                "Burkert halo",
+                format!("r: {r_core:.2} kpc, ρ₀: {rho_0:.3e} M☉/kpc³"),
+            ));
+        }
+
+        result.extend([
+            ("M/L", format!("{:.2}", self.mass_to_light_ratio)),
+            ("Distance", format!("{:.0} kpc", self.dist_from_earth)),
+            ("Eccentricity", format!("{:.2}", self.eccentricity)),
+            ("Disk thickness", format!("{:.2} kpc", self.disk_thickness)),
+            ("Disk density data", data_range(&self.mass_density_disk)),
+            ("Disk rotation data", data_range(&self.rotation_curve_disk)),
+        ]);
+        if !self.mass_density_bulge.is_empty() {
+            result.push(("Bulge density data", data_range(&self.mass_density_bulge)));
+        }
+        if !self.rotation_curve_bulge.is_empty() {
+            result.push((
+                "Bulge rotation data",
+                data_range(&self.rotation_curve_bulge),
+            ));
+        }
+        result
+    }
+}
+
+impl fmt::Display for GalaxyDescrip {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let rows = self.summary();
+        let width = rows
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or_default();
+
+        for (label, val) in rows {
+            writeln!(f, "{label:<width$}  {val}")?;
+        }
+        Ok(())
+    }
 }
 
 /// Create mass density from luminosity. X axis for both is r (distance from the galactic center).
@@ -216,7 +590,7 @@ fn make_distrib_along_rings(
     v_scaler: f64,
 ) -> Vec<Body> {
     let mut result = Vec::with_capacity(num_bodies);
-    let mut rng = rand::thread_rng();
+    let mut rng = StdRng::from_os_rng();
 
     let num_rings = num_bodies / DISK_RING_PORTION;
 
@@ -248,6 +622,7 @@ fn make_distrib_along_rings(
                 mass_per_body_by_r[i],
                 v_mag,
                 eccentricity,
+                DISK_THICKNESS_DEFAULT,
                 three_d,
                 &mut rng,
             ));
@@ -327,8 +702,9 @@ fn create_body(
     mass: f64,
     v_mag: f64,
     eccentricity: f64,
+    disk_thickness: f64,
     three_d: bool,
-    rng: &mut ThreadRng,
+    rng: &mut StdRng,
 ) -> Body {
     let θ = rng.random_range(0.0..TAU);
 
@@ -371,9 +747,12 @@ fn create_body(
         let x = r * θ.cos();
         let y = r * θ.sin();
 
-        // todo: Temp mesasure for a finite-thick disk.
-        let disk_thickness_div2 = 0.2;
-        let z = rng.random_range(-disk_thickness_div2..disk_thickness_div2);
+        let half_thickness = disk_thickness / 2.;
+        let z = if half_thickness > 0. {
+            rng.random_range(-half_thickness..half_thickness)
+        } else {
+            0.
+        };
 
         let scale_x = 1.0 - eccentricity; // Eccentricity factor for x-axis
         let posit = Vec3::new(x * scale_x, y, z);
@@ -392,6 +771,7 @@ fn create_body(
         vel,
         accel: Vec3::new_zero(),
         mass,
+        component: Default::default(),
     }
 }
 
@@ -402,12 +782,16 @@ pub fn make_distrib_data_area(
     vel: &[(f64, f64)],
     mass_total: f64,
     eccentricity: f64,
+    disk_thickness: f64,
     num_bodies: usize,
     three_d: bool,
     v_scaler: f64,
+    central_model: CentralModel,
+    num_bodies_core: usize,
+    refinement: Option<RingRefinement>,
+    rng: &mut StdRng,
 ) -> Vec<Body> {
     let mut result = Vec::with_capacity(num_bodies);
-    let mut rng = rand::rng();
 
     // let r_all: Vec<f64> = mass_density.iter().map(|(r, _mass)| r).collect();
     // let dr = r_all[1] - r_all[0];
@@ -417,85 +801,96 @@ pub fn make_distrib_data_area(
 
     // let (bodies_by_r, mass_per_body_by_r) = select_num_bodies(&r_all, dr, mass_density, num_bodies);
 
-    // We will model the inner most area by a single body, to minimize chaotic effects.
-    // let rings_in_center = 30; // todo Crude metric that depends on the data. Starting point.
-    let rings_in_center = 1; // todo Crude metric that depends on the data. Starting point.
-
-    // todo: This is fuzzy, since we don't have a total-mass-in-region estimate.
-
-    {
-        let center_section_r = mass_density[rings_in_center].0;
-        let area_center = center_section_r.powi(2) * TAU / 2.;
-        // let volume_center = volume_sphere(center_section_r);
-
-        let mass_density_center: f64 = mass_density[..rings_in_center].iter().map(|m| m.1).sum();
-        // todo: This averages the mass density of the inner rings. Is this what we want?
-        let mass_center = mass_density_center / rings_in_center as f64 * area_center;
-
-        // todo: Temp rm.
-
-        // result.push(Body {
-        //     posit: Vec3::new_zero(),
-        //     vel: Vec3::new_zero(),
-        //     accel: Vec3::new_zero(),
-        //     mass: mass_center,
-        // });
-    }
+    let (mut center_bodies, rings_in_center) =
+        make_central_region(mass_density, central_model, num_bodies_core, v_scaler, rng);
+    result.append(&mut center_bodies);
 
     // Create bands of masses centered on each r.
-    for (i, (r, mass)) in mass_density[0..mass_density.len() - 1].iter().enumerate() {
+    let mut annuli = Vec::with_capacity(mass_density.len());
+    for (i, (r, density)) in mass_density[0..mass_density.len() - 1].iter().enumerate() {
         if i < rings_in_center {
             // instead of indexing 1.., this keeps i in sync.
             continue;
         }
-        let r_prev = mass_density[i - 1].0;
         let r_this = mass_density[i].0;
         let r_next = mass_density[i + 1].0;
 
-        let dr_prev = r_this - r_prev;
         let dr_next = r_next - r_this;
 
-        let r_inner = r_this - dr_prev / 2.;
+        // The innermost band extends to the center when resolving the core.
+        let r_inner = if i == 0 {
+            0.
+        } else {
+            r_this - (r_this - mass_density[i - 1].0) / 2.
+        };
         let r_outer = r_this + dr_next / 2.;
 
-        let area = {
-            let area_outer = r_outer.powi(2) * TAU / 2.;
-            let area_inner = r_inner.powi(2) * TAU / 2.;
+        annuli.push(Annulus {
+            r: *r,
+            r_inner,
+            r_outer,
+            density: *density,
+        });
+    }
+
+    if let Some(refinement) = refinement {
+        let num_data_annuli = annuli.len();
+        annuli = refine_annuli(annuli, mass_density, &refinement, num_bodies);
+        println!(
+            "Refined {num_data_annuli} data annuli into {} annuli.",
+            annuli.len()
+        );
+    }
+
+    // Each annulus gets its own generator, seeded in order from the main one, so annuli can be
+    // filled in parallel, and the result is the same for a given seed regardless of thread count.
+    let annuli: Vec<_> = annuli
+        .into_iter()
+        .map(|annulus| (annulus, rng.random::<u64>()))
+        .collect();
 
-            area_outer - area_inner
-        };
+    // todo temp: Even distribution. At least one body each, so no annulus's mass is dropped.
+    let body_num_per_area = (num_bodies / annuli.len().max(1)).max(1);
 
+    for (annulus, _) in &annuli {
         // todo: Handle the outer edge case too.
         // Set mass proportionally to initial body numbers. This line multiplies mass/area x area.
-        let mass_this_area = mass * area;
-
-        // todo temp: Even distribution.
-        let body_num_this_area = num_bodies / (mass_density.len() - rings_in_center);
-
-        let mass_per_body = mass_this_area / body_num_this_area as f64;
+        let mass_this_area = annulus.mass();
+        let mass_per_body = mass_this_area / body_num_per_area as f64;
 
         println!(
-            "Body data. r: {r} N bodies: {:?} mass-per-body: {:.0?}k, mass-this-r: {:.4?}",
-            body_num_this_area,
+            "Body data. r: {} N bodies: {:?} mass-per-body: {:.0?}k, mass-this-r: {:.4?}",
+            annulus.r,
+            body_num_per_area,
             mass_per_body / 1000.,
             mass_this_area
         );
-
-        for _ in 0..body_num_this_area {
-            let r_body = rng.random_range(r_inner..r_outer);
-            let v_mag = interpolate(vel, r_body).unwrap() * v_scaler;
-
-            result.push(create_body(
-                r_body,
-                mass_per_body,
-                v_mag,
-                eccentricity,
-                three_d,
-                &mut rng,
-            ));
-        }
     }
 
+    let mut annulus_bodies: Vec<Body> = annuli
+        .par_iter()
+        .flat_map_iter(|(annulus, seed)| {
+            let mut rng = StdRng::seed_from_u64(*seed);
+            let mass_per_body = annulus.mass() / body_num_per_area as f64;
+
+            (0..body_num_per_area).map(move |_| {
+                let r_body = rng.random_range(annulus.r_inner..annulus.r_outer);
+                let v_mag = interpolate(vel, r_body).unwrap() * v_scaler;
+
+                create_body(
+                    r_body,
+                    mass_per_body,
+                    v_mag,
+                    eccentricity,
+                    disk_thickness,
+                    three_d,
+                    &mut rng,
+                )
+            })
+        })
+        .collect();
+    result.append(&mut annulus_bodies);
+
     let mut mass_sum = 0.;
     for body in &result {
         mass_sum += body.mass;
@@ -517,3 +912,430 @@ pub fn make_distrib_data_area(
 
     result
 }
+
+/// Model the innermost region of a component, as set by `central_model`. Returns the bodies created,
+/// and the number of inner data rings they replace.
+fn make_central_region(
+    mass_density: &[(f64, f64)],
+    central_model: CentralModel,
+    num_bodies_core: usize,
+    v_scaler: f64,
+    rng: &mut StdRng,
+) -> (Vec<Body>, usize) {
+    // We model the inner most area by a single body, or a Plummer core, or leave it out, to minimize
+    // chaotic effects.
+    // let rings_in_center = 30; // todo Crude metric that depends on the data. Starting point.
+    let rings_in_center = match central_model {
+        CentralModel::Resolved => 0,
+        _ => 1, // todo Crude metric that depends on the data. Starting point.
+    };
+
+    // todo: This is fuzzy, since we don't have a total-mass-in-region estimate.
+
+    if rings_in_center == 0 {
+        return (Vec::new(), 0);
+    }
+
+    let center_section_r = mass_density[rings_in_center].0;
+    let area_center = center_section_r.powi(2) * TAU / 2.;
+    // let volume_center = volume_sphere(center_section_r);
+
+    let mass_density_center: f64 = mass_density[..rings_in_center].iter().map(|m| m.1).sum();
+    // todo: This averages the mass density of the inner rings. Is this what we want?
+    let mass_center = mass_density_center / rings_in_center as f64 * area_center;
+
+    let bodies = match central_model {
+        CentralModel::PointMass => vec![Body {
+            posit: Vec3::new_zero(),
+            vel: Vec3::new_zero(),
+            accel: Vec3::new_zero(),
+            mass: mass_center,
+            component: Default::default(),
+        }],
+        CentralModel::PlummerCore => make_plummer_core(
+            mass_center,
+            center_section_r,
+            num_bodies_core,
+            v_scaler,
+            rng,
+        ),
+        CentralModel::Resolved | CentralModel::Omitted => Vec::new(),
+    };
+
+    (bodies, rings_in_center)
+}
+
+/// Samples radii directly from the normalized cumulative mass profile (inverse CDF), instead of
+/// filling bands. This gives a smooth, unbinned realization of the density data. All bodies outside
+/// the central region have equal mass.
+pub fn make_distrib_inverse_cdf(
+    mass_density: &[(f64, f64)],
+    vel: &[(f64, f64)],
+    mass_total: f64,
+    eccentricity: f64,
+    disk_thickness: f64,
+    num_bodies: usize,
+    three_d: bool,
+    v_scaler: f64,
+    central_model: CentralModel,
+    num_bodies_core: usize,
+    rng: &mut StdRng,
+) -> Vec<Body> {
+    let mut result = Vec::with_capacity(num_bodies);
+
+    let (mut center_bodies, rings_in_center) =
+        make_central_region(mass_density, central_model, num_bodies_core, v_scaler, rng);
+    result.append(&mut center_bodies);
+
+    let data = &mass_density[rings_in_center..];
+    if data.len() < 2 {
+        return result;
+    }
+
+    // Cumulative mass at each data radius, using the trapezoid rule on dM = Σ(r) τ r dr.
+    let mut cdf = Vec::with_capacity(data.len());
+    cdf.push(0.);
+    for i in 1..data.len() {
+        let (r0, ρ0) = data[i - 1];
+        let (r1, ρ1) = data[i];
+        let dm = (ρ0 * r0 + ρ1 * r1) / 2. * TAU * (r1 - r0);
+        cdf.push(cdf[i - 1] + dm.max(0.));
+    }
+
+    let mass_sampled = *cdf.last().unwrap();
+    if mass_sampled <= 0. {
+        eprintln!("Error sampling from the mass profile: No mass outside the central region.");
+        return result;
+    }
+
+    let mass_per_body = mass_sampled / num_bodies as f64;
+
+    for _ in 0..num_bodies {
+        let target = rng.random_range(0.0..mass_sampled);
+
+        // The first data index with cumulative mass above the target; we interpolate between
+        // it and the previous one.
+        let i = cdf
+            .partition_point(|m| *m <= target)
+            .clamp(1, cdf.len() - 1);
+        let portion = (target - cdf[i - 1]) / (cdf[i] - cdf[i - 1]);
+        let r_body = data[i - 1].0 + portion * (data[i].0 - data[i - 1].0);
+
+        let v_mag = interpolate(vel, r_body).unwrap() * v_scaler;
+
+        result.push(create_body(
+            r_body,
+            mass_per_body,
+            v_mag,
+            eccentricity,
+            disk_thickness,
+            three_d,
+            rng,
+        ));
+    }
+
+    let mass_sum: f64 = result.iter().map(|b| b.mass).sum();
+    let mass_scaler = mass_total / mass_sum;
+    for body in &mut result {
+        body.mass *= mass_scaler;
+    }
+
+    println!("Total bodies {:?}", result.len());
+    println!("Total mass: {:.0?} e9", mass_total / 1e9);
+
+    result
+}
+
+/// Create a Plummer sphere of bodies, to represent a dense core. `r_max` is the outer edge of the
+/// region it replaces; we use it to set the Plummer scale radius. Velocities are set from the
+/// circular velocity of the Plummer mass enclosed at each body's radius, with random orientation.
+fn make_plummer_core(
+    mass: f64,
+    r_max: f64,
+    num_bodies: usize,
+    v_scaler: f64,
+    rng: &mut StdRng,
+) -> Vec<Body> {
+    let mut result = Vec::with_capacity(num_bodies);
+    if num_bodies == 0 {
+        return result;
+    }
+
+    // todo: Experiment with this ratio.
+    let a = r_max / 2.;
+    let mass_per_body = mass / num_bodies as f64;
+
+    for _ in 0..num_bodies {
+        // Inverse-CDF sampling of the Plummer cumulative mass, M(r) / M = r^3 / (r^2 + a^2)^(3/2).
+        // We truncate at `r_max`, so the core doesn't spill into the resolved rings.
+        let r = loop {
+            let m: f64 = rng.random_range(1e-6..1.);
+            let r = a / (m.powf(-2. / 3.) - 1.).sqrt();
+            if r <= r_max {
+                break r;
+            }
+        };
+
+        let v_circ = (G * mass * r.powi(2) / (r.powi(2) + a.powi(2)).powf(1.5)).sqrt();
+        result.push(create_body(
+            r,
+            mass_per_body,
+            v_circ * v_scaler,
+            0.,
+            0.,
+            true,
+            rng,
+        ));
+    }
+
+    result
+}
+
+/// Subdivide annuli until each one's mass, and density change across it, fall below the
+/// thresholds. Densities of the new annuli are interpolated from the data at their midpoints, then
+/// scaled so each pair has its parent's mass; refinement moves mass within an annulus, but doesn't
+/// change it. We stop at `max_annuli`, so each annulus can have at least one body.
+fn refine_annuli(
+    annuli: Vec<Annulus>,
+    mass_density: &[(f64, f64)],
+    refinement: &RingRefinement,
+    max_annuli: usize,
+) -> Vec<Annulus> {
+    let mass_total: f64 = annuli.iter().map(|a| a.mass()).sum();
+    if mass_total <= 0. {
+        return annuli;
+    }
+
+    let density_at = |r: f64| interpolate(mass_density, r).unwrap().max(0.);
+
+    let mut result = annuli;
+    for _ in 0..MAX_REFINE_PASSES {
+        let mut split_any = false;
+        let mut count = result.len();
+        let mut next = Vec::with_capacity(result.len() * 2);
+
+        for annulus in result {
+            let ρ_inner = density_at(annulus.r_inner);
+            let ρ_outer = density_at(annulus.r_outer);
+            let ρ_max = ρ_inner.max(ρ_outer);
+
+            let density_change = if ρ_max > 0. {
+                (ρ_outer - ρ_inner).abs() / ρ_max
+            } else {
+                0.
+            };
+
+            if count < max_annuli
+                && (annulus.mass() / mass_total > refinement.max_mass_portion
+                    || density_change > refinement.max_density_change)
+            {
+                let r_mid = (annulus.r_inner + annulus.r_outer) / 2.;
+
+                let mut children = [(annulus.r_inner, r_mid), (r_mid, annulus.r_outer)].map(
+                    |(r_inner, r_outer)| {
+                        let r = (r_inner + r_outer) / 2.;
+                        Annulus {
+                            r,
+                            r_inner,
+                            r_outer,
+                            density: density_at(r),
+                        }
+                    },
+                );
+
+                let mass_children: f64 = children.iter().map(|c| c.mass()).sum();
+                for child in &mut children {
+                    child.density = if mass_children > 0. {
+                        child.density * annulus.mass() / mass_children
+                    } else {
+                        annulus.density
+                    };
+                }
+
+                next.extend(children);
+                count += 1;
+                split_any = true;
+            } else {
+                next.push(annulus);
+            }
+        }
+
+        result = next;
+        if !split_any {
+            break;
+        }
+    }
+
+    result
+}
+
+/// Set each body's speed to the circular velocity from the Newtonian field of the generated bodies,
+/// keeping its direction. We use the tree (or a direct sum, if `skip_tree` is set), with the same
+/// softening as the simulation. Velocities from the observed curve, and `v_scaler`, are discarded.
+pub fn set_velocities_self_consistent(bodies: &mut [Body], cfg: &Config) {
+    let accs: Vec<Vec3> = if cfg.skip_tree {
+        (0..bodies.len())
+            .into_par_iter()
+            .map(|id| acc_newton(bodies[id].posit, id, bodies, None, cfg.softening_factor_sq))
+            .collect()
+    } else {
+        let Some(bb) = Cube::from_bodies(bodies, BOUNDING_BOX_PAD, true) else {
+            eprintln!("Error building the tree for self-consistent velocities.");
+            return;
+        };
+        let tree = Tree::new(bodies, &bb, &cfg.bh_config);
+
+        bodies
+            .par_iter()
+            .enumerate()
+            .map(|(id, body)| {
+                acc_tree(
+                    body.posit,
+                    id,
+                    &tree,
+                    &cfg.bh_config,
+                    None,
+                    cfg.softening_factor_sq,
+                )
+            })
+            .collect()
+    };
+
+    for (body, acc) in bodies.iter_mut().zip(accs) {
+        body.accel = acc;
+
+        let r = body.posit.magnitude();
+        let v = body.vel.magnitude();
+        if r < f64::EPSILON || v < f64::EPSILON {
+            continue; // E.g. a central point mass.
+        }
+
+        // We use the spherical radial component, since bulge bodies orbit in arbitrary planes.
+        // For the disk, this is close to the cylindrical one.
+        let a_inward = -acc.dot(body.posit / r);
+        let v_circ = (a_inward.max(0.) * r).sqrt();
+
+        body.vel = body.vel / v * v_circ;
+    }
+}
+
+/// Number of radial bins we report velocity offsets in.
+const VEL_CHECK_BINS: usize = 8;
+
+/// The result of comparing generated body speeds to the circular velocity implied by the mass
+/// enclosed at each body's radius.
+#[derive(Debug)]
+pub struct VelocityReport {
+    /// Mean of v / v_circ, over all bodies.
+    pub ratio_mean: f64,
+    /// (r bin outer edge (kpc), mean v / v_circ in the bin, body count in the bin)
+    pub ratio_by_r: Vec<(f64, f64, usize)>,
+    /// Bodies whose angular momentum opposes the total angular momentum.
+    pub num_counter_rotating: usize,
+    pub num_bodies: usize,
+    pub corrected: bool,
+}
+
+impl fmt::Display for VelocityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "v / v_circ mean: {:.3}", self.ratio_mean)?;
+        for (r, ratio, count) in &self.ratio_by_r {
+            writeln!(f, "  r < {r:.2} kpc: {ratio:.3} (N: {count})")?;
+        }
+        writeln!(
+            f,
+            "Counter-rotating: {} of {} ({:.1}%)",
+            self.num_counter_rotating,
+            self.num_bodies,
+            100. * self.num_counter_rotating as f64 / self.num_bodies.max(1) as f64
+        )?;
+        if self.corrected {
+            writeln!(f, "Speeds corrected to v_circ.")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare each body's speed to the circular velocity implied by the (spherically-approximated) mass
+/// enclosed at its radius, and check angular momentum directions against the total. If `correct`
+/// is set, we set each body's speed to the circular velocity, keeping its direction.
+pub fn check_velocities(bodies: &mut [Body], correct: bool) -> VelocityReport {
+    let mut l_total = Vec3::new_zero();
+    for body in bodies.iter() {
+        l_total += body.posit.cross(body.vel) * body.mass;
+    }
+    let l_dir = if l_total.magnitude() > 0. {
+        l_total.to_normalized()
+    } else {
+        Vec3::new(0., 0., 1.)
+    };
+
+    let num_counter_rotating = bodies
+        .iter()
+        .filter(|b| b.posit.cross(b.vel).dot(l_dir) < 0.)
+        .count();
+
+    // Enclosed mass at each body; we sort indices by radius, and accumulate.
+    let mut ids: Vec<usize> = (0..bodies.len()).collect();
+    ids.sort_by(|a, b| {
+        bodies[*a]
+            .posit
+            .magnitude()
+            .partial_cmp(&bodies[*b].posit.magnitude())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let r_max = ids
+        .last()
+        .map(|i| bodies[*i].posit.magnitude())
+        .unwrap_or(0.);
+    let bin_width = r_max / VEL_CHECK_BINS as f64;
+
+    let mut bins = vec![(0., 0); VEL_CHECK_BINS];
+    let mut ratio_sum = 0.;
+    let mut num_ratios = 0;
+    let mut mass_enclosed = 0.;
+
+    for i in ids {
+        let body = &mut bodies[i];
+        let r = body.posit.magnitude();
+        let v = body.vel.magnitude();
+
+        if r > f64::EPSILON && mass_enclosed > 0. {
+            let v_circ = (G * mass_enclosed / r).sqrt();
+            let ratio = v / v_circ;
+
+            ratio_sum += ratio;
+            num_ratios += 1;
+
+            let bin = ((r / bin_width) as usize).min(VEL_CHECK_BINS - 1);
+            bins[bin].0 += ratio;
+            bins[bin].1 += 1;
+
+            if correct && v > 0. {
+                body.vel = body.vel / v * v_circ;
+            }
+        }
+
+        mass_enclosed += body.mass;
+    }
+
+    VelocityReport {
+        ratio_mean: ratio_sum / num_ratios.max(1) as f64,
+        ratio_by_r: bins
+            .iter()
+            .enumerate()
+            .map(|(i, (sum, count))| {
+                (
+                    (i + 1) as f64 * bin_width,
+                    sum / (*count).max(1) as f64,
+                    *count,
+                )
+            })
+            .collect(),
+        num_counter_rotating,
+        num_bodies: bodies.len(),
+        corrected: correct,
+    }
+}
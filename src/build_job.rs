@@ -0,0 +1,225 @@
+//! Builds started from the UI run on a worker thread, so the window stays responsive, and
+//! snapshots can be viewed as they're taken. The bodies, and other simulation data, are copied to a
+//! `State` owned by the worker, and ours are replaced with its when it finishes; until then, ours
+//! are the initial conditions. Progress, and snapshots, arrive over channels; the UI polls them
+//! each frame.
+
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    hooks::{StepControl, StepHook, StepInfo},
+    playback::SnapShot,
+    run, ForceModel, State, StateUi,
+};
+
+/// Run last each step: Reports progress, and stops the build if cancelled.
+struct BuildControl {
+    progress: Sender<usize>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl StepHook for BuildControl {
+    fn on_step(&mut self, info: &StepInfo) -> StepControl {
+        let _ = self.progress.send(info.step + 1);
+
+        if self.cancel.load(Ordering::Relaxed) {
+            StepControl::Stop
+        } else {
+            StepControl::Continue
+        }
+    }
+
+    fn name(&self) -> String {
+        "Cancel".to_owned()
+    }
+}
+
+pub struct BuildJob {
+    handle: JoinHandle<State>,
+    snapshots: Receiver<SnapShot>,
+    progress: Receiver<usize>,
+    cancel: Arc<AtomicBool>,
+    start: Instant,
+    pub steps_done: usize,
+    pub num_steps: usize,
+}
+
+impl BuildJob {
+    /// Start integrating the current bodies, from the current time.
+    pub fn spawn(state: &mut State, force_model: ForceModel) -> Self {
+        let (snapshot_tx, snapshot_rx) = mpsc::channel();
+        let (progress_tx, progress_rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        state.add_step_hook(BuildControl {
+            progress: progress_tx,
+            cancel: cancel.clone(),
+        });
+        let step_hooks = mem::take(&mut state.step_hooks);
+
+        let stream = state.ui.stream_snapshots;
+        let mut worker = State {
+            config: state.config.clone(),
+            ui: StateUi {
+                draw_tree: state.ui.draw_tree,
+                stream_snapshots: stream,
+                run_labels: state.ui.run_labels.clone(),
+                galaxy_catalog: state.ui.galaxy_catalog.clone(),
+                ..Default::default()
+            },
+            bodies: state.bodies.clone(),
+            shells: state.shells.clone(),
+            // If streaming, these start the file. Otherwise, new snapshots are appended to ours.
+            snapshots: if stream {
+                mem::take(&mut state.snapshots)
+            } else {
+                Vec::new()
+            },
+            body_masses: state.body_masses.clone(),
+            body_components: state.body_components.clone(),
+            body_ids: state.body_ids.clone(),
+            body_set_changed: state.body_set_changed,
+            time_elapsed: state.time_elapsed,
+            charge_mode: state.charge_mode,
+            dev: state.dev.clone(),
+            diagnostics: state.diagnostics.clone(),
+            step_hooks,
+            zoom_boundary: state.zoom_boundary.take(),
+            #[cfg(feature = "shell_interaction")]
+            shell_interaction: mem::take(&mut state.shell_interaction),
+            ..Default::default()
+        };
+
+        // For display until the stream is done.
+        if stream {
+            state.take_snapshot(0., Vec::new());
+        }
+
+        state.ui.building = true;
+        state.ui.run_labels.force_model = force_model.to_str();
+        state.ui.notifications.set_status("build", "Building...");
+
+        let live = (!stream).then_some(snapshot_tx);
+        let handle = thread::spawn(move || {
+            run(&mut worker, force_model, live);
+            worker
+        });
+
+        Self {
+            handle,
+            snapshots: snapshot_rx,
+            progress: progress_rx,
+            cancel,
+            start: Instant::now(),
+            steps_done: 0,
+            num_steps: state.config.num_timesteps,
+        }
+    }
+
+    /// End the build after the current step. Snapshots taken so far are kept.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn portion_done(&self) -> f32 {
+        self.steps_done as f32 / self.num_steps.max(1) as f32
+    }
+
+    /// Estimated time remaining, from the mean time per step so far. Stop criteria may end the
+    /// build sooner.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.steps_done == 0 {
+            return None;
+        }
+        let remaining = self.num_steps.saturating_sub(self.steps_done);
+        Some(
+            self.start
+                .elapsed()
+                .mul_f64(remaining as f64 / self.steps_done as f64),
+        )
+    }
+}
+
+/// Add snapshots taken since the last poll, and update progress. If the build is done, move its
+/// results back. Call each frame.
+pub fn poll(state: &mut State) {
+    let Some(job) = &mut state.build_job else {
+        return;
+    };
+
+    state.snapshots.extend(job.snapshots.try_iter());
+    if let Some(steps) = job.progress.try_iter().last() {
+        job.steps_done = steps;
+    }
+
+    if job.handle.is_finished() {
+        finish(state);
+    }
+}
+
+/// Wait for the build to finish, and move its results back.
+fn finish(state: &mut State) {
+    let Some(job) = state.build_job.take() else {
+        return;
+    };
+
+    state.ui.building = false;
+    state.ui.notifications.clear_status("build");
+
+    let Ok(mut worker) = job.handle.join() else {
+        state
+            .ui
+            .notifications
+            .error("The build thread panicked; regenerating bodies");
+        state.refresh_bodies();
+        return;
+    };
+    // Sent before the worker exited.
+    state.snapshots.extend(job.snapshots.try_iter());
+
+    worker.step_hooks.pop(); // Ours.
+    state.step_hooks = worker.step_hooks;
+
+    state.bodies = worker.bodies;
+    state.shells = worker.shells;
+    state.body_masses = worker.body_masses;
+    state.body_components = worker.body_components;
+    state.body_ids = worker.body_ids;
+    state.body_set_changed = worker.body_set_changed;
+    state.time_elapsed = worker.time_elapsed;
+    state.diagnostics = worker.diagnostics;
+    state.zoom_boundary = worker.zoom_boundary;
+    #[cfg(feature = "shell_interaction")]
+    {
+        state.shell_interaction = worker.shell_interaction;
+    }
+
+    if worker.snapshot_stream.is_some() {
+        state.snapshot_stream = worker.snapshot_stream;
+        state.snapshots = Vec::new();
+    }
+
+    state.ui.notifications.append(worker.ui.notifications);
+}
+
+/// Cancel the build, if one is running, and wait for it. Call before anything that replaces
+/// bodies or snapshots.
+pub fn stop(state: &mut State) {
+    if let Some(job) = &state.build_job {
+        job.cancel();
+        finish(state);
+    }
+}
@@ -1,5 +1,7 @@
 //! Related to Cold Dark Matter (CDM)
 
+use std::f64::consts::PI;
+
 /// Generate a Berkert Halo. Generally gives good fites to rotation curves.
 /// rho_0 is the central density. r_core is the core radius.
 pub fn density_burkert(r: f64, rho_0: f64, r_core: f64) -> f64 {
@@ -11,3 +13,15 @@ pub fn density_burkert(r: f64, rho_0: f64, r_core: f64) -> f64 {
 pub fn density_nfw(r: f64, rho_s: f64, r_s: f64) -> f64 {
     rho_s / ((r / r_s) * (1. + r / r_s).powi(2))
 }
+
+/// Mass of a Burkert halo within radius `r`. M☉, with `rho_0` in M☉/kpc³.
+pub fn mass_burkert(r: f64, rho_0: f64, r_core: f64) -> f64 {
+    let x = r / r_core;
+    PI * rho_0 * r_core.powi(3) * (((1. + x).powi(2) * (1. + x.powi(2))).ln() - 2. * x.atan())
+}
+
+/// Mass of a NFW halo within radius `r`. M☉, with `rho_s` in M☉/kpc³.
+pub fn mass_nfw(r: f64, rho_s: f64, r_s: f64) -> f64 {
+    let x = r / r_s;
+    4. * PI * rho_s * r_s.powi(3) * ((1. + x).ln() - x / (1. + x))
+}
@@ -5,7 +5,10 @@ use std::{collections::HashMap, f64::consts::TAU, fmt, fmt::Formatter};
 use lin_alg::f64::{Quaternion, Vec3};
 use rand::Rng;
 
-use crate::{properties::plot, Body};
+use crate::{
+    properties::{plot, PlotOutput},
+    Body,
+};
 
 pub fn coulomb_force(
     acc_dir: Vec3,
@@ -79,6 +82,7 @@ pub fn make_particles() -> Vec<Body> {
             vel,
             accel: Vec3::new_zero(),
             mass: 1.,
+            component: Default::default(),
         });
     }
 
@@ -546,7 +550,7 @@ pub fn get_accel_curl(bodies: &[Body], posit: Vec3, dx: f64) -> Vec3 {
     }
 }
 
-pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
+pub fn plot_field_properties(out: &PlotOutput, properties: &Vec<(f64, FieldProperties)>) {
     // Todo: Magnitudes for now; most quantities are vector quantities.
 
     let mut avg_vel = Vec::new();
@@ -571,6 +575,7 @@ pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
     // todo: Show in the labels the number of bodies.
 
     plot(
+        out,
         &avg_vel,
         "r",
         "|Vel|",
@@ -579,6 +584,7 @@ pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
     );
 
     plot(
+        out,
         &density,
         "r",
         "ρ",
@@ -587,6 +593,7 @@ pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
     );
 
     plot(
+        out,
         &flux,
         "r",
         "flux",
@@ -595,6 +602,7 @@ pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
     );
 
     plot(
+        out,
         &divergence,
         "r",
         "Divergence",
@@ -602,9 +610,17 @@ pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
         &format!("divergence_plot"),
     );
 
-    plot(&curl, "r", "Curl", &format!("Curl"), &format!("curl_plot"));
+    plot(
+        out,
+        &curl,
+        "r",
+        "Curl",
+        &format!("Curl"),
+        &format!("curl_plot"),
+    );
 
     plot(
+        out,
         &avg_accel,
         "r",
         "|Accel|",
@@ -613,6 +629,7 @@ pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
     );
 
     plot(
+        out,
         &accel_divergence,
         "r",
         "Accel divergence",
@@ -621,6 +638,7 @@ pub fn plot_field_properties(properties: &Vec<(f64, FieldProperties)>) {
     );
 
     plot(
+        out,
         &accel_curl,
         "r",
         "|Accel curl|",
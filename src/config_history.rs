@@ -0,0 +1,132 @@
+//! Undo and redo of config changes, e.g. to recover from accidental edits before a long build. We
+//! detect changes by comparing the config to its state at the end of the previous UI frame, so any
+//! edit is covered, wherever it's made. Rapid changes, e.g. from dragging a value, are merged into
+//! one step.
+
+use std::time::{Duration, Instant};
+
+use bincode::config;
+
+use crate::Config;
+
+/// Changes closer together than this are merged into one undo step.
+const MERGE_INTERVAL: Duration = Duration::from_millis(800);
+/// Oldest steps are dropped past this.
+const MAX_STEPS: usize = 100;
+
+/// Configs don't implement `PartialEq`; we compare their encodings.
+fn encoded(cfg: &Config) -> Vec<u8> {
+    bincode::encode_to_vec(cfg, config::standard()).unwrap_or_default()
+}
+
+/// If generated bodies differ between the configs. If so, they need regenerating when switching.
+pub fn affects_bodies(a: &Config, b: &Config) -> bool {
+    let fields = |c: &Config| {
+        bincode::encode_to_vec(
+            (
+                &c.num_bodies_disk,
+                &c.num_bodies_bulge,
+                &c.num_bodies_core,
+                &c.v_scaler,
+                &c.body_sampling,
+                &c.central_model,
+                &c.refine_rings,
+                &c.ring_refinement,
+                &c.velocity_init,
+                &c.velocity_check,
+                &c.seed,
+            ),
+            config::standard(),
+        )
+        .unwrap_or_default()
+    };
+    fields(a) != fields(b)
+}
+
+#[derive(Default)]
+pub struct ConfigHistory {
+    undo: Vec<Config>,
+    redo: Vec<Config>,
+    /// The config at the end of the last frame, and its encoding.
+    last: Option<(Config, Vec<u8>)>,
+    last_change: Option<Instant>,
+    /// The config of the last build.
+    built: Option<Config>,
+}
+
+impl ConfigHistory {
+    /// Call once per frame, after UI edits.
+    pub fn record(&mut self, current: &Config) {
+        let current_enc = encoded(current);
+
+        let Some((last, last_enc)) = &self.last else {
+            self.last = Some((current.clone(), current_enc));
+            return;
+        };
+        if *last_enc == current_enc {
+            return;
+        }
+
+        let now = Instant::now();
+        let merge = self
+            .last_change
+            .is_some_and(|t| now.duration_since(t) < MERGE_INTERVAL);
+
+        if !merge {
+            self.undo.push(last.clone());
+            if self.undo.len() > MAX_STEPS {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        self.last_change = Some(now);
+        self.last = Some((current.clone(), current_enc));
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Move to `cfg` without recording it as a new change.
+    fn switch_to(&mut self, cfg: Config) -> Config {
+        self.last = Some((cfg.clone(), encoded(&cfg)));
+        self.last_change = None;
+        cfg
+    }
+
+    /// The config before the last change, to replace `current` with.
+    pub fn undo(&mut self, current: &Config) -> Option<Config> {
+        let prev = self.undo.pop()?;
+        self.redo.push(current.clone());
+        Some(self.switch_to(prev))
+    }
+
+    pub fn redo(&mut self, current: &Config) -> Option<Config> {
+        let next = self.redo.pop()?;
+        self.undo.push(current.clone());
+        Some(self.switch_to(next))
+    }
+
+    pub fn set_built(&mut self, cfg: &Config) {
+        self.built = Some(cfg.clone());
+    }
+
+    /// If there's a build, and the config has changed since.
+    pub fn can_revert(&self, current: &Config) -> bool {
+        self.built
+            .as_ref()
+            .is_some_and(|b| encoded(b) != encoded(current))
+    }
+
+    /// The config of the last build. Reverting can itself be undone.
+    pub fn revert(&mut self, current: &Config) -> Option<Config> {
+        let built = self.built.clone()?;
+        self.undo.push(current.clone());
+        self.redo.clear();
+        Some(self.switch_to(built))
+    }
+}
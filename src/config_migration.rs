@@ -0,0 +1,354 @@
+//! Versioning and migration for saved config files. These files start with a magic number, followed
+//! by the encoded config: a version, then each field, in order. Fields added in later versions are
+//! only decoded if the file's version includes them; otherwise, they keep their default values. Files
+//! saved before versioning have no header; we treat them as version 0.
+//!
+//! When adding a field to `Config`: Append it to both `encode` and `decode_fields`, bump
+//! `CONFIG_VERSION`, and gate its decoding on the new version.
+
+use std::{
+    fs::File,
+    io,
+    io::{ErrorKind, Read, Write},
+    path::Path,
+};
+
+use bincode::{
+    config,
+    de::{read::SliceReader, Decoder, DecoderImpl},
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    impl_borrow_decode, Decode, Encode,
+};
+
+use crate::Config;
+
+/// Identifies a versioned config file.
+const MAGIC: &[u8; 4] = b"CGcf";
+
+pub const CONFIG_VERSION: u16 = 17;
+
+impl Encode for Config {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        CONFIG_VERSION.encode(encoder)?;
+
+        // Version 0
+        self.num_timesteps.encode(encoder)?;
+        self.dt_integration_max.encode(encoder)?;
+        self.dt.encode(encoder)?;
+        self.dynamic_dt_scaler.encode(encoder)?;
+        self.shell_creation_ratio.encode(encoder)?;
+        self.num_bodies_disk.encode(encoder)?;
+        self.num_bodies_bulge.encode(encoder)?;
+        self.softening_factor_sq.encode(encoder)?;
+        self.snapshot_ratio.encode(encoder)?;
+        self.bh_config.encode(encoder)?;
+        self.v_scaler.encode(encoder)?;
+        self.skip_tree.encode(encoder)?;
+
+        // Version 1
+        self.body_sampling.encode(encoder)?;
+        self.central_model.encode(encoder)?;
+        self.num_bodies_core.encode(encoder)?;
+        self.refine_rings.encode(encoder)?;
+        self.ring_refinement.encode(encoder)?;
+        self.velocity_init.encode(encoder)?;
+        self.velocity_check.encode(encoder)?;
+
+        // Version 2
+        self.stop_criteria.encode(encoder)?;
+
+        // Version 3
+        self.seed.encode(encoder)?;
+
+        // Version 4
+        self.superluminal.encode(encoder)?;
+
+        // Version 5
+        self.shell_anisotropy.encode(encoder)?;
+
+        // Version 6
+        self.shell_speed.encode(encoder)?;
+
+        // Version 7
+        self.shell_geometry.encode(encoder)?;
+
+        // Version 8
+        self.plot_output.encode(encoder)?;
+
+        // Version 9
+        self.scf.encode(encoder)?;
+
+        // Version 10
+        self.gpu.block_size.encode(encoder)?;
+
+        // Version 11
+        self.gpu.tile_size.encode(encoder)?;
+
+        // Version 12
+        self.gpu.precision.encode(encoder)?;
+
+        // Version 13
+        self.integrator.encode(encoder)?;
+
+        // Version 14
+        self.block_timesteps.encode(encoder)?;
+
+        // Version 15
+        self.snapshot_decimation.encode(encoder)?;
+
+        // Version 16
+        self.diagnostics_ratio.encode(encoder)?;
+
+        // Version 17
+        self.mass_refinement.encode(encoder)?;
+
+        Ok(())
+    }
+}
+
+impl<Ctx> Decode<Ctx> for Config {
+    fn decode<D: Decoder<Context = Ctx>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let version = u16::decode(decoder)?;
+        decode_fields(decoder, version)
+    }
+}
+
+impl_borrow_decode!(Config);
+
+/// Decode the fields present in a given version's layout. Fields not present keep their defaults.
+fn decode_fields<D: Decoder>(decoder: &mut D, version: u16) -> Result<Config, DecodeError> {
+    if version > CONFIG_VERSION {
+        return Err(DecodeError::Other(
+            "Config was saved by a newer version of this program",
+        ));
+    }
+
+    let mut result = Config::default();
+
+    result.num_timesteps = Decode::decode(decoder)?;
+    result.dt_integration_max = Decode::decode(decoder)?;
+    result.dt = Decode::decode(decoder)?;
+    result.dynamic_dt_scaler = Decode::decode(decoder)?;
+    result.shell_creation_ratio = Decode::decode(decoder)?;
+    result.num_bodies_disk = Decode::decode(decoder)?;
+    result.num_bodies_bulge = Decode::decode(decoder)?;
+    result.softening_factor_sq = Decode::decode(decoder)?;
+    result.snapshot_ratio = Decode::decode(decoder)?;
+    result.bh_config = Decode::decode(decoder)?;
+    result.v_scaler = Decode::decode(decoder)?;
+    result.skip_tree = Decode::decode(decoder)?;
+
+    if version >= 1 {
+        result.body_sampling = Decode::decode(decoder)?;
+        result.central_model = Decode::decode(decoder)?;
+        result.num_bodies_core = Decode::decode(decoder)?;
+        result.refine_rings = Decode::decode(decoder)?;
+        result.ring_refinement = Decode::decode(decoder)?;
+        result.velocity_init = Decode::decode(decoder)?;
+        result.velocity_check = Decode::decode(decoder)?;
+    }
+
+    if version >= 2 {
+        result.stop_criteria = Decode::decode(decoder)?;
+    }
+
+    if version >= 3 {
+        result.seed = Decode::decode(decoder)?;
+    }
+
+    if version >= 4 {
+        result.superluminal = Decode::decode(decoder)?;
+    }
+
+    if version >= 5 {
+        result.shell_anisotropy = Decode::decode(decoder)?;
+    }
+
+    if version >= 6 {
+        result.shell_speed = Decode::decode(decoder)?;
+    }
+
+    if version >= 7 {
+        result.shell_geometry = Decode::decode(decoder)?;
+    }
+
+    if version >= 8 {
+        result.plot_output = Decode::decode(decoder)?;
+    }
+
+    if version >= 9 {
+        result.scf = Decode::decode(decoder)?;
+    }
+
+    if version >= 10 {
+        result.gpu.block_size = Decode::decode(decoder)?;
+    }
+
+    if version >= 11 {
+        result.gpu.tile_size = Decode::decode(decoder)?;
+    }
+
+    if version >= 12 {
+        result.gpu.precision = Decode::decode(decoder)?;
+    }
+
+    if version >= 13 {
+        result.integrator = Decode::decode(decoder)?;
+    }
+
+    if version >= 14 {
+        result.block_timesteps = Decode::decode(decoder)?;
+    }
+
+    if version >= 15 {
+        result.snapshot_decimation = Decode::decode(decoder)?;
+    }
+
+    if version >= 16 {
+        result.diagnostics_ratio = Decode::decode(decoder)?;
+    }
+
+    if version >= 17 {
+        result.mass_refinement = Decode::decode(decoder)?;
+    }
+
+    Ok(result)
+}
+
+/// Save the config, with a header identifying it as versioned.
+pub fn save(path: &Path, cfg: &Config) -> io::Result<()> {
+    let encoded = bincode::encode_to_vec(cfg, config::standard())
+        .map_err(|e| io::Error::new(ErrorKind::Other, e.to_string()))?;
+
+    let mut file = File::create(path)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&encoded)?;
+    Ok(())
+}
+
+/// Load a config, migrating it to the current layout if it was saved by an older version.
+pub fn load(path: &Path) -> io::Result<Config> {
+    let mut file = File::open(path)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    let result = match buffer.strip_prefix(MAGIC) {
+        Some(data) => bincode::decode_from_slice(data, config::standard()).map(|(cfg, _len)| cfg),
+        None => {
+            println!("Migrating a config saved before versioning.");
+            let mut decoder = DecoderImpl::new(SliceReader::new(&buffer), config::standard(), ());
+            decode_fields(&mut decoder, 0)
+        }
+    };
+
+    result.map_err(|e| {
+        eprintln!("Error loading config: {e}");
+        io::Error::new(ErrorKind::InvalidData, e.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use barnes_hut::BhConfig;
+
+    use super::*;
+    use crate::{body_creation::CentralModel, integrate::IntegratorKind};
+
+    /// The baseline's config, before versioning. Its file was the derived encoding of these fields,
+    /// in this order.
+    #[derive(Encode)]
+    struct ConfigV0 {
+        num_timesteps: usize,
+        dt_integration_max: f64,
+        dt: f64,
+        dynamic_dt_scaler: f64,
+        shell_creation_ratio: usize,
+        num_bodies_disk: usize,
+        num_bodies_bulge: usize,
+        softening_factor_sq: f64,
+        snapshot_ratio: usize,
+        bh_config: BhConfig,
+        v_scaler: f64,
+        skip_tree: bool,
+    }
+
+    /// Fields aren't compared directly, since not all of their types implement `PartialEq`.
+    fn assert_same(actual: &Config, expected: &Config) {
+        assert_eq!(format!("{actual:?}"), format!("{expected:?}"));
+    }
+
+    /// A field missing from either `encode` or `decode_fields` shifts the ones after it, so we set
+    /// fields throughout the layout, including the last, to non-default values.
+    #[test]
+    fn round_trip() {
+        let mut cfg = Config::default();
+        cfg.num_timesteps = 1_234;
+        cfg.dt = 5e-3;
+        cfg.shell_creation_ratio = 3;
+        cfg.softening_factor_sq = 2e-4;
+        cfg.skip_tree = true;
+        cfg.central_model = CentralModel::PlummerCore;
+        cfg.num_bodies_core = 77;
+        cfg.refine_rings = true;
+        cfg.seed = Some(42);
+        cfg.gpu.block_size = Some(128);
+        cfg.gpu.tile_size = Some(4_096);
+        cfg.integrator = IntegratorKind::Yoshida4;
+        cfg.diagnostics_ratio = 7;
+
+        let encoded = bincode::encode_to_vec(&cfg, config::standard()).unwrap();
+        assert_eq!(&encoded[..1], &[CONFIG_VERSION as u8]);
+
+        let (decoded, len): (Config, _) =
+            bincode::decode_from_slice(&encoded, config::standard()).unwrap();
+        assert_eq!(len, encoded.len());
+        assert_same(&decoded, &cfg);
+    }
+
+    /// A file saved before versioning loads, with its fields, and defaults for later ones.
+    #[test]
+    fn migrate_v0() {
+        let v0 = ConfigV0 {
+            num_timesteps: 1_234,
+            dt_integration_max: 0.02,
+            dt: 5e-3,
+            dynamic_dt_scaler: 0.2,
+            shell_creation_ratio: 3,
+            num_bodies_disk: 321,
+            num_bodies_bulge: 45,
+            softening_factor_sq: 2e-4,
+            snapshot_ratio: 5,
+            bh_config: Default::default(),
+            v_scaler: 1.5,
+            skip_tree: true,
+        };
+
+        let path = env::temp_dir().join("causal_grav_config_v0.grav");
+        fs::write(
+            &path,
+            bincode::encode_to_vec(&v0, config::standard()).unwrap(),
+        )
+        .unwrap();
+        let loaded = load(&path);
+        fs::remove_file(&path).ok();
+
+        let mut expected = Config::default();
+        expected.num_timesteps = v0.num_timesteps;
+        expected.dt_integration_max = v0.dt_integration_max;
+        expected.dt = v0.dt;
+        expected.dynamic_dt_scaler = v0.dynamic_dt_scaler;
+        expected.shell_creation_ratio = v0.shell_creation_ratio;
+        expected.num_bodies_disk = v0.num_bodies_disk;
+        expected.num_bodies_bulge = v0.num_bodies_bulge;
+        expected.softening_factor_sq = v0.softening_factor_sq;
+        expected.snapshot_ratio = v0.snapshot_ratio;
+        expected.bh_config = v0.bh_config;
+        expected.v_scaler = v0.v_scaler;
+        expected.skip_tree = v0.skip_tree;
+
+        assert_same(&loaded.unwrap(), &expected);
+    }
+}
@@ -0,0 +1,220 @@
+//! A text form of the config's scalar parameters, for editing in a text editor, e.g. while tuning a
+//! run. It's a flat TOML file of `key = value` pairs; `ConfigWatch` watches it, and offers to apply
+//! changes. Settings that aren't scalars, e.g. the shell speed table, are only in the binary config,
+//! and are left unchanged on import; so are keys missing from the file.
+
+use std::{fmt::Write as _, fs, io, io::ErrorKind, path::Path, str::FromStr};
+
+use crate::Config;
+
+pub const CONFIG_TEXT_FILE: &str = "config.toml";
+
+/// For `seed`, when each generation is different.
+const SEED_RANDOM: &str = "\"random\"";
+
+/// The config's scalar parameters, as TOML.
+pub fn to_text(cfg: &Config) -> String {
+    let mut result = "# Edit and save; the program offers to apply changes. Missing keys are left \
+        unchanged.\n\n"
+        .to_owned();
+
+    let mut line = |comment: &str, key: &str, val: String| {
+        writeln!(result, "# {comment}\n{key} = {val}\n").unwrap();
+    };
+
+    line(
+        "Steps per build.",
+        "num_timesteps",
+        cfg.num_timesteps.to_string(),
+    );
+    line("Myr", "dt", format!("{:?}", cfg.dt));
+    line(
+        "Emit a shell every this many steps.",
+        "shell_creation_ratio",
+        cfg.shell_creation_ratio.to_string(),
+    );
+    line(
+        "Bodies in the disk.",
+        "num_bodies_disk",
+        cfg.num_bodies_disk.to_string(),
+    );
+    line(
+        "Bodies in the bulge.",
+        "num_bodies_bulge",
+        cfg.num_bodies_bulge.to_string(),
+    );
+    line(
+        "Bodies in the core, with the Plummer core central model.",
+        "num_bodies_core",
+        cfg.num_bodies_core.to_string(),
+    );
+    line(
+        "kpc²",
+        "softening_factor_sq",
+        format!("{:?}", cfg.softening_factor_sq),
+    );
+    line(
+        "Take a snapshot every this many steps.",
+        "snapshot_ratio",
+        cfg.snapshot_ratio.to_string(),
+    );
+    line(
+        "Barnes-Hut opening angle.",
+        "theta",
+        format!("{:?}", cfg.bh_config.θ),
+    );
+    line(
+        "Scales all published V magnitudes.",
+        "v_scaler",
+        format!("{:?}", cfg.v_scaler),
+    );
+    line(
+        "Direct sums, vice the tree.",
+        "skip_tree",
+        cfg.skip_tree.to_string(),
+    );
+    line(
+        "Subdivide data annuli where the density profile is steep.",
+        "refine_rings",
+        cfg.refine_rings.to_string(),
+    );
+    line(
+        "An integer, or \"random\".",
+        "seed",
+        cfg.seed
+            .map_or_else(|| SEED_RANDOM.to_owned(), |s| s.to_string()),
+    );
+    line(
+        "Sample energy and angular momentum every this many steps. 0 to disable.",
+        "diagnostics_ratio",
+        cfg.diagnostics_ratio.to_string(),
+    );
+
+    result
+}
+
+fn parse<T: FromStr>(key: &str, val: &str) -> Result<T, String> {
+    val.parse()
+        .map_err(|_| format!("Invalid value for `{key}`: {val}"))
+}
+
+/// As `parse`, for values that must be above 0, e.g. the timestep, and ratios used as divisors.
+fn parse_positive<T: FromStr + PartialOrd + Default>(key: &str, val: &str) -> Result<T, String> {
+    let result = parse(key, val)?;
+    if result > T::default() {
+        Ok(result)
+    } else {
+        Err(format!("`{key}` must be greater than 0: {val}"))
+    }
+}
+
+fn apply_line(cfg: &mut Config, key: &str, val: &str) -> Result<(), String> {
+    match key {
+        "num_timesteps" => cfg.num_timesteps = parse(key, val)?,
+        "dt" => cfg.dt = parse_positive(key, val)?,
+        "shell_creation_ratio" => cfg.shell_creation_ratio = parse_positive(key, val)?,
+        "num_bodies_disk" => cfg.num_bodies_disk = parse(key, val)?,
+        "num_bodies_bulge" => cfg.num_bodies_bulge = parse(key, val)?,
+        "num_bodies_core" => cfg.num_bodies_core = parse(key, val)?,
+        "softening_factor_sq" => cfg.softening_factor_sq = parse(key, val)?,
+        "snapshot_ratio" => cfg.snapshot_ratio = parse_positive(key, val)?,
+        "theta" => cfg.bh_config.θ = parse(key, val)?,
+        "v_scaler" => cfg.v_scaler = parse(key, val)?,
+        "skip_tree" => cfg.skip_tree = parse(key, val)?,
+        "refine_rings" => cfg.refine_rings = parse(key, val)?,
+        "seed" if val == SEED_RANDOM => cfg.seed = None,
+        "seed" => cfg.seed = Some(parse(key, val)?),
+        "diagnostics_ratio" => cfg.diagnostics_ratio = parse(key, val)?,
+        _ => return Err(format!("unknown key `{key}`")),
+    }
+    Ok(())
+}
+
+/// Apply parameters from text, as written by `to_text`, to a config. On error, e.g. an unknown key
+/// or invalid value, the config is unchanged.
+pub fn apply_text(cfg: &mut Config, text: &str) -> Result<(), String> {
+    let mut result = cfg.clone();
+
+    for (i, line) in text.lines().enumerate() {
+        // Values are numbers, bools, or `SEED_RANDOM`, so don't contain `#`.
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((key, val)) = line.split_once('=') else {
+            return Err(format!("Line {}: expected `key = value`", i + 1));
+        };
+
+        apply_line(&mut result, key.trim(), val.trim())
+            .map_err(|e| format!("Line {}: {e}", i + 1))?;
+    }
+
+    *cfg = result;
+    Ok(())
+}
+
+pub fn save(path: &Path, cfg: &Config) -> io::Result<()> {
+    fs::write(path, to_text(cfg))
+}
+
+/// Load parameters from a text config onto `cfg`.
+pub fn load(path: &Path, cfg: &mut Config) -> io::Result<()> {
+    let text = fs::read_to_string(path)?;
+    apply_text(cfg, &text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply() {
+        let mut cfg = Config::default();
+        apply_text(
+            &mut cfg,
+            "dt = 0.5 # Myr\n\nsnapshot_ratio = 3\nseed = \"random\"",
+        )
+        .unwrap();
+
+        assert_eq!(cfg.dt, 0.5);
+        assert_eq!(cfg.snapshot_ratio, 3);
+        assert_eq!(cfg.seed, None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cfg = Config {
+            dt: 0.25,
+            num_bodies_disk: 1_234,
+            skip_tree: true,
+            seed: Some(7),
+            ..Default::default()
+        };
+        cfg.bh_config.θ = 0.3;
+
+        let mut loaded = Config::default();
+        apply_text(&mut loaded, &to_text(&cfg)).unwrap();
+
+        assert_eq!(to_text(&loaded), to_text(&cfg));
+    }
+
+    #[test]
+    fn reject_invalid() {
+        for (text, line) in [
+            ("snapshot_ratio = 0", 1),
+            ("dt = 1.\nshell_creation_ratio = 0", 2),
+            ("dt = -1.", 1),
+            ("dt = NaN", 1),
+            ("\ntheta = fast", 2),
+            ("num_bodies = 10", 1),
+            ("skip_tree", 1),
+        ] {
+            let mut cfg = Config::default();
+            let err = apply_text(&mut cfg, text).unwrap_err();
+
+            assert!(err.starts_with(&format!("Line {line}: ")), "{text}: {err}");
+            assert_eq!(to_text(&cfg), to_text(&Config::default()));
+        }
+    }
+}
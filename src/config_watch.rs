@@ -0,0 +1,63 @@
+//! Detects changes to the config's text form (see `config_text`) made outside the program, e.g. in a
+//! text editor, or by a script generating parameter sweeps, so they can be applied without
+//! restarting. We poll the file's modification time, vice using a platform file watcher; this is
+//! cheap, and needs no additional dependencies.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct ConfigWatch {
+    path: PathBuf,
+    /// As of when we last loaded, saved, or dismissed a change to the file.
+    modified: Option<SystemTime>,
+    last_poll: Option<Instant>,
+    /// The file has changed, and we haven't applied or dismissed it.
+    pub changed: bool,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ConfigWatch {
+    /// Watch a file, as of its current state. Call after loading or saving it, so our own saves
+    /// aren't reported as changes.
+    pub fn watch(&mut self, path: &Path) {
+        self.path = path.to_owned();
+        self.modified = modified(path);
+        self.changed = false;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Check if the file has changed. Call each frame; this only reads the file system once per
+    /// poll interval.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+            return self.changed;
+        }
+        self.last_poll = Some(Instant::now());
+
+        // A missing file, e.g. while an editor replaces it, isn't a change.
+        if let Some(current) = modified(&self.path) {
+            if self.modified != Some(current) {
+                self.changed = true;
+            }
+        }
+        self.changed
+    }
+
+    /// Ignore the current change; only later ones are reported.
+    pub fn dismiss(&mut self) {
+        self.modified = modified(&self.path);
+        self.changed = false;
+    }
+}
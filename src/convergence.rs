@@ -0,0 +1,166 @@
+//! Convergence studies: Rerun the current scenario over a range of dt and body count values, and
+//! plot how observables change. Helps pick parameters that are converged, instead of guessing.
+//!
+//! Each sweep varies a single parameter, and holds the rest at their config values. We use a fixed
+//! seed, so differences between runs are from the parameter, vice random initial conditions.
+
+use std::{fmt, fmt::Formatter};
+
+use lin_alg::f64::Vec3;
+use rand::Rng;
+
+use crate::{
+    build,
+    properties::{bar_strength, plot, rot_curve_change, rotation_curve, PlotOutput},
+    units::C,
+    BuildStatus, ForceModel, State,
+};
+
+/// Multipliers of the config's dt. Simulated time is held constant, so timestep count scales inversely.
+pub const DT_FACTORS: [f64; 4] = [4., 2., 1., 0.5];
+/// Multipliers of the config's disk and bulge body counts.
+pub const NUM_BODIES_FACTORS: [f64; 4] = [0.25, 0.5, 1., 2.];
+
+pub struct ConvergencePoint {
+    /// The value of the parameter varied, e.g. dt, or body count.
+    pub param: f64,
+    pub curve: Vec<(f64, f64)>,
+    pub bar_strength: f64,
+    /// Relative rotation curve difference from the most refined run in the sweep.
+    pub curve_diff: f64,
+}
+
+pub struct ConvergenceStudy {
+    pub seed: u64,
+    pub dt: Vec<ConvergencePoint>,
+    pub num_bodies: Vec<ConvergencePoint>,
+    /// Runs whose build aborted, e.g. on NaN positions, by parameter, with the reason. They're
+    /// left out of the sweeps.
+    pub aborted: Vec<(String, String)>,
+}
+
+impl fmt::Display for ConvergenceStudy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Convergence study. Seed: {}", self.seed)?;
+
+        for (name, points) in [("dt", &self.dt), ("N", &self.num_bodies)] {
+            writeln!(f, "{name} sweep:")?;
+            for pt in points {
+                writeln!(
+                    f,
+                    "  {name}: {:<10} Rot curve Δ: {:.4}  Bar strength: {:.4}",
+                    pt.param, pt.curve_diff, pt.bar_strength
+                )?;
+            }
+        }
+        for (param, reason) in &self.aborted {
+            writeln!(f, "Excluded {param}: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Build with the current config, and measure observables from the final state. `Err` if the build
+/// aborted.
+fn run_point(
+    state: &mut State,
+    force_model: ForceModel,
+    param: f64,
+) -> Result<ConvergencePoint, String> {
+    if let BuildStatus::Aborted(e) = build(state, force_model) {
+        return Err(e);
+    }
+
+    Ok(ConvergencePoint {
+        param,
+        curve: rotation_curve(&state.bodies, Vec3::new_zero(), C),
+        bar_strength: bar_strength(&state.bodies, Vec3::new_zero()),
+        curve_diff: 0.,
+    })
+}
+
+/// Set each point's rotation curve difference, relative to the last, i.e. most refined, point.
+fn set_curve_diffs(points: &mut [ConvergencePoint]) {
+    let Some(last) = points.last() else {
+        return;
+    };
+    let reference = last.curve.clone();
+    for pt in points {
+        pt.curve_diff = rot_curve_change(&reference, &pt.curve);
+    }
+}
+
+pub fn run_convergence(state: &mut State, force_model: ForceModel) -> ConvergenceStudy {
+    let cfg_prev = state.config.clone();
+    let seed = cfg_prev.seed.unwrap_or_else(|| rand::rng().random());
+    state.config.seed = Some(seed);
+
+    let sim_time = cfg_prev.dt * cfg_prev.num_timesteps as f64;
+
+    let mut aborted = Vec::new();
+
+    let mut dt = Vec::with_capacity(DT_FACTORS.len());
+    for factor in DT_FACTORS {
+        state.config.dt = cfg_prev.dt * factor;
+        state.config.num_timesteps = ((sim_time / state.config.dt) as usize).max(1);
+
+        println!("\nConvergence run. dt: {}", state.config.dt);
+        match run_point(state, force_model, state.config.dt) {
+            Ok(pt) => dt.push(pt),
+            Err(e) => aborted.push((format!("dt: {}", state.config.dt), e)),
+        }
+    }
+    state.config.dt = cfg_prev.dt;
+    state.config.num_timesteps = cfg_prev.num_timesteps;
+
+    let mut num_bodies = Vec::with_capacity(NUM_BODIES_FACTORS.len());
+    for factor in NUM_BODIES_FACTORS {
+        state.config.num_bodies_disk = (cfg_prev.num_bodies_disk as f64 * factor) as usize;
+        state.config.num_bodies_bulge = (cfg_prev.num_bodies_bulge as f64 * factor) as usize;
+
+        let n = state.config.num_bodies_disk + state.config.num_bodies_bulge;
+        println!("\nConvergence run. N: {n}");
+        match run_point(state, force_model, n as f64) {
+            Ok(pt) => num_bodies.push(pt),
+            Err(e) => aborted.push((format!("N: {n}"), e)),
+        }
+    }
+
+    state.config = cfg_prev;
+
+    // The most refined runs are the smallest dt, and the largest N; they're last.
+    set_curve_diffs(&mut dt);
+    set_curve_diffs(&mut num_bodies);
+
+    ConvergenceStudy {
+        seed,
+        dt,
+        num_bodies,
+        aborted,
+    }
+}
+
+/// Plot rotation curve difference and bar strength against each swept parameter.
+pub fn plot_convergence(out: &PlotOutput, study: &ConvergenceStudy) {
+    for (name, points) in [("dt", &study.dt), ("N", &study.num_bodies)] {
+        let diffs: Vec<(f64, f64)> = points.iter().map(|p| (p.param, p.curve_diff)).collect();
+        let bars: Vec<(f64, f64)> = points.iter().map(|p| (p.param, p.bar_strength)).collect();
+
+        plot(
+            out,
+            &diffs,
+            name,
+            "Rot curve Δ",
+            &format!("Rotation curve convergence over {name}"),
+            &format!("convergence_{name}_rot_curve"),
+        );
+        plot(
+            out,
+            &bars,
+            name,
+            "A₂",
+            &format!("Bar strength over {name}"),
+            &format!("convergence_{name}_bar"),
+        );
+    }
+}
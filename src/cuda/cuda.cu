@@ -3,20 +3,88 @@
 
 #include "util.cu"
 
+// How pair terms are summed. Keep in sync with `gpu::GpuPrecision`.
+#define PRECISION_F32 0
+#define PRECISION_KAHAN 1
+#define PRECISION_F64_ACCUM 2
 
-__device__
-void leaves(dtype3 posit_target,  )
-
+// Newtonian acceleration on each target from all sources. Pair terms are computed in `dtype`;
+// `precision` sets how they're summed. `out` holds 3 doubles per target.
 extern "C" __global__
-void acc_bh_kernel(
-    dtype *out,
-    dtype3 *nodes
-    dtype3 posit_target,
-    size_t id_target,
-    Vec3 *node, // todo temp
-    dtype theta,
-    size_t max_bodies_per_mode,
+void acc_newton_kernel(
+    double *out,
+    const dtype3 *posits_src,
+    const dtype3 *posits_tgt,
+    const dtype *masses,
+    size_t n_sources,
+    size_t n_targets,
+    dtype softening_sq,
+    unsigned int precision
 ) {
-//     dtype3 acc_diff =
-//     dtype dist = calc_dist(); // todo: You are double-subtracting; don't do that.
-}
\ No newline at end of file
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= n_targets) {
+        return;
+    }
+
+    dtype3 tgt = posits_tgt[i];
+
+    dtype3 sum = {0, 0, 0};
+    dtype3 comp = {0, 0, 0}; // Kahan compensation.
+    double3 sum_f64 = {0, 0, 0};
+
+    for (size_t j = 0; j < n_sources; j++) {
+        dtype3 diff;
+        diff.x = posits_src[j].x - tgt.x;
+        diff.y = posits_src[j].y - tgt.y;
+        diff.z = posits_src[j].z - tgt.z;
+
+        dtype dist = std::sqrt(diff.x * diff.x + diff.y * diff.y + diff.z * diff.z);
+        // Skips self-interaction, since targets are usually also sources.
+        if (dist == 0) {
+            continue;
+        }
+
+        dtype3 acc_dir;
+        acc_dir.x = diff.x / dist;
+        acc_dir.y = diff.y / dist;
+        acc_dir.z = diff.z / dist;
+
+        dtype3 term = acc_newton(acc_dir, masses[j], dist, softening_sq);
+
+        // `precision` is uniform across threads, so this doesn't diverge.
+        if (precision == PRECISION_KAHAN) {
+            dtype3 y, t;
+            y.x = term.x - comp.x;
+            y.y = term.y - comp.y;
+            y.z = term.z - comp.z;
+            t.x = sum.x + y.x;
+            t.y = sum.y + y.y;
+            t.z = sum.z + y.z;
+            comp.x = (t.x - sum.x) - y.x;
+            comp.y = (t.y - sum.y) - y.y;
+            comp.z = (t.z - sum.z) - y.z;
+            sum = t;
+        } else if (precision == PRECISION_F64_ACCUM) {
+            sum_f64.x += term.x;
+            sum_f64.y += term.y;
+            sum_f64.z += term.z;
+        } else {
+            sum.x += term.x;
+            sum.y += term.y;
+            sum.z += term.z;
+        }
+    }
+
+    if (precision != PRECISION_F64_ACCUM) {
+        sum_f64.x = sum.x;
+        sum_f64.y = sum.y;
+        sum_f64.z = sum.z;
+    }
+
+    out[i * 3] = sum_f64.x;
+    out[i * 3 + 1] = sum_f64.y;
+    out[i * 3 + 2] = sum_f64.z;
+}
+
+
+// todo: A Barnes-Hut kernel, traversing a flattened tree per target.
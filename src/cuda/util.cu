@@ -7,8 +7,11 @@ using dtype3 = float3;
 
 __device__
 const dtype G = 0.0000000000044984f; // todo: QC and keep in sync.
-__device__
-const dtype SOFTENING_FACTOR_SQ = 0.000000000001f;
+
+// Checked against `gpu::KERNEL_ABI_VERSION` on startup. Bump both when changing kernel names or
+// signatures.
+extern "C" __device__
+const unsigned int KERNEL_ABI_VERSION = 2;
 
 // __device__
 // const dtype EPS_DIV0 = 0.00000000001f;
@@ -24,9 +27,11 @@ dtype calc_dist(dtype3 point0, dtype3 point1) {
     return std::sqrt(diff.x * diff.x + diff.y * diff.y + diff.z * diff.z);
 }
 
+// Plummer-softened; matches `accel::acc_newton_inner` on the CPU.
 __device__
-dtype3 acc_newton(dtype3 acc_dir, dtype src_mass, dtype dist) {
-    dtype acc_mag = G * src_mass / (dist * dist + SOFTENING_FACTOR_SQ);
+dtype3 acc_newton(dtype3 acc_dir, dtype src_mass, dtype dist, dtype softening_sq) {
+    dtype d_sq_soft = dist * dist + softening_sq;
+    dtype acc_mag = G * src_mass * dist / (d_sq_soft * std::sqrt(d_sq_soft));
 
     dtype3 result;
     result.x = acc_dir.x * acc_mag;
@@ -15,11 +15,11 @@ struct double3;
 
 // Declaration of constants
 extern __device__ const dtype G;
-extern __device__ const dtype SOFTENING_FACTOR_SQ;
+extern "C" __device__ const unsigned int KERNEL_ABI_VERSION;
 
 
 // Function declarations
-__device__ dtype3 acc_newton(dtype3 acc_dir, dtype src_mass, dtype dist);
+__device__ dtype3 acc_newton(dtype3 acc_dir, dtype src_mass, dtype dist, dtype softening_sq);
 
 
 #endif // UTIL_H
@@ -0,0 +1,59 @@
+//! Editing a galaxy description's scalar parameters from the UI, so they can be explored without
+//! changing `galaxy_data` and recompiling. Edits are held as a draft until applied; applying
+//! regenerates bodies.
+
+use crate::body_creation::GalaxyDescrip;
+
+/// A galaxy description's editable parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DescripParams {
+    /// M☉
+    pub mass_disk: f64,
+    /// M☉
+    pub mass_bulge: f64,
+    pub eccentricity: f64,
+    /// kpc
+    pub disk_thickness: f64,
+    /// (core radius (kpc), central density (M☉/kpc³))
+    pub burkert_params: (f64, f64),
+    pub mass_to_light_ratio: f64,
+}
+
+impl DescripParams {
+    pub fn new(descrip: &GalaxyDescrip) -> Self {
+        Self {
+            mass_disk: descrip.mass_disk,
+            mass_bulge: descrip.mass_bulge,
+            eccentricity: descrip.eccentricity,
+            disk_thickness: descrip.disk_thickness,
+            burkert_params: descrip.burkert_params,
+            mass_to_light_ratio: descrip.mass_to_light_ratio,
+        }
+    }
+
+    /// Set these parameters on the description. The disk's mass comes from its luminosity, so a
+    /// change in M/L scales its density profile, and its mass, unless that was also edited.
+    pub fn apply(&self, descrip: &mut GalaxyDescrip) {
+        let prev = Self::new(descrip);
+
+        let ml_ratio = if prev.mass_to_light_ratio > 0. && self.mass_to_light_ratio > 0. {
+            self.mass_to_light_ratio / prev.mass_to_light_ratio
+        } else {
+            1.
+        };
+        for (_, density) in &mut descrip.mass_density_disk {
+            *density *= ml_ratio;
+        }
+
+        descrip.mass_disk = if self.mass_disk != prev.mass_disk {
+            self.mass_disk
+        } else {
+            prev.mass_disk * ml_ratio
+        };
+        descrip.mass_bulge = self.mass_bulge;
+        descrip.eccentricity = self.eccentricity;
+        descrip.disk_thickness = self.disk_thickness;
+        descrip.burkert_params = self.burkert_params;
+        descrip.mass_to_light_ratio = self.mass_to_light_ratio;
+    }
+}
@@ -0,0 +1,144 @@
+//! Conservation diagnostics: Total kinetic and potential energy, and total angular momentum, sampled
+//! during builds. An isolated system conserves both, so drift in them comes from the integrator,
+//! the timestep, softening, or the force model itself. E.g. the causal shell model's retarded
+//! forces aren't conservative in general, so comparing its drift to Newton's at the same settings
+//! separates physics from integration error.
+//!
+//! We also track the virial ratio, to flag initial conditions far from equilibrium, and center of
+//! mass drift, which, for an isolated system, means the net force on it isn't zero.
+
+use barnes_hut::{BhConfig, Cube, Tree};
+use bincode::{Decode, Encode};
+use lin_alg::f64::Vec3;
+
+use crate::{
+    properties,
+    properties::{plot_multi, PlotOutput},
+    Body, BOUNDING_BOX_PAD,
+};
+
+/// Above this many bodies, the potential is summed with the tree, vice directly.
+const DIRECT_SUM_MAX: usize = 5_000;
+
+/// Warn about initial conditions with a virial ratio outside this range.
+pub const VIRIAL_RATIO_RANGE: (f64, f64) = (0.5, 1.5);
+
+#[derive(Clone, Copy, Debug, Default, Encode, Decode)]
+pub struct Diagnostics {
+    /// M☉ kpc² / Myr²
+    pub kinetic: f64,
+    /// M☉ kpc² / Myr²
+    pub potential: f64,
+    /// About the origin. M☉ kpc² / Myr
+    pub ang_mom: Vec3,
+    /// Center of mass. kpc
+    pub com_posit: Vec3,
+    /// Center of mass velocity. kpc/Myr
+    pub com_vel: Vec3,
+}
+
+impl Diagnostics {
+    pub fn energy(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+
+    /// 2K / |U|.
+    pub fn virial_ratio(&self) -> Option<f64> {
+        properties::virial_ratio(self.kinetic, self.potential)
+    }
+
+    /// Center of mass position (kpc) and velocity (kpc/Myr) changes from `first`.
+    pub fn com_drift(&self, first: &Self) -> (f64, f64) {
+        (
+            (self.com_posit - first.com_posit).magnitude(),
+            (self.com_vel - first.com_vel).magnitude(),
+        )
+    }
+}
+
+/// The potential energy, using the tree: Each body's potential is summed with Barnes-Hut, and
+/// U = ½ Σ mᵢ φᵢ.
+fn potential_tree(bodies: &[Body], bh_config: &BhConfig, softening_factor_sq: f64) -> f64 {
+    let Some(bb) = Cube::from_bodies(bodies, BOUNDING_BOX_PAD, false) else {
+        return 0.;
+    };
+    let tree = Tree::new(bodies, &bb, bh_config);
+
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(id, body)| {
+            0.5 * body.mass
+                * properties::potential_at(body.posit, id, &tree, bh_config, softening_factor_sq)
+        })
+        .sum()
+}
+
+pub fn compute(bodies: &[Body], bh_config: &BhConfig, softening_factor_sq: f64) -> Diagnostics {
+    let (kinetic, potential) = if bodies.len() > DIRECT_SUM_MAX {
+        let kinetic = bodies
+            .iter()
+            .map(|b| 0.5 * b.mass * b.vel.magnitude_squared())
+            .sum();
+        (
+            kinetic,
+            potential_tree(bodies, bh_config, softening_factor_sq),
+        )
+    } else {
+        properties::energy(bodies, softening_factor_sq)
+    };
+
+    let ang_mom = bodies.iter().map(|b| b.posit.cross(b.vel) * b.mass).sum();
+    let (com_posit, com_vel) = properties::center_of_mass(bodies);
+
+    Diagnostics {
+        kinetic,
+        potential,
+        ang_mom,
+        com_posit,
+        com_vel,
+    }
+}
+
+/// Relative drift from the first sample: (t, E / E₀ - 1), and (t, |L - L₀| / |L₀|).
+pub fn drift(history: &[(f64, Diagnostics)]) -> (Vec<(f64, f64)>, Vec<(f64, f64)>) {
+    let Some((_, first)) = history.first() else {
+        return (Vec::new(), Vec::new());
+    };
+    let e_0 = first.energy();
+    let l_0 = first.ang_mom;
+
+    let energy = history
+        .iter()
+        .map(|(t, d)| (*t, if e_0 == 0. { 0. } else { d.energy() / e_0 - 1. }))
+        .collect();
+
+    let l_0_mag = l_0.magnitude();
+    let ang_mom = history
+        .iter()
+        .map(|(t, d)| {
+            let drift = if l_0_mag == 0. {
+                0.
+            } else {
+                (d.ang_mom - l_0).magnitude() / l_0_mag
+            };
+            (*t, drift)
+        })
+        .collect();
+
+    (energy, ang_mom)
+}
+
+/// Plot energy and angular momentum drift vs time.
+pub fn plot_drift(out: &PlotOutput, history: &[(f64, Diagnostics)]) {
+    let (energy, ang_mom) = drift(history);
+
+    plot_multi(
+        out,
+        &[("E / E₀ - 1", &energy), ("|L - L₀| / |L₀|", &ang_mom)],
+        "t (Myr)",
+        "Relative drift",
+        "Energy and angular momentum drift",
+        "conservation",
+    );
+}
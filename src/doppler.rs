@@ -0,0 +1,73 @@
+//! Doppler coloring: Color bodies by line-of-sight velocity from the camera, blue approaching and
+//! red receding, as in observed velocity fields. This makes the rotation's direction, and
+//! kinematic features like warps and non-circular motion, visible at a glance.
+//!
+//! Velocities are relative to the mass-weighted mean (systemic) velocity, as observed maps are.
+
+use graphics::Entity;
+use lin_alg::f32::Vec3;
+
+use crate::units::KPC_MYR_PER_KM_S;
+
+type Color = (f32, f32, f32);
+
+/// At zero line-of-sight velocity.
+const COLOR_REST: Color = (0.85, 0.85, 0.85);
+const COLOR_APPROACHING: Color = (0.15, 0.35, 1.);
+const COLOR_RECEDING: Color = (1., 0.15, 0.1);
+
+/// Mass-weighted mean velocity.
+fn systemic_vel(vels: &[Vec3], masses: &[f32]) -> Vec3 {
+    let mut total = Vec3::new_zero();
+    let mut mass_total = 0.;
+    for (i, v) in vels.iter().enumerate() {
+        let m = masses.get(i).copied().unwrap_or_default();
+        total += *v * m;
+        mass_total += m;
+    }
+
+    if mass_total > 0. {
+        total / mass_total
+    } else {
+        Vec3::new_zero()
+    }
+}
+
+fn lerp(a: Color, b: Color, t: f32) -> Color {
+    (
+        a.0 + (b.0 - a.0) * t,
+        a.1 + (b.1 - a.1) * t,
+        a.2 + (b.2 - a.2) * t,
+    )
+}
+
+/// The color for a line-of-sight velocity; positive is receding. Saturates at ±`v_max`. km/s.
+pub fn doppler_color(v_los: f32, v_max: f32) -> Color {
+    let t = (v_los / v_max.max(f32::EPSILON)).clamp(-1., 1.);
+    if t < 0. {
+        lerp(COLOR_REST, COLOR_APPROACHING, -t)
+    } else {
+        lerp(COLOR_REST, COLOR_RECEDING, t)
+    }
+}
+
+/// Color body entities by their line-of-sight velocity from the camera. Bodies must be the first
+/// entities, in the same order as `vels`. `vels` is in kpc/Myr; `v_max` is in km/s.
+pub fn apply_doppler_colors(
+    entities: &mut [Entity],
+    vels: &[Vec3],
+    masses: &[f32],
+    camera_posit: Vec3,
+    v_max: f32,
+) {
+    let systemic = systemic_vel(vels, masses);
+
+    for (entity, vel) in entities.iter_mut().zip(vels) {
+        let los = entity.position - camera_posit;
+        if los.magnitude_squared() <= f32::EPSILON {
+            continue;
+        }
+        let v_los = (*vel - systemic).dot(los.to_normalized()) / KPC_MYR_PER_KM_S as f32;
+        entity.color = doppler_color(v_los, v_max);
+    }
+}
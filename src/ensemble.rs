@@ -0,0 +1,148 @@
+//! Ensemble runs: Repeat a build with different random seeds, and report the mean and scatter of
+//! observables. This quantifies the noise from randomly-generated initial conditions, which is
+//! significant at low body counts.
+
+use std::{fmt, fmt::Formatter};
+
+use lin_alg::f64::Vec3;
+use rand::Rng;
+
+use crate::{
+    build,
+    properties::{bar_strength, rotation_curve},
+    units::C,
+    util, BuildStatus, ForceModel, State,
+};
+
+/// Mean and (sample) standard deviation.
+fn mean_std(vals: &[f64]) -> (f64, f64) {
+    let n = vals.len() as f64;
+    if vals.is_empty() {
+        return (0., 0.);
+    }
+
+    let mean = vals.iter().sum::<f64>() / n;
+    if vals.len() < 2 {
+        return (mean, 0.);
+    }
+
+    let var = vals.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.);
+    (mean, var.sqrt())
+}
+
+pub struct EnsembleReport {
+    pub num_runs: usize,
+    /// Runs use seeds `base_seed`, `base_seed + 1` etc.
+    pub base_seed: u64,
+    /// Radii the rotation curve is sampled at. (kpc)
+    pub r: Vec<f64>,
+    /// Final rotation curve mean, and standard deviation, at each radius. (km/s)
+    pub v_mean: Vec<f64>,
+    pub v_std: Vec<f64>,
+    /// Final bar strength (A₂), mean and standard deviation.
+    pub bar_mean: f64,
+    pub bar_std: f64,
+    /// Runs whose build aborted, e.g. on NaN positions, by seed, with the reason. They're excluded
+    /// from the statistics.
+    pub aborted: Vec<(u64, String)>,
+}
+
+impl EnsembleReport {
+    /// Runs included in the statistics.
+    pub fn num_completed(&self) -> usize {
+        self.num_runs - self.aborted.len()
+    }
+}
+
+impl fmt::Display for EnsembleReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Ensemble of {} runs, {} completed. Seeds: {}..{}",
+            self.num_runs,
+            self.num_completed(),
+            self.base_seed,
+            self.base_seed.wrapping_add(self.num_runs as u64)
+        )?;
+        for (seed, reason) in &self.aborted {
+            writeln!(f, "Excluded seed {seed}: {reason}")?;
+        }
+        writeln!(
+            f,
+            "Bar strength (A₂): {:.4} ± {:.4}",
+            self.bar_mean, self.bar_std
+        )?;
+        writeln!(f, "Rotation curve:")?;
+
+        for (i, r) in self.r.iter().enumerate() {
+            writeln!(
+                f,
+                "  r: {r:.2} kpc  v: {:.2} ± {:.2} km/s",
+                self.v_mean[i], self.v_std[i]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Build `num_runs` times with consecutive seeds, and compare the final states. Uses the config's
+/// seed as the base if set; otherwise, a random one. The config's seed is restored afterwards.
+/// Aborted runs are skipped, and listed in the report.
+pub fn run_ensemble(state: &mut State, force_model: ForceModel, num_runs: usize) -> EnsembleReport {
+    let seed_prev = state.config.seed;
+    let base_seed = seed_prev.unwrap_or_else(|| rand::rng().random());
+
+    let mut curves = Vec::with_capacity(num_runs);
+    let mut bar_strengths = Vec::with_capacity(num_runs);
+    let mut aborted = Vec::new();
+
+    for i in 0..num_runs {
+        println!("\nEnsemble run {} of {num_runs}", i + 1);
+        let seed = base_seed.wrapping_add(i as u64);
+        state.config.seed = Some(seed);
+
+        if let BuildStatus::Aborted(e) = build(state, force_model) {
+            aborted.push((seed, e));
+            continue;
+        }
+
+        curves.push(rotation_curve(&state.bodies, Vec3::new_zero(), C));
+        bar_strengths.push(bar_strength(&state.bodies, Vec3::new_zero()));
+    }
+
+    state.config.seed = seed_prev;
+
+    // Each run's curve is sampled out to its own maximum radius; interpolate them onto the first's.
+    let r: Vec<f64> = curves
+        .first()
+        .map(|c| c.iter().map(|(r, _)| *r).collect())
+        .unwrap_or_default();
+
+    let mut v_mean = Vec::with_capacity(r.len());
+    let mut v_std = Vec::with_capacity(r.len());
+
+    for r_ in &r {
+        let v: Vec<f64> = curves
+            .iter()
+            .filter(|c| c.len() >= 2)
+            .filter_map(|c| util::interpolate(c, *r_))
+            .collect();
+
+        let (mean, std) = mean_std(&v);
+        v_mean.push(mean);
+        v_std.push(std);
+    }
+
+    let (bar_mean, bar_std) = mean_std(&bar_strengths);
+
+    EnsembleReport {
+        num_runs,
+        base_seed,
+        r,
+        v_mean,
+        v_std,
+        bar_mean,
+        bar_std,
+        aborted,
+    }
+}
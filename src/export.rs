@@ -0,0 +1,140 @@
+//! Export playback as a PNG sequence, and optionally an MP4, for sharing runs. Frames are drawn in
+//! software from the scene's entities, as seen from the current camera, so they match the view
+//! (Doppler colors, luminosity etc.) without capturing the GPU surface. Bodies and other spheres
+//! are drawn as discs; other meshes, e.g. tree cubes and grid lines, are omitted.
+//!
+//! MP4 encoding uses `ffmpeg`, if it's on the path.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use graphics::{Camera, Entity};
+use plotters::prelude::{
+    BitMapBackend, Circle, Color, IntoDrawingArea, IntoFont, RGBColor, Text, BLACK, WHITE,
+};
+
+use crate::render::MESH_SPHERE;
+
+pub const EXPORT_DIR: &str = "export";
+const VIDEO_FILE: &str = "playback.mp4";
+
+pub struct ExportSettings {
+    /// Pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Also encode the frames as an MP4.
+    pub mp4: bool,
+    /// Video frames, i.e. snapshots, per second.
+    pub fps: u32,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            width: 1_280,
+            height: 720,
+            mp4: true,
+            fps: 30,
+        }
+    }
+}
+
+pub fn frame_path(dir: &Path, i: usize) -> PathBuf {
+    dir.join(format!("frame_{i:05}.png"))
+}
+
+fn to_io_err(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+/// Draw spheres among `entities`, as seen from the camera, back to front, with a label in the
+/// top left.
+pub fn render_frame(
+    path: &Path,
+    entities: &[Entity],
+    camera: &Camera,
+    settings: &ExportSettings,
+    label: &str,
+) -> io::Result<()> {
+    let (width, height) = (settings.width as f32, settings.height as f32);
+    let half_height_per_depth = (camera.fov_y / 2.).tan();
+    let to_cam = camera.orientation.inverse();
+
+    // (x, y, radius, depth, entity)
+    let mut discs: Vec<(i32, i32, u32, f32, &Entity)> = entities
+        .iter()
+        .filter(|e| e.mesh == MESH_SPHERE && e.scale > 0.)
+        .filter_map(|e| {
+            // As in the overlay's projection.
+            let v = to_cam.rotate_vec(e.position - camera.position);
+            if v.z <= camera.near {
+                return None;
+            }
+            let half_height = v.z * half_height_per_depth;
+            let half_width = half_height * width / height;
+
+            let x = width / 2. + v.x / half_width * width / 2.;
+            let y = height / 2. - v.y / half_height * height / 2.;
+            let r = (e.scale / half_height * height / 2.).max(1.);
+
+            Some((x as i32, y as i32, r as u32, v.z, e))
+        })
+        .collect();
+    discs.sort_by(|a, b| b.3.total_cmp(&a.3));
+
+    let root = BitMapBackend::new(path, (settings.width, settings.height)).into_drawing_area();
+    root.fill(&BLACK).map_err(to_io_err)?;
+
+    for (x, y, r, _, e) in discs {
+        let (red, green, blue) = e.color;
+        let color = RGBColor(
+            (red.clamp(0., 1.) * 255.) as u8,
+            (green.clamp(0., 1.) * 255.) as u8,
+            (blue.clamp(0., 1.) * 255.) as u8,
+        );
+        root.draw(&Circle::new(
+            (x, y),
+            r,
+            color.mix(e.opacity.clamp(0., 1.) as f64).filled(),
+        ))
+        .map_err(to_io_err)?;
+    }
+
+    root.draw(&Text::new(
+        label.to_owned(),
+        (10, 10),
+        ("sans-serif", 20).into_font().color(&WHITE),
+    ))
+    .map_err(to_io_err)?;
+
+    root.present().map_err(to_io_err)
+}
+
+/// Encode the frames in `dir` as an MP4 with ffmpeg. Returns the video's path.
+pub fn encode_video(dir: &Path, fps: u32) -> io::Result<PathBuf> {
+    let out = dir.join(VIDEO_FILE);
+
+    let output = Command::new("ffmpeg")
+        .arg("-y")
+        .args(["-framerate", &fps.to_string()])
+        .arg("-i")
+        .arg(dir.join("frame_%05d.png"))
+        // yuv420p, and even dimensions, for compatibility with most players.
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+        .arg(&out)
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("Unable to run ffmpeg: {e}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(to_io_err(format!(
+            "ffmpeg failed: {}",
+            stderr.lines().last().unwrap_or_default()
+        )));
+    }
+    Ok(out)
+}
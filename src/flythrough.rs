@@ -0,0 +1,115 @@
+//! Camera paths for flythroughs: Keyframes of camera position and orientation, at snapshot times.
+//! During playback, the camera follows the path, interpolated between keyframes, so a recording
+//! of the window can be repeated exactly, e.g. for presentations.
+//!
+//! Positions are stored in kpc, so a path is independent of the world scale. Keyframes are tied to
+//! simulation time, vice wall time, so the camera stays in sync with the bodies at any playback
+//! speed.
+
+use std::{io, path::Path};
+
+use bincode::{Decode, Encode};
+use graphics::Camera;
+use lin_alg::f32::{Quaternion, Vec3};
+
+use crate::util;
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct Keyframe {
+    /// Snapshot time. Myr.
+    pub time: f32,
+    /// kpc
+    pub posit: Vec3,
+    /// (w, x, y, z)
+    orientation: [f32; 4],
+}
+
+impl Keyframe {
+    pub fn orientation(&self) -> Quaternion {
+        let [w, x, y, z] = self.orientation;
+        Quaternion { w, x, y, z }
+    }
+}
+
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct CameraPath {
+    /// Sorted by time.
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    /// Add a keyframe from the camera, replacing any existing one at the same time.
+    pub fn add(&mut self, time: f32, camera: &Camera, world_scale: f32) {
+        let q = camera.orientation;
+        let keyframe = Keyframe {
+            time,
+            posit: camera.position / world_scale,
+            orientation: [q.w, q.x, q.y, q.z],
+        };
+
+        match self.keyframes.binary_search_by(|k| k.time.total_cmp(&time)) {
+            Ok(i) => self.keyframes[i] = keyframe,
+            Err(i) => self.keyframes.insert(i, keyframe),
+        }
+    }
+
+    /// Move the camera to the path's position and orientation at `time`. Positions between
+    /// keyframes are on a Catmull-Rom spline, so the camera moves smoothly through them;
+    /// orientations are slerped. Before the first keyframe, and after the last, the camera holds
+    /// still. Returns false, leaving the camera as is, if the path is empty.
+    pub fn apply(&self, time: f32, camera: &mut Camera, world_scale: f32) -> bool {
+        let (Some(first), Some(last)) = (self.keyframes.first(), self.keyframes.last()) else {
+            return false;
+        };
+
+        let (posit, orientation) = if time <= first.time {
+            (first.posit, first.orientation())
+        } else if time >= last.time {
+            (last.posit, last.orientation())
+        } else {
+            // The keyframes bracketing `time`, and their neighbors.
+            let i = self.keyframes.partition_point(|k| k.time <= time) - 1;
+            let k = |j: isize| {
+                let j = j.clamp(0, self.keyframes.len() as isize - 1) as usize;
+                &self.keyframes[j]
+            };
+            let (k0, k1, k2, k3) = (
+                k(i as isize - 1),
+                k(i as isize),
+                k(i as isize + 1),
+                k(i as isize + 2),
+            );
+
+            let t = (time - k1.time) / (k2.time - k1.time);
+            (
+                catmull_rom(k0.posit, k1.posit, k2.posit, k3.posit, t),
+                k1.orientation().slerp(k2.orientation(), t),
+            )
+        };
+
+        camera.position = posit * world_scale;
+        camera.orientation = orientation;
+        true
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        util::save(path, self)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        util::load(path)
+    }
+}
+
+/// A uniform Catmull-Rom spline from `start` to `end`, at `t` from 0 to 1 between them. `before`
+/// and `after` are the neighboring points, which set the tangents.
+fn catmull_rom(before: Vec3, start: Vec3, end: Vec3, after: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (start * 2.
+        + (end - before) * t
+        + (before * 2. - start * 5. + end * 4. - after) * t2
+        + (-before + start * 3. - end * 3. + after) * t3)
+        * 0.5
+}
@@ -0,0 +1,207 @@
+//! A numerical experiment to extract the effective force law of the causal shell model. We place a
+//! single static source, and rings of test probes around it, run the shell model until shells fill
+//! the probe region, then fit the measured acceleration vs distance to a power law, and to MOND.
+//!
+//! Uses the config's shell settings (speed law, anisotropy, geometry, emission rate), so it can compare them.
+
+use std::{f64::consts::TAU, fmt, fmt::Formatter};
+
+use lin_alg::{f64::Vec3, linspace};
+
+use crate::{
+    accel::calc_acc_shell,
+    grav_shell::{self, MAX_SHELL_R, SRC_MASS_TEST},
+    properties::{plot_multi, PlotOutput},
+    units::{A0_MOND, G},
+    Config,
+};
+
+/// Probes per ring. The measured acceleration is the mean of each ring's inward component.
+const PROBES_PER_RING: usize = 8;
+
+pub struct ForceLawFit {
+    /// X: r (kpc). Y: measured acceleration (kpc/Myr²).
+    pub measured: Vec<(f64, f64)>,
+    pub newton: Vec<(f64, f64)>,
+    /// Fit of a = coeff × r^exponent.
+    pub exponent: f64,
+    pub coeff: f64,
+    /// Fit of a = a_N / μ(a / a0), using the simple interpolating function.
+    pub a0: f64,
+    /// RMS of the log residuals, for each fit. Lower is better.
+    pub rms_power: f64,
+    pub rms_mond: f64,
+    pub rms_newton: f64,
+}
+
+impl fmt::Display for ForceLawFit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Effective force law, from {} probe radii:",
+            self.measured.len()
+        )?;
+        writeln!(
+            f,
+            "Power law: a ∝ r^{:.4}  (Newton: r^-2)  RMS log residual: {:.4}",
+            self.exponent, self.rms_power
+        )?;
+        writeln!(
+            f,
+            "MOND (simple μ): a0 = {:.4e} ({:.3} × standard)  RMS log residual: {:.4}",
+            self.a0,
+            self.a0 / A0_MOND,
+            self.rms_mond
+        )?;
+        writeln!(f, "Newton RMS log residual: {:.4}", self.rms_newton)
+    }
+}
+
+/// Least-squares line fit. Returns (slope, intercept).
+fn linear_fit(pts: &[(f64, f64)]) -> (f64, f64) {
+    let n = pts.len() as f64;
+    let x_mean = pts.iter().map(|p| p.0).sum::<f64>() / n;
+    let y_mean = pts.iter().map(|p| p.1).sum::<f64>() / n;
+
+    let mut num = 0.;
+    let mut denom = 0.;
+    for (x, y) in pts {
+        num += (x - x_mean) * (y - y_mean);
+        denom += (x - x_mean).powi(2);
+    }
+
+    let slope = if denom == 0. { 0. } else { num / denom };
+    (slope, y_mean - slope * x_mean)
+}
+
+/// RMS of ln(a_measured) - ln(a_model).
+fn rms_log(measured: &[(f64, f64)], model: impl Fn(f64) -> f64) -> f64 {
+    let sum: f64 = measured
+        .iter()
+        .map(|(r, a)| (a.ln() - model(*r).ln()).powi(2))
+        .sum();
+    (sum / measured.len() as f64).sqrt()
+}
+
+/// Solve a = a_N / μ(a / a0) for a, with the simple μ (`MondFn::Simple`):
+/// a = a_N (1 + √(1 + 4 a0 / a_N)) / 2.
+fn mond_simple(a_newton: f64, a0: f64) -> f64 {
+    a_newton * (1. + (1. + 4. * a0 / a_newton).sqrt()) / 2.
+}
+
+pub fn extract_force_law(cfg: &Config, num_radii: usize) -> Result<ForceLawFit, String> {
+    let gauss_c = cfg.shell_gauss_c();
+    let r_max = MAX_SHELL_R / 2.;
+    let radii = linspace(r_max / num_radii as f64, r_max, num_radii);
+
+    let shells = grav_shell::steady_state_shells(
+        SRC_MASS_TEST,
+        &cfg.shell_speed.law,
+        cfg.dt,
+        cfg.shell_creation_ratio,
+        gauss_c,
+        r_max,
+    )?;
+
+    let mut measured = Vec::with_capacity(num_radii);
+    let mut newton = Vec::with_capacity(num_radii);
+
+    for r in &radii {
+        let mut a_sum = 0.;
+        for i in 0..PROBES_PER_RING {
+            let θ = TAU * i as f64 / PROBES_PER_RING as f64;
+            let dir = Vec3::new(θ.cos(), θ.sin(), 0.);
+
+            // The probe ID is anything other than the source's.
+            let acc = calc_acc_shell(
+                &shells,
+                dir * *r,
+                1,
+                gauss_c,
+                cfg.softening_factor_sq,
+                cfg.shell_anisotropy,
+                &cfg.shell_speed,
+                cfg.shell_geometry,
+            );
+            a_sum += -acc.dot(dir);
+        }
+
+        measured.push((*r, a_sum / PROBES_PER_RING as f64));
+        newton.push((*r, G * SRC_MASS_TEST / r.powi(2)));
+    }
+
+    // Fits require positive accelerations; e.g. probes outside the shells may have none.
+    let valid: Vec<(f64, f64)> = measured.iter().copied().filter(|p| p.1 > 0.).collect();
+    if valid.len() < 2 {
+        eprintln!("Not enough probes with inward acceleration to fit the force law.");
+        return Ok(ForceLawFit {
+            measured,
+            newton,
+            exponent: 0.,
+            coeff: 0.,
+            a0: 0.,
+            rms_power: f64::MAX,
+            rms_mond: f64::MAX,
+            rms_newton: f64::MAX,
+        });
+    }
+
+    let log_pts: Vec<(f64, f64)> = valid.iter().map(|(r, a)| (r.ln(), a.ln())).collect();
+    let (exponent, intercept) = linear_fit(&log_pts);
+    let coeff = intercept.exp();
+
+    let a_newton = |r: f64| G * SRC_MASS_TEST / r.powi(2);
+
+    // Grid search for a0, over several orders of magnitude around the standard value.
+    let mut a0 = A0_MOND;
+    let mut rms_mond = f64::MAX;
+    for log_scale in linspace(-4., 4., 801) {
+        let a0_trial = A0_MOND * 10_f64.powf(log_scale);
+        let rms = rms_log(&valid, |r| mond_simple(a_newton(r), a0_trial));
+        if rms < rms_mond {
+            rms_mond = rms;
+            a0 = a0_trial;
+        }
+    }
+
+    Ok(ForceLawFit {
+        rms_power: rms_log(&valid, |r| coeff * r.powf(exponent)),
+        rms_newton: rms_log(&valid, a_newton),
+        measured,
+        newton,
+        exponent,
+        coeff,
+        a0,
+        rms_mond,
+    })
+}
+
+/// Plot measured acceleration, with Newton and the fitted models.
+pub fn plot_force_law(out: &PlotOutput, fit: &ForceLawFit) {
+    let power: Vec<(f64, f64)> = fit
+        .measured
+        .iter()
+        .map(|(r, _)| (*r, fit.coeff * r.powf(fit.exponent)))
+        .collect();
+    let mond: Vec<(f64, f64)> = fit
+        .newton
+        .iter()
+        .map(|(r, a_n)| (*r, mond_simple(*a_n, fit.a0)))
+        .collect();
+
+    let power_label = format!("r^{:.3}", fit.exponent);
+
+    plot_multi(
+        out,
+        &[
+            ("Measured", &fit.measured),
+            ("Newton", &fit.newton),
+            (&power_label, &power),
+            ("MOND fit", &mond),
+        ],
+        "r (kpc)",
+        "a (kpc/Myr²)",
+        "Effective force law of the shell model",
+        "force_law_fit",
+    );
+}
@@ -1,6 +1,28 @@
 //! Gravitoelectromagnetism
+//!
+//! Spin, and frame dragging: In the weak-field, slow-motion limit, mass currents produce a
+//! gravitomagnetic field, analogous to the magnetic field of moving charges. We express it as
+//! the frame-dragging angular velocity 𝛀: The rate local inertial frames, e.g. gyroscopes, rotate
+//! relative to distant stars. A body with spin angular momentum J is a gravitomagnetic dipole:
+//! 𝛀 = G/c² · (3(J·r̂)r̂ - J) / r³, and a moving mass m contributes 2Gm/c² · v × R / R³. A test mass
+//! moving with v through the field accelerates by 2 𝛀 × v.
+//!
+//! For a rotating central mass, the orbit-averaged torque from this precesses an orbit's nodes at
+//! the Lense–Thirring rate, 2GJ / (c² a³ (1 - e²)^(3/2)), which we compare against.
 
-use lin_alg::f64::Vec3;
+use std::f64::consts::TAU;
+
+use lin_alg::{f64::Vec3, linspace};
+use rayon::prelude::*;
+
+use crate::{
+    properties::{plot_multi, PlotOutput},
+    units::G,
+    Body,
+};
+
+/// Points around each test orbit, for averaging the torque.
+const N_ORBIT_PTS: usize = 360;
 
 pub struct FourPotential {
     /// ϕ
@@ -23,3 +45,195 @@ impl FourPotential {
 
 // https://en.wikipedia.org/wiki/Gravitoelectromagnetism
 //todo A/R the 4 GEM equations.
+
+/// A source of the gravitomagnetic field: A body, with its intrinsic spin.
+#[derive(Clone, Debug)]
+pub struct GemSource {
+    pub posit: Vec3,
+    pub vel: Vec3,
+    /// M☉
+    pub mass: f64,
+    /// Intrinsic spin angular momentum, about the body's center. M☉ kpc²/Myr.
+    pub spin: Vec3,
+}
+
+impl GemSource {
+    /// Sources from bodies, with a spin per body. Bodies without one, e.g. if `spins` is shorter,
+    /// don't spin.
+    pub fn from_bodies(bodies: &[Body], spins: &[Vec3]) -> Vec<Self> {
+        bodies
+            .iter()
+            .enumerate()
+            .map(|(i, b)| Self {
+                posit: b.posit,
+                vel: b.vel,
+                mass: b.mass,
+                spin: spins.get(i).copied().unwrap_or_else(Vec3::new_zero),
+            })
+            .collect()
+    }
+}
+
+/// Spin magnitude of a Kerr black hole with dimensionless spin `χ`, from 0 to 1: J = χ G M² / c.
+pub fn spin_kerr(mass: f64, χ: f64, c: f64) -> f64 {
+    χ * G * mass.powi(2) / c
+}
+
+/// Frame-dragging angular velocity at a position, from the sources' spins and mass currents.
+/// `c` is the speed gravity propagates at; `units::C_LIGHT` for GR. rad/Myr.
+pub fn frame_dragging(
+    sources: &[GemSource],
+    posit: Vec3,
+    c: f64,
+    softening_factor_sq: f64,
+) -> Vec3 {
+    let mut result = Vec3::new_zero();
+
+    for src in sources {
+        let diff = posit - src.posit;
+        let dist_sq = diff.magnitude_squared() + softening_factor_sq;
+        if dist_sq < f64::EPSILON {
+            continue;
+        }
+        let dist = dist_sq.sqrt();
+        let r_hat = diff / dist;
+
+        let dipole = (r_hat * (3. * src.spin.dot(r_hat)) - src.spin) / dist.powi(3);
+        let current = src.vel.cross(diff) * (2. * src.mass) / dist.powi(3);
+
+        result += (dipole + current) * (G / c.powi(2));
+    }
+
+    result
+}
+
+/// Acceleration of a test mass moving with `vel` through a frame-dragging field 𝛀. kpc/Myr².
+pub fn acc_gravitomagnetic(frame_drag: Vec3, vel: Vec3) -> Vec3 {
+    frame_drag.cross(vel) * 2.
+}
+
+/// Lense–Thirring nodal precession rate of an orbit with semi-major axis `a` (kpc), and
+/// eccentricity `e`, about a mass with spin magnitude `spin`. rad/Myr.
+pub fn lense_thirring_nodal(spin: f64, a: f64, e: f64, c: f64) -> f64 {
+    2. * G * spin / (c.powi(2) * a.powi(3) * (1. - e.powi(2)).powf(1.5))
+}
+
+/// Nodal precession rate of a circular test orbit of radius `a` about `center`, inclined by
+/// `inclination` (rad) to `axis`, from the orbit-averaged gravitomagnetic torque. The orbital
+/// speed cancels from the rate; we use the circular speed about `mass`. rad/Myr.
+#[allow(clippy::too_many_arguments)]
+pub fn nodal_precession(
+    sources: &[GemSource],
+    center: Vec3,
+    axis: Vec3,
+    mass: f64,
+    a: f64,
+    inclination: f64,
+    c: f64,
+    softening_factor_sq: f64,
+) -> f64 {
+    let axis = axis.to_normalized();
+    let ref_vec = if axis.x.abs() < 0.9 {
+        Vec3::new(1., 0., 0.)
+    } else {
+        Vec3::new(0., 1., 0.)
+    };
+    // The line of nodes, and the in-orbit direction perpendicular to it.
+    let e_node = axis.cross(ref_vec).to_normalized();
+    let e_perp = axis.cross(e_node) * inclination.cos() + axis * inclination.sin();
+
+    let v = (G * mass / a).sqrt();
+
+    let torque = (0..N_ORBIT_PTS)
+        .map(|i| {
+            let θ = TAU * i as f64 / N_ORBIT_PTS as f64;
+            let r = (e_node * θ.cos() + e_perp * θ.sin()) * a;
+            let vel = (e_perp * θ.cos() - e_node * θ.sin()) * v;
+
+            let frame_drag = frame_dragging(sources, center + r, c, softening_factor_sq);
+            r.cross(acc_gravitomagnetic(frame_drag, vel))
+        })
+        .fold(Vec3::new_zero(), |acc, t| acc + t)
+        / N_ORBIT_PTS as f64;
+
+    // dΩ/dt: The torque's component along the line of nodes, over L sin i.
+    let ang_mom = a * v;
+    torque.dot(e_node) / (ang_mom * inclination.sin().max(1e-6))
+}
+
+/// Nodal precession of test orbits about a spinning central body. X: a (kpc). Y: rad/Myr.
+pub struct FrameDragProfile {
+    pub central_only: Vec<(f64, f64)>,
+    pub all_sources: Vec<(f64, f64)>,
+    pub lense_thirring: Vec<(f64, f64)>,
+}
+
+/// Nodal precession at a range of radii, from the central body's field alone, from all sources,
+/// and from Lense–Thirring. `sources` may include other bodies, e.g. the disk, whose mass currents
+/// add to the central spin's field.
+pub fn frame_drag_profile(
+    central: &GemSource,
+    sources: &[GemSource],
+    r_max: f64,
+    num_radii: usize,
+    inclination: f64,
+    c: f64,
+    softening_factor_sq: f64,
+) -> FrameDragProfile {
+    let axis = if central.spin.magnitude() > 0. {
+        central.spin.to_normalized()
+    } else {
+        Vec3::new(0., 0., 1.)
+    };
+    let central_only = [central.clone()];
+    let radii = linspace(r_max / num_radii as f64, r_max, num_radii);
+
+    let precession = |srcs: &[GemSource]| -> Vec<(f64, f64)> {
+        radii
+            .par_iter()
+            .map(|a| {
+                let rate = nodal_precession(
+                    srcs,
+                    central.posit,
+                    axis,
+                    central.mass,
+                    *a,
+                    inclination,
+                    c,
+                    softening_factor_sq,
+                );
+                (*a, rate)
+            })
+            .collect()
+    };
+
+    FrameDragProfile {
+        central_only: precession(&central_only),
+        all_sources: precession(sources),
+        lense_thirring: radii
+            .iter()
+            .map(|a| {
+                (
+                    *a,
+                    lense_thirring_nodal(central.spin.magnitude(), *a, 0., c),
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Plot nodal precession rates against orbit radius, from the simulated field, and Lense–Thirring.
+pub fn plot_frame_dragging(out: &PlotOutput, profile: &FrameDragProfile, desc: &str) {
+    plot_multi(
+        out,
+        &[
+            ("Central spin", &profile.central_only),
+            ("All bodies", &profile.all_sources),
+            ("Lense–Thirring", &profile.lense_thirring),
+        ],
+        "a (kpc)",
+        "Ω_node (rad/Myr)",
+        &format!("Frame dragging nodal precession, {desc}"),
+        &format!("frame_dragging_{desc}"),
+    );
+}
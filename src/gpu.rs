@@ -1,83 +1,401 @@
-//! GPU computation, via CUDA.
+//! GPU computation, via CUDA. Launch settings are always compiled, so they can live in the config;
+//! everything else requires the `cuda` feature.
 
-use std::{sync::Arc, time::Instant};
+use bincode::{Decode, Encode};
 
-use cudarc::driver::{CudaStream, CudaModule, LaunchConfig};
+#[cfg(feature = "cuda")]
+use std::{
+    ffi::c_int,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+#[cfg(feature = "cuda")]
+use cudarc::{
+    driver::{
+        result, CudaContext, CudaFunction, CudaModule, CudaStream, LaunchArgs, LaunchConfig,
+        PushKernelArg,
+    },
+    nvrtc::Ptx,
+};
+#[cfg(feature = "cuda")]
 use lin_alg::f64::{alloc_vec3s, Vec3};
 
 // The floating point type used on the device. This makes switching between f32 and f64 easier.
+#[cfg(feature = "cuda")]
 type FDev = f32;
 
+/// Our kernels, compiled from `src/cuda` by `build.rs`. Embedded, so the binary runs from anywhere.
+#[cfg(feature = "cuda")]
+const PTX: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/cuda.ptx"));
+
+/// Must match `KERNEL_ABI_VERSION` in `cuda/util.cu`. Bump both when changing kernel names or
+/// signatures, so a stale PTX build is caught at startup instead of as a failed launch.
+#[cfg(feature = "cuda")]
+const KERNEL_ABI_VERSION: u32 = 2;
+
+/// Block sizes to benchmark when the occupancy API is unavailable. Multiples of the warp size.
+#[cfg(feature = "cuda")]
+const BLOCK_SIZE_CANDIDATES: [u32; 5] = [64, 128, 256, 512, 1024];
+
+/// Device memory per source: A float3 position, and a mass.
+#[cfg(feature = "cuda")]
+const BYTES_PER_SRC: usize = 16;
+/// Device memory per target: A float3 position, and a double3 output.
+#[cfg(feature = "cuda")]
+const BYTES_PER_TGT: usize = 36;
+/// The portion of free device memory tiles may use. Leaves headroom for other allocations.
+#[cfg(feature = "cuda")]
+const DEVICE_MEM_PORTION: f64 = 0.5;
+/// Used if we can't query free device memory.
+#[cfg(feature = "cuda")]
+const TILE_SIZE_DEFAULT: usize = 1 << 20;
+
+/// Benchmarked block sizes, by kernel name. Benchmarking is slow, so we only do it once per kernel.
+#[cfg(feature = "cuda")]
+static BENCHMARKED: Mutex<Vec<(&'static str, u32)>> = Mutex::new(Vec::new());
+
+/// How kernels sum per-pair terms. Terms are always computed in f32; summing many of them in f32
+/// loses precision as body count grows. Discriminants are passed to kernels; keep in sync with
+/// `PRECISION_*` in `cuda/cuda.cu`.
+///
+/// The f32 sum's error vs the f64 CPU path (`accel::acc_newton`) grows with body count. Kahan
+/// matches f64 sums; past that, error is dominated by f32 position differences in close pairs,
+/// which no summation fixes. The tests below emulate the kernel's arithmetic on the CPU, to check
+/// this; GPU results may differ slightly due to FMA contraction.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum GpuPrecision {
+    /// f32 sums. Fastest.
+    F32 = 0,
+    /// Kahan-compensated f32 sums. A few extra f32 operations per term.
+    #[default]
+    Kahan = 1,
+    /// f64 sums. Slow on consumer GPUs, which have little f64 throughput.
+    F64Accum = 2,
+}
+
+#[cfg(feature = "cuda")]
+impl GpuPrecision {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::F32 => "f32",
+            Self::Kahan => "f32 + Kahan",
+            Self::F64Accum => "f32 + f64 sum",
+        }
+        .to_owned()
+    }
+}
+
+/// Settings for kernel launches. Ignored when running on the CPU. Encoded field by field in
+/// `config_migration`.
+#[derive(Clone, Debug, Default)]
+pub struct GpuConfig {
+    /// Threads per block. If `None`, we choose per kernel using the occupancy API, or by
+    /// benchmarking if that fails.
+    pub block_size: Option<u32>,
+    /// Max bodies per source and target tile. If `None`, sized so a pair of tiles fits in free
+    /// device memory.
+    pub tile_size: Option<usize>,
+    pub precision: GpuPrecision,
+}
+
+/// Read the kernel ABI version from PTX text. It's a global, e.g.
+/// `.visible .global .align 4 .u32 KERNEL_ABI_VERSION = 1;`
+#[cfg(feature = "cuda")]
+fn ptx_abi_version(ptx: &str) -> Option<u32> {
+    let line = ptx.lines().find(|l| l.contains("KERNEL_ABI_VERSION"))?;
+    let (_, val) = line.split_once('=')?;
+    val.trim().trim_end_matches(';').trim().parse().ok()
+}
+
+/// Set up a CUDA context on the first device, and load our kernels. Returns an error describing what
+/// failed, e.g. no device or driver, or mismatched kernels, so the caller can fall back to the CPU.
+#[cfg(feature = "cuda")]
+pub fn init() -> Result<(Arc<CudaStream>, Arc<CudaModule>), String> {
+    let Some(version) = ptx_abi_version(PTX) else {
+        return Err("Kernel ABI version missing from the embedded PTX.".to_owned());
+    };
+    if version != KERNEL_ABI_VERSION {
+        return Err(format!(
+            "Kernel ABI version {version} doesn't match the host's, {KERNEL_ABI_VERSION}. Rebuild \
+             the CUDA kernels."
+        ));
+    }
+
+    let ctx = CudaContext::new(0).map_err(|e| format!("No CUDA device available: {e:?}"))?;
+    let stream = ctx.default_stream();
+
+    let module = ctx
+        .load_module(Ptx::from_src(PTX))
+        .map_err(|e| format!("Unable to load kernels: {e:?}"))?;
+
+    Ok((stream, module))
+}
+
+/// A 1D launch config covering `n` elements.
+#[cfg(feature = "cuda")]
+fn launch_cfg(n: usize, block_size: u32) -> LaunchConfig {
+    LaunchConfig {
+        grid_dim: ((n as u32).div_ceil(block_size), 1, 1),
+        block_dim: (block_size, 1, 1),
+        shared_mem_bytes: 0,
+    }
+}
+
+/// Our kernels don't use dynamic shared memory.
+#[cfg(feature = "cuda")]
+extern "C" fn no_dynamic_smem(_block_size: c_int) -> usize {
+    0
+}
+
+/// Choose threads per block for a kernel: The config override if set. Otherwise, the size the
+/// occupancy API suggests for this GPU. If that's unavailable, time each candidate size with
+/// `launch_args`, and use the fastest.
+#[cfg(feature = "cuda")]
+fn block_size(
+    stream: &Arc<CudaStream>,
+    func: &CudaFunction,
+    name: &'static str,
+    launch_args: &mut LaunchArgs,
+    n: usize,
+    cfg: &GpuConfig,
+) -> u32 {
+    if let Some(size) = cfg.block_size {
+        return size;
+    }
+
+    match func.occupancy_max_potential_block_size(no_dynamic_smem, 0, 0, None) {
+        Ok((_min_grid_size, size)) if size > 0 => return size,
+        Ok(_) => eprintln!("Occupancy API returned no block size for {name}; benchmarking."),
+        Err(e) => eprintln!("Occupancy API unavailable for {name}: {e:?}; benchmarking."),
+    }
+
+    if let Some((_, size)) = BENCHMARKED.lock().unwrap().iter().find(|(k, _)| *k == name) {
+        return *size;
+    }
 
-/// Run coulomb attraction via the GPU. Computes per-sample potentials in paralle on the GPU; runs
-/// the per-charge logic serial in the same kernel. This prevents needing to compute the sum on the CPU
-/// afterwards. Returns a potential-per-sample Vec. Same API as the parallel+CPU approach above.
+    let mut best = (BLOCK_SIZE_CANDIDATES[0], f64::MAX);
+    for size in BLOCK_SIZE_CANDIDATES {
+        let start = Instant::now();
+        if unsafe { launch_args.launch(launch_cfg(n, size)) }.is_err() {
+            // E.g. too many registers for this size.
+            continue;
+        }
+        stream.synchronize().unwrap();
+
+        let time = start.elapsed().as_secs_f64();
+        if time < best.1 {
+            best = (size, time);
+        }
+    }
+
+    println!("Benchmarked block size for {name}: {}", best.0);
+    BENCHMARKED.lock().unwrap().push((name, best.0));
+    best.0
+}
+
+/// The max number of bodies in each source and target tile, so that one of each fits on the device
+/// at once.
+#[cfg(feature = "cuda")]
+fn tile_size(stream: &Arc<CudaStream>, cfg: &GpuConfig) -> usize {
+    if let Some(size) = cfg.tile_size {
+        return size.max(1);
+    }
+
+    let free = stream
+        .context()
+        .bind_to_thread()
+        .and_then(|_| result::mem_get_info());
+
+    match free {
+        Ok((free, _total)) => {
+            ((free as f64 * DEVICE_MEM_PORTION) as usize / (BYTES_PER_SRC + BYTES_PER_TGT)).max(1)
+        }
+        Err(e) => {
+            eprintln!("Unable to query free device memory: {e:?}. Using the default tile size.");
+            TILE_SIZE_DEFAULT
+        }
+    }
+}
+
+/// Newtonian acceleration on each target from all sources, via the GPU. Computes per-target sums in
+/// parallel; each thread runs the per-source logic serially, so there's no sum to compute on the CPU
+/// afterwards. Matches `accel::acc_newton`, up to `cfg.precision`. Bodies at a target's position,
+/// e.g. the target itself, are skipped.
+///
+/// Sources and targets are split into tiles that fit in device memory, so body count is limited by
+/// host memory. We run each target tile against each source tile, and accumulate on the host.
+#[cfg(feature = "cuda")]
 pub fn run_newton(
     stream: &Arc<CudaStream>,
     module: &Arc<CudaModule>,
     posits_src: &[Vec3],
     posits_tgt: &[Vec3],
-    charges: &[f64], // Corresponds 1:1 with `posit_charges`.
-) -> Vec<f64> {
-    let start = Instant::now();
+    masses: &[f64], // Corresponds 1:1 with `posits_src`.
+    softening_factor_sq: f64,
+    cfg: &GpuConfig,
+) -> Vec<Vec3> {
+    let tile = tile_size(stream, cfg);
+
+    let masses: Vec<FDev> = masses.iter().map(|m| *m as FDev).collect();
+
+    let func = module.load_function("acc_newton_kernel").unwrap();
 
-    // allocate buffers
-    let n_sources = posits_src.len();
-    let n_targets = posits_tgt.len();
+    let mut block = None;
+    let mut result = vec![Vec3::new_zero(); posits_tgt.len()];
 
-    let posit_charges_gpus = alloc_vec3s(stream, posits_src);
-    let posits_sample_gpu = alloc_vec3s(stream, posits_tgt);
+    for (result_tile, tgt_tile) in result.chunks_mut(tile).zip(posits_tgt.chunks(tile)) {
+        let n_targets = tgt_tile.len();
+        let posits_tgt_gpu = alloc_vec3s(stream, tgt_tile);
+        // 3 per target; accumulated in f64 on the device, depending on precision.
+        let mut acc_gpu = stream.alloc_zeros::<f64>(n_targets * 3).unwrap();
 
-    // Note: This step is not required when using f64ss.
-    let charges: Vec<f32> = charges.iter().map(|c| *c as f32).collect();
+        for (src_tile, masses_tile) in posits_src.chunks(tile).zip(masses.chunks(tile)) {
+            let n_sources = src_tile.len();
+            let posits_src_gpu = alloc_vec3s(stream, src_tile);
 
-    let mut charges_gpu = stream.alloc_zeros::<f32>(n_sources).unwrap();
-    stream.memcpy_htod(&charges, &mut charges_gpu).unwrap();
+            let mut masses_gpu = stream.alloc_zeros::<FDev>(n_sources).unwrap();
+            stream.memcpy_htod(masses_tile, &mut masses_gpu).unwrap();
 
-    let mut V_per_sample = stream.alloc_zeros::<f32>(n_targets).unwrap();
+            let softening_sq = softening_factor_sq as FDev;
+            let precision = cfg.precision as u32;
 
-    // let func_coulomb = module.load_function("coulomb_kernel").unwrap();
-    let func_lj_V = module.load_function("lj_V_kernel").unwrap();
-    // let func_lj_force = module.load_function("lj_force_kernel").unwrap();
+            let mut launch_args = stream.launch_builder(&func);
 
-    // let cfg = LaunchConfig::for_num_elems(n_targets as u32);
+            launch_args.arg(&mut acc_gpu);
+            launch_args.arg(&posits_src_gpu);
+            launch_args.arg(&posits_tgt_gpu);
+            launch_args.arg(&masses_gpu);
+            launch_args.arg(&n_sources);
+            launch_args.arg(&n_targets);
+            launch_args.arg(&softening_sq);
+            launch_args.arg(&precision);
 
-    let cfg = {
-        const NUM_THREADS: u32 = 1024;
-        let num_blocks = (n_targets as u32).div_ceil(NUM_THREADS);
+            let size = *block.get_or_insert_with(|| {
+                block_size(
+                    stream,
+                    &func,
+                    "acc_newton_kernel",
+                    &mut launch_args,
+                    n_targets,
+                    cfg,
+                )
+            });
+            unsafe { launch_args.launch(launch_cfg(n_targets, size)) }.unwrap();
 
-        // Custom launch config for 2-dimensional data (?)
-        LaunchConfig {
-            grid_dim: (num_blocks, 1, 1),
-            block_dim: (NUM_THREADS, 1, 1),
-            shared_mem_bytes: 0,
+            let partial = stream.memcpy_dtov(&acc_gpu).unwrap();
+            for (r, a) in result_tile.iter_mut().zip(partial.chunks_exact(3)) {
+                *r += Vec3::new(a[0], a[1], a[2]);
+            }
         }
-    };
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use lin_alg::f64::Vec3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{accel::acc_newton, units::G, Body};
+
+    const SOFTENING_SQ: f64 = 1e-6;
+
+    /// A seeded exponential disk, with 1e9 M☉ total.
+    fn make_disk(n: usize) -> Vec<Body> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let scale_len = 1.3;
 
-    let mut launch_args = stream.launch_builder(&func_lj_V);
+        (0..n)
+            .map(|_| {
+                let r = -scale_len * (1. - rng.random_range(0. ..1.0_f64)).ln();
+                let φ = rng.random_range(0. ..std::f64::consts::TAU);
+                Body {
+                    posit: Vec3::new(r * φ.cos(), r * φ.sin(), rng.random_range(-0.1..0.1)),
+                    vel: Vec3::new_zero(),
+                    accel: Vec3::new_zero(),
+                    mass: 1e9 / n as f64,
+                    component: Default::default(),
+                }
+            })
+            .collect()
+    }
 
-    launch_args.arg(&mut V_per_sample);
-    launch_args.arg(&posit_charges_gpus);
-    launch_args.arg(&posits_sample_gpu);
-    launch_args.arg(&charges_gpu);
-    launch_args.arg(n_sources,);
-    launch_args.arg(n_targets);
+    /// `acc_newton_kernel`'s arithmetic for one target, in f32, summed per `precision`.
+    fn acc_emulated(bodies: &[Body], i: usize, precision: GpuPrecision) -> Vec3 {
+        let to_f32 = |v: Vec3| [v.x as f32, v.y as f32, v.z as f32];
+        let tgt = to_f32(bodies[i].posit);
+        let softening_sq = SOFTENING_SQ as f32;
 
-    unsafe { launch_args.launch(cfg)}.unwrap();
+        let mut sum = [0_f32; 3];
+        let mut comp = [0_f32; 3];
+        let mut sum_f64 = [0_f64; 3];
 
-    let result = stream.memcpy_dtov(&V_per_sample).unwrap();
+        for body in bodies {
+            let src = to_f32(body.posit);
+            let diff = [src[0] - tgt[0], src[1] - tgt[1], src[2] - tgt[2]];
+            let dist = (diff[0] * diff[0] + diff[1] * diff[1] + diff[2] * diff[2]).sqrt();
+            if dist == 0. {
+                continue;
+            }
 
-    // Some profiling numbers for certain grid sizes.
-    // 2D, f32: 99.144 ms
-    // 3D, f32: 400.06 ms
-    // 2D, f64: 1_658 ms
-    // 3D, f64: 1_643 ms
-    // 300 ms for both large and small sizes on f32 with std::sqrt???
+            let d_sq_soft = dist * dist + softening_sq;
+            let acc_mag = G as f32 * body.mass as f32 * dist / (d_sq_soft * d_sq_soft.sqrt());
 
-    let time_diff = Instant::now() - start;
-    println!("GPU coulomb data collected. Time: {:?}", time_diff);
+            for k in 0..3 {
+                let term = diff[k] / dist * acc_mag;
+                match precision {
+                    GpuPrecision::F32 => sum[k] += term,
+                    GpuPrecision::Kahan => {
+                        let y = term - comp[k];
+                        let t = sum[k] + y;
+                        comp[k] = (t - sum[k]) - y;
+                        sum[k] = t;
+                    }
+                    GpuPrecision::F64Accum => sum_f64[k] += term as f64,
+                }
+            }
+        }
+
+        if precision != GpuPrecision::F64Accum {
+            sum_f64 = sum.map(f64::from);
+        }
+        Vec3::new(sum_f64[0], sum_f64[1], sum_f64[2])
+    }
+
+    /// RMS relative error of the emulated kernel vs the f64 CPU path.
+    fn rms_error(bodies: &[Body], precision: GpuPrecision) -> f64 {
+        let sum_sq: f64 = (0..bodies.len())
+            .map(|i| {
+                let expected = acc_newton(bodies[i].posit, i, bodies, None, SOFTENING_SQ);
+                let actual = acc_emulated(bodies, i, precision);
+                ((actual - expected).magnitude() / expected.magnitude()).powi(2)
+            })
+            .sum();
+        (sum_sq / bodies.len() as f64).sqrt()
+    }
 
-    // This step is not required when using f64.
-    result.iter().map(|v| *v as f64).collect()
-    // result
+    #[test]
+    fn precision_matches_cpu() {
+        let bodies = make_disk(2_000);
+
+        let err_f32 = rms_error(&bodies, GpuPrecision::F32);
+        let err_kahan = rms_error(&bodies, GpuPrecision::Kahan);
+        let err_f64 = rms_error(&bodies, GpuPrecision::F64Accum);
+
+        for err in [err_f32, err_kahan, err_f64] {
+            assert!(err < 1e-4, "RMS relative error {err:.2e}");
+        }
+        // Compensated sums are at least as good as plain f32 ones, and match f64 sums.
+        assert!(
+            err_kahan <= err_f32 * 1.01,
+            "Kahan {err_kahan:.2e}, f32 {err_f32:.2e}"
+        );
+        assert!(
+            (err_kahan - err_f64).abs() <= 0.1 * err_f64,
+            "Kahan {err_kahan:.2e}, f64 {err_f64:.2e}"
+        );
+    }
 }
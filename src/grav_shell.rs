@@ -1,6 +1,13 @@
+use bincode::{Decode, Encode};
 use lin_alg::f64::Vec3;
 
-use crate::{gaussian::GaussianShell, units::C};
+use crate::{
+    accel::acc_newton_inner,
+    gaussian::GaussianShell,
+    shell_geometry::ShellGeometry,
+    units::{C, G},
+    util::interpolate,
+};
 
 // Find a value of C, given spacing and amplitude, that provides a good balance between distribution
 // uniformity, and sharp edges.
@@ -16,6 +23,120 @@ pub const COEFF_C: f64 = 0.6;
 pub const AMP_SCALER: f64 = 0.6649; // Based on COEFF = 0.6. Found from trial + error using `gauss_spacing.py`.
                                     // pub const AMP_SCALER: f64 = 0.7253; // Based on COEFF = 0.55. Found from trial + error using `gauss_spacing.py`.
 
+/// Direction-dependent shell amplitude, for testing hypotheses about anisotropic causal emission.
+/// The amplitude at a point is scaled by a factor depending on the direction from the shell's center
+/// to that point; the factor is clamped at 0, so shells never attract in reverse.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum ShellAnisotropy {
+    #[default]
+    Isotropic,
+    /// Factor: 1 + k (v · n̂) / c, using the source body's velocity at emission.
+    VelocityDipole(f64),
+    /// Factor: 1 + k (â · n̂), using the direction of the source body's acceleration at emission.
+    AccelDipole(f64),
+}
+
+impl ShellAnisotropy {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Isotropic => "Isotropic",
+            Self::VelocityDipole(_) => "v dipole",
+            Self::AccelDipole(_) => "a dipole",
+        }
+        .to_owned()
+    }
+
+    /// The dipole strength `k`, if applicable.
+    pub fn strength_mut(&mut self) -> Option<&mut f64> {
+        match self {
+            Self::Isotropic => None,
+            Self::VelocityDipole(k) | Self::AccelDipole(k) => Some(k),
+        }
+    }
+}
+
+/// How shell propagation speed varies with radius. Values are relative to C.
+#[derive(Clone, PartialEq, Debug, Default, Encode, Decode)]
+pub enum ShellSpeedLaw {
+    #[default]
+    Constant,
+    /// C(r) = C / (1 + r / r0)
+    Falloff { r0: f64 },
+    /// User-defined. X: r (kpc). Y: C(r) / C. Interpolated between points; clamped at the ends.
+    Table(Vec<(f64, f64)>),
+}
+
+impl ShellSpeedLaw {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Constant => "Constant",
+            Self::Falloff { .. } => "1/(1+r/r0)",
+            Self::Table(_) => "Table",
+        }
+        .to_owned()
+    }
+
+    /// C(r) / C.
+    pub fn ratio(&self, r: f64) -> f64 {
+        match self {
+            Self::Constant => 1.,
+            Self::Falloff { r0 } => 1. / (1. + r / r0),
+            Self::Table(data) => {
+                if data.len() < 2 {
+                    return data.first().map(|d| d.1).unwrap_or(1.);
+                }
+                if r <= data[0].0 {
+                    return data[0].1;
+                }
+                if r >= data[data.len() - 1].0 {
+                    return data[data.len() - 1].1;
+                }
+                interpolate(data, r).unwrap_or(1.)
+            }
+        }
+    }
+
+    /// Load a table from a CSV file with rows of `r, C(r) / C`. Lines that don't parse, e.g. headers,
+    /// are skipped.
+    pub fn table_from_csv(text: &str) -> Option<Self> {
+        let mut data = Vec::new();
+        for line in text.lines() {
+            let mut cols = line.split(',').map(|c| c.trim().parse::<f64>());
+            if let (Some(Ok(r)), Some(Ok(ratio))) = (cols.next(), cols.next()) {
+                data.push((r, ratio));
+            }
+        }
+
+        if data.is_empty() {
+            return None;
+        }
+        data.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Some(Self::Table(data))
+    }
+}
+
+/// Shell propagation speed settings. Slower propagation packs shells closer together, so for a given
+/// emission rate, the summed amplitude scales inversely with speed. This is the hypothesized source of
+/// MOND-like behavior at distance.
+#[derive(Clone, PartialEq, Debug, Default, Encode, Decode)]
+pub struct ShellSpeed {
+    pub law: ShellSpeedLaw,
+    /// Narrow each shell's gaussian in proportion to the local speed, so the summed amplitude matches
+    /// that of constant speed. A control for the effect above.
+    pub normalize_amplitude: bool,
+}
+
+impl ShellSpeed {
+    /// Gaussian width for a shell at radius `r`, given the width at constant speed.
+    pub fn gauss_c(&self, r: f64, gauss_c: f64) -> f64 {
+        if self.normalize_amplitude {
+            gauss_c * self.law.ratio(r)
+        } else {
+            gauss_c
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 /// Represents gravitational potential, as a shell. This allows for gravitational force to have finite speed,
 /// and act locally. We combine gaussians to achieve a uniform-like distribution.
@@ -38,25 +159,185 @@ pub struct GravShell {
     /// Is this also required?
     /// *photon-rocket* accel doesn't affect it, but gravitational accel?
     pub body_acc: Vec3,
+    /// Multiplies amplitude. 1 unless modified by shell-shell interactions (`shell_interaction` feature).
+    pub amp_scale: f64,
 }
 
 impl GravShell {
-    /// Expand the radius at C, in one timestep.
-    pub fn iter_t(&mut self, dt: f64) {
-        self.radius += C * dt;
+    /// Expand the radius at C(r), in one timestep. Uses the speed at the step's midpoint.
+    pub fn iter_t(&mut self, dt: f64, speed_law: &ShellSpeedLaw) {
+        if *speed_law == ShellSpeedLaw::Constant {
+            self.radius += C * dt;
+            return;
+        }
+
+        let r_mid = self.radius + C * speed_law.ratio(self.radius) * dt / 2.;
+        self.radius += C * speed_law.ratio(r_mid) * dt;
     }
 
-    pub fn value(&self, posit: Vec3, gauss_c: f64) -> f64 {
-        let gauss = GaussianShell {
-            center: self.center,
-            radius: self.radius,
-            a: self.src_mass,
-            c: gauss_c,
+    pub fn value(&self, posit: Vec3, gauss_c: f64, geometry: ShellGeometry) -> f64 {
+        let a = self.src_mass * self.amp_scale;
+
+        if geometry == ShellGeometry::Sphere {
+            let gauss = GaussianShell {
+                center: self.center,
+                radius: self.radius,
+                a,
+                c: gauss_c,
+            };
+            return gauss.value(posit);
+        }
+
+        let (x_1d, vert_factor) = geometry.surface_dist(posit, self.center, self.radius);
+        // As in `GaussianShell::value`.
+        if x_1d > gauss_c * 5. {
+            return 0.;
+        }
+        a * (-x_1d.powi(2) / (2. * gauss_c.powi(2))).exp() * vert_factor
+    }
+
+    /// Amplitude scale factor at a point, from the shell's emission anisotropy.
+    pub fn anisotropy_factor(&self, posit: Vec3, anisotropy: ShellAnisotropy) -> f64 {
+        let dir = || {
+            let diff = posit - self.center;
+            let dist = diff.magnitude();
+            if dist < f64::EPSILON {
+                Vec3::new_zero()
+            } else {
+                diff / dist
+            }
         };
 
-        gauss.value(posit)
+        let factor = match anisotropy {
+            ShellAnisotropy::Isotropic => return 1.,
+            ShellAnisotropy::VelocityDipole(k) => 1. + k * self.body_vel.dot(dir()) / C,
+            ShellAnisotropy::AccelDipole(k) => {
+                let acc_mag = self.body_acc.magnitude();
+                if acc_mag < f64::EPSILON {
+                    1.
+                } else {
+                    1. + k * (self.body_acc / acc_mag).dot(dir())
+                }
+            }
+        };
+
+        factor.max(0.)
     }
 }
 
 // pub const MAX_SHELL_R: f64 = 50.; // todo: Adjust this approach A/R.
 pub const MAX_SHELL_R: f64 = 20.;
+
+/// Source mass for single-source shell experiments. M☉. Galaxy-scale, so accelerations at our probe
+/// radii span the MOND regime.
+pub const SRC_MASS_TEST: f64 = 1.0e10;
+/// Extra shell generations emitted after the first passes the region, to settle the field.
+const SETTLE_GENERATIONS: usize = 20;
+/// Single-source runs stop if the leading shell slows below this portion of C, or if it hasn't
+/// filled the region in the time it would take at this speed; a speed law that goes to zero would
+/// otherwise run, and accumulate shells, indefinitely.
+const MIN_SPEED_RATIO: f64 = 0.01;
+
+/// Emit shells from a single static source at the origin until they fill `r_max`, and the field
+/// there is steady. Shells past `MAX_SHELL_R` are dropped. Returns an error if the speed law is too
+/// slow to fill the region.
+pub fn steady_state_shells(
+    src_mass: f64,
+    speed_law: &ShellSpeedLaw,
+    dt: f64,
+    shell_creation_ratio: usize,
+    gauss_c: f64,
+    r_max: f64,
+) -> Result<Vec<GravShell>, String> {
+    let r_fill = r_max + gauss_c * 5.;
+    let creation_ratio = shell_creation_ratio.max(1);
+    // The time to fill the region at the floor speed, plus settling.
+    let max_steps =
+        (r_fill / (C * MIN_SPEED_RATIO * dt)).ceil() as usize + SETTLE_GENERATIONS * creation_ratio;
+
+    let mut shells: Vec<GravShell> = Vec::new();
+    // The leading shell's radius. Tracked separately, since it may be dropped past `MAX_SHELL_R`.
+    let mut r_lead = 0.;
+    let mut generations_after_fill = 0;
+
+    for t in 0..max_steps {
+        if t % creation_ratio == 0 {
+            shells.push(GravShell {
+                source_id: 0,
+                center: Vec3::new_zero(),
+                radius: 0.,
+                src_mass,
+                body_vel: Vec3::new_zero(),
+                body_acc: Vec3::new_zero(),
+                amp_scale: 1.,
+            });
+
+            if r_lead > r_fill {
+                generations_after_fill += 1;
+            }
+        }
+
+        if r_lead <= r_fill && speed_law.ratio(r_lead) < MIN_SPEED_RATIO {
+            return Err(format!(
+                "Shells slowed below {MIN_SPEED_RATIO} c at r = {r_lead:.2} kpc, before filling \
+                 {r_max:.2} kpc"
+            ));
+        }
+
+        for shell in &mut shells {
+            shell.iter_t(dt, speed_law);
+        }
+        r_lead = r_lead.max(shells[0].radius);
+        shells.retain(|s| s.radius <= MAX_SHELL_R);
+
+        if generations_after_fill >= SETTLE_GENERATIONS {
+            return Ok(shells);
+        }
+    }
+
+    Err(format!(
+        "Shells didn't fill {r_max:.2} kpc in {max_steps} steps"
+    ))
+}
+
+/// Diagnostic: The effective force law from a single static source, under a given speed law. Runs
+/// shells to steady state out to `r_max`, then measures acceleration along a line.
+/// X: r (kpc). Y: a / a_Newton.
+pub fn effective_force_law(
+    speed: &ShellSpeed,
+    geometry: ShellGeometry,
+    dt: f64,
+    shell_creation_ratio: usize,
+    gauss_c: f64,
+    r_max: f64,
+    num_pts: usize,
+) -> Result<Vec<(f64, f64)>, String> {
+    let shells = steady_state_shells(
+        SRC_MASS_TEST,
+        &speed.law,
+        dt,
+        shell_creation_ratio,
+        gauss_c,
+        r_max,
+    )?;
+
+    let mut result = Vec::with_capacity(num_pts);
+    for i in 1..=num_pts {
+        let r = r_max * i as f64 / num_pts as f64;
+        let posit = Vec3::new(r, 0., 0.);
+        let dir = Vec3::new(-1., 0., 0.);
+
+        let acc: f64 = shells
+            .iter()
+            .map(|s| {
+                let value = s.value(posit, speed.gauss_c(s.radius, gauss_c), geometry);
+                acc_newton_inner(dir, value, r, 0.).magnitude() * geometry.flux_factor(r)
+            })
+            .sum::<f64>()
+            * AMP_SCALER;
+
+        result.push((r, acc / (G * SRC_MASS_TEST / r.powi(2))));
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,192 @@
+//! Per-timestep callbacks, run from `build()`. Lets embedders add custom diagnostics, or stop
+//! criteria, without modifying the build loop. Hooks get read access to bodies and time.
+//!
+//! Closures of the form `FnMut(&StepInfo) -> StepControl` implement `StepHook` directly. Hooks
+//! must be `Send`, since builds from the UI run on a worker thread.
+
+use std::time::{Duration, Instant};
+
+use bincode::{Decode, Encode};
+use lin_alg::f64::Vec3;
+
+use crate::{
+    properties::{rot_curve_change, rotation_curve},
+    units::C,
+    Body,
+};
+
+/// Passed to hooks each timestep.
+pub struct StepInfo<'a> {
+    /// Index of the timestep. In `on_finish`, the number of steps run.
+    pub step: usize,
+    /// Simulated time elapsed, including this step.
+    pub time: f64,
+    pub dt: f64,
+    pub bodies: &'a [Body],
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StepControl {
+    Continue,
+    /// End the build after this step. Snapshots taken so far are kept.
+    Stop,
+}
+
+pub trait StepHook: Send {
+    fn on_step(&mut self, info: &StepInfo) -> StepControl;
+
+    /// Called once when the build ends, whether it ran all timesteps or was stopped.
+    fn on_finish(&mut self, _info: &StepInfo) {}
+
+    /// For display, e.g. when this hook stops a build.
+    fn name(&self) -> String {
+        "Step hook".to_owned()
+    }
+}
+
+impl<F> StepHook for F
+where
+    F: FnMut(&StepInfo) -> StepControl + Send,
+{
+    fn on_step(&mut self, info: &StepInfo) -> StepControl {
+        self(info)
+    }
+}
+
+/// Run all hooks for a step. Returns the name of the first hook that requested a stop, if any.
+/// All hooks run each step, even if an earlier one requests a stop.
+pub fn run_hooks(hooks: &mut [Box<dyn StepHook>], info: &StepInfo) -> Option<String> {
+    let mut stopped_by = None;
+
+    for hook in hooks {
+        if hook.on_step(info) == StepControl::Stop && stopped_by.is_none() {
+            stopped_by = Some(hook.name());
+        }
+    }
+
+    stopped_by
+}
+
+/// Optional conditions for ending a build before `num_timesteps`. Set in the config, and applied
+/// as step hooks.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct StopCriteria {
+    /// Seconds, of real (wall-clock) time.
+    pub max_wall_time: Option<f64>,
+    /// Myr, of simulated time.
+    pub max_sim_time: Option<f64>,
+    /// Stop when the rotation curve's mean relative change between checks is below this.
+    pub rot_curve_tol: Option<f64>,
+    /// Check for rotation curve convergence every this many steps.
+    pub rot_curve_check_ratio: usize,
+}
+
+impl Default for StopCriteria {
+    fn default() -> Self {
+        Self {
+            max_wall_time: None,
+            max_sim_time: None,
+            rot_curve_tol: None,
+            rot_curve_check_ratio: 500,
+        }
+    }
+}
+
+impl StopCriteria {
+    /// Create hooks for the criteria that are set. Call at the start of each build, so the wall
+    /// clock timer and convergence history start fresh.
+    pub fn make_hooks(&self) -> Vec<Box<dyn StepHook>> {
+        let mut result: Vec<Box<dyn StepHook>> = Vec::new();
+
+        if let Some(max) = self.max_wall_time {
+            result.push(Box::new(WallTimeLimit {
+                max: Duration::from_secs_f64(max),
+                start: Instant::now(),
+            }));
+        }
+        if let Some(max) = self.max_sim_time {
+            result.push(Box::new(SimTimeLimit { max }));
+        }
+        if let Some(tol) = self.rot_curve_tol {
+            result.push(Box::new(RotCurveConvergence {
+                tol,
+                check_ratio: self.rot_curve_check_ratio.max(1),
+                prev: None,
+            }));
+        }
+
+        result
+    }
+}
+
+pub struct WallTimeLimit {
+    pub max: Duration,
+    pub start: Instant,
+}
+
+impl StepHook for WallTimeLimit {
+    fn on_step(&mut self, _info: &StepInfo) -> StepControl {
+        if self.start.elapsed() >= self.max {
+            StepControl::Stop
+        } else {
+            StepControl::Continue
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("Wall time limit ({:.0} s)", self.max.as_secs_f64())
+    }
+}
+
+pub struct SimTimeLimit {
+    /// Myr
+    pub max: f64,
+}
+
+impl StepHook for SimTimeLimit {
+    fn on_step(&mut self, info: &StepInfo) -> StepControl {
+        if info.time >= self.max {
+            StepControl::Stop
+        } else {
+            StepControl::Continue
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("Sim time limit ({} Myr)", self.max)
+    }
+}
+
+/// Stops when the rotation curve settles; a proxy for the galaxy reaching a steady state.
+pub struct RotCurveConvergence {
+    pub tol: f64,
+    pub check_ratio: usize,
+    prev: Option<Vec<(f64, f64)>>,
+}
+
+impl StepHook for RotCurveConvergence {
+    fn on_step(&mut self, info: &StepInfo) -> StepControl {
+        if info.step % self.check_ratio != 0 {
+            return StepControl::Continue;
+        }
+
+        let curve = rotation_curve(info.bodies, Vec3::new_zero(), C);
+
+        let mut result = StepControl::Continue;
+        if let Some(prev) = &self.prev {
+            let change = rot_curve_change(prev, &curve);
+            println!("Rotation curve change at step {}: {change:.5}", info.step);
+
+            if change < self.tol {
+                result = StepControl::Stop;
+            }
+        }
+
+        self.prev = Some(curve);
+        result
+    }
+
+    fn name(&self) -> String {
+        format!("Rotation curve convergence (tol {})", self.tol)
+    }
+}
@@ -0,0 +1,118 @@
+//! Initial-condition previews: Generate bodies, and check them without running the integration.
+//! The bodies are shown in the scene as the run's first snapshot; this adds their rotation curve,
+//! compared with the observed one, their disk surface density, compared with the input profile,
+//! and statistics on their density structure, thickness, and velocities, so problems show up
+//! before committing to a long build.
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    body_creation::Component,
+    properties::{self, disk_axis, SersicFit},
+    units::{C, KPC_MYR_PER_KM_S},
+    Body,
+};
+
+pub struct IcPreview {
+    /// Of the generated bodies. X: R (kpc). Y: km/s.
+    pub curve: Vec<(f64, f64)>,
+    /// X: R (kpc). Y: km/s.
+    pub observed: Vec<(f64, f64)>,
+    pub num_bodies: usize,
+    /// M☉
+    pub mass_total: f64,
+    /// The radius enclosing half the mass. kpc.
+    pub r_half: f64,
+    /// RMS height of disk bodies above the disk plane. kpc.
+    pub thickness: f64,
+    /// RMS vertical velocity of disk bodies. km/s.
+    pub sigma_z: f64,
+    /// Of the disk bodies. X: R (kpc). Y: M☉/kpc².
+    pub surface_density: Vec<(f64, f64)>,
+    /// The input disk profile, scaled to the disk bodies' mass. X: R (kpc). Y: M☉/kpc².
+    pub surface_density_target: Vec<(f64, f64)>,
+    /// RMS difference between the two, in dex.
+    pub surface_density_dev: Option<f64>,
+    /// Of the disk bodies' surface density.
+    pub sersic: Option<SersicFit>,
+}
+
+impl IcPreview {
+    /// `observed` is in kpc/Myr, and `density_disk` in M☉/kpc², as in `GalaxyDescrip`.
+    pub fn new(bodies: &[Body], observed: &[(f64, f64)], density_disk: &[(f64, f64)]) -> Self {
+        let center = Vec3::new_zero();
+        let axis = disk_axis(bodies, center);
+
+        let mass_total: f64 = bodies.iter().map(|b| b.mass).sum();
+
+        let mut by_r: Vec<(f64, f64)> = bodies
+            .iter()
+            .map(|b| ((b.posit - center).magnitude(), b.mass))
+            .collect();
+        by_r.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut mass_enclosed = 0.;
+        let r_half = by_r
+            .iter()
+            .find(|(_, m)| {
+                mass_enclosed += m;
+                mass_enclosed >= mass_total / 2.
+            })
+            .map_or(0., |(r, _)| *r);
+
+        let disk: Vec<Body> = bodies
+            .iter()
+            .filter(|b| b.component == Component::Disk)
+            .cloned()
+            .collect();
+        let rms = |vals: Vec<f64>| {
+            if vals.is_empty() {
+                0.
+            } else {
+                (vals.iter().map(|v| v.powi(2)).sum::<f64>() / vals.len() as f64).sqrt()
+            }
+        };
+        let thickness = rms(disk.iter().map(|b| (b.posit - center).dot(axis)).collect());
+        let sigma_z = rms(disk.iter().map(|b| b.vel.dot(axis)).collect()) / KPC_MYR_PER_KM_S;
+
+        let surface_density = properties::surface_density(&disk);
+        let (surface_density_target, surface_density_dev) =
+            properties::surface_density_deviation(&surface_density, density_disk);
+
+        Self {
+            curve: properties::rotation_curve(bodies, center, C),
+            observed: observed
+                .iter()
+                .map(|(r, v)| (*r, v / KPC_MYR_PER_KM_S))
+                .collect(),
+            num_bodies: bodies.len(),
+            mass_total,
+            r_half,
+            thickness,
+            sigma_z,
+            sersic: properties::sersic(&disk),
+            surface_density,
+            surface_density_target,
+            surface_density_dev,
+        }
+    }
+
+    /// For display.
+    pub fn summary(&self) -> Vec<String> {
+        let mut result = vec![
+            format!("{} bodies, {:.2e} M☉", self.num_bodies, self.mass_total),
+            format!("Half-mass radius: {:.2} kpc", self.r_half),
+            format!(
+                "Disk thickness: {:.3} kpc   σ_z: {:.1} km/s",
+                self.thickness, self.sigma_z
+            ),
+        ];
+        if let Some(dev) = self.surface_density_dev {
+            result.push(format!("Disk Σ vs input: {dev:.3} dex RMS"));
+        }
+        if let Some(fit) = self.sersic {
+            result.push(format!("Disk Sérsic {fit}"));
+        }
+        result
+    }
+}
@@ -0,0 +1,70 @@
+//! Body injection: Add a body to a run at a snapshot, and continue the run from there with it.
+//! This is for perturbation experiments, e.g. dropping an intruder mass into a steady disk, and
+//! watching the disk respond.
+//!
+//! The run resumes from the snapshot's body positions and velocities; see `resume_with_body` in
+//! `main`. The position can be entered directly, or picked by clicking on the galactic plane.
+
+use graphics::Entity;
+use lin_alg::{
+    f32::{Quaternion, Vec3 as Vec3f32},
+    f64::Vec3,
+};
+
+use crate::{render::MESH_SPHERE, units::KPC_MYR_PER_KM_S, Body};
+
+type Color = (f32, f32, f32);
+
+const MARKER_COLOR: Color = (0.2, 1., 0.4);
+const MARKER_SIZE: f32 = 0.3;
+const MARKER_OPACITY: f32 = 0.6;
+const MARKER_SHINYNESS: f32 = 1.;
+
+#[derive(Clone, Debug)]
+pub struct Injection {
+    /// kpc
+    pub posit: Vec3,
+    /// km/s
+    pub vel: Vec3,
+    /// M☉
+    pub mass: f64,
+    /// If set, the next click in the scene sets the position, on the galactic plane.
+    pub picking: bool,
+}
+
+impl Default for Injection {
+    fn default() -> Self {
+        Self {
+            posit: Vec3::new(20., 0., 5.),
+            vel: Vec3::new(0., 100., 0.),
+            mass: 1e10,
+            picking: false,
+        }
+    }
+}
+
+impl Injection {
+    pub fn body(&self) -> Body {
+        Body {
+            posit: self.posit,
+            vel: self.vel * KPC_MYR_PER_KM_S,
+            accel: Vec3::new_zero(),
+            mass: self.mass,
+            component: Default::default(),
+        }
+    }
+}
+
+/// Mark where the body will be injected.
+pub fn add_marker(entities: &mut Vec<Entity>, injection: &Injection, world_scale: f32) {
+    let mut entity = Entity::new(
+        MESH_SPHERE,
+        Vec3f32::from(injection.posit) * world_scale,
+        Quaternion::new_identity(),
+        MARKER_SIZE,
+        MARKER_COLOR,
+        MARKER_SHINYNESS,
+    );
+    entity.opacity = MARKER_OPACITY;
+    entities.push(entity);
+}
@@ -0,0 +1,368 @@
+//! Bodies in the space of energy, and angular momentum about the disk axis (E–L_z). In a steady,
+//! axisymmetric potential, both are conserved, so populations with a shared origin stay together
+//! in this space long after they've mixed in position: Accreted or stripped material forms
+//! clumps and streaks apart from the disk, and resonant trapping, e.g. by a bar, shows as bodies
+//! piling up along lines of constant Jacobi energy.
+//!
+//! Energies use the spherically averaged potential, integrated from the bodies' stored
+//! accelerations, as in `orbits`, so they reflect the run's force model. Outside the bodies, the
+//! potential is taken to be Keplerian. For MOND, whose potential grows logarithmically without
+//! bound, energies are relative to that; comparisons between bodies in a snapshot hold.
+//!
+//! Optionally, bodies are grouped by k-means clustering, in E and L_z scaled to unit variance.
+
+use std::fmt::Write as _;
+
+use lin_alg::f64::Vec3;
+use plotters::{
+    element::PathElement,
+    prelude::{
+        BitMapBackend, ChartBuilder, Circle, Color, HSLColor, IntoDrawingArea, BLACK, WHITE,
+    },
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    body_creation::Component,
+    properties::{center_of_mass, disk_axis, PlotOutput},
+    units::KPC_MYR_PER_KM_S,
+    Body,
+};
+
+const N_SHELLS: usize = 60;
+/// Shells extend to this percentile of bodies' radii.
+const R_MAX_PERCENTILE: f64 = 0.99;
+const MAX_CLUSTER_ITERS: usize = 100;
+/// Bodies drawn per cluster. Beyond this, we draw an even sample, so large runs plot quickly.
+const MAX_PLOT_PTS: usize = 20_000;
+
+/// Per body.
+#[derive(Clone, Copy, Debug)]
+pub struct Integrals {
+    /// Specific energy. kpc²/Myr².
+    pub energy: f64,
+    /// Specific angular momentum about the disk axis. kpc²/Myr.
+    pub l_z: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cluster {
+    /// Bodies, by index into the snapshot.
+    pub members: Vec<usize>,
+    /// Mean E and L_z of members.
+    pub center: Integrals,
+    /// Portion of the total mass.
+    pub mass_portion: f64,
+}
+
+/// (r, Φ), from the spherically averaged radial acceleration. kpc, kpc²/Myr².
+fn potential_profile(bodies: &[Body], center: Vec3) -> Vec<(f64, f64)> {
+    // (r, outward acceleration)
+    let radial: Vec<(f64, f64)> = bodies
+        .iter()
+        .filter_map(|b| {
+            let diff = b.posit - center;
+            let r = diff.magnitude();
+            (r > f64::EPSILON).then(|| (r, b.accel.dot(diff / r)))
+        })
+        .collect();
+
+    let mut radii: Vec<f64> = radial.iter().map(|(r, _)| *r).collect();
+    if radii.is_empty() {
+        return Vec::new();
+    }
+    radii.sort_by(f64::total_cmp);
+    let r_max = radii[((radii.len() - 1) as f64 * R_MAX_PERCENTILE) as usize];
+    let dr = r_max / N_SHELLS as f64;
+
+    // (shell's mid radius, mean inward acceleration), for shells with bodies.
+    let mut g = Vec::with_capacity(N_SHELLS);
+    for i in 0..N_SHELLS {
+        let (r_inner, r_outer) = (i as f64 * dr, (i + 1) as f64 * dr);
+        let in_shell: Vec<f64> = radial
+            .iter()
+            .filter(|(r, _)| *r >= r_inner && *r < r_outer)
+            .map(|(_, a)| -a)
+            .collect();
+        if !in_shell.is_empty() {
+            let g_mean = in_shell.iter().sum::<f64>() / in_shell.len() as f64;
+            g.push(((r_inner + r_outer) / 2., g_mean));
+        }
+    }
+
+    // Keplerian outside: Φ = -g r. Integrate inward: dΦ/dr = g.
+    let Some(&(r_edge, g_edge)) = g.last() else {
+        return Vec::new();
+    };
+    let mut phi = -g_edge.max(0.) * r_edge;
+    let mut result = vec![(r_edge, phi)];
+    for pair in g.windows(2).rev() {
+        let ((r0, g0), (r1, g1)) = (pair[0], pair[1]);
+        phi -= (g0 + g1) / 2. * (r1 - r0);
+        result.push((r0, phi));
+    }
+    result.reverse();
+
+    result
+}
+
+/// E and L_z of each body, in the frame of the center of mass, and its disk.
+pub fn integrals(bodies: &[Body]) -> Vec<Integrals> {
+    let (center, vel) = center_of_mass(bodies);
+    let axis = disk_axis(bodies, center);
+    let profile = potential_profile(bodies, center);
+
+    let phi = |r: f64| -> f64 {
+        let Some(&(r_edge, phi_edge)) = profile.last() else {
+            return 0.;
+        };
+        if r >= r_edge {
+            return phi_edge * r_edge / r;
+        }
+        // Flat inside the innermost shell.
+        if r <= profile[0].0 {
+            return profile[0].1;
+        }
+        let i = profile.partition_point(|(r_, _)| *r_ < r);
+        let ((r0, phi_0), (r1, phi_1)) = (profile[i - 1], profile[i]);
+        phi_0 + (r - r0) / (r1 - r0) * (phi_1 - phi_0)
+    };
+
+    bodies
+        .iter()
+        .map(|b| {
+            let diff = b.posit - center;
+            let v = b.vel - vel;
+            Integrals {
+                energy: 0.5 * v.magnitude_squared() + phi(diff.magnitude()),
+                l_z: diff.cross(v).dot(axis),
+            }
+        })
+        .collect()
+}
+
+/// Group bodies into `k` clusters by k-means, with k-means++ seeding. E and L_z are each scaled
+/// to unit variance first, so neither dominates from its units.
+pub fn cluster(
+    bodies: &[Body],
+    integrals: &[Integrals],
+    k: usize,
+    seed: Option<u64>,
+) -> Vec<Cluster> {
+    let n = integrals.len();
+    if k == 0 || n == 0 {
+        return Vec::new();
+    }
+    let k = k.min(n);
+
+    let std_dev = |f: fn(&Integrals) -> f64| {
+        let mean = integrals.iter().map(f).sum::<f64>() / n as f64;
+        let var = integrals.iter().map(|v| (f(v) - mean).powi(2)).sum::<f64>() / n as f64;
+        var.sqrt().max(f64::EPSILON)
+    };
+    let (scale_e, scale_l) = (std_dev(|v| v.energy), std_dev(|v| v.l_z));
+    let pts: Vec<(f64, f64)> = integrals
+        .iter()
+        .map(|v| (v.energy / scale_e, v.l_z / scale_l))
+        .collect();
+    let dist_sq = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2);
+
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+
+    // k-means++: Each next center is drawn with probability ∝ squared distance to the nearest.
+    let mut centers = vec![pts[rng.random_range(0..n)]];
+    while centers.len() < k {
+        let weights: Vec<f64> = pts
+            .iter()
+            .map(|p| {
+                centers
+                    .iter()
+                    .map(|c| dist_sq(*p, *c))
+                    .fold(f64::INFINITY, f64::min)
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0. {
+            break;
+        }
+        let mut target = rng.random_range(0.0..total);
+        let i = weights
+            .iter()
+            .position(|w| {
+                target -= w;
+                target <= 0.
+            })
+            .unwrap_or(n - 1);
+        centers.push(pts[i]);
+    }
+
+    let mut assignment = vec![usize::MAX; n];
+    for _ in 0..MAX_CLUSTER_ITERS {
+        let next: Vec<usize> = pts
+            .iter()
+            .map(|p| {
+                (0..centers.len())
+                    .min_by(|a, b| dist_sq(*p, centers[*a]).total_cmp(&dist_sq(*p, centers[*b])))
+                    .unwrap_or_default()
+            })
+            .collect();
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+
+        for (c, center) in centers.iter_mut().enumerate() {
+            let members: Vec<_> = (0..n).filter(|i| assignment[*i] == c).collect();
+            if !members.is_empty() {
+                let sum = members
+                    .iter()
+                    .fold((0., 0.), |acc, i| (acc.0 + pts[*i].0, acc.1 + pts[*i].1));
+                *center = (sum.0 / members.len() as f64, sum.1 / members.len() as f64);
+            }
+        }
+    }
+
+    let mass_total: f64 = bodies.iter().map(|b| b.mass).sum::<f64>().max(f64::EPSILON);
+    let mut result: Vec<Cluster> = (0..centers.len())
+        .filter_map(|c| {
+            let members: Vec<usize> = (0..n).filter(|i| assignment[*i] == c).collect();
+            if members.is_empty() {
+                return None;
+            }
+            let count = members.len() as f64;
+            Some(Cluster {
+                center: Integrals {
+                    energy: members.iter().map(|i| integrals[*i].energy).sum::<f64>() / count,
+                    l_z: members.iter().map(|i| integrals[*i].l_z).sum::<f64>() / count,
+                },
+                mass_portion: members.iter().map(|i| bodies[*i].mass).sum::<f64>() / mass_total,
+                members,
+            })
+        })
+        .collect();
+
+    // Most bound first.
+    result.sort_by(|a, b| a.center.energy.total_cmp(&b.center.energy));
+    result
+}
+
+/// A table of clusters: size, mass, mean E and L_z, and which galaxy components their bodies were
+/// generated for. `components` is by body index into the snapshot; it may be empty.
+pub fn cluster_report(clusters: &[Cluster], components: &[Component]) -> String {
+    let e_unit = KPC_MYR_PER_KM_S * KPC_MYR_PER_KM_S;
+    let mut result = "E–L_z clusters (E in (km/s)², L_z in kpc·km/s):\n".to_owned();
+
+    for (c, cluster) in clusters.iter().enumerate() {
+        write!(
+            result,
+            "{c}: {} bodies, {:.1}% of mass. E: {:.0}, L_z: {:.0}",
+            cluster.members.len(),
+            cluster.mass_portion * 100.,
+            cluster.center.energy / e_unit,
+            cluster.center.l_z / KPC_MYR_PER_KM_S,
+        )
+        .unwrap();
+
+        if !components.is_empty() {
+            let mut counts: Vec<(Component, usize)> = Vec::new();
+            for i in &cluster.members {
+                let comp = components.get(*i).copied().unwrap_or_default();
+                match counts.iter_mut().find(|(c, _)| *c == comp) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((comp, 1)),
+                }
+            }
+            let counts: Vec<_> = counts
+                .iter()
+                .map(|(comp, count)| format!("{} {count}", comp.to_str()))
+                .collect();
+            write!(result, ". {}", counts.join(", ")).unwrap();
+        }
+        result.push('\n');
+    }
+
+    result
+}
+
+/// Scatter plot of bodies in E–L_z space, colored by cluster if clustered.
+pub fn plot_elz(out: &PlotOutput, integrals: &[Integrals], clusters: &[Cluster], desc: &str) {
+    let Some(path) = out.path(&format!("elz_{desc}")) else {
+        return;
+    };
+    if integrals.is_empty() {
+        return;
+    }
+
+    let e_unit = KPC_MYR_PER_KM_S * KPC_MYR_PER_KM_S;
+    let pt = |v: &Integrals| (v.l_z / KPC_MYR_PER_KM_S, v.energy / e_unit);
+
+    let (mut x_min, mut x_max, mut y_min, mut y_max) = (
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+    );
+    for (x, y) in integrals.iter().map(pt) {
+        x_min = x_min.min(x);
+        x_max = x_max.max(x);
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+
+    let root = BitMapBackend::new(&path, (800, 600)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("E–L_z: {desc}"), ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(x_min..x_max, y_min..y_max)
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .x_desc("L_z (kpc·km/s)")
+        .y_desc("E ((km/s)²)")
+        .draw()
+        .unwrap();
+
+    // Unclustered, all bodies are one group.
+    let all: Vec<usize> = (0..integrals.len()).collect();
+    let groups: Vec<&[usize]> = if clusters.is_empty() {
+        vec![&all]
+    } else {
+        clusters.iter().map(|c| c.members.as_slice()).collect()
+    };
+
+    for (c, members) in groups.iter().enumerate() {
+        let color = HSLColor(c as f64 / groups.len() as f64, 0.8, 0.45);
+        let step = members.len().div_ceil(MAX_PLOT_PTS).max(1);
+
+        let series = chart
+            .draw_series(
+                members
+                    .iter()
+                    .step_by(step)
+                    .map(|i| Circle::new(pt(&integrals[*i]), 1, color.filled())),
+            )
+            .unwrap();
+        if !clusters.is_empty() {
+            series
+                .label(format!("Cluster {c}"))
+                .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color));
+        }
+    }
+
+    if !clusters.is_empty() {
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .unwrap();
+    }
+}
@@ -1,7 +1,109 @@
+//! Integration of body motion. RK4 advances one body at a time, given an acceleration function;
+//! sources are held at their positions at the start of the step. The symplectic schemes advance all
+//! bodies together, re-evaluating the field at each substep, since symplecticity relies on the
+//! sources moving in sync with the target. Hermite also advances all bodies together, since jerk
+//! depends on the sources' motion.
+//!
+//! With block timesteps, each body steps at dt / 2^level, its level set by its acceleration, so
+//! bodies in the dense core take small steps, while the outer disk takes the full step. Levels are
+//! powers of two, so steps nest, and all bodies are in sync at the end of each full step.
+
+use bincode::{Decode, Encode};
 use lin_alg::f64::Vec3;
+use rayon::prelude::*;
 
 use crate::Body;
 
+/// Yoshida (1990) 4th-order coefficients: w₁ = 1 / (2 - 2^(1/3)), w₀ = 1 - 2w₁.
+const YOSHIDA_W1: f64 = 1.351_207_191_959_657_8;
+const YOSHIDA_W0: f64 = -1.702_414_383_919_315_3;
+
+/// The scheme used to advance bodies each step.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum IntegratorKind {
+    /// Classic 4th-order Runge-Kutta. 4 evaluations per step, with sources held still. Not
+    /// symplectic; energy drifts.
+    #[default]
+    Rk4,
+    /// Kick-drift-kick leapfrog. 2nd order, and symplectic, so energy errors stay bounded. 2
+    /// evaluations per step.
+    Leapfrog,
+    /// Yoshida's 4th-order composition of three leapfrog steps. Symplectic. 4 evaluations per step.
+    Yoshida4,
+    /// 4th-order Hermite predictor-corrector, using jerk. 2 evaluations per step. Accurate for
+    /// close encounters, e.g. in the bulge. Newtonian, by direct sum.
+    Hermite4,
+}
+
+/// Hierarchical (block) individual timesteps, for leapfrog. The config's dt is the longest step.
+/// Each body's step is η √(ε / |a|), rounded down to dt / 2^level. (ε is the softening length)
+#[derive(Clone, Copy, Debug, Encode, Decode)]
+pub struct BlockTimesteps {
+    /// The shortest step is dt / 2^max_level.
+    pub max_level: u8,
+    /// Accuracy parameter. Lower is more accurate, and slower.
+    pub η: f64,
+}
+
+impl Default for BlockTimesteps {
+    fn default() -> Self {
+        Self {
+            max_level: 6,
+            η: 0.025,
+        }
+    }
+}
+
+impl BlockTimesteps {
+    /// The level for a body with acceleration `accel`; the shallowest whose step is at most the
+    /// body's ideal one.
+    fn level(&self, accel: Vec3, softening_len: f64, dt: f64) -> u8 {
+        let a = accel.magnitude();
+        if a == 0. {
+            return 0;
+        }
+        let dt_ideal = self.η * (softening_len / a).sqrt();
+        if dt_ideal <= 0. {
+            return self.max_level;
+        }
+
+        (dt / dt_ideal)
+            .log2()
+            .ceil()
+            .clamp(0., self.max_level as f64) as u8
+    }
+}
+
+/// Each body's block timestep level, kept between steps. Empty until the first step.
+#[derive(Default)]
+pub struct BlockLevels {
+    levels: Vec<u8>,
+}
+
+impl BlockLevels {
+    /// Number of bodies at each level.
+    pub fn counts(&self) -> Vec<usize> {
+        let max = self.levels.iter().copied().max().unwrap_or_default() as usize;
+        let mut result = vec![0; max + 1];
+        for level in &self.levels {
+            result[*level as usize] += 1;
+        }
+        result
+    }
+}
+
+impl IntegratorKind {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Rk4 => "RK4",
+            Self::Leapfrog => "Leapfrog",
+            Self::Yoshida4 => "Yoshida 4",
+            Self::Hermite4 => "Hermite 4",
+        }
+        .to_owned()
+    }
+}
+
 /// Compute acceleration, position, and velocity, using RK4.
 /// The acc fn: (id, target posit, target charge) -> Acceleration.
 /// Target charge is N/A for gravity mode.
@@ -31,3 +133,233 @@ where
     body_tgt.vel += (k1_v + k2_v * 2. + k3_v * 2. + k4_v) / 6.;
     body_tgt.posit += (k1_pos + k2_pos * 2. + k3_pos * 2. + k4_pos) / 6.;
 }
+
+/// As `integrate_rk4`, for all bodies at once, for evaluators that batch targets, e.g. the GPU.
+/// `acc_many` evaluates the acceleration on each body at the given positions, with sources held at
+/// the start of the step.
+pub fn integrate_rk4_many<F>(bodies: &mut [Body], acc_many: &F, dt: f64)
+where
+    F: Fn(&[Vec3]) -> Vec<Vec3>,
+{
+    let posits: Vec<Vec3> = bodies.iter().map(|b| b.posit).collect();
+    let k1_v: Vec<Vec3> = acc_many(&posits).into_iter().map(|a| a * dt).collect();
+    let k1_pos: Vec<Vec3> = bodies.iter().map(|b| b.vel * dt).collect();
+
+    // Positions and velocities at a stage, offset from the start by a fraction of the previous
+    // stage's k-values.
+    let stage = |k_pos: &[Vec3], k_v: &[Vec3], frac: f64| {
+        let posits: Vec<Vec3> = bodies
+            .iter()
+            .zip(k_pos)
+            .map(|(b, k)| b.posit + *k * frac)
+            .collect();
+        let k_v_next: Vec<Vec3> = acc_many(&posits).into_iter().map(|a| a * dt).collect();
+        let k_pos_next: Vec<Vec3> = bodies
+            .iter()
+            .zip(k_v)
+            .map(|(b, k)| (b.vel + *k * frac) * dt)
+            .collect();
+        (k_pos_next, k_v_next)
+    };
+
+    let (k2_pos, k2_v) = stage(&k1_pos, &k1_v, 0.5);
+    let (k3_pos, k3_v) = stage(&k2_pos, &k2_v, 0.5);
+    let (k4_pos, k4_v) = stage(&k3_pos, &k3_v, 1.);
+
+    for (i, body) in bodies.iter_mut().enumerate() {
+        body.accel = k1_v[i] / dt;
+        body.vel += (k1_v[i] + k2_v[i] * 2. + k3_v[i] * 2. + k4_v[i]) / 6.;
+        body.posit += (k1_pos[i] + k2_pos[i] * 2. + k3_pos[i] * 2. + k4_pos[i]) / 6.;
+    }
+}
+
+/// A kick-drift-kick leapfrog step for all bodies. `acc_all` evaluates the acceleration on each
+/// body, at the bodies' current positions. `acc_start` is at the start of the step; returns the
+/// acceleration at the end, so composed steps can reuse it.
+fn leapfrog_step<F>(bodies: &mut [Body], acc_all: &F, acc_start: &[Vec3], dt: f64) -> Vec<Vec3>
+where
+    F: Fn(&[Body]) -> Vec<Vec3>,
+{
+    for (body, a) in bodies.iter_mut().zip(acc_start) {
+        body.vel += *a * dt * 0.5;
+        body.posit += body.vel * dt;
+    }
+
+    let acc_end = acc_all(bodies);
+    for (body, a) in bodies.iter_mut().zip(&acc_end) {
+        body.vel += *a * dt * 0.5;
+    }
+
+    acc_end
+}
+
+/// The acceleration on each body at the start of a step. If `accel_current`, it's from the end of
+/// the previous step, stored in `Body::accel`. Otherwise, e.g. on the first step, or after bodies
+/// are added or removed, it's evaluated.
+fn acc_start<F>(bodies: &[Body], acc_all: &F, accel_current: bool) -> Vec<Vec3>
+where
+    F: Fn(&[Body]) -> Vec<Vec3>,
+{
+    if accel_current {
+        bodies.iter().map(|b| b.accel).collect()
+    } else {
+        acc_all(bodies)
+    }
+}
+
+/// Advance all bodies one step, using kick-drift-kick leapfrog. Sets `Body::accel` to the
+/// acceleration at the end of the step, so the next step can reuse it; see `acc_start`.
+pub fn integrate_leapfrog<F>(bodies: &mut [Body], acc_all: &F, dt: f64, accel_current: bool)
+where
+    F: Fn(&[Body]) -> Vec<Vec3>,
+{
+    let acc = acc_start(bodies, acc_all, accel_current);
+    let acc = leapfrog_step(bodies, acc_all, &acc, dt);
+
+    for (body, a) in bodies.iter_mut().zip(acc) {
+        body.accel = a;
+    }
+}
+
+/// Advance all bodies one step, using Yoshida's 4th-order composition of leapfrog steps of w₁dt,
+/// w₀dt, and w₁dt. The middle step goes backwards in time. Sets `Body::accel` as
+/// `integrate_leapfrog` does.
+pub fn integrate_yoshida4<F>(bodies: &mut [Body], acc_all: &F, dt: f64, accel_current: bool)
+where
+    F: Fn(&[Body]) -> Vec<Vec3>,
+{
+    let mut acc = acc_start(bodies, acc_all, accel_current);
+    for w in [YOSHIDA_W1, YOSHIDA_W0, YOSHIDA_W1] {
+        acc = leapfrog_step(bodies, acc_all, &acc, w * dt);
+    }
+
+    for (body, a) in bodies.iter_mut().zip(acc) {
+        body.accel = a;
+    }
+}
+
+/// Advance all bodies one step, using the 4th-order Hermite predictor-corrector. (Makino & Aarseth,
+/// 1992) `acc_jerk` is (target posit, target vel, target id, sources) -> (acceleration, jerk).
+///
+/// Predict each body's state from its acceleration and jerk, re-evaluate them at the predicted
+/// states, then correct:
+///
+/// v₁ = v₀ + (a₀ + a₁) dt / 2 + (j₀ - j₁) dt² / 12
+///
+/// x₁ = x₀ + (v₀ + v₁) dt / 2 + (a₀ - a₁) dt² / 12
+pub fn integrate_hermite4<F>(bodies: &mut [Body], acc_jerk: &F, dt: f64)
+where
+    F: Fn(Vec3, Vec3, usize, &[Body]) -> (Vec3, Vec3) + Sync,
+{
+    let start: Vec<(Vec3, Vec3)> = bodies
+        .par_iter()
+        .enumerate()
+        .map(|(id, body)| acc_jerk(body.posit, body.vel, id, bodies))
+        .collect();
+
+    let predicted: Vec<Body> = bodies
+        .iter()
+        .zip(&start)
+        .map(|(body, (a, j))| Body {
+            posit: body.posit + body.vel * dt + *a * (dt.powi(2) / 2.) + *j * (dt.powi(3) / 6.),
+            vel: body.vel + *a * dt + *j * (dt.powi(2) / 2.),
+            ..body.clone()
+        })
+        .collect();
+
+    let end: Vec<(Vec3, Vec3)> = predicted
+        .par_iter()
+        .enumerate()
+        .map(|(id, body)| acc_jerk(body.posit, body.vel, id, &predicted))
+        .collect();
+
+    bodies
+        .par_iter_mut()
+        .zip(start.par_iter().zip(&end))
+        .for_each(|(body, ((a0, j0), (a1, j1)))| {
+            let vel = body.vel + (*a0 + *a1) * (dt / 2.) + (*j0 - *j1) * (dt.powi(2) / 12.);
+            body.posit += (body.vel + vel) * (dt / 2.) + (*a0 - *a1) * (dt.powi(2) / 12.);
+            body.vel = vel;
+            body.accel = *a0;
+        });
+}
+
+/// Advance all bodies one step, dt, with block timesteps: Kick-drift-kick leapfrog, with each body
+/// kicked at the start and end of its own step, and all bodies drifted each substep of the shortest
+/// step. `acc_some` evaluates the acceleration on the listed bodies, at all bodies' current
+/// positions; it only runs for bodies whose step ends. If `accel_current`, `Body::accel` is from
+/// the end of the previous step, and is reused.
+///
+/// A body moves to a deeper level at the end of any of its steps, and to a shallower one only where
+/// its step ends in sync with that level's, so steps stay nested.
+pub fn integrate_block<F>(
+    bodies: &mut [Body],
+    levels: &mut BlockLevels,
+    cfg: &BlockTimesteps,
+    acc_some: &F,
+    softening_len: f64,
+    dt: f64,
+    accel_current: bool,
+) where
+    F: Fn(&[Body], &[usize]) -> Vec<Vec3>,
+{
+    let max_level = cfg.max_level;
+    let levels = &mut levels.levels;
+
+    if !accel_current || levels.len() != bodies.len() {
+        let ids: Vec<usize> = (0..bodies.len()).collect();
+        let acc = acc_some(bodies, &ids);
+        for (body, a) in bodies.iter_mut().zip(acc) {
+            body.accel = a;
+        }
+    }
+
+    // On the first step, or if bodies were added or removed.
+    if levels.len() != bodies.len() {
+        *levels = bodies
+            .iter()
+            .map(|b| cfg.level(b.accel, softening_len, dt))
+            .collect();
+    }
+
+    // In substeps; the number of them in a step at `level`.
+    let stride = |level: u8| 1_usize << (max_level - level.min(max_level));
+    let dt_level = |level: u8| dt / (1_usize << level) as f64;
+
+    let num_substeps = 1_usize << max_level;
+    let dt_sub = dt / num_substeps as f64;
+
+    for sub in 0..num_substeps {
+        for (body, level) in bodies.iter_mut().zip(levels.iter()) {
+            if sub % stride(*level) == 0 {
+                body.vel += body.accel * (dt_level(*level) * 0.5);
+            }
+        }
+
+        for body in bodies.iter_mut() {
+            body.posit += body.vel * dt_sub;
+        }
+
+        let due: Vec<usize> = (0..bodies.len())
+            .filter(|i| (sub + 1) % stride(levels[*i]) == 0)
+            .collect();
+        if due.is_empty() {
+            continue;
+        }
+
+        let acc = acc_some(bodies, &due);
+        for (i, a) in due.into_iter().zip(acc) {
+            let level = levels[i];
+            let body = &mut bodies[i];
+            body.accel = a;
+            body.vel += a * (dt_level(level) * 0.5);
+
+            let wanted = cfg.level(a, softening_len, dt);
+            let mut new_level = level.max(wanted);
+            while new_level > wanted && (sub + 1) % stride(new_level - 1) == 0 {
+                new_level -= 1;
+            }
+            levels[i] = new_level;
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Headless parameter sweeps, e.g. on a cluster. Each config file in a directory is a job. Several
+//! processes can share a sweep: Each runs the jobs for its rank, so a launcher such as `srun` or
+//! `mpirun` can spread them across nodes, without us linking MPI. Each job's results go in its own
+//! directory, under a shared output directory.
+//!
+//! Usage: `causal_grav jobs <config dir> <output dir> [--galaxy NAME] [--force MODEL]
+//! [--rank R --ranks N]`
+//!
+//! Splitting one large run across nodes (domain decomposition) isn't supported; within a node,
+//! builds use all cores.
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs, io,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    accel::MondFn,
+    build, diagnostics,
+    galaxy_data::GalaxyCatalog,
+    properties::{bar_strength, rotation_curve},
+    sparc,
+    units::C,
+    BuildStatus, Config, ForceModel, State,
+};
+
+/// Written last, so its presence marks a job as done. Done jobs are skipped, so an interrupted
+/// sweep can be resumed by re-running it.
+const SUMMARY_FILE: &str = "summary.txt";
+
+/// Rank, and number of ranks, as set by common launchers: Slurm, Open MPI, and MPICH.
+const RANK_VARS: [(&str, &str); 3] = [
+    ("SLURM_PROCID", "SLURM_NTASKS"),
+    ("OMPI_COMM_WORLD_RANK", "OMPI_COMM_WORLD_SIZE"),
+    ("PMI_RANK", "PMI_SIZE"),
+];
+
+/// Models selectable by name from the command line.
+const FORCE_MODELS: [ForceModel; 7] = [
+    ForceModel::Newton,
+    ForceModel::Mond(MondFn::Simple),
+    ForceModel::Mond(MondFn::Standard),
+    ForceModel::MondNet(MondFn::Simple),
+    ForceModel::MondNet(MondFn::Standard),
+    ForceModel::Qumond(MondFn::Standard),
+    ForceModel::GaussShells,
+];
+
+struct JobArgs {
+    config_dir: PathBuf,
+    out_dir: PathBuf,
+    galaxy: Option<String>,
+    force_model: ForceModel,
+    rank: usize,
+    num_ranks: usize,
+}
+
+fn rank_from_env() -> Option<(usize, usize)> {
+    RANK_VARS.iter().find_map(|(rank, size)| {
+        let rank = env::var(rank).ok()?.parse().ok()?;
+        let size = env::var(size).ok()?.parse().ok()?;
+        Some((rank, size))
+    })
+}
+
+fn parse_args(args: &[String]) -> Result<JobArgs, String> {
+    let mut positional = Vec::new();
+    let mut galaxy = None;
+    let mut force_model = ForceModel::Newton;
+    let (mut rank, mut num_ranks) = rank_from_env().unwrap_or((0, 1));
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing a value for {arg}"))
+        };
+
+        match arg.as_str() {
+            "--galaxy" => galaxy = Some(value()?),
+            "--force" => {
+                let name = value()?;
+                force_model = FORCE_MODELS
+                    .into_iter()
+                    .find(|m| m.to_str().eq_ignore_ascii_case(&name))
+                    .ok_or_else(|| {
+                        let names: Vec<_> = FORCE_MODELS.iter().map(|m| m.to_str()).collect();
+                        format!("Unknown force model: {name}. Options: {}", names.join(", "))
+                    })?;
+            }
+            "--rank" => rank = value()?.parse().map_err(|_| "Invalid rank")?,
+            "--ranks" => num_ranks = value()?.parse().map_err(|_| "Invalid number of ranks")?,
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [config_dir, out_dir] = <[PathBuf; 2]>::try_from(positional)
+        .map_err(|_| "Usage: jobs <config dir> <output dir> [--galaxy NAME] [--force MODEL]")?;
+
+    if num_ranks == 0 || rank >= num_ranks {
+        return Err(format!("Rank {rank} is out of range for {num_ranks} ranks"));
+    }
+
+    Ok(JobArgs {
+        config_dir,
+        out_dir,
+        galaxy,
+        force_model,
+        rank,
+        num_ranks,
+    })
+}
+
+/// Config files in the directory, in name order, so each rank sees the same job indices.
+fn find_jobs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "grav"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Build with the config, and save its snapshots, final rotation curve, and a summary.
+fn run_job(
+    state: &mut State,
+    config: Config,
+    force_model: ForceModel,
+    dir: &Path,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    state.apply_config(config);
+    let start = Instant::now();
+    // Aborted jobs don't get a summary, so they're counted as failed, and rerun on resume.
+    if let BuildStatus::Aborted(e) = build(state, force_model) {
+        return Err(io::Error::new(ErrorKind::Other, e));
+    }
+    let wall_time = start.elapsed().as_secs_f64();
+
+    state.save_snapshots(&dir.join("snapshots.grav"))?;
+
+    let curve = rotation_curve(&state.bodies, Vec3::new_zero(), C);
+    let mut csv = "r_kpc,v_km_s\n".to_owned();
+    for (r, v) in &curve {
+        writeln!(csv, "{r},{v}").unwrap();
+    }
+    fs::write(dir.join("rotation_curve.csv"), csv)?;
+
+    let (drift_e, drift_l) = diagnostics::drift(&state.diagnostics);
+    let mut summary = String::new();
+    writeln!(summary, "Galaxy: {}", state.ui.run_labels.galaxy).unwrap();
+    let prov = &state.ui.galaxy_descrip.provenance;
+    writeln!(summary, "Source: {}", prov.paper).unwrap();
+    writeln!(summary, "Survey: {}", prov.survey).unwrap();
+    writeln!(summary, "Distance basis: {}", prov.distance).unwrap();
+    writeln!(summary, "Force model: {}", force_model.to_str()).unwrap();
+    writeln!(summary, "Bodies: {}", state.bodies.len()).unwrap();
+    writeln!(summary, "Time: {:.3} Myr", state.time_elapsed).unwrap();
+    writeln!(summary, "Wall time: {wall_time:.1} s").unwrap();
+    writeln!(
+        summary,
+        "Bar strength (A₂): {:.4}",
+        bar_strength(&state.bodies, Vec3::new_zero())
+    )
+    .unwrap();
+    if let (Some(e), Some(l)) = (drift_e.last(), drift_l.last()) {
+        writeln!(summary, "Energy drift: {:.3e}", e.1).unwrap();
+        writeln!(summary, "Angular momentum drift: {:.3e}", l.1).unwrap();
+    }
+    fs::write(dir.join(SUMMARY_FILE), summary)
+}
+
+/// Run this rank's jobs. `args` are the command line arguments following `jobs`.
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+    let args = parse_args(args)?;
+
+    let jobs = find_jobs(&args.config_dir)
+        .map_err(|e| format!("Unable to read {:?}: {e}", args.config_dir))?;
+    if jobs.is_empty() {
+        return Err(format!("No config files in {:?}", args.config_dir));
+    }
+
+    let mut state = State::default();
+    if let Some(name) = &args.galaxy {
+        let catalog = GalaxyCatalog::scan(Path::new(sparc::SPARC_DIR));
+        let Some(model) = catalog.models().find(|m| m.name.eq_ignore_ascii_case(name)) else {
+            return Err(format!("Unknown galaxy: {name}"));
+        };
+        state.ui.galaxy_descrip = model.descrip();
+        state.ui.galaxy_model = model.clone();
+    } else {
+        state.ui.galaxy_descrip = state.ui.galaxy_model.descrip();
+    }
+
+    let mine: Vec<&PathBuf> = jobs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| i % args.num_ranks == args.rank)
+        .map(|(_, path)| path)
+        .collect();
+    println!(
+        "Rank {} of {}: {} of {} jobs",
+        args.rank,
+        args.num_ranks,
+        mine.len(),
+        jobs.len()
+    );
+
+    let mut num_failed = 0;
+    for path in mine {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let dir = args.out_dir.join(name.as_ref());
+        if dir.join(SUMMARY_FILE).exists() {
+            println!("Skipping {name}; it's done");
+            continue;
+        }
+
+        let result = Config::load(path)
+            .and_then(|config| run_job(&mut state, config, args.force_model, &dir));
+        match result {
+            Ok(()) => println!("Job {name} complete. Results are in {dir:?}"),
+            Err(e) => {
+                eprintln!("Job {name} failed: {e}");
+                num_failed += 1;
+            }
+        }
+    }
+
+    if num_failed > 0 {
+        return Err(format!("{num_failed} jobs failed"));
+    }
+    Ok(())
+}
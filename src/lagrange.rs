@@ -0,0 +1,536 @@
+//! Lagrange points and the effective potential of two-body dominated configurations, e.g. a galaxy
+//! and a satellite. In the frame co-rotating with the pair, Φ_eff = Φ - ½ ω² R², where R is the
+//! distance from the rotation axis through the pair's center of mass. Lagrange points are where its
+//! gradient vanishes. Φ and its gradient come from field probes, so this works under any force model.
+
+use std::path::Path;
+
+use lin_alg::f64::Vec3;
+use plotters::prelude::{
+    BitMapBackend, ChartBuilder, Circle, Color, HSLColor, IntoDrawingArea, Rectangle, Text, BLACK,
+    RED, WHITE,
+};
+use rayon::prelude::*;
+
+use crate::{
+    probe::Field,
+    properties::{plot_multi, PlotOutput},
+    units::KPC_MYR_PER_KM_S,
+    Body,
+};
+
+const MAX_CLUSTER_ITERS: usize = 50;
+/// Below this portion of the total mass, the secondary doesn't matter; there's no binary.
+const MIN_MASS_FRAC: f64 = 1e-3;
+/// Samples along the axis when bracketing L1-L3.
+const N_SCAN: usize = 200;
+/// Effective potential map resolution, per side.
+const N_GRID: usize = 50;
+/// Map half-width, as a multiple of the separation. Covers L2 and L3.
+const MAP_HALF_WIDTH: f64 = 1.6;
+const NEWTON_ITERS: usize = 40;
+
+/// A mass concentration, e.g. a galaxy, or satellite.
+#[derive(Clone, Debug)]
+pub struct Clump {
+    /// M☉
+    pub mass: f64,
+    /// Center of mass. kpc.
+    pub posit: Vec3,
+    /// kpc/Myr
+    pub vel: Vec3,
+    /// About `posit`. kpc.
+    pub r_half: f64,
+    /// Indices into the bodies.
+    pub members: Vec<usize>,
+}
+
+impl Clump {
+    fn new(bodies: &[Body], members: Vec<usize>) -> Option<Self> {
+        let mass: f64 = members.iter().map(|i| bodies[*i].mass).sum();
+        if mass <= 0. {
+            return None;
+        }
+
+        let weighted = |f: fn(&Body) -> Vec3| {
+            members.iter().fold(Vec3::new_zero(), |acc, i| {
+                acc + f(&bodies[*i]) * bodies[*i].mass
+            }) / mass
+        };
+        let posit = weighted(|b| b.posit);
+        let vel = weighted(|b| b.vel);
+
+        let mut radii: Vec<(f64, f64)> = members
+            .iter()
+            .map(|i| ((bodies[*i].posit - posit).magnitude(), bodies[*i].mass))
+            .collect();
+        radii.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut cum = 0.;
+        let r_half = radii
+            .iter()
+            .find(|(_, m)| {
+                cum += m;
+                cum >= mass / 2.
+            })
+            .map(|(r, _)| *r)
+            .unwrap_or_default();
+
+        Some(Self {
+            mass,
+            posit,
+            vel,
+            r_half,
+            members,
+        })
+    }
+}
+
+/// The two dominant mass concentrations.
+#[derive(Clone, Debug)]
+pub struct Binary {
+    /// The more massive.
+    pub primary: Clump,
+    pub secondary: Clump,
+}
+
+impl Binary {
+    /// Split the bodies into two clumps, by mass-weighted 2-means clustering. Distances are scaled
+    /// by each clump's half-mass radius, so a compact satellite doesn't claim the host's outskirts.
+    /// `None` if the result isn't two distinct clumps, e.g. for a single galaxy.
+    pub fn from_bodies(bodies: &[Body]) -> Option<Self> {
+        if bodies.len() < 2 {
+            return None;
+        }
+        let n = bodies.len();
+
+        // Seed the second center at the body farthest from the center of mass; for a satellite,
+        // likely in or beyond it.
+        let com = Clump::new(bodies, (0..n).collect())?.posit;
+        let (far, _) = bodies
+            .iter()
+            .map(|b| (b.posit - com).magnitude_squared())
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        let mut centers = [com, bodies[far].posit];
+        let mut scales = [1., 1.];
+        let mut assignment = vec![0; n];
+
+        let mut clumps = None;
+        for i in 0..MAX_CLUSTER_ITERS {
+            let next: Vec<usize> = bodies
+                .par_iter()
+                .map(|b| {
+                    let d0 = (b.posit - centers[0]).magnitude() / scales[0];
+                    let d1 = (b.posit - centers[1]).magnitude() / scales[1];
+                    usize::from(d1 < d0)
+                })
+                .collect();
+
+            let converged = i > 0 && next == assignment;
+            assignment = next;
+
+            let members = |k| (0..n).filter(|i| assignment[*i] == k).collect();
+            let (a, b) = (
+                Clump::new(bodies, members(0))?,
+                Clump::new(bodies, members(1))?,
+            );
+
+            let sep = (b.posit - a.posit).magnitude();
+            centers = [a.posit, b.posit];
+            scales = [
+                a.r_half.max(sep * 1e-3).max(f64::EPSILON),
+                b.r_half.max(sep * 1e-3).max(f64::EPSILON),
+            ];
+            clumps = Some((a, b));
+
+            if converged {
+                break;
+            }
+        }
+
+        let (a, b) = clumps?;
+        let (primary, secondary) = if a.mass >= b.mass { (a, b) } else { (b, a) };
+
+        if secondary.mass < MIN_MASS_FRAC * (primary.mass + secondary.mass) {
+            return None;
+        }
+        // A single system, split in two: The halves overlap.
+        if (secondary.posit - primary.posit).magnitude() < primary.r_half + secondary.r_half {
+            return None;
+        }
+
+        Some(Self { primary, secondary })
+    }
+
+    /// kpc
+    pub fn separation(&self) -> f64 {
+        (self.secondary.posit - self.primary.posit).magnitude()
+    }
+
+    pub fn com(&self) -> Vec3 {
+        (self.primary.posit * self.primary.mass + self.secondary.posit * self.secondary.mass)
+            / (self.primary.mass + self.secondary.mass)
+    }
+}
+
+/// The frame co-rotating with a binary.
+#[derive(Clone, Debug)]
+pub struct RotatingFrame {
+    /// The pair's center of mass, on the rotation axis. kpc.
+    pub origin: Vec3,
+    /// Unit vector from the primary to the secondary.
+    pub x: Vec3,
+    /// In the orbital plane, in the secondary's direction of motion.
+    pub y: Vec3,
+    /// Along the orbital angular momentum; the rotation axis.
+    pub z: Vec3,
+    /// rad/Myr
+    pub ω: f64,
+}
+
+impl RotatingFrame {
+    /// The angular speed is that of a circular orbit at the current separation, under the probed
+    /// field; this is the restricted three-body assumption. The orbital plane is from the relative
+    /// velocity, or the XY plane if the pair has no relative tangential motion.
+    pub fn new(binary: &Binary, field: &Field, bodies: &[Body]) -> Option<Self> {
+        let sep = binary.separation();
+        if sep <= f64::EPSILON {
+            return None;
+        }
+        let x = (binary.secondary.posit - binary.primary.posit) / sep;
+
+        let ang_mom = x.cross(binary.secondary.vel - binary.primary.vel);
+        let z = if ang_mom.magnitude() > f64::EPSILON {
+            ang_mom.to_normalized()
+        } else {
+            let z_axis = Vec3::new(0., 0., 1.);
+            let perp = z_axis - x * x.dot(z_axis);
+            if perp.magnitude() > 1e-6 {
+                perp.to_normalized()
+            } else {
+                Vec3::new(0., 1., 0.).cross(x).to_normalized()
+            }
+        };
+        let y = z.cross(x);
+
+        // Each clump's mass-weighted mean acceleration. Self-forces cancel, leaving the pull of the
+        // other; probing at the centers alone is dominated by graininess.
+        let acc_mean = |clump: &Clump| {
+            field
+                .acc_bodies(&clump.members)
+                .iter()
+                .zip(&clump.members)
+                .fold(Vec3::new_zero(), |acc, (a, i)| acc + *a * bodies[*i].mass)
+                / clump.mass
+        };
+
+        // Relative acceleration of the pair, along the line between them.
+        let acc_rel = acc_mean(&binary.secondary) - acc_mean(&binary.primary);
+        let ω_sq = -acc_rel.dot(x) / sep;
+        if ω_sq <= 0. {
+            return None;
+        }
+
+        Some(Self {
+            origin: binary.com(),
+            x,
+            y,
+            z,
+            ω: ω_sq.sqrt(),
+        })
+    }
+
+    /// Offset from the rotation axis. kpc.
+    fn cyl_offset(&self, posit: Vec3) -> Vec3 {
+        let rel = posit - self.origin;
+        rel - self.z * rel.dot(self.z)
+    }
+
+    /// A position in the orbital plane, from in-plane coordinates. kpc.
+    pub fn in_plane(&self, u: f64, v: f64) -> Vec3 {
+        self.origin + self.x * u + self.y * v
+    }
+
+    /// Gradient of the effective potential, negated: Gravity, plus centrifugal. kpc/Myr².
+    pub fn acc_eff(&self, field: &Field, posit: Vec3) -> Vec3 {
+        field.acc(posit) + self.cyl_offset(posit) * self.ω.powi(2)
+    }
+
+    /// kpc²/Myr²
+    pub fn potential_eff(&self, field: &Field, posit: Vec3) -> f64 {
+        field.potential(posit) - 0.5 * self.ω.powi(2) * self.cyl_offset(posit).magnitude_squared()
+    }
+}
+
+/// The first root of `f` between `from` and `to`, where it changes sign in the direction given by
+/// `rising`, in scan order. Bracketed by sampling, then refined by bisection.
+fn first_crossing(f: impl Fn(f64) -> f64, from: f64, to: f64, rising: bool) -> Option<f64> {
+    let step = (to - from) / N_SCAN as f64;
+    let crosses = |a: f64, b: f64| {
+        if rising {
+            a < 0. && b >= 0.
+        } else {
+            a > 0. && b <= 0.
+        }
+    };
+
+    let mut prev = f(from);
+    for i in 1..=N_SCAN {
+        let t = from + step * i as f64;
+        let val = f(t);
+
+        if crosses(prev, val) {
+            let (mut lo, mut hi) = (t - step, t);
+            for _ in 0..50 {
+                let mid = (lo + hi) / 2.;
+                if crosses(f(lo), f(mid)) {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            return Some((lo + hi) / 2.);
+        }
+        prev = val;
+    }
+    None
+}
+
+/// Refine a guess in the orbital plane to where the effective acceleration vanishes, by Newton's
+/// method with a finite-difference Jacobian. `None` if it doesn't converge near the guess.
+fn refine_in_plane(
+    field: &Field,
+    frame: &RotatingFrame,
+    guess: (f64, f64),
+    sep: f64,
+) -> Option<Vec3> {
+    let h = sep * 1e-3;
+    let g = |u: f64, v: f64| {
+        let a = frame.acc_eff(field, frame.in_plane(u, v));
+        (a.dot(frame.x), a.dot(frame.y))
+    };
+
+    let (mut u, mut v) = guess;
+    for _ in 0..NEWTON_ITERS {
+        let (gu, gv) = g(u, v);
+
+        let (gu_du, gv_du) = {
+            let (p, m) = (g(u + h, v), g(u - h, v));
+            ((p.0 - m.0) / (2. * h), (p.1 - m.1) / (2. * h))
+        };
+        let (gu_dv, gv_dv) = {
+            let (p, m) = (g(u, v + h), g(u, v - h));
+            ((p.0 - m.0) / (2. * h), (p.1 - m.1) / (2. * h))
+        };
+
+        let det = gu_du * gv_dv - gu_dv * gv_du;
+        if det.abs() < f64::MIN_POSITIVE {
+            return None;
+        }
+        let du = (gu * gv_dv - gv * gu_dv) / det;
+        let dv = (gv * gu_du - gu * gv_du) / det;
+        u -= du;
+        v -= dv;
+
+        if (u - guess.0).hypot(v - guess.1) > sep {
+            return None;
+        }
+        if du.hypot(dv) < sep * 1e-7 {
+            return Some(frame.in_plane(u, v));
+        }
+    }
+    None
+}
+
+/// L1 through L5. L1 is between the pair, L2 beyond the secondary, and L3 beyond the primary. L4
+/// leads the secondary, and L5 trails it. `None` for points not found, e.g. if the secondary is
+/// within its own tidal radius of the primary.
+pub fn lagrange_points(field: &Field, frame: &RotatingFrame, binary: &Binary) -> [Option<Vec3>; 5] {
+    let sep = binary.separation();
+    let s_primary = (binary.primary.posit - frame.origin).dot(frame.x);
+    let s_secondary = (binary.secondary.posit - frame.origin).dot(frame.x);
+
+    // Effective acceleration along the axis; positive is toward the secondary.
+    let f = |s: f64| frame.acc_eff(field, frame.in_plane(s, 0.)).dot(frame.x);
+    // Skip each center; the field vanishes there too.
+    let δ = sep * 0.02;
+
+    let on_axis = |s: Option<f64>| s.map(|s| frame.in_plane(s, 0.));
+    let l1 = on_axis(first_crossing(f, s_primary + δ, s_secondary - δ, true));
+    let l2 = on_axis(first_crossing(f, s_secondary + δ, s_secondary + sep, true));
+    let l3 = on_axis(first_crossing(
+        f,
+        s_primary - δ,
+        s_primary - 2. * sep,
+        false,
+    ));
+
+    // L4 and L5 start from the equilateral points of the point-mass problem.
+    let u_tri = s_primary + sep / 2.;
+    let v_tri = sep * 3_f64.sqrt() / 2.;
+    let l4 = refine_in_plane(field, frame, (u_tri, v_tri), sep);
+    let l5 = refine_in_plane(field, frame, (u_tri, -v_tri), sep);
+
+    [l1, l2, l3, l4, l5]
+}
+
+/// Plot the effective potential in the orbital plane as a heat map, marking the pair and their
+/// Lagrange points. Colors are by rank, so the deep wells around each body don't wash out the
+/// saddles.
+pub fn plot_effective_potential(
+    path: &Path,
+    field: &Field,
+    frame: &RotatingFrame,
+    binary: &Binary,
+    points: &[Option<Vec3>; 5],
+    title: &str,
+) {
+    let half_width = binary.separation() * MAP_HALF_WIDTH;
+    let cell = 2. * half_width / N_GRID as f64;
+    let coords: Vec<f64> = (0..N_GRID)
+        .map(|i| -half_width + (i as f64 + 0.5) * cell)
+        .collect();
+
+    let grid: Vec<(f64, f64)> = coords
+        .iter()
+        .flat_map(|u| coords.iter().map(move |v| (*u, *v)))
+        .collect();
+    let potential: Vec<f64> = grid
+        .par_iter()
+        .map(|(u, v)| frame.potential_eff(field, frame.in_plane(*u, *v)))
+        .collect();
+
+    let mut sorted = potential.clone();
+    sorted.sort_by(f64::total_cmp);
+    let rank = |val: f64| sorted.partition_point(|p| *p < val) as f64 / sorted.len() as f64;
+
+    let root = BitMapBackend::new(path, (800, 800)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(-half_width..half_width, -half_width..half_width)
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .x_desc("Along the pair (kpc)")
+        .y_desc("Direction of motion (kpc)")
+        .draw()
+        .unwrap();
+
+    // Darker is deeper.
+    let cells = grid.iter().zip(&potential).map(|((u, v), phi)| {
+        let color = HSLColor(0.6, 0.7, 0.15 + 0.8 * rank(*phi));
+        Rectangle::new(
+            [
+                (u - cell / 2., v - cell / 2.),
+                (u + cell / 2., v + cell / 2.),
+            ],
+            color.filled(),
+        )
+    });
+    chart.draw_series(cells).unwrap();
+
+    let to_plane = |p: Vec3| {
+        let rel = p - frame.origin;
+        (rel.dot(frame.x), rel.dot(frame.y))
+    };
+
+    let bodies = [&binary.primary, &binary.secondary].map(|c| to_plane(c.posit));
+    chart
+        .draw_series(bodies.iter().map(|p| Circle::new(*p, 6, RED.filled())))
+        .unwrap();
+
+    for (i, point) in points.iter().enumerate() {
+        let Some(point) = point else {
+            continue;
+        };
+        let p = to_plane(*point);
+        chart
+            .draw_series([Circle::new(p, 4, BLACK.filled())])
+            .unwrap();
+        chart
+            .draw_series([Text::new(format!("L{}", i + 1), p, ("sans-serif", 16))])
+            .unwrap();
+    }
+}
+
+/// Find the binary, print its Lagrange points, and plot the effective potential in the orbital
+/// plane, and along the axis through the pair. Returns `false` if there's no binary.
+pub fn plot_lagrange(out: &PlotOutput, field: &Field, bodies: &[Body], desc: &str) -> bool {
+    let Some(binary) = Binary::from_bodies(bodies) else {
+        eprintln!("No two-body dominated configuration found.");
+        return false;
+    };
+    let Some(frame) = RotatingFrame::new(&binary, field, bodies) else {
+        eprintln!("The pair isn't bound under this field; no co-rotating frame.");
+        return false;
+    };
+
+    let sep = binary.separation();
+    let points = lagrange_points(field, &frame, &binary);
+
+    println!(
+        "\nBinary: M1: {:.3e} M☉  M2: {:.3e} M☉ ({} bodies)  Separation: {sep:.2} kpc  \
+        Period: {:.0} Myr",
+        binary.primary.mass,
+        binary.secondary.mass,
+        binary.secondary.members.len(),
+        std::f64::consts::TAU / frame.ω,
+    );
+    for (i, point) in points.iter().enumerate() {
+        match point {
+            Some(p) => {
+                let dist = (*p - binary.secondary.posit).magnitude();
+                println!(
+                    "L{}: ({:.2}, {:.2}, {:.2}) kpc; {dist:.2} kpc from the secondary",
+                    i + 1,
+                    p.x,
+                    p.y,
+                    p.z
+                );
+            }
+            None => println!("L{}: Not found", i + 1),
+        }
+    }
+
+    if let Some(path) = out.path(&format!("lagrange_{desc}")) {
+        plot_effective_potential(
+            &path,
+            field,
+            &frame,
+            &binary,
+            &points,
+            &format!("Effective potential of {desc}, co-rotating"),
+        );
+    }
+
+    // Along the axis through the pair.
+    const N_AXIS: usize = 200;
+    let half_width = sep * MAP_HALF_WIDTH;
+    let axis: Vec<(f64, f64)> = (0..N_AXIS)
+        .into_par_iter()
+        .map(|i| {
+            let s = -half_width + 2. * half_width * i as f64 / (N_AXIS - 1) as f64;
+            let phi = frame.potential_eff(field, frame.in_plane(s, 0.));
+            (s, phi / KPC_MYR_PER_KM_S.powi(2))
+        })
+        .collect();
+
+    plot_multi(
+        out,
+        &[("Φ_eff", &axis)],
+        "Along the pair (kpc)",
+        "Φ_eff (km²/s²)",
+        &format!("Effective potential of {desc}, along the pair"),
+        &format!("lagrange_axis_{desc}"),
+    );
+
+    true
+}
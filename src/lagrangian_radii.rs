@@ -0,0 +1,156 @@
+//! Lagrangian radii: The radii enclosing fixed portions of the disk's mass, over a run. A shrinking
+//! inner radius indicates core collapse, and growing outer ones, disk spreading; in an equilibrium
+//! disk, they stay near constant. This is a standard check for unphysical evolution, e.g. from the
+//! shell model, or too little softening.
+//!
+//! Radii are about the disk's center of mass, so drift of the system as a whole doesn't show.
+
+use std::{fmt, fmt::Formatter, mem};
+
+use crate::{
+    body_creation::Component,
+    properties::{center_of_mass, plot_multi, PlotOutput},
+    Body, State,
+};
+
+/// Portions of disk mass enclosed. One per plot color.
+pub const MASS_FRACTIONS: [f64; 5] = [0.1, 0.25, 0.5, 0.75, 0.9];
+
+pub struct LagrangianRadiiHistory {
+    /// Myr
+    pub times: Vec<f64>,
+    /// kpc. Outer: snapshot. Inner: mass fraction, as in `MASS_FRACTIONS`.
+    pub radii: Vec<[f64; MASS_FRACTIONS.len()]>,
+}
+
+impl fmt::Display for LagrangianRadiiHistory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (Some(first), Some(last)) = (self.radii.first(), self.radii.last()) else {
+            return writeln!(f, "No Lagrangian radii");
+        };
+
+        writeln!(
+            f,
+            "Lagrangian radii of the disk over {} snapshots:",
+            self.times.len()
+        )?;
+        for (i, portion) in MASS_FRACTIONS.iter().enumerate() {
+            let change = if first[i] > 0. {
+                (last[i] / first[i] - 1.) * 100.
+            } else {
+                0.
+            };
+            writeln!(
+                f,
+                "{:>3.0}%: {:.3} → {:.3} kpc ({change:+.1}%)",
+                portion * 100.,
+                first[i],
+                last[i]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The radii, about the bodies' center of mass, enclosing each portion of their total mass. kpc.
+pub fn lagrangian_radii(bodies: &[Body]) -> [f64; MASS_FRACTIONS.len()] {
+    let mut result = [0.; MASS_FRACTIONS.len()];
+
+    let (com, _) = center_of_mass(bodies);
+    let mut dists: Vec<(f64, f64)> = bodies
+        .iter()
+        .map(|b| ((b.posit - com).magnitude(), b.mass))
+        .collect();
+    dists.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mass_total: f64 = dists.iter().map(|(_, m)| m).sum();
+    if mass_total <= 0. {
+        return result;
+    }
+
+    let mut enclosed = 0.;
+    let mut i = 0;
+    for (r, mass) in &dists {
+        enclosed += mass;
+        while i < MASS_FRACTIONS.len() && enclosed >= MASS_FRACTIONS[i] * mass_total {
+            result[i] = *r;
+            i += 1;
+        }
+    }
+
+    result
+}
+
+/// Compute Lagrangian radii of the disk at each snapshot of the current run, from memory or a
+/// stream. If bodies' components aren't known, e.g. after loading snapshots, all bodies are used.
+pub fn lagrangian_radii_history(state: &mut State) -> Option<LagrangianRadiiHistory> {
+    let body_components = mem::take(&mut state.body_components);
+
+    let mut times = Vec::new();
+    let mut radii = Vec::new();
+
+    for i in 0..state.num_snapshots() {
+        // Snapshots of a subset of bodies are skipped; they don't have all the mass.
+        let r = state.with_snapshot(i, |snap, body_masses| {
+            snap.is_full().then(|| {
+                let bodies = snap.bodies(body_masses);
+                let disk: Vec<Body> = if body_components.is_empty() {
+                    bodies
+                } else {
+                    bodies
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(k, _)| {
+                            body_components.get(snap.body_id(*k)) == Some(&Component::Disk)
+                        })
+                        .map(|(_, b)| b)
+                        .collect()
+                };
+                (snap.time as f64, lagrangian_radii(&disk))
+            })
+        });
+
+        if let Some(Some((time, r))) = r {
+            times.push(time);
+            radii.push(r);
+        }
+    }
+
+    state.body_components = body_components;
+
+    (!times.is_empty()).then_some(LagrangianRadiiHistory { times, radii })
+}
+
+/// Plot each Lagrangian radius vs time.
+pub fn plot_lagrangian_radii(out: &PlotOutput, history: &LagrangianRadiiHistory, desc: &str) {
+    let labels: Vec<String> = MASS_FRACTIONS
+        .iter()
+        .map(|p| format!("{:.0}% of mass", p * 100.))
+        .collect();
+
+    let series: Vec<Vec<(f64, f64)>> = (0..MASS_FRACTIONS.len())
+        .map(|i| {
+            history
+                .times
+                .iter()
+                .zip(&history.radii)
+                .map(|(t, r)| (*t, r[i]))
+                .collect()
+        })
+        .collect();
+
+    let series_ref: Vec<(&str, &[(f64, f64)])> = labels
+        .iter()
+        .zip(&series)
+        .map(|(l, s)| (l.as_str(), s.as_slice()))
+        .collect();
+
+    plot_multi(
+        out,
+        &series_ref,
+        "t (Myr)",
+        "R (kpc)",
+        &format!("Lagrangian radii of the disk, {desc}"),
+        &format!("lagrangian_radii_{desc}"),
+    );
+}
@@ -2,56 +2,142 @@
 #![allow(non_ascii_idents)]
 
 use std::{
-    io,
+    env, fs, io, mem,
     path::{Path, PathBuf},
+    process,
     str::FromStr,
-    sync::Arc,
+    sync::{mpsc::Sender, Arc},
     time::Instant,
 };
 
 use barnes_hut::{BhConfig, BodyModel, Cube, Node, Tree};
-use bincode::{Decode, Encode};
 #[cfg(feature = "cuda")]
-use cudarc::{driver::{CudaContext, CudaStream, CudaModule}, nvrtc::Ptx};
-use galaxy_data::GalaxyModel;
-use grav_shell::{GravShell, MAX_SHELL_R};
-use lin_alg::f64::Vec3;
+use cudarc::driver::{CudaModule, CudaStream};
+use galaxy_data::{BuiltinGalaxy, GalaxyCatalog, GalaxyModel};
+use grav_shell::{GravShell, ShellAnisotropy, ShellSpeed, MAX_SHELL_R};
+use lin_alg::{
+    f32::{Quaternion, Vec3 as Vec3f32},
+    f64::Vec3,
+};
 use rand::Rng;
 use rayon::prelude::*;
 
 use crate::{
-    accel::{acc_newton_inner_with_mond, MondFn},
-    body_creation::GalaxyDescrip,
+    accel::MondFn,
+    body_creation::{
+        BodySampling, CentralModel, Component, GalaxyDescrip, RingRefinement, VelocityCheck,
+        VelocityInit,
+    },
+    build_job::BuildJob,
     charge::coulomb_force,
+    cluster::ClusterParams,
+    config_history::ConfigHistory,
+    config_watch::ConfigWatch,
+    descrip_editor::DescripParams,
+    diagnostics::Diagnostics,
+    export::ExportSettings,
+    flythrough::CameraPath,
     gaussian::GaussianShell,
+    gpu::GpuConfig,
     grav_shell::COEFF_C,
-    integrate::integrate_rk4,
-    playback::{GravShellSnapshot, SnapShot},
-    render::render,
-    units::{A0_MOND, C},
+    hooks::{StepHook, StepInfo, StopCriteria},
+    ic_preview::IcPreview,
+    injection::Injection,
+    integrate::{
+        integrate_block, integrate_hermite4, integrate_leapfrog, integrate_rk4, integrate_rk4_many,
+        integrate_yoshida4, BlockLevels, BlockTimesteps, IntegratorKind,
+    },
+    mass_refinement::MassRefinement,
+    notifications::Notifications,
+    orbits::Inspector,
+    overlay::RunLabels,
+    playback::{BodyColorMode, SnapShot, SnapshotDecimation, SnapshotSubset, TrailHistory},
+    probe::FieldRefs,
+    properties::PlotOutput,
+    pv_diagram::Slit,
+    qumond::QumondGrid,
+    ray_bending::MicrolensParams,
+    render::{render, LightingSettings, SHELL_OPACITY},
+    rot_model::{self, RotCurveModel},
+    scf::{ScfConfig, ScfExpansion},
+    shapiro::ShapiroParams,
+    shell_geometry::ShellGeometry,
+    snapshot_stream::{RawSnapshot, RunMeta, SnapshotCache, SnapshotSink},
+    stereo::StereoMode,
+    superluminal::{SuperluminalAction, SuperluminalGuard},
+    tidal::TidalHistory,
+    units::{A0_MOND, C, KPC_MYR_PER_KM_S},
+    zoom::{ZoomBoundary, ZoomRegion},
 };
 
 mod accel;
+mod asym_drift;
 mod body_creation;
+mod build_job;
 mod cdm;
 mod fluid_dynamics;
+mod flythrough;
+mod force_law;
 // mod fmm_gpt;
 mod charge;
+mod cluster;
+mod config_history;
+mod config_migration;
+mod config_text;
+mod config_watch;
+mod convergence;
+mod descrip_editor;
+mod diagnostics;
+mod doppler;
+mod ensemble;
+mod export;
 mod galaxy_data;
 mod gaussian;
 mod gem;
-#[cfg(feature = "cuda")]
 mod gpu;
 mod grav_shell;
+mod hooks;
+mod ic_preview;
 mod image_parsing;
+mod injection;
+mod integrals;
 mod integrate;
+mod jobs;
+mod lagrange;
+mod lagrangian_radii;
+mod mass_flux;
+mod mass_refinement;
+mod model_compare;
+mod notifications;
+mod obs_uncertainty;
+mod orbits;
+mod overlay;
 mod playback;
+mod preview;
+mod probe;
 mod properties;
+mod pv_diagram;
+mod qumond;
 mod ray_bending;
 mod render;
+mod report;
+mod retarded;
+mod rot_compare;
+mod rot_model;
+mod scf;
+mod shapiro;
+mod shell_geometry;
+#[cfg(feature = "shell_interaction")]
+mod shell_interaction;
+mod snapshot_stream;
+mod sparc;
+mod stereo;
+mod superluminal;
+mod tidal;
 mod ui;
 mod units;
 mod util;
+mod zoom;
 // todo: Try a Galaxy filament simulation; large scale CDM theory. Can we get filaments without CDM?
 // todo: - try an earth-perspective visualization and analysis. From the perspective of earth, validate these
 // todo galaxies vs the images we get.
@@ -83,8 +169,14 @@ mod util;
 const BOUNDING_BOX_PAD: f64 = 0.;
 const BB_GEN_RATIO: usize = 1;
 
+/// The config loaded at startup.
 const SAVE_FILE: &str = "config.grav";
-const DEFAULT_SNAPSHOT_FILE: &str = "snapshot.grav";
+// Named configs and snapshots have separate default locations.
+const CONFIG_DIR: &str = "configs";
+const DEFAULT_SNAPSHOT_FILE: &str = "snapshots/snapshot.grav";
+/// Stores the paths of recently saved or loaded configs.
+const RECENT_CONFIGS_FILE: &str = "recent_configs.grav";
+const MAX_RECENT_CONFIGS: usize = 8;
 
 const DISK_RING_PORTION: usize = 10;
 const BULGE_RING_PORTION: usize = 5;
@@ -97,8 +189,20 @@ pub enum ComputationDevice {
     Gpu((Arc<CudaStream>, Arc<CudaModule>)),
 }
 
+impl ComputationDevice {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Cpu => "CPU",
+            #[cfg(feature = "cuda")]
+            Self::Gpu(_) => "GPU",
+        }
+        .to_owned()
+    }
+}
+
 // todo: Custom Bincode config that only contains the fields you customize directly.
-#[derive(Encode, Decode)]
+/// Note: Encoding and decoding are implemented in `config_migration`; update them when changing this.
+#[derive(Clone, Debug)]
 pub struct Config {
     num_timesteps: usize,
     dt_integration_max: f64,
@@ -119,6 +223,51 @@ pub struct Config {
     v_scaler: f64,
     /// Use instantaneous Newtonian forces instead of tree code.
     skip_tree: bool,
+    /// How to place bodies within each component; bands around the data radii, or sampling directly
+    /// from the cumulative mass profile.
+    body_sampling: BodySampling,
+    /// How to model the innermost region of each component.
+    central_model: CentralModel,
+    /// Number of bodies making up the core, when using `CentralModel::PlummerCore`.
+    num_bodies_core: usize,
+    /// Subdivide the data-driven annuli where the density profile is steep.
+    refine_rings: bool,
+    ring_refinement: RingRefinement,
+    /// Set initial speeds from the observed rotation curve, or from the generated bodies' field.
+    velocity_init: VelocityInit,
+    /// Compare generated speeds to the circular velocity from enclosed mass, and optionally correct.
+    velocity_check: VelocityCheck,
+    /// Optional conditions for ending a build early.
+    stop_criteria: StopCriteria,
+    /// Seed for generating bodies. If `None`, each generation is different.
+    seed: Option<u64>,
+    /// Flag, and optionally clamp or remove, bodies moving faster than a portion of C.
+    superluminal: SuperluminalGuard,
+    /// Direction-dependent shell amplitude, for the causal shell model.
+    shell_anisotropy: ShellAnisotropy,
+    /// Shell propagation speed as a function of radius, for the causal shell model.
+    shell_speed: ShellSpeed,
+    /// Spherical shells, or rings in the disk plane.
+    shell_geometry: ShellGeometry,
+    /// Where plots are written, and whether to write them.
+    plot_output: PlotOutput,
+    /// If set, compute instantaneous forces with a basis function expansion, instead of the tree.
+    /// For near-equilibrium spheroids.
+    scf: Option<ScfConfig>,
+    /// CUDA kernel launch settings.
+    gpu: GpuConfig,
+    /// The scheme used to advance bodies each step.
+    integrator: IntegratorKind,
+    /// If set, and integrating with leapfrog, each body steps at its own power-of-two fraction of
+    /// dt.
+    block_timesteps: Option<BlockTimesteps>,
+    /// Store a subset of bodies in most snapshots, for large runs.
+    snapshot_decimation: SnapshotDecimation,
+    /// Sample energy and angular momentum every this many steps. Snapshots taken on the same step
+    /// include them. 0 to disable.
+    diagnostics_ratio: usize,
+    /// If set, split bodies into lighter children at set times, for finer mass resolution.
+    mass_refinement: Option<MassRefinement>,
 }
 
 impl Default for Config {
@@ -150,14 +299,39 @@ impl Default for Config {
             },
             v_scaler: 1.0,
             skip_tree: false,
+            body_sampling: Default::default(),
+            central_model: Default::default(),
+            num_bodies_core: 50,
+            refine_rings: false,
+            ring_refinement: Default::default(),
+            velocity_init: Default::default(),
+            velocity_check: Default::default(),
+            stop_criteria: Default::default(),
+            seed: None,
+            superluminal: Default::default(),
+            shell_anisotropy: Default::default(),
+            shell_speed: Default::default(),
+            shell_geometry: Default::default(),
+            plot_output: Default::default(),
+            scf: None,
+            gpu: Default::default(),
+            integrator: Default::default(),
+            block_timesteps: None,
+            snapshot_decimation: Default::default(),
+            diagnostics_ratio: 100,
+            mass_refinement: None,
         }
     }
 }
 
 impl Config {
-    /// Load the config.w
-    pub fn load(&mut self, path: &Path) -> io::Result<Self> {
-        util::load(path)
+    /// Load the config, migrating from older versions A/R.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        config_migration::load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        config_migration::save(path, self)
     }
 
     /// Width for our shells; sets the gaussian C parameter.
@@ -172,8 +346,81 @@ impl Config {
 pub enum ForceModel {
     #[default]
     Newton,
+    /// MOND applied to each source's contribution.
     Mond(MondFn),
+    /// MOND applied to the net Newtonian acceleration on each body. (AQUAL-like)
+    MondNet(MondFn),
+    /// MOND from the field equations, solved on a mesh. See `qumond`.
+    Qumond(MondFn),
     GaussShells,
+    /// A combination of the above, for hybrid hypotheses.
+    Composite(ForceComposition),
+}
+
+impl ForceModel {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Newton => "Newton".to_owned(),
+            Self::Mond(MondFn::Simple) => "MOND simple".to_owned(),
+            Self::Mond(MondFn::Standard) => "MOND".to_owned(),
+            Self::MondNet(MondFn::Simple) => "MOND net simple".to_owned(),
+            Self::MondNet(MondFn::Standard) => "MOND net".to_owned(),
+            Self::Qumond(MondFn::Simple) => "QUMOND simple".to_owned(),
+            Self::Qumond(MondFn::Standard) => "QUMOND".to_owned(),
+            Self::GaussShells => "Causal shells".to_owned(),
+            Self::Composite(c) => c.to_str(),
+        }
+    }
+
+    /// If this model requires propagating gravity shells.
+    pub fn uses_shells(&self) -> bool {
+        matches!(self, Self::GaussShells | Self::Composite(_))
+    }
+
+    /// If this model includes an instantaneous (Newton or MOND) component, computed with the tree
+    /// unless `skip_tree` is set.
+    pub fn uses_instantaneous(&self) -> bool {
+        match self {
+            Self::Newton | Self::Mond(_) | Self::MondNet(_) => true,
+            Self::Qumond(_) | Self::GaussShells => false,
+            Self::Composite(c) => c.uses_instantaneous(),
+        }
+    }
+}
+
+/// Ways of composing the shell model with instantaneous ones.
+#[derive(Copy, Clone, PartialEq)]
+pub enum ForceComposition {
+    /// Shells for the baryonic, causal part, with a MOND correction applied to their acceleration.
+    ShellsWithMond(MondFn),
+    /// Newtonian, plus the shell model's deviation from Newton, scaled by `weight`. A weight of 0 is
+    /// Newton; 1 is shells.
+    NewtonPlusShells { weight: f64 },
+}
+
+impl ForceComposition {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::ShellsWithMond(_) => "Shells + MOND",
+            Self::NewtonPlusShells { .. } => "Newton + shell Δ",
+        }
+        .to_owned()
+    }
+
+    pub fn uses_instantaneous(&self) -> bool {
+        matches!(self, Self::NewtonPlusShells { .. })
+    }
+
+    /// Combine the component accelerations. `acc_newton` is only evaluated if required.
+    pub fn combine(&self, acc_shells: Vec3, acc_newton: impl Fn() -> Vec3) -> Vec3 {
+        match self {
+            Self::ShellsWithMond(mond_fn) => accel::apply_mond(acc_shells, *mond_fn),
+            Self::NewtonPlusShells { weight } => {
+                let acc_newton = acc_newton();
+                acc_newton + (acc_shells - acc_newton) * *weight
+            }
+        }
+    }
 }
 
 pub struct StateUi {
@@ -186,15 +433,135 @@ pub struct StateUi {
     v_scaler_input: String,
     // num_timesteps_input: String,
     add_halo: bool, // todo: A/R
+    /// If set, build a cluster of realizations of the galaxy, vice a single one.
+    cluster: Option<ClusterParams>,
+    /// Galaxies available to build, from built-in data and files found at startup.
+    galaxy_catalog: GalaxyCatalog,
     galaxy_model: GalaxyModel,
     /// For display in the UI. cached.
     galaxy_descrip: GalaxyDescrip,
     draw_tree: bool,
+    /// The config file we save to, and last loaded from.
+    config_path: PathBuf,
+    /// Most recent first.
+    recent_configs: Vec<PathBuf>,
+    /// Changes to the config file made outside the program.
+    config_watch: ConfigWatch,
+    notifications: Notifications,
+    /// Number of runs for ensemble mode.
+    ensemble_runs: usize,
+    /// Microlensing search settings. The lens distance is taken from the galaxy, if known.
+    microlens: MicrolensParams,
+    /// Shapiro delay sight lines.
+    shapiro: ShapiroParams,
+    /// Dimensionless spin of the central mass, for frame dragging. 0 to 1.
+    frame_drag_spin: f64,
+    /// For the E–L_z plot. 0 skips clustering.
+    elz_clusters: usize,
+    /// Wall-clock budget for fast previews. Seconds.
+    preview_budget: f64,
+    /// Write build snapshots to disk as they're taken, and play back from there, vice keeping them
+    /// in memory. For large runs.
+    stream_snapshots: bool,
+    /// Tracer body indices, for snapshot decimation, as typed.
+    tracers_input: String,
+    /// Mass refinement times, as typed.
+    refine_times_input: String,
+    /// For position-velocity diagrams.
+    slit: Slit,
+    /// Estimated two-body relaxation time of the current bodies. Myr. Cached.
+    relaxation_time: Option<f64>,
+    /// Satellite tidal radius at each snapshot, for the viewer overlay. Cached.
+    tidal_history: Option<TidalHistory>,
+    /// Scene units per kpc. Set by fitting the view to the bodies, and adjustable.
+    world_scale: f32,
+    /// Fit the view to the bodies on the next snapshot reset, e.g. after loading.
+    fit_view: bool,
+    show_scale_overlay: bool,
+    /// Reference grid and rings in the galactic plane.
+    show_grid: bool,
+    show_axes: bool,
+    show_hud: bool,
+    run_labels: RunLabels,
+    stereo_mode: StereoMode,
+    /// Between the eyes, seen from the focal point. Degrees.
+    stereo_angle: f32,
+    lighting: LightingSettings,
+    show_lighting: bool,
+    /// Advance through snapshots automatically.
+    playing: bool,
+    /// Snapshots per second.
+    playback_speed: f32,
+    /// Fractional snapshot index, so playback between frames is smooth.
+    playback_pos: f32,
+    camera_path: CameraPath,
+    show_camera_path: bool,
+    /// Add a camera path keyframe at each snapshot, while playing.
+    path_recording: bool,
+    /// Move the camera along the path.
+    path_flying: bool,
+    /// Rendering playback to image files.
+    export: ExportSettings,
+    /// Unapplied edits to the galaxy description. `None` to take them from it.
+    descrip_edit: Option<DescripParams>,
+    show_descrip_editor: bool,
+    /// The selected snapshot's rotation curve, plotted in the UI. X: R (kpc). Y: km/s.
+    rot_curve: Vec<(f64, f64)>,
+    /// The selected snapshot's velocity dispersion, plotted with its rotation curve: [σ_R, σ_φ, σ_z].
+    /// X: R (kpc). Y: km/s.
+    vel_dispersion: [Vec<(f64, f64)>; 3],
+    /// The selected snapshot's potential, from the tree, if `show_escape_vel`. X: R (kpc).
+    /// Y: kpc²/Myr².
+    potential: Vec<(f64, f64)>,
+    show_escape_vel: bool,
+    show_rot_curve: bool,
+    /// Of the current initial conditions; shown over the first snapshot.
+    ic_preview: Option<IcPreview>,
+    show_ic_preview: bool,
+    /// Draw gravity shells as translucent spheres.
+    show_shells: bool,
+    shell_opacity: f32,
+    /// Draw trails through bodies' recent positions.
+    show_trails: bool,
+    /// Snapshots per trail.
+    trail_len: usize,
+    trail_history: TrailHistory,
+    /// What body colors show. Doppler coloring, if enabled, takes precedence.
+    color_mode: BodyColorMode,
+    /// Color bodies by line-of-sight velocity from the camera.
+    doppler_color: bool,
+    /// Doppler colors saturate at ± this. km/s.
+    doppler_v_max: f32,
+    /// Draw bodies at their retarded positions, as seen from the camera.
+    retarded_view: bool,
+    /// A body to add to the run, at the selected snapshot.
+    injection: Injection,
+    show_injection: bool,
+    /// A region to resimulate at a finer timestep.
+    zoom: ZoomRegion,
+    show_zoom: bool,
+    /// An analytic rotation curve, for quick fitting.
+    rot_model: RotCurveModel,
+    show_rot_model: bool,
+    /// A body to show orbital elements for.
+    inspector: Inspector,
+    show_inspector: bool,
+    /// The camera the entities were made for. Stereo copies, retarded positions, and Doppler
+    /// colors are remade when it moves.
+    entities_camera: Option<(Vec3f32, Quaternion)>,
+    /// The camera rides along with this body, by index.
+    ride_body: Option<usize>,
+    /// When riding, look along the body's velocity.
+    ride_vel_frame: bool,
+    /// The camera keeps this body, by index, at the center of the view.
+    follow_body: Option<usize>,
+    /// Where the followed body was last frame. Scene units.
+    follow_target: Option<Vec3f32>,
 }
 
 impl Default for StateUi {
     fn default() -> Self {
-        let galaxy_model = Default::default();
+        let galaxy = BuiltinGalaxy::default();
 
         Self {
             snapshot_selected: Default::default(),
@@ -204,9 +571,79 @@ impl Default for StateUi {
             θ_input: Default::default(),
             v_scaler_input: Default::default(),
             add_halo: Default::default(),
-            galaxy_model,
-            galaxy_descrip: galaxy_model.descrip(),
+            cluster: None,
+            // The catalog, and photometry files, are loaded at startup; not here, so this doesn't
+            // read from disk.
+            galaxy_catalog: Default::default(),
+            galaxy_descrip: galaxy.descrip(),
+            galaxy_model: GalaxyModel::builtin(galaxy),
             draw_tree: false,
+            config_path: PathBuf::from(SAVE_FILE),
+            recent_configs: Vec::new(),
+            config_watch: Default::default(),
+            notifications: Default::default(),
+            ensemble_runs: 8,
+            microlens: Default::default(),
+            shapiro: Default::default(),
+            frame_drag_spin: 0.9,
+            elz_clusters: 0,
+            preview_budget: 30.,
+            stream_snapshots: false,
+            tracers_input: String::new(),
+            refine_times_input: String::new(),
+            slit: Default::default(),
+            relaxation_time: None,
+            tidal_history: None,
+            world_scale: 1.,
+            fit_view: false,
+            show_scale_overlay: true,
+            show_grid: false,
+            show_axes: false,
+            show_hud: true,
+            run_labels: Default::default(),
+            stereo_mode: Default::default(),
+            stereo_angle: 4.,
+            lighting: Default::default(),
+            show_lighting: false,
+            playing: false,
+            playback_speed: 10.,
+            playback_pos: 0.,
+            camera_path: Default::default(),
+            show_camera_path: false,
+            path_recording: false,
+            path_flying: false,
+            export: Default::default(),
+            descrip_edit: None,
+            show_descrip_editor: false,
+            rot_curve: Vec::new(),
+            vel_dispersion: Default::default(),
+            potential: Vec::new(),
+            show_escape_vel: false,
+            show_rot_curve: false,
+            ic_preview: None,
+            show_ic_preview: false,
+            show_shells: false,
+            shell_opacity: SHELL_OPACITY,
+            show_trails: false,
+            trail_len: 20,
+            trail_history: Default::default(),
+            color_mode: Default::default(),
+            doppler_color: false,
+            doppler_v_max: 200.,
+            retarded_view: false,
+            injection: Default::default(),
+            show_injection: false,
+            zoom: Default::default(),
+            show_zoom: false,
+            rot_model: Default::default(),
+            show_rot_model: false,
+            inspector: Default::default(),
+            show_inspector: false,
+            entities_camera: None,
+            ride_body: None,
+            ride_vel_frame: false,
+            follow_body: None,
+            follow_target: None,
         }
     }
 }
@@ -219,53 +656,397 @@ struct State {
     // rays: Vec<GravRay>,
     shells: Vec<GravShell>,
     snapshots: Vec<SnapShot>,
-    /// For rendering; separate from snapshots since it's invariant.
+    /// Each body's mass when it was created, by ID. For rendering, and analysis; separate from
+    /// snapshots, since it's invariant unless bodies are split.
     body_masses: Vec<f32>,
+    /// Each body's galaxy component, by ID, for coloring by population. Not saved with snapshots.
+    body_components: Vec<Component>,
+    /// The ID of each current body: Its index in `body_masses` and `body_components`. These stay
+    /// the same when bodies are removed or added during a build, so snapshots from before and after
+    /// agree on which body is which.
+    body_ids: Vec<u32>,
+    /// If bodies have been removed or split since their IDs were assigned. Snapshots then store
+    /// their own IDs and masses.
+    body_set_changed: bool,
+    /// When playing back a snapshot stream from disk, this is used instead of `snapshots`.
+    snapshot_stream: Option<SnapshotCache>,
     time_elapsed: f64,
     charge_mode: bool, // Likely temporary.
+    /// (time, energy and angular momentum), sampled during builds. For checking conservation.
+    diagnostics: Vec<(f64, Diagnostics)>,
+    /// Run each timestep of `build()`; see the `hooks` module.
+    step_hooks: Vec<Box<dyn StepHook>>,
+    /// Where computations run. Set at startup; the CPU if CUDA is unavailable.
+    dev: ComputationDevice,
+    /// During a zoom resimulation, bodies outside the region follow the parent run's paths.
+    zoom_boundary: Option<ZoomBoundary>,
+    /// Undo and redo of config edits.
+    config_history: ConfigHistory,
+    /// A build running on a worker thread, started from the UI.
+    build_job: Option<BuildJob>,
+    /// Experimental pairwise shell interactions, for the causal shell model.
+    #[cfg(feature = "shell_interaction")]
+    shell_interaction: shell_interaction::InteractionLaw,
 }
 
 impl State {
     fn refresh_bodies(&mut self) {
+        build_job::stop(self);
+
         if self.charge_mode {
             self.bodies = charge::make_particles();
+        } else if let Some(params) = &self.ui.cluster {
+            self.bodies = cluster::make_cluster(&self.ui.galaxy_descrip, &self.config, params);
+            println!(
+                "\nCluster of {} galaxies from {}. {} bodies",
+                params.num_galaxies,
+                self.ui.galaxy_model.to_str(),
+                self.bodies.len()
+            );
         } else {
-            self.bodies = self.ui.galaxy_descrip.make_bodies(
-                self.config.num_bodies_disk,
-                self.config.num_bodies_bulge,
-                self.config.v_scaler,
+            println!(
+                "\n{}:\n{}",
+                self.ui.galaxy_model.to_str(),
+                self.ui.galaxy_descrip
             );
+            self.bodies = self.ui.galaxy_descrip.make_bodies(&self.config);
+
+            if self.config.velocity_init == VelocityInit::SelfConsistent {
+                body_creation::set_velocities_self_consistent(&mut self.bodies, &self.config);
+            }
+
+            if self.config.velocity_check != VelocityCheck::Off {
+                let report = body_creation::check_velocities(
+                    &mut self.bodies,
+                    self.config.velocity_check == VelocityCheck::Correct,
+                );
+                println!("\nVelocity check:\n{report}");
+            }
         }
 
-        self.body_masses = self.bodies.iter().map(|b| b.mass as f32).collect();
+        self.reset_body_ids();
+        self.ui.run_labels = RunLabels {
+            galaxy: if self.charge_mode {
+                "Charges".to_owned()
+            } else if self.ui.cluster.is_some() {
+                format!("{} cluster", self.ui.galaxy_model.to_str())
+            } else {
+                self.ui.galaxy_model.to_str()
+            },
+            force_model: "Initial conditions".to_owned(),
+        };
+        self.ui.relaxation_time = properties::relaxation_time(&self.bodies, Vec3::new_zero());
+        self.ui.tidal_history = None;
+        self.ui.ic_preview = (!self.charge_mode && self.ui.cluster.is_none()).then(|| {
+            IcPreview::new(
+                &self.bodies,
+                rot_model::observed_curve(&self.ui.galaxy_descrip),
+                &self.ui.galaxy_descrip.mass_density_disk,
+            )
+        });
 
         self.time_elapsed = 0.;
         self.snapshots = Vec::new();
+        self.snapshot_stream = None;
+        self.diagnostics = Vec::new();
+        self.record_diagnostics();
+        self.check_virial_ratio();
         self.take_snapshot(0., Vec::new()); // Initial snapshot; t=0.
         self.ui.snapshot_selected = 0;
 
         self.shells = Vec::new();
 
-        let rotation_curve = properties::rotation_curve(&self.bodies, Vec3::new_zero(), C);
         let mass_density = properties::mass_density(&self.bodies, Vec3::new_zero());
-        properties::plot_rotation_curve(&rotation_curve, &self.ui.galaxy_model.to_str());
         // todo: Temp rm; freeze.
-        // properties::plot_mass_density(&mass_density, &self.ui.galaxy_model.to_str());
+        // properties::plot_mass_density(&self.config.plot_output, &mass_density, &self.ui.galaxy_model.to_str());
+    }
+
+    /// Set the config, e.g. after loading one, and sync the UI's text inputs to it.
+    fn apply_config(&mut self, config: Config) {
+        self.config = config;
+
+        self.ui.dt_input = self.config.dt.to_string();
+        self.ui.θ_input = self.config.bh_config.θ.to_string();
+        self.ui.v_scaler_input = self.config.v_scaler.to_string();
+        self.ui.tracers_input = match &self.config.snapshot_decimation.subset {
+            SnapshotSubset::Tracers(ids) => ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => String::new(),
+        };
+        self.ui.refine_times_input = self
+            .config
+            .mass_refinement
+            .as_ref()
+            .map(|r| r.times_text())
+            .unwrap_or_default();
+    }
+
+    fn load_config(&mut self, path: &Path) -> io::Result<()> {
+        let config = Config::load(path)?;
+        self.apply_config(config);
+
+        self.ui.config_path = path.to_owned();
+        self.add_recent_config(path);
+        Ok(())
+    }
+
+    /// Write the config's scalar parameters as text, for editing outside the program, and watch
+    /// the file for changes.
+    fn export_config_text(&mut self, path: &Path) -> io::Result<()> {
+        config_text::save(path, &self.config)?;
+        self.ui.config_watch.watch(path);
+        Ok(())
+    }
+
+    /// Apply parameters from a text config.
+    fn load_config_text(&mut self, path: &Path) -> io::Result<()> {
+        let mut config = self.config.clone();
+        config_text::load(path, &mut config)?;
+        self.apply_config(config);
+        Ok(())
+    }
+
+    /// What's stored with snapshots, to replay the run later.
+    fn run_meta(&self) -> RunMeta {
+        RunMeta {
+            body_masses: self.body_masses.clone(),
+            config: Some(self.config.clone()),
+            labels: Some(self.ui.run_labels.clone()),
+        }
+    }
+
+    /// Save snapshots for playback, as a stream, with the run's config and labels.
+    fn save_snapshots(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        snapshot_stream::write_stream(path, &self.run_meta(), &self.snapshots)
+    }
+
+    /// Load a snapshot stream for playback. The run's config is applied, without rebuilding
+    /// bodies. Returns the number of snapshots.
+    fn load_snapshots(&mut self, path: &Path) -> io::Result<usize> {
+        build_job::stop(self);
+        let name = ui::file_name(path);
+
+        let stream = SnapshotCache::open(path)?;
+        let meta = stream.meta().clone();
+        self.snapshot_stream = Some(stream);
+        self.snapshots = Vec::new();
+
+        self.body_masses = meta.body_masses;
+        self.body_components = Vec::new();
+        if let Some(config) = meta.config {
+            self.apply_config(config);
+        }
+        self.ui.run_labels = meta.labels.unwrap_or(RunLabels {
+            galaxy: name,
+            force_model: "Force model not recorded".to_owned(),
+        });
+
+        // Conservation samples, from the snapshots taken with them. For streams, we only read the
+        // first, as the reference for drift readouts, vice reading the whole file.
+        self.diagnostics = match &self.snapshot_stream {
+            Some(_) => self
+                .with_snapshot(0, |snap, _| snap.diagnostics.map(|d| (snap.time as f64, d)))
+                .flatten()
+                .into_iter()
+                .collect(),
+            None => self
+                .snapshots
+                .iter()
+                .filter_map(|snap| snap.diagnostics.map(|d| (snap.time as f64, d)))
+                .collect(),
+        };
+
+        self.ui.snapshot_selected = 0;
+        self.ui.tidal_history = None;
+        self.ui.fit_view = true;
+
+        Ok(self.num_snapshots())
+    }
+
+    fn save_config(&mut self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        self.config.save(path)?;
+
+        self.ui.config_path = path.to_owned();
+        self.add_recent_config(path);
+        Ok(())
+    }
+
+    fn add_recent_config(&mut self, path: &Path) {
+        let recent = &mut self.ui.recent_configs;
+        recent.retain(|p| p != path);
+        recent.insert(0, path.to_owned());
+        recent.truncate(MAX_RECENT_CONFIGS);
+
+        if let Err(e) = util::save(Path::new(RECENT_CONFIGS_FILE), recent) {
+            self.ui
+                .notifications
+                .warning(format!("Error saving recent configs: {e}"));
+        }
+    }
+
+    /// Run a hook each timestep of later builds, e.g. for custom diagnostics, or stop criteria. See
+    /// the `hooks` module.
+    pub fn add_step_hook(&mut self, hook: impl StepHook + 'static) {
+        self.step_hooks.push(Box::new(hook));
     }
 
     fn remove_far_shells(&mut self) {
         self.shells.retain(|shell| shell.radius <= MAX_SHELL_R);
     }
 
-    fn take_snapshot(&mut self, dt: f64, tree_nodes: Vec<Cube>) {
-        self.snapshots.push(SnapShot {
-            time: self.time_elapsed as f32,
-            body_posits: self.bodies.iter().map(|b| b.posit.into()).collect(),
-            body_accs: self.bodies.iter().map(|b| b.accel.into()).collect(),
-            shells: self.shells.iter().map(GravShellSnapshot::new).collect(),
-            dt: dt as f32,
+    /// Number of snapshots available for playback, from memory or a stream.
+    fn num_snapshots(&self) -> usize {
+        match &self.snapshot_stream {
+            Some(stream) => stream.len(),
+            None => self.snapshots.len(),
+        }
+    }
+
+    /// Run a function on a snapshot and the body masses, from memory or a stream.
+    fn with_snapshot<T>(&mut self, i: usize, f: impl FnOnce(&SnapShot, &[f32]) -> T) -> Option<T> {
+        match &mut self.snapshot_stream {
+            Some(stream) => match stream.get(i) {
+                Ok(snap) => Some(f(&snap, stream.body_masses())),
+                Err(e) => {
+                    eprintln!("Error reading snapshot {i}: {e}");
+                    None
+                }
+            },
+            None => self.snapshots.get(i).map(|snap| f(snap, &self.body_masses)),
+        }
+    }
+
+    fn record_diagnostics(&mut self) {
+        let sample = diagnostics::compute(
+            &self.bodies,
+            &self.config.bh_config,
+            self.config.softening_factor_sq,
+        );
+        self.diagnostics.push((self.time_elapsed, sample));
+    }
+
+    /// Warn if the initial conditions are far from virial equilibrium; these collapse or fly apart,
+    /// which can look like a force model effect.
+    fn check_virial_ratio(&mut self) {
+        let Some(ratio) = self.diagnostics.last().and_then(|(_, d)| d.virial_ratio()) else {
+            return;
+        };
+        let (min, max) = diagnostics::VIRIAL_RATIO_RANGE;
+
+        println!("Initial virial ratio (2K / |U|): {ratio:.3}");
+        if ratio < min || ratio > max {
+            self.ui.notifications.warning(format!(
+                "The initial virial ratio, 2K / |U|, is {ratio:.2}; these conditions are far from \
+                 equilibrium, and will {}",
+                if ratio < min { "collapse" } else { "expand" }
+            ));
+        }
+    }
+
+    /// Assign bodies new IDs, in order, and record their masses and components by them. For when
+    /// the bodies are replaced, vice changed during a build.
+    fn reset_body_ids(&mut self) {
+        self.body_masses = self.bodies.iter().map(|b| b.mass as f32).collect();
+        self.body_components = self.bodies.iter().map(|b| b.component).collect();
+        self.body_ids = (0..self.bodies.len() as u32).collect();
+        self.body_set_changed = false;
+    }
+
+    /// Assign IDs to bodies added at the end during a build, from index `start`, e.g. by mass
+    /// refinement.
+    fn add_body_ids(&mut self, start: usize) {
+        for body in &self.bodies[start..] {
+            self.body_ids.push(self.body_masses.len() as u32);
+            self.body_masses.push(body.mass as f32);
+            self.body_components.push(body.component);
+        }
+        self.body_set_changed = true;
+    }
+
+    /// The ID of the body at index `i`.
+    fn body_id(&self, i: usize) -> u32 {
+        self.body_ids.get(i).copied().unwrap_or(i as u32)
+    }
+
+    /// Copy the data needed for a snapshot. Conversion is deferred, so it can run off the
+    /// simulation thread. `subset` is the IDs of the bodies to include, sorted; `None` for all.
+    fn raw_snapshot(&self, dt: f64, tree_nodes: Vec<Cube>, subset: Option<&[u32]>) -> RawSnapshot {
+        let indices: Vec<usize> = match subset {
+            // Bodies may have been removed during the build.
+            Some(ids) => (0..self.bodies.len())
+                .filter(|i| ids.binary_search(&self.body_id(*i)).is_ok())
+                .collect(),
+            None => (0..self.bodies.len()).collect(),
+        };
+        let bodies: Vec<&Body> = indices.iter().map(|i| &self.bodies[*i]).collect();
+
+        // IDs are only needed if they don't match bodies' positions in the snapshot.
+        let body_ids = if subset.is_some() || self.body_set_changed {
+            indices.iter().map(|i| self.body_id(*i)).collect()
+        } else {
+            Vec::new()
+        };
+        let body_masses = if self.body_set_changed {
+            bodies.iter().map(|b| b.mass as f32).collect()
+        } else {
+            Vec::new()
+        };
+
+        RawSnapshot {
+            time: self.time_elapsed,
+            dt,
+            body_posits: bodies.iter().map(|b| b.posit).collect(),
+            body_accs: bodies.iter().map(|b| b.accel).collect(),
+            body_vels: bodies.iter().map(|b| b.vel).collect(),
+            shells: self.shells.clone(),
             tree_cubes: tree_nodes,
-        })
+            body_ids,
+            body_masses,
+            subset: subset.is_some(),
+            // If sampled at this time.
+            diagnostics: self
+                .diagnostics
+                .last()
+                .filter(|(t, _)| *t == self.time_elapsed)
+                .map(|(_, d)| *d),
+        }
+    }
+
+    fn take_snapshot(&mut self, dt: f64, tree_nodes: Vec<Cube>) {
+        let snapshot = self.raw_snapshot(dt, tree_nodes, None).convert();
+        self.snapshots.push(snapshot);
+    }
+
+    /// Collect snapshots from a build's sink. If they were streamed to disk, play back from there.
+    fn finish_snapshots(&mut self, sink: SnapshotSink, stream_path: Option<&Path>) {
+        match sink.finish() {
+            Ok(snapshots) => self.snapshots = snapshots,
+            Err(e) => {
+                self.ui
+                    .notifications
+                    .error(format!("Error writing snapshots: {e}"));
+                return;
+            }
+        }
+
+        if let Some(path) = stream_path {
+            match SnapshotCache::open(path) {
+                Ok(stream) => self.snapshot_stream = Some(stream),
+                Err(e) => self
+                    .ui
+                    .notifications
+                    .error(format!("Error opening the snapshot stream: {e}")),
+            }
+        }
     }
 }
 
@@ -275,6 +1056,7 @@ struct Body {
     pub vel: Vec3,
     pub accel: Vec3,
     pub mass: f64,
+    pub component: Component,
 }
 
 impl Body {
@@ -287,6 +1069,7 @@ impl Body {
             src_mass: self.mass,
             body_vel: self.vel,   // todo: A/R
             body_acc: self.accel, // todo: A/R
+            amp_scale: 1.,
         }
     }
 }
@@ -301,16 +1084,100 @@ impl BodyModel for Body {
     }
 }
 
+/// How a build ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildStatus {
+    /// Ran all steps, or stopped by a stop criterion.
+    Done,
+    /// Stopped early due to an error, e.g. NaN positions. The snapshots up to then are kept.
+    Aborted(String),
+}
+
 /// Entry point for computation; rename A/R.
-fn build(state: &mut State, force_model: ForceModel) {
+fn build(state: &mut State, force_model: ForceModel) -> BuildStatus {
+    // We must refresh bodies prior to building, to reset their positions after the previous update.
+    state.refresh_bodies();
+    state.config_history.set_built(&state.config);
+    run(state, force_model, None)
+}
+
+/// As `build`, but runs on a worker thread, so the UI stays responsive. Snapshots are added to
+/// `state.snapshots` as they're taken. See the `build_job` module.
+fn build_background(state: &mut State, force_model: ForceModel) {
+    state.refresh_bodies();
+    state.config_history.set_built(&state.config);
+
+    let job = BuildJob::spawn(state, force_model);
+    state.build_job = Some(job);
+}
+
+/// Add a body at snapshot `i`, e.g. an intruder mass, and continue the run from there for the
+/// configured number of steps. Later snapshots are replaced. Bodies are reconstructed from the
+/// snapshot, so shells, which snapshots store only compactly, start over. If playing back from a
+/// stream, the new run starts at the injection, since the stream file is rewritten. The run is in
+/// the background, as with other builds from the UI.
+fn resume_with_body(state: &mut State, force_model: ForceModel, i: usize, body: Body) {
+    build_job::stop(state);
+
+    let Some((time, mut bodies, ids, full)) = state.with_snapshot(i, |snap, body_masses| {
+        let ids: Vec<usize> = (0..snap.body_posits.len())
+            .map(|k| snap.body_id(k))
+            .collect();
+        (snap.time, snap.bodies(body_masses), ids, snap.is_full())
+    }) else {
+        state
+            .ui
+            .notifications
+            .error(format!("Unable to read snapshot {i} to inject into"));
+        return;
+    };
+    if !full {
+        state.ui.notifications.error(format!(
+            "Snapshot {i} only has a subset of bodies; inject at one with all of them"
+        ));
+        return;
+    }
+    // Snapshots don't store components.
+    for (body, id) in bodies.iter_mut().zip(&ids) {
+        if let Some(component) = state.body_components.get(*id) {
+            body.component = *component;
+        }
+    }
+    bodies.push(body);
+
+    if state.snapshot_stream.take().is_some() {
+        state.snapshots = Vec::new();
+    } else {
+        state.snapshots.truncate(i);
+    }
+
+    state.bodies = bodies;
+    state.reset_body_ids();
+    state.time_elapsed = time as f64;
+    state.shells = Vec::new();
+    state.ui.tidal_history = None;
+    state.diagnostics = Vec::new();
+    state.record_diagnostics();
+    state.take_snapshot(0., Vec::new());
+    state.ui.snapshot_selected = state.snapshots.len() - 1;
+
+    let job = BuildJob::spawn(state, force_model);
+    state.build_job = Some(job);
+}
+
+/// Integrate the current bodies, from the current time, taking snapshots. If `live` is set, and
+/// we're not streaming to disk, snapshots are sent there as they're taken, vice collected in
+/// `state.snapshots`.
+fn run(state: &mut State, force_model: ForceModel, live: Option<Sender<SnapShot>>) -> BuildStatus {
     println!("Building...");
     state.ui.building = true;
+    state.ui.notifications.set_status("build", "Building...");
+    let start_time_build = Instant::now();
 
-    // We must refresh bodies prior to building, to reset their positions after the previous update.
-    state.refresh_bodies();
+    state.ui.run_labels.force_model = force_model.to_str();
 
     let mut integrate_start_t = 0.;
-    if force_model == ForceModel::GaussShells {
+    if force_model.uses_shells() {
         // Allow gravity shells to propogate to reach a steady state, ideally.
         // todo: make this dynamic
         let mut farthest_r = 0.;
@@ -322,7 +1189,7 @@ fn build(state: &mut State, force_model: ForceModel) {
         }
         // 2x: For the case of opposite sides of circle.
         farthest_r *= 2.;
-        integrate_start_t = farthest_r / C;
+        integrate_start_t = state.time_elapsed + farthest_r / C;
     }
 
     println!(
@@ -345,21 +1212,153 @@ fn build(state: &mut State, force_model: ForceModel) {
     // todo: Make a guess at capacity based on bodies.
     // let mut nodes = Vec::with_capacity(69);
 
-    let gauss_c = state.config.shell_gauss_c();
+    // Snapshots are converted, and optionally streamed to disk, on a background thread.
+    let stream_path = state
+        .ui
+        .stream_snapshots
+        .then(|| PathBuf::from(DEFAULT_SNAPSHOT_FILE));
+    if let Some(dir) = stream_path.as_ref().and_then(|p| p.parent()) {
+        let _ = fs::create_dir_all(dir);
+    }
+
+    let initial = mem::take(&mut state.snapshots);
+    let sink = match &stream_path {
+        Some(path) => SnapshotSink::spawn(initial, Some((path, &state.run_meta())), None),
+        None => SnapshotSink::spawn(initial, None, live),
+    };
+    let sink = match sink {
+        Ok(s) => s,
+        Err(e) => {
+            let msg = format!("Unable to create the snapshot stream: {e}");
+            state.ui.building = false;
+            state.ui.notifications.clear_status("build");
+            state.ui.notifications.error(msg.clone());
+            return BuildStatus::Aborted(msg);
+        }
+    };
+
+    // The mesh is fixed for the run, so each solve starts from the previous one.
+    let mut qumond_grid = match force_model {
+        ForceModel::Qumond(_) => QumondGrid::new(&state.bodies),
+        _ => None,
+    };
+
+    let mut scf = match &state.config.scf {
+        Some(scf_cfg) if force_model.uses_instantaneous() => {
+            ScfExpansion::new(&state.bodies, scf_cfg)
+        }
+        _ => None,
+    };
+    if scf.is_some() && matches!(force_model, ForceModel::Mond(_)) {
+        eprintln!("SCF has no pairwise interactions; applying MOND to the net acceleration.");
+    }
+
+    let use_tree = force_model.uses_instantaneous() && !state.config.skip_tree && scf.is_none();
+
+    let integrator = state.config.integrator;
+    // Hermite uses Newtonian jerk, by direct sum.
+    let hermite = integrator == IntegratorKind::Hermite4
+        && force_model == ForceModel::Newton
+        && !state.charge_mode;
+    if integrator == IntegratorKind::Hermite4 && !hermite {
+        state
+            .ui
+            .notifications
+            .warning("Hermite 4 requires the Newton force model; integrating with RK4.");
+    }
+    let block = state
+        .config
+        .block_timesteps
+        .filter(|_| integrator == IntegratorKind::Leapfrog);
+    let mut block_levels = BlockLevels::default();
+    // For leapfrog, Yoshida, and block steps: Whether `Body::accel` is from the end of the previous
+    // step, so it can be reused at the start of this one. Not on the first step, or after bodies are
+    // added, removed, or moved by the zoom boundary. Only if the field is from the bodies alone; SCF,
+    // the QUMOND mesh, and shells are updated between steps.
+    let accel_reusable = scf.is_none()
+        && matches!(
+            force_model,
+            ForceModel::Newton | ForceModel::Mond(_) | ForceModel::MondNet(_)
+        );
+    let mut accel_current = false;
+    println!(
+        "Integrator: {}{}",
+        integrator.to_str(),
+        if block.is_some() {
+            ", block timesteps"
+        } else {
+            ""
+        }
+    );
+
+    // For output decimation. The initial snapshot, taken before this, has all bodies. The subset
+    // is chosen by index, then stored by ID, so it stays the same bodies if others are removed.
+    let subset_ids = state
+        .config
+        .snapshot_decimation
+        .subset
+        .body_ids(state.bodies.len(), state.config.seed)
+        .map(|indices| {
+            indices
+                .iter()
+                .map(|i| state.body_id(*i as usize))
+                .collect::<Vec<_>>()
+        });
+    let mut num_snapshots_sent = 1;
+
+    let mut stop_hooks = state.config.stop_criteria.make_hooks();
+    let mut steps_run = state.config.num_timesteps;
+    let mut num_superluminal = 0;
+
+    // Refinement times before the start, e.g. when resuming, have passed.
+    let mut refinements_done = state.config.mass_refinement.as_ref().map_or(0, |r| {
+        r.times.iter().filter(|t| **t < state.time_elapsed).count()
+    });
 
     for t in 0..state.config.num_timesteps {
-        if force_model == ForceModel::GaussShells && t % state.config.shell_creation_ratio == 0 {
+        if let Some(refinement) = &state.config.mass_refinement {
+            while refinement
+                .times
+                .get(refinements_done)
+                .is_some_and(|t_split| *t_split <= state.time_elapsed)
+            {
+                let seed = state
+                    .config
+                    .seed
+                    .map(|s| s.wrapping_add(refinements_done as u64));
+                let num_bodies_prev = state.bodies.len();
+                let num_split = refinement.split(&mut state.bodies, seed);
+                if num_split > 0 {
+                    state.add_body_ids(num_bodies_prev);
+                    accel_current = false;
+                }
+                println!(
+                    "Mass refinement at {:.3} Myr: Split {num_split} bodies. Now {} bodies.",
+                    state.time_elapsed,
+                    state.bodies.len()
+                );
+                refinements_done += 1;
+            }
+        }
+
+        if force_model.uses_shells() && t % state.config.shell_creation_ratio == 0 {
             state.remove_far_shells(); // Note grouped above due to a borrow problem.
         }
 
-        if force_model == ForceModel::GaussShells {
+        if force_model.uses_shells() {
             if t % state.config.shell_creation_ratio == 0 {
                 for (id, body) in state.bodies.iter().enumerate() {
                     state.shells.push(body.create_shell(id));
                 }
             }
             for shell in &mut state.shells {
-                shell.iter_t(state.config.dt);
+                shell.iter_t(state.config.dt, &state.config.shell_speed.law);
+            }
+
+            #[cfg(feature = "shell_interaction")]
+            if let Some(law) = state.shell_interaction.as_law() {
+                let gauss_c = state.config.shell_gauss_c();
+                shell_interaction::apply(&mut state.shells, law, gauss_c, state.config.dt);
             }
         }
 
@@ -370,8 +1369,12 @@ fn build(state: &mut State, force_model: ForceModel) {
         }
 
         if bb.width.is_nan() {
-            eprintln!("Error: NaN");
-            return;
+            let msg = format!("Build aborted: NaN in body positions at step {t}");
+            state.finish_snapshots(sink, stream_path.as_deref());
+            state.ui.building = false;
+            state.ui.notifications.clear_status("build");
+            state.ui.notifications.error(msg.clone());
+            return BuildStatus::Aborted(msg);
         }
 
         // Calculate dt for this step, based on the closest/fastest rel velocity.
@@ -385,11 +1388,15 @@ fn build(state: &mut State, force_model: ForceModel) {
         }
 
         let mut tree = None;
-        if state.charge_mode || (force_model != ForceModel::GaussShells && !cfg.skip_tree) {
+        if state.charge_mode || use_tree {
             tree = Some(Tree::new(&state.bodies, &bb, &cfg.bh_config));
         }
 
-        if t % BENCH_RATIO == 0 && force_model != ForceModel::GaussShells && !cfg.skip_tree {
+        if let Some(s) = &mut scf {
+            s.update(&state.bodies);
+        }
+
+        if t % BENCH_RATIO == 0 && use_tree {
             tree_time = start_time_tree.elapsed().as_micros();
         }
 
@@ -403,12 +1410,34 @@ fn build(state: &mut State, force_model: ForceModel) {
             start_time_integ = Instant::now();
         }
 
+        if let (ForceModel::Qumond(mond_fn), Some(grid)) = (force_model, &mut qumond_grid) {
+            grid.solve(&state.bodies, mond_fn);
+
+            if t % BENCH_RATIO == 0 {
+                println!(
+                    "t: {}k, QUMOND iterations: {:?} Phantom mass: {:.3e} M☉",
+                    t / 1_000,
+                    grid.iters,
+                    grid.phantom_mass()
+                );
+            }
+        }
+
         let bodies_other = if cfg.skip_tree {
             Some(state.bodies.clone())
         } else {
             None
         };
 
+        let refs = FieldRefs {
+            config: cfg,
+            bodies: bodies_other.as_deref().unwrap_or_default(),
+            tree: tree.as_ref(),
+            scf: scf.as_ref(),
+            qumond: qumond_grid.as_ref(),
+            shells: &state.shells,
+        };
+
         // This acceleration function acts on a target id and position.
         // (q_target here is only used for charge mode; discarded for grav)
         let acc = |id_target, posit_target, q_target| {
@@ -451,92 +1480,172 @@ fn build(state: &mut State, force_model: ForceModel) {
                 //     &acc_fn,
                 // )
             } else {
-                match force_model {
-                    ForceModel::Newton => {
-                        if cfg.skip_tree {
-                            accel::acc_newton(
-                                posit_target,
-                                id_target,
-                                &bodies_other.as_ref().unwrap(),
-                                None,
-                                cfg.softening_factor_sq,
-                            )
-                        } else {
-                            let acc_fn = |acc_dir, mass_src, dist| {
-                                acc_newton_inner_with_mond(
-                                    acc_dir,
-                                    mass_src,
-                                    dist,
-                                    None,
-                                    cfg.softening_factor_sq,
-                                )
-                            };
-
-                            barnes_hut::run_bh(
-                                posit_target,
-                                id_target,
-                                tree.as_ref().unwrap(),
-                                &cfg.bh_config,
-                                &acc_fn,
-                            )
-                        }
-                    }
-                    ForceModel::GaussShells => accel::calc_acc_shell(
-                        &state.shells,
-                        posit_target,
-                        id_target,
-                        gauss_c,
+                probe::acc_model(force_model, &refs, posit_target, id_target)
+            }
+        };
+
+        // Direct sums run on the GPU in place of the CPU, if it's available and direct sums are
+        // selected (`skip_tree`), for the models that only need the Newtonian field of the bodies.
+        // This returns the Newtonian field on targets; bodies at a target's position are skipped.
+        #[cfg(feature = "cuda")]
+        let acc_gpu = match &state.dev {
+            ComputationDevice::Gpu((stream, module))
+                if cfg.skip_tree
+                    && !state.charge_mode
+                    && scf.is_none()
+                    && matches!(force_model, ForceModel::Newton | ForceModel::MondNet(_)) =>
+            {
+                Some(move |bodies_src: &[Body], posits_tgt: &[Vec3]| {
+                    let posits: Vec<Vec3> = bodies_src.iter().map(|b| b.posit).collect();
+                    let masses: Vec<f64> = bodies_src.iter().map(|b| b.mass).collect();
+
+                    gpu::run_newton(
+                        stream,
+                        module,
+                        &posits,
+                        posits_tgt,
+                        &masses,
                         cfg.softening_factor_sq,
-                    ),
-                    ForceModel::Mond(mond_fn) => {
-                        if cfg.skip_tree {
-                            accel::acc_newton(
-                                posit_target,
-                                id_target,
-                                &bodies_other.as_ref().unwrap(),
-                                Some(mond_fn),
-                                cfg.softening_factor_sq,
-                            )
-                        } else {
-                            let acc_fn = |acc_dir, mass_src, dist| {
-                                acc_newton_inner_with_mond(
-                                    acc_dir,
-                                    mass_src,
-                                    dist,
-                                    Some(mond_fn),
-                                    cfg.softening_factor_sq,
-                                )
-                            };
-
-                            barnes_hut::run_bh(
-                                posit_target,
-                                id_target,
-                                tree.as_ref().unwrap(),
-                                &cfg.bh_config,
-                                &acc_fn,
-                            )
-                        }
-                    }
-                }
+                        &cfg.gpu,
+                    )
+                })
             }
+            _ => None,
         };
+        #[cfg(not(feature = "cuda"))]
+        let acc_gpu: Option<fn(&[Body], &[Vec3]) -> Vec<Vec3>> = None;
+
+        let mond_net = |accs: Vec<Vec3>| match force_model {
+            ForceModel::MondNet(mond_fn) => accs
+                .into_iter()
+                .map(|a| accel::apply_mond_net(a, mond_fn))
+                .collect(),
+            _ => accs,
+        };
+
+        // For the symplectic schemes, which move all bodies between evaluations: The field at the
+        // bodies' current positions, with the tree rebuilt for them. SCF, the QUMOND mesh, and
+        // shells are from the start of the step.
+        // For block timesteps, only some bodies are evaluated.
+        let acc_some = |bodies: &[Body], ids: &[usize]| -> Vec<Vec3> {
+            if state.charge_mode {
+                return ids
+                    .iter()
+                    .map(|id| acc(*id, bodies[*id].posit, bodies[*id].mass))
+                    .collect();
+            }
 
-        if force_model != ForceModel::GaussShells || state.time_elapsed > integrate_start_t {
+            if let Some(acc_gpu) = &acc_gpu {
+                let posits_tgt: Vec<Vec3> = ids.iter().map(|id| bodies[*id].posit).collect();
+                return mond_net(acc_gpu(bodies, &posits_tgt));
+            }
+
+            let tree = use_tree.then(|| Tree::new(bodies, &bb, &cfg.bh_config));
+            let refs = FieldRefs {
+                config: cfg,
+                bodies: if cfg.skip_tree { bodies } else { &[] },
+                tree: tree.as_ref(),
+                scf: scf.as_ref(),
+                qumond: qumond_grid.as_ref(),
+                shells: &state.shells,
+            };
+
+            ids.par_iter()
+                .map(|id| probe::acc_model(force_model, &refs, bodies[*id].posit, *id))
+                .collect()
+        };
+        let acc_all = |bodies: &[Body]| {
+            let ids: Vec<usize> = (0..bodies.len()).collect();
+            acc_some(bodies, &ids)
+        };
+
+        if !force_model.uses_shells() || state.time_elapsed > integrate_start_t {
             // todo: COme back to skiping the first body. Setting the central body as immovable for now.
             // todo: While we have a central body...
             // Iterate, in parallel, over target bodies. The loop over source bodies, per target, is handled
             // by the acceleration function.
-            state
-                .bodies
-                .par_iter_mut()
-                .enumerate()
-                // .skip(1) // Skip the central body
-                .for_each(|(id_target, body_target)| {
-                    integrate_rk4(body_target, id_target, &acc, dt);
-                });
+            match integrator {
+                IntegratorKind::Hermite4 if hermite => {
+                    let softening_factor_sq = cfg.softening_factor_sq;
+                    let acc_jerk = |posit, vel, id, bodies_src: &[Body]| {
+                        accel::acc_jerk_newton(posit, vel, id, bodies_src, softening_factor_sq)
+                    };
+                    integrate_hermite4(&mut state.bodies, &acc_jerk, dt);
+                }
+                IntegratorKind::Rk4 | IntegratorKind::Hermite4 => match (&acc_gpu, &bodies_other) {
+                    (Some(acc_gpu), Some(bodies_src)) => {
+                        // Sources are held at the start of the step. Away from it, the GPU doesn't
+                        // skip a body's own contribution, so we remove it here.
+                        let acc_many = |posits: &[Vec3]| {
+                            let mut accs = acc_gpu(bodies_src, posits);
+                            for ((a, posit), src) in accs.iter_mut().zip(posits).zip(bodies_src) {
+                                let diff = src.posit - *posit;
+                                let dist = diff.magnitude();
+                                if dist > 0. {
+                                    *a = *a
+                                        - accel::acc_newton_inner(
+                                            diff / dist,
+                                            src.mass,
+                                            dist,
+                                            cfg.softening_factor_sq,
+                                        );
+                                }
+                            }
+                            mond_net(accs)
+                        };
+                        integrate_rk4_many(&mut state.bodies, &acc_many, dt);
+                    }
+                    _ => {
+                        state
+                            .bodies
+                            .par_iter_mut()
+                            .enumerate()
+                            // .skip(1) // Skip the central body
+                            .for_each(|(id_target, body_target)| {
+                                integrate_rk4(body_target, id_target, &acc, dt);
+                            });
+                    }
+                },
+                IntegratorKind::Leapfrog => match &block {
+                    Some(block) => integrate_block(
+                        &mut state.bodies,
+                        &mut block_levels,
+                        block,
+                        &acc_some,
+                        cfg.softening_factor_sq.sqrt(),
+                        dt,
+                        accel_current,
+                    ),
+                    None => integrate_leapfrog(&mut state.bodies, &acc_all, dt, accel_current),
+                },
+                IntegratorKind::Yoshida4 => {
+                    integrate_yoshida4(&mut state.bodies, &acc_all, dt, accel_current)
+                }
+            }
+            accel_current = accel_reusable;
+        }
+
+        let num_bodies_prev = state.bodies.len();
+        num_superluminal +=
+            cfg.superluminal
+                .apply(&mut state.bodies, &mut state.body_ids, &mut state.shells, t);
+        if state.bodies.len() != num_bodies_prev {
+            state.body_set_changed = true;
+            accel_current = false;
+        }
+
+        if let Some(boundary) = &state.zoom_boundary {
+            accel_current = false;
+            if !boundary.apply(&mut state.bodies, state.time_elapsed) {
+                state.zoom_boundary = None;
+                state.ui.notifications.warning(
+                    "Bodies were removed during the zoom resimulation; the boundary now evolves \
+                    freely",
+                );
+            }
         }
 
-        if t % BENCH_RATIO == 0 && force_model != ForceModel::GaussShells && !cfg.skip_tree {
+        if t % BENCH_RATIO == 0 && use_tree {
             println!(
                 "t: {}k, Tree time: {}μs Tree size: {} Integ time: {}μs",
                 t / 1_000,
@@ -545,9 +1654,20 @@ fn build(state: &mut State, force_model: ForceModel) {
                 start_time_integ.elapsed().as_micros()
             );
         }
+        if t % BENCH_RATIO == 0 && block.is_some() {
+            println!(
+                "Bodies per block timestep level: {:?}",
+                block_levels.counts()
+            );
+        }
+
+        // Before the snapshot, so it can include them.
+        if state.config.diagnostics_ratio > 0 && t % state.config.diagnostics_ratio == 0 {
+            state.record_diagnostics();
+        }
 
         // Save the current state to a snapshot, for later playback.
-        if t % cfg.snapshot_ratio == 0 {
+        if t % state.config.snapshot_ratio == 0 {
             let nodes: Vec<Cube> = if let Some(t) = &tree {
                 if state.ui.draw_tree {
                     // Whole tree
@@ -563,50 +1683,190 @@ fn build(state: &mut State, force_model: ForceModel) {
             } else {
                 Vec::new()
             };
-            state.take_snapshot(dt, nodes);
+            let full = match state.config.snapshot_decimation.full_ratio {
+                0 => false,
+                ratio => num_snapshots_sent % ratio == 0,
+            };
+            let body_ids = subset_ids.as_deref().filter(|_| !full);
+            sink.send(state.raw_snapshot(dt, nodes, body_ids));
+            num_snapshots_sent += 1;
         }
+
+        let info = StepInfo {
+            step: t,
+            time: state.time_elapsed,
+            dt,
+            bodies: &state.bodies,
+        };
+
+        let stopped_by = hooks::run_hooks(&mut state.step_hooks, &info)
+            .or(hooks::run_hooks(&mut stop_hooks, &info));
+
+        if let Some(name) = stopped_by {
+            state
+                .ui
+                .notifications
+                .info(format!("{name} stopped the build at step {t}"));
+            steps_run = t + 1;
+            break;
+        }
+    }
+
+    let info = StepInfo {
+        step: steps_run,
+        time: state.time_elapsed,
+        dt: state.config.dt,
+        bodies: &state.bodies,
+    };
+    for hook in &mut state.step_hooks {
+        hook.on_finish(&info);
     }
 
+    state.finish_snapshots(sink, stream_path.as_deref());
+
     state.ui.building = false;
-    println!("Final V/c: {:.6}", state.bodies[0].vel.magnitude() / C); // todo temp
+    state.ui.notifications.clear_status("build");
+    state.ui.notifications.success(format!(
+        "Build complete: {} snapshots in {:.1} s",
+        state.num_snapshots(),
+        start_time_build.elapsed().as_secs_f32()
+    ));
+
+    if num_superluminal > 0 {
+        let action = match state.config.superluminal.action {
+            SuperluminalAction::Clamp => ", and clamped",
+            SuperluminalAction::Remove => ", and removed",
+            _ => "",
+        };
+        state.ui.notifications.warning(format!(
+            "{num_superluminal} body-steps over {} c{action}. See the log for details.",
+            state.config.superluminal.v_max_portion
+        ));
+    }
+
+    let (drift_e, drift_l) = diagnostics::drift(&state.diagnostics);
+    if let (Some(e), Some(l)) = (drift_e.last(), drift_l.last()) {
+        println!(
+            "Energy drift: {:.3e}. Angular momentum drift: {:.3e}",
+            e.1, l.1
+        );
+    }
+
+    if let (Some((_, first)), Some((_, last))) =
+        (state.diagnostics.first(), state.diagnostics.last())
+    {
+        let (com_posit, com_vel) = last.com_drift(first);
+        println!(
+            "Virial ratio: {:.3}. COM drift: {com_posit:.3e} kpc, {:.3e} km/s",
+            last.virial_ratio().unwrap_or_default(),
+            com_vel / KPC_MYR_PER_KM_S
+        );
+    }
+
     println!("Build complete.");
+    BuildStatus::Done
 }
 
 fn main() {
-    #[cfg(feature = "cuda")]
-    let dev = {
-        // This is compiled in `build_`.
-        let ctx = CudaContext::new(0).unwrap();
-        let stream = ctx.default_stream();
-
-        let module = ctx.load_module(Ptx::from_file("./cuda.ptx")).unwrap();
-
-        // todo: Store/cache this likely.
-        // let func_newton = module.load_function("newton_kernel").unwrap();
+    // Headless parameter sweeps; see the `jobs` module.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "jobs") {
+        if let Err(e) = jobs::run_cli(&args[1..]) {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        return;
+    }
 
-        // println!("Using the GPU for computations.");
-        ComputationDevice::Gpu((stream, module))
+    #[cfg(feature = "cuda")]
+    let dev = match gpu::init() {
+        Ok(dev) => {
+            println!("Using the GPU for computations.");
+            ComputationDevice::Gpu(dev)
+        }
+        Err(e) => {
+            eprintln!("Warning: Unable to set up CUDA; falling back to the CPU. {e}");
+            ComputationDevice::Cpu
+        }
     };
 
     #[cfg(not(feature = "cuda"))]
     let dev = ComputationDevice::Cpu;
 
     let mut state = State::default();
-    if let Ok(cfg) = util::load(&PathBuf::from_str(SAVE_FILE).unwrap()) {
-        state.config = cfg;
+
+    #[cfg(feature = "cuda")]
+    if matches!(dev, ComputationDevice::Cpu) {
+        state
+            .ui
+            .notifications
+            .warning("CUDA is unavailable; running on the CPU. See the log for details.");
+    }
+    state.dev = dev;
+    state.ui.galaxy_catalog = GalaxyCatalog::scan(Path::new(sparc::SPARC_DIR));
+    state.ui.galaxy_descrip = state.ui.galaxy_model.descrip();
+    state.apply_config(Config::load(&PathBuf::from_str(SAVE_FILE).unwrap()).unwrap_or_default());
+    state
+        .ui
+        .config_watch
+        .watch(Path::new(config_text::CONFIG_TEXT_FILE));
+
+    if let Ok(recent) = util::load(Path::new(RECENT_CONFIGS_FILE)) {
+        state.ui.recent_configs = recent;
     }
 
     state.charge_mode = true;
 
-    state.ui.dt_input = state.config.dt.to_string();
-    state.ui.θ_input = state.config.bh_config.θ.to_string();
-    state.ui.v_scaler_input = state.config.v_scaler.to_string();
-
     state.refresh_bodies();
 
-    // if let Err(e) = util::save(&PathBuf::from(DEFAULT_SNAPSHOT_FILE), &state.snapshots) {
-    //     eprintln!("Error saving snapshots: {e}");
-    // }
-
     render(state);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::hooks::StepControl;
+
+    /// A closure registered with `add_step_hook` runs each step, and can stop the build.
+    #[test]
+    fn step_hook_stops_build() {
+        const STOP_STEP: usize = 4;
+
+        let mut state = State::default();
+        state.config.num_timesteps = 100;
+        state.bodies = [-1., 1.]
+            .into_iter()
+            .map(|x| Body {
+                posit: Vec3::new(x, 0., 0.),
+                vel: Vec3::new(0., x * 0.01, 0.),
+                accel: Vec3::new_zero(),
+                mass: 1e9,
+                component: Default::default(),
+            })
+            .collect();
+        state.reset_body_ids();
+
+        let steps = Arc::new(AtomicUsize::new(0));
+        let steps_hook = steps.clone();
+        state.add_step_hook(move |info: &StepInfo| {
+            steps_hook.fetch_add(1, Ordering::Relaxed);
+            if info.step == STOP_STEP {
+                StepControl::Stop
+            } else {
+                StepControl::Continue
+            }
+        });
+
+        let status = run(&mut state, ForceModel::Newton, None);
+
+        assert_eq!(status, BuildStatus::Done);
+        assert_eq!(steps.load(Ordering::Relaxed), STOP_STEP + 1);
+        let time_expected = (STOP_STEP + 1) as f64 * state.config.dt;
+        assert!((state.time_elapsed - time_expected).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,152 @@
+//! Net radial mass flux through a set of radii, over a run. Sustained inflow or outflow measures
+//! secular evolution, e.g. driven by bars, or numerical relaxation, and lets us compare force models.
+//!
+//! Flux at radius R uses a shell estimator: Ṁ(R) = Σ mᵢ v_r,ᵢ / Δr, over bodies within Δr / 2 of R.
+//! Positive is outward.
+
+use std::{fmt, fmt::Formatter};
+
+use lin_alg::{f64::Vec3, linspace};
+
+use crate::{
+    playback::SnapShot,
+    properties::{plot_multi, PlotOutput},
+    Body, State,
+};
+
+/// Number of radii to measure at. Matches the number of distinct plot colors.
+const NUM_RADII: usize = 5;
+/// The outermost radius, as a portion of the initial extent; beyond this, sampling is sparse.
+const R_MAX_PORTION: f64 = 0.8;
+
+pub struct MassFluxHistory {
+    /// kpc
+    pub radii: Vec<f64>,
+    /// Myr
+    pub times: Vec<f64>,
+    /// M☉/Myr. Outer: snapshot. Inner: radius.
+    pub flux: Vec<Vec<f64>>,
+}
+
+impl MassFluxHistory {
+    /// Time-averaged flux at each radius. M☉/Myr.
+    pub fn mean_flux(&self) -> Vec<f64> {
+        let n = self.flux.len().max(1) as f64;
+
+        (0..self.radii.len())
+            .map(|i| self.flux.iter().map(|f| f[i]).sum::<f64>() / n)
+            .collect()
+    }
+}
+
+impl fmt::Display for MassFluxHistory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Mean radial mass flux over {} snapshots (+ is outward):",
+            self.times.len()
+        )?;
+        for (r, flux) in self.radii.iter().zip(self.mean_flux()) {
+            writeln!(f, "R: {r:.2} kpc  Ṁ: {flux:.4e} M☉/Myr")?;
+        }
+        Ok(())
+    }
+}
+
+/// Net radial mass flux through each radius, about `center`. Shells have width `dr`.
+pub fn radial_mass_flux(bodies: &[Body], center: Vec3, radii: &[f64], dr: f64) -> Vec<f64> {
+    radii
+        .iter()
+        .map(|r| {
+            bodies
+                .iter()
+                .filter_map(|b| {
+                    let diff = b.posit - center;
+                    let dist = diff.magnitude();
+                    if (dist - r).abs() > dr / 2. || dist < f64::EPSILON {
+                        return None;
+                    }
+                    Some(b.mass * b.vel.dot(diff / dist))
+                })
+                .sum::<f64>()
+                / dr
+        })
+        .collect()
+}
+
+/// Radii to measure at, evenly spaced out to a portion of the bodies' extent. Returns (radii, dr).
+fn sample_radii(snap: &SnapShot) -> (Vec<f64>, f64) {
+    let extent = snap
+        .body_posits
+        .iter()
+        .map(|p| p.magnitude() as f64)
+        .fold(0., f64::max);
+
+    let r_max = extent * R_MAX_PORTION;
+    let spacing = r_max / NUM_RADII as f64;
+
+    (linspace(spacing, r_max, NUM_RADII), spacing / 2.)
+}
+
+/// Compute flux at each snapshot of the current run, from memory or a stream.
+pub fn mass_flux_history(state: &mut State) -> Option<MassFluxHistory> {
+    let (radii, dr) = state.with_snapshot(0, |snap, _| sample_radii(snap))?;
+
+    let mut times = Vec::new();
+    let mut flux = Vec::new();
+
+    for i in 0..state.num_snapshots() {
+        // Snapshots of a subset of bodies are skipped; they don't have all the mass.
+        let r = state.with_snapshot(i, |snap, body_masses| {
+            snap.is_full().then(|| {
+                let bodies = snap.bodies(body_masses);
+                (
+                    snap.time as f64,
+                    radial_mass_flux(&bodies, Vec3::new_zero(), &radii, dr),
+                )
+            })
+        });
+
+        if let Some(Some((time, f))) = r {
+            times.push(time);
+            flux.push(f);
+        }
+    }
+
+    Some(MassFluxHistory { radii, times, flux })
+}
+
+/// Plot flux vs time at each radius.
+pub fn plot_mass_flux(out: &PlotOutput, history: &MassFluxHistory) {
+    let labels: Vec<String> = history
+        .radii
+        .iter()
+        .map(|r| format!("R = {r:.1} kpc"))
+        .collect();
+
+    let series: Vec<Vec<(f64, f64)>> = (0..history.radii.len())
+        .map(|i| {
+            history
+                .times
+                .iter()
+                .zip(&history.flux)
+                .map(|(t, f)| (*t, f[i]))
+                .collect()
+        })
+        .collect();
+
+    let series_ref: Vec<(&str, &[(f64, f64)])> = labels
+        .iter()
+        .zip(&series)
+        .map(|(l, s)| (l.as_str(), s.as_slice()))
+        .collect();
+
+    plot_multi(
+        out,
+        &series_ref,
+        "t (Myr)",
+        "Ṁ (M☉/Myr)",
+        "Radial mass flux (+ is outward)",
+        "mass_flux",
+    );
+}
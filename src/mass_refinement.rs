@@ -0,0 +1,116 @@
+//! Mass refinement: Split bodies into lighter children during a build, for finer mass resolution
+//! where the dynamics matter. At the body counts we run, Poisson noise from few, heavy bodies can
+//! dominate the dynamics; splitting reduces it in a region, without paying for the resolution
+//! everywhere.
+//!
+//! Children share their parent's velocity, so momentum and kinetic energy are conserved. They're
+//! placed randomly near the parent, offset so their center of mass is the parent's position.
+//! Potential energy changes slightly, since the parent's mass is spread out.
+
+use bincode::{Decode, Encode};
+use lin_alg::f64::Vec3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::Body;
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct MassRefinement {
+    /// Simulated times to split at, in order. Myr. A time of 0 splits the initial conditions.
+    pub times: Vec<f64>,
+    /// Each split body becomes this many.
+    pub num_children: usize,
+    /// Only bodies within this range of distances from the origin are split. kpc.
+    pub r_min: f64,
+    pub r_max: f64,
+    /// Children are placed within this distance of their parent. kpc. Keep it small compared to
+    /// the spacing between bodies.
+    pub jitter: f64,
+    /// Bodies lighter than this aren't split, e.g. children of an earlier split, to bound the
+    /// number of bodies. M☉.
+    pub mass_min: f64,
+}
+
+impl Default for MassRefinement {
+    fn default() -> Self {
+        Self {
+            times: vec![0.],
+            num_children: 4,
+            r_min: 0.,
+            r_max: 5.,
+            jitter: 0.02,
+            mass_min: 0.,
+        }
+    }
+}
+
+/// A uniform random point in a ball of this radius.
+fn random_in_ball(rng: &mut StdRng, radius: f64) -> Vec3 {
+    loop {
+        let v = Vec3::new(
+            rng.random_range(-1.0..1.),
+            rng.random_range(-1.0..1.),
+            rng.random_range(-1.0..1.),
+        );
+        if v.magnitude_squared() <= 1. {
+            return v * radius;
+        }
+    }
+}
+
+impl MassRefinement {
+    /// For display and editing, e.g. "0, 50".
+    pub fn times_text(&self) -> String {
+        self.times
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Split bodies in range. The first child takes the parent's place; the rest are added at the
+    /// end. Returns the number of bodies split.
+    pub fn split(&self, bodies: &mut Vec<Body>, seed: Option<u64>) -> usize {
+        if self.num_children < 2 {
+            return 0;
+        }
+
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        let mut num_split = 0;
+        for i in 0..bodies.len() {
+            let r = bodies[i].posit.magnitude();
+            if r < self.r_min || r > self.r_max || bodies[i].mass < self.mass_min {
+                continue;
+            }
+
+            let mut offsets: Vec<Vec3> = (0..self.num_children)
+                .map(|_| random_in_ball(&mut rng, self.jitter))
+                .collect();
+            let mean = offsets.iter().copied().sum::<Vec3>() / self.num_children as f64;
+            for offset in &mut offsets {
+                *offset -= mean;
+            }
+
+            let parent = bodies[i].clone();
+            let mass = parent.mass / self.num_children as f64;
+
+            bodies[i].posit = parent.posit + offsets[0];
+            bodies[i].mass = mass;
+
+            for offset in &offsets[1..] {
+                bodies.push(Body {
+                    posit: parent.posit + *offset,
+                    mass,
+                    ..parent.clone()
+                });
+            }
+
+            num_split += 1;
+        }
+
+        num_split
+    }
+}
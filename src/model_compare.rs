@@ -0,0 +1,178 @@
+//! Model comparison: Build the current galaxy once per force model, and score each final rotation
+//! curve against the observed one with reduced χ². This gives a systematic comparison of e.g.
+//! Newton, MOND, and Gauss shells, vice eyeballing plots.
+//!
+//! All runs use the same seed, so differences between them are from the force model, vice random
+//! initial conditions.
+
+use std::{fmt, fmt::Formatter, fmt::Write as _, fs, io, path::Path};
+
+use lin_alg::f64::Vec3;
+use rand::Rng;
+
+use crate::{
+    accel::MondFn,
+    build,
+    properties::rotation_curve,
+    rot_compare::{compare, observed_with_errors},
+    units::C,
+    BuildStatus, ForceModel, State,
+};
+
+pub const MODELS: [ForceModel; 4] = [
+    ForceModel::Newton,
+    ForceModel::Mond(MondFn::Simple),
+    ForceModel::Mond(MondFn::Standard),
+    ForceModel::GaussShells,
+];
+
+pub struct ModelFit {
+    pub force_model: ForceModel,
+    /// `None` if no observed points are in the simulated curve's range.
+    pub chi_sq_reduced: Option<f64>,
+    /// The number of observed points compared.
+    pub num_points: usize,
+    /// RMS of simulated - observed velocity. km/s.
+    pub resid_rms: f64,
+}
+
+pub struct ModelComparison {
+    pub galaxy: String,
+    pub seed: u64,
+    pub fits: Vec<ModelFit>,
+    /// Models whose build aborted, e.g. on NaN positions, with the reason. They have no fit.
+    pub aborted: Vec<(ForceModel, String)>,
+}
+
+impl ModelComparison {
+    /// The model with the lowest reduced χ².
+    pub fn best(&self) -> Option<&ModelFit> {
+        self.fits
+            .iter()
+            .filter(|f| f.chi_sq_reduced.is_some())
+            .min_by(|a, b| {
+                a.chi_sq_reduced
+                    .unwrap()
+                    .total_cmp(&b.chi_sq_reduced.unwrap())
+            })
+    }
+
+    /// One row per model.
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        let mut csv =
+            "galaxy,seed,force_model,chi_sq_reduced,num_points,resid_rms_km_s\n".to_owned();
+
+        for fit in &self.fits {
+            let chi_sq = fit
+                .chi_sq_reduced
+                .map(|c| c.to_string())
+                .unwrap_or_default();
+            writeln!(
+                csv,
+                "{},{},{},{chi_sq},{},{}",
+                self.galaxy,
+                self.seed,
+                fit.force_model.to_str(),
+                fit.num_points,
+                fit.resid_rms
+            )
+            .unwrap();
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, csv)
+    }
+}
+
+impl fmt::Display for ModelComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Model comparison for {}. Seed: {}",
+            self.galaxy, self.seed
+        )?;
+        writeln!(
+            f,
+            "  {:<18} {:>10} {:>8} {:>14}",
+            "Model", "χ²_ν", "Points", "RMS resid"
+        )?;
+
+        for fit in &self.fits {
+            let chi_sq = match fit.chi_sq_reduced {
+                Some(c) => format!("{c:.3}"),
+                None => "-".to_owned(),
+            };
+            writeln!(
+                f,
+                "  {:<18} {chi_sq:>10} {:>8} {:>9.2} km/s",
+                fit.force_model.to_str(),
+                fit.num_points,
+                fit.resid_rms
+            )?;
+        }
+
+        for (force_model, reason) in &self.aborted {
+            writeln!(f, "  {:<18} Aborted: {reason}", force_model.to_str())?;
+        }
+
+        if let Some(best) = self.best() {
+            writeln!(f, "Best fit: {}", best.force_model.to_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// Build with each model in `MODELS`, and compare the final rotation curves with the galaxy's
+/// observed one. Uses the config's seed if set; otherwise, a random one. The config's seed is
+/// restored afterwards. Models whose build aborted are skipped, and listed in the result.
+pub fn run_model_comparison(state: &mut State) -> ModelComparison {
+    let seed_prev = state.config.seed;
+    let seed = seed_prev.unwrap_or_else(|| rand::rng().random());
+    state.config.seed = Some(seed);
+
+    let observed = observed_with_errors(&state.ui.galaxy_descrip);
+
+    let mut fits = Vec::with_capacity(MODELS.len());
+    let mut aborted = Vec::new();
+    for force_model in MODELS {
+        println!("\nModel comparison run: {}", force_model.to_str());
+        if let BuildStatus::Aborted(e) = build(state, force_model) {
+            aborted.push((force_model, e));
+            continue;
+        }
+
+        let curve = rotation_curve(&state.bodies, Vec3::new_zero(), C);
+        let comparison = compare(&curve, &observed);
+
+        let num_points = comparison.residuals.len();
+        let resid_rms = if num_points == 0 {
+            0.
+        } else {
+            (comparison
+                .residuals
+                .iter()
+                .map(|(_, r)| r.powi(2))
+                .sum::<f64>()
+                / num_points as f64)
+                .sqrt()
+        };
+
+        fits.push(ModelFit {
+            force_model,
+            chi_sq_reduced: comparison.chi_sq_reduced,
+            num_points,
+            resid_rms,
+        });
+    }
+
+    state.config.seed = seed_prev;
+
+    ModelComparison {
+        galaxy: state.ui.run_labels.galaxy.clone(),
+        seed,
+        fits,
+        aborted,
+    }
+}
@@ -0,0 +1,150 @@
+//! An in-UI notification area, for errors and status that would otherwise only go to stdout,
+//! where GUI users don't see them. Errors and warnings are also printed, for CLI use.
+
+use std::time::{Duration, Instant};
+
+use egui::{Align2, Area, Color32, Context, Frame, Id, RichText};
+
+use crate::properties::PlotOutput;
+
+/// How long non-sticky notifications stay on screen.
+const DISPLAY_TIME: Duration = Duration::from_secs(6);
+/// Errors stay up longer, since they're more likely to need reading.
+const DISPLAY_TIME_ERROR: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NotifyLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl NotifyLevel {
+    pub fn color(self) -> Color32 {
+        match self {
+            Self::Info => Color32::LIGHT_BLUE,
+            Self::Success => Color32::LIGHT_GREEN,
+            Self::Warning => Color32::ORANGE,
+            Self::Error => Color32::LIGHT_RED,
+        }
+    }
+}
+
+struct Notification {
+    text: String,
+    level: NotifyLevel,
+    created: Instant,
+    /// Status of a long-running task. These stay up until cleared with the same key, instead of expiring.
+    status_key: Option<&'static str>,
+}
+
+#[derive(Default)]
+pub struct Notifications {
+    items: Vec<Notification>,
+}
+
+impl Notifications {
+    pub fn push(&mut self, level: NotifyLevel, text: impl Into<String>) {
+        let text = text.into();
+        match level {
+            NotifyLevel::Warning | NotifyLevel::Error => eprintln!("{text}"),
+            _ => (),
+        }
+
+        self.items.push(Notification {
+            text,
+            level,
+            created: Instant::now(),
+            status_key: None,
+        });
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(NotifyLevel::Info, text);
+    }
+
+    pub fn success(&mut self, text: impl Into<String>) {
+        self.push(NotifyLevel::Success, text);
+    }
+
+    pub fn warning(&mut self, text: impl Into<String>) {
+        self.push(NotifyLevel::Warning, text);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(NotifyLevel::Error, text);
+    }
+
+    /// Report plots written to `out`'s directory. Nothing is reported if plotting is disabled.
+    pub fn plots_saved(&mut self, out: &PlotOutput, what: &str) {
+        if let Some(msg) = out.saved_msg(what) {
+            self.success(msg);
+        }
+    }
+
+    /// Set, or update, the status of a long-running task. Shown until `clear_status` is called.
+    pub fn set_status(&mut self, key: &'static str, text: impl Into<String>) {
+        let text = text.into();
+
+        if let Some(n) = self.items.iter_mut().find(|n| n.status_key == Some(key)) {
+            n.text = text;
+            return;
+        }
+
+        self.items.push(Notification {
+            text,
+            level: NotifyLevel::Info,
+            created: Instant::now(),
+            status_key: Some(key),
+        });
+    }
+
+    pub fn clear_status(&mut self, key: &'static str) {
+        self.items.retain(|n| n.status_key != Some(key));
+    }
+
+    /// Add notifications made elsewhere, e.g. on a worker thread. Those were printed when made.
+    pub fn append(&mut self, mut other: Self) {
+        self.items.append(&mut other.items);
+    }
+
+    /// Draw notifications in the bottom right corner, and remove expired and dismissed ones.
+    pub fn draw(&mut self, ctx: &Context) {
+        self.items.retain(|n| {
+            let display_time = match n.level {
+                NotifyLevel::Error => DISPLAY_TIME_ERROR,
+                _ => DISPLAY_TIME,
+            };
+            n.status_key.is_some() || n.created.elapsed() < display_time
+        });
+
+        if self.items.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+
+        Area::new(Id::new("notifications"))
+            .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
+            .show(ctx, |ui| {
+                for (i, n) in self.items.iter().enumerate() {
+                    Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new(&n.text).color(n.level.color()));
+                            if n.status_key.is_none() && ui.small_button("×").clicked() {
+                                dismissed = Some(i);
+                            }
+                        });
+                    });
+                }
+            });
+
+        if let Some(i) = dismissed {
+            self.items.remove(i);
+        }
+
+        // So notifications are removed when they expire, even if there's no input.
+        ctx.request_repaint_after(Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,137 @@
+//! Propagates uncertainty in a galaxy's assumed distance and inclination to its observed rotation
+//! curve. Observed radii are angular, so scale with distance; observed velocities are line-of-sight,
+//! so scale with 1 / sin(inclination). We rescale the curve at the ±1σ bounds, vice rebuilding.
+
+use std::{io, path::Path};
+
+use crate::{
+    body_creation::GalaxyDescrip, properties::plot_multi_to, units::KPC_MYR_PER_KM_S,
+    util::interpolate,
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ObsUncertainty {
+    /// 1σ distance uncertainty. kpc.
+    pub dist_err: f64,
+    /// Inclination the published curve assumes. Degrees; 90 is edge-on. 0 if unknown, in which case
+    /// we don't vary it.
+    pub inclination: f64,
+    /// 1σ inclination uncertainty. Degrees.
+    pub inclination_err: f64,
+}
+
+impl ObsUncertainty {
+    pub fn is_set(&self) -> bool {
+        self.dist_err > 0. || (self.inclination > 0. && self.inclination_err > 0.)
+    }
+}
+
+/// Rescale an observed curve from its assumed distance and inclination to alternate ones.
+/// Inclinations are in degrees.
+pub fn rescale(
+    curve: &[(f64, f64)],
+    dist: f64,
+    dist_new: f64,
+    inclination: f64,
+    inclination_new: f64,
+) -> Vec<(f64, f64)> {
+    let r_scale = dist_new / dist;
+    let v_scale = if inclination > 0. {
+        inclination.to_radians().sin() / inclination_new.clamp(1., 90.).to_radians().sin()
+    } else {
+        1.
+    };
+
+    curve
+        .iter()
+        .map(|(r, v)| (r * r_scale, v * v_scale))
+        .collect()
+}
+
+/// The lower and upper ±1σ bounds of an observed curve, at its original radii. Each bound is the
+/// envelope of the curves at the four combinations of distance and inclination extremes.
+pub fn curve_bounds(
+    curve: &[(f64, f64)],
+    dist: f64,
+    unc: &ObsUncertainty,
+) -> (Vec<(f64, f64)>, Vec<(f64, f64)>) {
+    if curve.len() < 2 || dist <= 0. {
+        return (curve.to_vec(), curve.to_vec());
+    }
+
+    let dists = [dist - unc.dist_err, dist + unc.dist_err];
+    let incs = [
+        unc.inclination - unc.inclination_err,
+        unc.inclination + unc.inclination_err,
+    ];
+
+    let mut corners = Vec::new();
+    for d in dists {
+        for i in incs {
+            corners.push(rescale(
+                curve,
+                dist,
+                d.max(f64::EPSILON),
+                unc.inclination,
+                i,
+            ));
+        }
+    }
+
+    let mut lower = Vec::with_capacity(curve.len());
+    let mut upper = Vec::with_capacity(curve.len());
+
+    for (r, v) in curve {
+        let (mut v_min, mut v_max) = (*v, *v);
+        for corner in &corners {
+            if let Some(v_corner) = interpolate(corner, *r) {
+                v_min = v_min.min(v_corner);
+                v_max = v_max.max(v_corner);
+            }
+        }
+        lower.push((*r, v_min));
+        upper.push((*r, v_max));
+    }
+
+    (lower, upper)
+}
+
+/// Plot a simulated rotation curve against the galaxy's observed disk curve, with ±1σ bands if the
+/// galaxy has uncertainties set. `simulated` is in km/s.
+pub fn plot_rot_curve_overlay(
+    path: &Path,
+    descrip: &GalaxyDescrip,
+    simulated: &[(f64, f64)],
+    title: &str,
+) -> io::Result<()> {
+    let observed: Vec<(f64, f64)> = descrip
+        .rotation_curve_disk
+        .iter()
+        .map(|(r, v)| (*r, v / KPC_MYR_PER_KM_S))
+        .collect();
+
+    if !descrip.uncertainty.is_set() {
+        return plot_multi_to(
+            path,
+            &[("Observed", &observed), ("Simulated", simulated)],
+            "r (kpc)",
+            "v (km/s)",
+            title,
+        );
+    }
+
+    let (lower, upper) = curve_bounds(&observed, descrip.dist_from_earth, &descrip.uncertainty);
+
+    plot_multi_to(
+        path,
+        &[
+            ("Observed", &observed),
+            ("Simulated", simulated),
+            ("Observed -1σ", &lower),
+            ("Observed +1σ", &upper),
+        ],
+        "r (kpc)",
+        "v (km/s)",
+        title,
+    )
+}
@@ -0,0 +1,302 @@
+//! Approximate orbital elements, in the epicyclic approximation, relative to the axisymmetric
+//! part of a snapshot's potential. These are for studying orbital families, e.g. in barred runs:
+//! Bar orbits show as eccentric orbits with guiding radii inside corotation, and resonant families
+//! as clumps in epicyclic phase.
+//!
+//! The potential isn't computed directly. Instead, we azimuthally average the bodies' stored
+//! accelerations in annuli, so the elements reflect the run's force model, e.g. MOND, and not
+//! only Newtonian gravity from the bodies. The radial acceleration gives the circular velocity
+//! curve, from which come the guiding radius and epicyclic frequency. The vertical frequency comes
+//! from how the vertical acceleration grows with height near the plane.
+//!
+//! The approximation holds for near-circular orbits. Elements for e.g. halo bodies on radial
+//! orbits are rough.
+
+use std::{f64::consts::TAU, fmt::Write as _, fs, io, path::Path};
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    playback::SnapShot,
+    properties::{center_of_mass, disk_axis},
+    Body,
+};
+
+const N_ANNULI: usize = 40;
+/// Annuli extend to this percentile of bodies' cylindrical radii, so a few escaping bodies don't
+/// stretch them.
+const R_MAX_PERCENTILE: f64 = 0.95;
+/// Annuli with fewer bodies are skipped.
+const MIN_ANNULUS_BODIES: usize = 5;
+/// Bodies within this height of the plane are used to fit the vertical frequency. kpc.
+const Z_FIT_MAX: f64 = 0.5;
+
+/// For the inspected body.
+pub const HIGHLIGHT_COLOR: (f32, f32, f32) = (1., 0.85, 0.2);
+
+/// Body inspector settings.
+#[derive(Clone, Debug)]
+pub struct Inspector {
+    /// Index.
+    pub body: usize,
+    /// If set, the next click in the scene selects the body nearest it, on the galactic plane.
+    pub picking: bool,
+    /// Highlight the inspected body in each snapshot, so it can be followed during playback.
+    pub highlight: bool,
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        Self {
+            body: 0,
+            picking: false,
+            highlight: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitalElements {
+    /// Cylindrical radius. kpc.
+    pub r: f64,
+    /// Height above the disk plane. kpc.
+    pub z: f64,
+    /// Angular momentum about the disk axis, per unit mass. Negative for retrograde orbits.
+    /// kpc²/Myr.
+    pub l_z: f64,
+    /// The radius of a circular orbit with the same angular momentum. kpc.
+    pub r_guiding: f64,
+    /// (R_apo - R_peri) / (R_apo + R_peri), from the epicycle's amplitude.
+    pub eccentricity: f64,
+    /// The height of the vertical oscillation. kpc.
+    pub z_amplitude: f64,
+    /// Radians, from 0 to τ. 0 is apocenter, and π is pericenter.
+    pub epicyclic_phase: f64,
+    /// Epicyclic frequency at the guiding radius. 1/Myr.
+    pub kappa: f64,
+}
+
+/// The axisymmetric part of the potential, as profiles of circular velocity and vertical frequency
+/// by cylindrical radius, in the disk's frame.
+pub struct AxisymmetricPotential {
+    center: Vec3,
+    vel: Vec3,
+    axis: Vec3,
+    /// (R, v_c²). kpc, (kpc/Myr)².
+    v_circ_sq: Vec<(f64, f64)>,
+    /// (R, ν²). kpc, 1/Myr².
+    nu_sq: Vec<(f64, f64)>,
+}
+
+/// (x, y) on the line through the table's neighboring points; flat past its ends.
+fn lookup(table: &[(f64, f64)], x: f64) -> Option<f64> {
+    let (first, last) = (table.first()?, table.last()?);
+    if x <= first.0 {
+        return Some(first.1);
+    }
+    if x >= last.0 {
+        return Some(last.1);
+    }
+
+    let i = table.partition_point(|(x_, _)| *x_ < x);
+    let ((x0, y0), (x1, y1)) = (table[i - 1], table[i]);
+    Some(y0 + (x - x0) / (x1 - x0) * (y1 - y0))
+}
+
+impl AxisymmetricPotential {
+    pub fn new(bodies: &[Body]) -> Self {
+        let (center, vel) = center_of_mass(bodies);
+        let axis = disk_axis(bodies, center);
+
+        let mut result = Self {
+            center,
+            vel,
+            axis,
+            v_circ_sq: Vec::new(),
+            nu_sq: Vec::new(),
+        };
+
+        // (R, radial acceleration outward, z, vertical acceleration)
+        let cylindrical: Vec<(f64, f64, f64, f64)> = bodies
+            .iter()
+            .filter_map(|b| {
+                let (r_hat, r, z) = result.plane_coords(b.posit)?;
+                Some((r, b.accel.dot(r_hat), z, b.accel.dot(axis)))
+            })
+            .collect();
+
+        let mut radii: Vec<f64> = cylindrical.iter().map(|c| c.0).collect();
+        if radii.is_empty() {
+            return result;
+        }
+        radii.sort_by(|a, b| a.total_cmp(b));
+        let r_max = radii[((radii.len() - 1) as f64 * R_MAX_PERCENTILE) as usize];
+        let dr = r_max / N_ANNULI as f64;
+
+        for i in 0..N_ANNULI {
+            let (r_inner, r_outer) = (i as f64 * dr, (i + 1) as f64 * dr);
+            let in_annulus: Vec<_> = cylindrical
+                .iter()
+                .filter(|c| c.0 >= r_inner && c.0 < r_outer)
+                .collect();
+            if in_annulus.len() < MIN_ANNULUS_BODIES {
+                continue;
+            }
+
+            let r = (r_inner + r_outer) / 2.;
+            let g_r = -in_annulus.iter().map(|c| c.1).sum::<f64>() / in_annulus.len() as f64;
+            result.v_circ_sq.push((r, (r * g_r).max(0.)));
+
+            // a_z = -ν² z, fit through the origin.
+            let (mut az_z, mut z_sq) = (0., 0.);
+            for (_, _, z, a_z) in in_annulus.iter().filter(|c| c.2.abs() < Z_FIT_MAX) {
+                az_z += a_z * z;
+                z_sq += z * z;
+            }
+            if z_sq > 0. && az_z < 0. {
+                result.nu_sq.push((r, -az_z / z_sq));
+            }
+        }
+
+        result
+    }
+
+    /// The outward unit vector in the disk plane, cylindrical radius, and height. `None` on the
+    /// axis.
+    fn plane_coords(&self, posit: Vec3) -> Option<(Vec3, f64, f64)> {
+        let diff = posit - self.center;
+        let z = diff.dot(self.axis);
+        let in_plane = diff - self.axis * z;
+        let r = in_plane.magnitude();
+        (r > f64::EPSILON).then(|| (in_plane / r, r, z))
+    }
+
+    fn v_circ(&self, r: f64) -> Option<f64> {
+        lookup(&self.v_circ_sq, r).map(f64::sqrt)
+    }
+
+    /// κ² = 2Ω² + (1/R) d(v_c²)/dR.
+    fn kappa_sq(&self, r: f64) -> Option<f64> {
+        let v_sq = lookup(&self.v_circ_sq, r)?;
+        let h = (r * 0.05).max(1e-3);
+        let dv_sq = lookup(&self.v_circ_sq, r + h)? - lookup(&self.v_circ_sq, (r - h).max(0.))?;
+        let deriv = dv_sq / (r + h - (r - h).max(0.));
+
+        Some(2. * v_sq / (r * r) + deriv / r)
+    }
+
+    /// The radius where a circular orbit has angular momentum `l`. Past the profile's end, the
+    /// curve is taken to be flat.
+    fn r_guiding(&self, l: f64) -> Option<f64> {
+        let mut prev = (0., 0.);
+        for (r, v_sq) in &self.v_circ_sq {
+            let l_circ = r * v_sq.sqrt();
+            if l_circ >= l {
+                let t = (l - prev.1) / (l_circ - prev.1).max(f64::EPSILON);
+                return Some(prev.0 + t * (r - prev.0));
+            }
+            prev = (*r, l_circ);
+        }
+
+        let (r_last, _) = self.v_circ_sq.last()?;
+        let v = self.v_circ(*r_last)?;
+        (v > 0.).then(|| l / v)
+    }
+
+    /// Elements of a body with this position (kpc) and velocity (kpc/Myr). `None` if they can't be
+    /// estimated, e.g. if the body is on the axis, or the profile is empty or locally unstable.
+    pub fn elements(&self, posit: Vec3, vel: Vec3) -> Option<OrbitalElements> {
+        let (r_hat, r, z) = self.plane_coords(posit)?;
+        let vel = vel - self.vel;
+        let v_r = vel.dot(r_hat);
+        let v_φ = vel.dot(self.axis.cross(r_hat));
+        let v_z = vel.dot(self.axis);
+
+        let l_z = r * v_φ;
+        let r_guiding = self.r_guiding(l_z.abs()).filter(|r| *r > 0.)?;
+
+        let kappa_sq = self.kappa_sq(r_guiding)?;
+        if kappa_sq <= 0. {
+            return None;
+        }
+        let kappa = kappa_sq.sqrt();
+
+        // R - R_g = A cos θ; v_R = -A κ sin θ.
+        let x = r - r_guiding;
+        let amplitude = (x * x + (v_r / kappa).powi(2)).sqrt();
+        let epicyclic_phase = (-v_r / kappa).atan2(x).rem_euclid(TAU);
+
+        // Without a vertical fit, e.g. for a thin or face-on-only sample, use the spherical value.
+        let nu_sq = lookup(&self.nu_sq, r)
+            .or_else(|| self.v_circ(r).map(|v| (v / r).powi(2)))
+            .filter(|n| *n > 0.)?;
+        let z_amplitude = (z * z + v_z * v_z / nu_sq).sqrt();
+
+        Some(OrbitalElements {
+            r,
+            z,
+            l_z,
+            r_guiding,
+            eccentricity: amplitude / r_guiding.max(f64::EPSILON),
+            z_amplitude,
+            epicyclic_phase,
+            kappa,
+        })
+    }
+}
+
+/// The index of the body closest to `posit` in the disk plane, ignoring height.
+pub fn nearest_body(snap: &SnapShot, posit: Vec3) -> Option<usize> {
+    snap.body_posits
+        .iter()
+        .map(|p| (p.x as f64 - posit.x).powi(2) + (p.y as f64 - posit.y).powi(2))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(k, _)| snap.body_id(k))
+}
+
+/// Elements of each body in the snapshot, by body index.
+pub fn snapshot_elements(
+    snap: &SnapShot,
+    body_masses: &[f32],
+) -> Vec<(usize, Option<OrbitalElements>)> {
+    let bodies = snap.bodies(body_masses);
+    let potential = AxisymmetricPotential::new(&bodies);
+
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(k, b)| (snap.body_id(k), potential.elements(b.posit, b.vel)))
+        .collect()
+}
+
+/// Save elements as CSV, one row per body. Bodies without elements have empty fields.
+pub fn save_csv(path: &Path, elements: &[(usize, Option<OrbitalElements>)]) -> io::Result<()> {
+    let mut csv = "body,r_kpc,z_kpc,l_z_kpc2_myr,r_guiding_kpc,eccentricity,z_amplitude_kpc,\
+        epicyclic_phase_rad,kappa_per_myr\n"
+        .to_owned();
+
+    for (id, el) in elements {
+        match el {
+            Some(el) => writeln!(
+                csv,
+                "{id},{},{},{},{},{},{},{},{}",
+                el.r,
+                el.z,
+                el.l_z,
+                el.r_guiding,
+                el.eccentricity,
+                el.z_amplitude,
+                el.epicyclic_phase,
+                el.kappa
+            ),
+            None => writeln!(csv, "{id},,,,,,,,"),
+        }
+        .unwrap();
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, csv)
+}
@@ -0,0 +1,475 @@
+//! Overlays for spatial context. In the 3D scene: A reference grid in the galactic plane, rings at
+//! fixed radii, and coordinate axes. Drawn over the view in 2D: Their labels, a scale bar in kpc,
+//! an axis triad showing the camera's orientation, a HUD describing the run, and a Doppler color
+//! bar.
+
+use std::f32::consts::TAU;
+
+use bincode::{Decode, Encode};
+use egui::{
+    pos2, vec2, Align2, Color32, Context, FontId, Id, LayerId, Order, Pos2, Rect, Shape, Stroke,
+};
+use graphics::{Camera, Entity, Mesh, FWD_VEC, UP_VEC};
+use lin_alg::f32::{Quaternion, Vec3};
+
+use crate::{
+    doppler::doppler_color,
+    render::{MESH_ARROW, MESH_GRID_LINE, MESH_SPHERE},
+};
+
+type Color = (f32, f32, f32);
+
+/// Clicks this close to a body on screen select it. Pixels.
+const PICK_RADIUS_PX: f32 = 6.;
+
+/// From the view's top left. Pixels.
+const HUD_PADDING: f32 = 8.;
+const HUD_LINE_SPACING: f32 = 2.;
+
+/// Grid lines span ±this, in X and Y. kpc.
+const GRID_EXTENT: i32 = 20;
+/// kpc
+const GRID_SPACING: f32 = 1.;
+/// Scene units, independent of world scale.
+const GRID_LINE_WIDTH: f32 = 0.02;
+const GRID_COLOR: Color = (0.25, 0.25, 0.3);
+const GRID_SHINYNESS: f32 = 0.;
+
+/// Labeled rings. kpc.
+const RING_RADII: [f32; 3] = [5., 10., 20.];
+const RING_DOTS: usize = 120;
+/// Scene units.
+const RING_DOT_SIZE: f32 = 0.04;
+const RING_COLOR: Color = (0.5, 0.5, 0.65);
+
+/// kpc
+const AXIS_LEN_KPC: f32 = 5.;
+const AXIS_COLORS_3D: [Color; 3] = [(1., 0.2, 0.2), (0.2, 1., 0.2), (0.3, 0.5, 1.)];
+const AXIS_SHINYNESS: f32 = 1.;
+
+/// The scale bar is at most this long. Pixels.
+const SCALE_BAR_MAX_LEN: f32 = 200.;
+/// Axis triad arm length. Pixels.
+const AXIS_LEN: f32 = 40.;
+/// From the view's edges. Pixels.
+const MARGIN: f32 = 30.;
+
+/// Doppler color bar. Pixels.
+const LEGEND_WIDTH: f32 = 160.;
+const LEGEND_HEIGHT: f32 = 12.;
+const LEGEND_STEPS: usize = 32;
+
+/// Curve inset, e.g. for initial-condition previews. Pixels.
+const INSET_WIDTH: f32 = 280.;
+const INSET_HEIGHT: f32 = 160.;
+/// Below the Doppler color bar.
+const INSET_TOP: f32 = 80.;
+
+const OVERLAY_COLOR: Color32 = Color32::WHITE;
+const AXIS_COLORS: [Color32; 3] = [Color32::RED, Color32::GREEN, Color32::LIGHT_BLUE];
+
+/// A grid line, spanning the grid at a given world scale. Its length and width are baked in, since
+/// entity scale is uniform.
+pub fn grid_line_mesh(world_scale: f32) -> Mesh {
+    let len = 2. * GRID_EXTENT as f32 * GRID_SPACING * world_scale;
+    Mesh::new_box(len, GRID_LINE_WIDTH, GRID_LINE_WIDTH)
+}
+
+/// Add grid lines in the XY (galactic) plane, and dotted rings at fixed radii. Uses the grid line
+/// mesh, which must be built at the same world scale.
+pub fn add_grid(entities: &mut Vec<Entity>, world_scale: f32) {
+    // Lines are built along X; rotate them for the ones along Y.
+    let along_y = Quaternion::from_axis_angle(FWD_VEC, TAU / 4.);
+
+    for i in -GRID_EXTENT..=GRID_EXTENT {
+        let offset = i as f32 * GRID_SPACING * world_scale;
+
+        for (posit, orientation) in [
+            (Vec3::new(0., offset, 0.), Quaternion::new_identity()),
+            (Vec3::new(offset, 0., 0.), along_y),
+        ] {
+            entities.push(Entity::new(
+                MESH_GRID_LINE,
+                posit,
+                orientation,
+                1.,
+                GRID_COLOR,
+                GRID_SHINYNESS,
+            ));
+        }
+    }
+
+    for r in RING_RADII {
+        for i in 0..RING_DOTS {
+            let θ = TAU * i as f32 / RING_DOTS as f32;
+            entities.push(Entity::new(
+                MESH_SPHERE,
+                Vec3::new(θ.cos(), θ.sin(), 0.) * r * world_scale,
+                Quaternion::new_identity(),
+                RING_DOT_SIZE,
+                RING_COLOR,
+                GRID_SHINYNESS,
+            ));
+        }
+    }
+}
+
+/// Add arrows along the X, Y, and Z axes, from the origin.
+pub fn add_axes(entities: &mut Vec<Entity>, world_scale: f32) {
+    for (axis, color) in unit_axes().into_iter().zip(AXIS_COLORS_3D) {
+        entities.push(Entity::new(
+            MESH_ARROW,
+            Vec3::new_zero(),
+            Quaternion::from_unit_vecs(UP_VEC, axis),
+            AXIS_LEN_KPC * world_scale,
+            color,
+            AXIS_SHINYNESS,
+        ));
+    }
+}
+
+fn unit_axes() -> [Vec3; 3] {
+    [
+        Vec3::new(1., 0., 0.),
+        Vec3::new(0., 1., 0.),
+        Vec3::new(0., 0., 1.),
+    ]
+}
+
+/// A scene position on screen, in the view's rect. `None` if it's behind the camera.
+fn project(camera: &Camera, rect: Rect, posit: Vec3) -> Option<Pos2> {
+    let v = camera
+        .orientation
+        .inverse()
+        .rotate_vec(posit - camera.position);
+    if v.z <= camera.near {
+        return None;
+    }
+
+    let half_height = v.z * (camera.fov_y / 2.).tan();
+    let half_width = half_height * rect.aspect_ratio();
+    let center = rect.center();
+
+    Some(pos2(
+        center.x + v.x / half_width * rect.width() / 2.,
+        center.y - v.y / half_height * rect.height() / 2.,
+    ))
+}
+
+/// The point on the galactic (XY) plane under a screen position, in world coordinates; the inverse
+/// of `project`, for points on the plane. `None` if the view ray doesn't cross the plane ahead of
+/// the camera.
+pub fn pick_plane(camera: &Camera, rect: Rect, screen: Pos2) -> Option<Vec3> {
+    let dir = view_ray(camera, rect, screen);
+    if dir.z.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    let dist = -camera.position.z / dir.z;
+    if dist <= 0. {
+        return None;
+    }
+    Some(camera.position + dir * dist)
+}
+
+/// The entity, of the first `count`, that the view ray under a screen position hits first. Each is
+/// treated as a sphere of its scale, grown to at least `PICK_RADIUS_PX` on screen, so small bodies
+/// can be clicked.
+pub fn pick_entity(
+    camera: &Camera,
+    rect: Rect,
+    screen: Pos2,
+    entities: &[Entity],
+    count: usize,
+) -> Option<usize> {
+    let dir = view_ray(camera, rect, screen);
+    let dir_len = dir.magnitude();
+    // Scene units per pixel, per unit of distance along the view axis.
+    let px_size = 2. * (camera.fov_y / 2.).tan() / rect.height();
+
+    let mut nearest: Option<(usize, f32)> = None;
+    for (k, entity) in entities.iter().take(count).enumerate() {
+        // E.g. the body we're riding is hidden.
+        if entity.scale <= 0. {
+            continue;
+        }
+        let offset = entity.position - camera.position;
+        // Distance along the view axis, since `dir` has unit Z in the camera's frame.
+        let depth = offset.dot(dir) / (dir_len * dir_len);
+        if depth <= camera.near {
+            continue;
+        }
+
+        let miss = (offset - dir * depth).magnitude();
+        let radius = entity.scale.max(PICK_RADIUS_PX * px_size * depth);
+        if miss <= radius && nearest.is_none_or(|(_, d)| depth < d) {
+            nearest = Some((k, depth));
+        }
+    }
+    nearest.map(|(k, _)| k)
+}
+
+/// The direction through a screen position from the camera, in world coordinates. Scaled to unit
+/// length along the view axis.
+fn view_ray(camera: &Camera, rect: Rect, screen: Pos2) -> Vec3 {
+    let half_height = (camera.fov_y / 2.).tan();
+    let half_width = half_height * rect.aspect_ratio();
+    let center = rect.center();
+
+    camera.orientation.rotate_vec(Vec3::new(
+        (screen.x - center.x) / (rect.width() / 2.) * half_width,
+        -(screen.y - center.y) / (rect.height() / 2.) * half_height,
+        1.,
+    ))
+}
+
+/// Label the rings and axes in the scene, where they're visible.
+pub fn draw_scene_labels(ctx: &Context, camera: &Camera, world_scale: f32, grid: bool, axes: bool) {
+    let rect = ctx.available_rect();
+    let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("scene_labels")));
+    let font = FontId::proportional(13.);
+
+    if grid {
+        let color = Color32::from_rgb(160, 160, 200);
+        for r in RING_RADII {
+            // Between the X and Y axes, clear of the axis labels.
+            let dir = Vec3::new(1., 1., 0.).to_normalized();
+            if let Some(p) = project(camera, rect, dir * r * world_scale) {
+                let text = format!("{r:.0} kpc");
+                painter.text(p, Align2::LEFT_BOTTOM, text, font.clone(), color);
+            }
+        }
+    }
+
+    if axes {
+        for ((axis, label), color) in unit_axes()
+            .into_iter()
+            .zip(["X", "Y", "Z"])
+            .zip(AXIS_COLORS)
+        {
+            let tip = axis * AXIS_LEN_KPC * world_scale * 1.1;
+            if let Some(p) = project(camera, rect, tip) {
+                painter.text(p, Align2::CENTER_CENTER, label, font.clone(), color);
+            }
+        }
+    }
+}
+
+/// The largest of 1, 2, or 5 × 10ⁿ that's at most `max`.
+fn round_length(max: f32) -> f32 {
+    let magnitude = 10_f32.powf(max.log10().floor());
+    [5., 2., 1.]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|l| *l <= max)
+        .unwrap_or(magnitude)
+}
+
+/// Draw a scale bar, and axis triad. `world_scale` is scene units per kpc. The bar is true to
+/// scale at the origin's depth from the camera; it's omitted if the origin is behind the camera.
+pub fn draw_scale_overlay(ctx: &Context, camera: &Camera, world_scale: f32) {
+    let rect = ctx.available_rect();
+    let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("scale_overlay")));
+    let stroke = Stroke::new(2., OVERLAY_COLOR);
+    let font = FontId::proportional(14.);
+
+    let fwd = camera.orientation.rotate_vec(FWD_VEC);
+    let depth = (-camera.position).dot(fwd);
+
+    if depth > camera.near && world_scale > 0. {
+        let px_per_unit = rect.height() / 2. / (depth * (camera.fov_y / 2.).tan());
+        let px_per_kpc = px_per_unit * world_scale;
+
+        let len_kpc = round_length(SCALE_BAR_MAX_LEN / px_per_kpc);
+        let len_px = len_kpc * px_per_kpc;
+        let decimals = (-len_kpc.log10().floor()).max(0.) as usize;
+
+        let start = rect.left_bottom() + vec2(MARGIN, -MARGIN);
+        let end = start + vec2(len_px, 0.);
+        painter.line_segment([start, end], stroke);
+        for p in [start, end] {
+            painter.line_segment([p + vec2(0., -5.), p + vec2(0., 5.)], stroke);
+        }
+        painter.text(
+            start + vec2(len_px / 2., -8.),
+            Align2::CENTER_BOTTOM,
+            format!("{len_kpc:.decimals$} kpc"),
+            font.clone(),
+            OVERLAY_COLOR,
+        );
+    }
+
+    // Each axis, in the camera's frame. Screen Y is down.
+    let center = rect.right_bottom() + vec2(-MARGIN - AXIS_LEN, -MARGIN - AXIS_LEN);
+    let to_cam = camera.orientation.inverse();
+    let axes = ["X", "Y", "Z"].into_iter().zip(unit_axes());
+
+    for ((label, axis), color) in axes.zip(AXIS_COLORS) {
+        let v = to_cam.rotate_vec(axis);
+        let tip = center + vec2(v.x, -v.y) * AXIS_LEN;
+
+        painter.line_segment([center, tip], Stroke::new(2., color));
+        painter.text(
+            tip + vec2(v.x, -v.y) * 8.,
+            Align2::CENTER_CENTER,
+            label,
+            font.clone(),
+            color,
+        );
+    }
+    painter.circle_filled(center, 2., OVERLAY_COLOR);
+}
+
+/// A small plot of curves, e.g. rotation curves, at the view's right, with lines of text below.
+/// Each curve is (points, color, label). Axes start at 0.
+pub fn draw_curve_inset(
+    ctx: &Context,
+    curves: &[(&[(f64, f64)], Color32, &str)],
+    x_label: &str,
+    y_label: &str,
+    text: &[String],
+) {
+    let rect = ctx.available_rect();
+    let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("curve_inset")));
+    let font = FontId::proportional(13.);
+    let stroke = Stroke::new(1., OVERLAY_COLOR);
+
+    let origin = rect.right_top() + vec2(-MARGIN - INSET_WIDTH, INSET_TOP);
+    let plot = Rect::from_min_size(origin, vec2(INSET_WIDTH, INSET_HEIGHT));
+    let text_height = text.len() as f32 * (13. + HUD_LINE_SPACING);
+    let background = Rect::from_min_max(
+        plot.min - vec2(HUD_PADDING + 20., HUD_PADDING + 16.),
+        plot.max + vec2(HUD_PADDING, HUD_PADDING + 20. + text_height),
+    );
+    painter.rect_filled(background, 4., Color32::from_black_alpha(160));
+
+    let points = curves.iter().flat_map(|(c, _, _)| c.iter());
+    let (x_max, y_max) = points.fold((0., 0.), |(x, y): (f64, f64), p| (x.max(p.0), y.max(p.1)));
+    if x_max <= 0. || y_max <= 0. {
+        return;
+    }
+    let to_screen = |(x, y): (f64, f64)| {
+        pos2(
+            plot.left() + (x / x_max) as f32 * plot.width(),
+            plot.bottom() - (y.max(0.) / y_max) as f32 * plot.height(),
+        )
+    };
+
+    painter.line_segment([plot.left_bottom(), plot.right_bottom()], stroke);
+    painter.line_segment([plot.left_bottom(), plot.left_top()], stroke);
+    painter.text(
+        plot.right_bottom() + vec2(0., 4.),
+        Align2::RIGHT_TOP,
+        format!("{x_max:.0} {x_label}"),
+        font.clone(),
+        OVERLAY_COLOR,
+    );
+    painter.text(
+        plot.left_top() - vec2(4., 0.),
+        Align2::RIGHT_CENTER,
+        format!("{y_max:.0}"),
+        font.clone(),
+        OVERLAY_COLOR,
+    );
+    painter.text(
+        plot.left_top() - vec2(0., 4.),
+        Align2::LEFT_BOTTOM,
+        y_label,
+        font.clone(),
+        OVERLAY_COLOR,
+    );
+
+    let mut legend_pos = plot.right_top() + vec2(-4., 4.);
+    for (curve, color, label) in curves {
+        let line: Vec<Pos2> = curve.iter().copied().map(to_screen).collect();
+        painter.add(Shape::line(line, Stroke::new(2., *color)));
+
+        painter.text(legend_pos, Align2::RIGHT_TOP, *label, font.clone(), *color);
+        legend_pos.y += 13. + HUD_LINE_SPACING;
+    }
+
+    let mut pos = plot.left_bottom() + vec2(0., 20.);
+    for line in text {
+        painter.text(pos, Align2::LEFT_TOP, line, font.clone(), OVERLAY_COLOR);
+        pos.y += 13. + HUD_LINE_SPACING;
+    }
+}
+
+/// Describes the run being shown, for the HUD. Set when it's built or loaded, vice taken from the
+/// UI selections, which may have changed since.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct RunLabels {
+    pub galaxy: String,
+    pub force_model: String,
+}
+
+/// A heads-up display of the run, and the snapshot's time and body count, at the view's top left.
+/// It's drawn over the view, so screenshots and recordings of the window are self-describing.
+pub fn draw_hud(ctx: &Context, labels: &RunLabels, time: f32, num_bodies: usize) {
+    let rect = ctx.available_rect();
+    let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("hud")));
+    let font = FontId::monospace(14.);
+
+    let lines = [
+        labels.galaxy.clone(),
+        labels.force_model.clone(),
+        format!("t = {time:.1} Myr"),
+        format!("{num_bodies} bodies"),
+    ];
+    let galleys: Vec<_> = lines
+        .into_iter()
+        .map(|l| painter.layout_no_wrap(l, font.clone(), OVERLAY_COLOR))
+        .collect();
+
+    let width = galleys.iter().map(|g| g.size().x).fold(0., f32::max);
+    let height: f32 = galleys.iter().map(|g| g.size().y + HUD_LINE_SPACING).sum();
+
+    let origin = rect.left_top() + vec2(HUD_PADDING, HUD_PADDING);
+    let background = Rect::from_min_size(origin, vec2(width, height)).expand(HUD_PADDING / 2.);
+    painter.rect_filled(background, 4., Color32::from_black_alpha(160));
+
+    let mut pos = origin;
+    for galley in galleys {
+        let line_height = galley.size().y;
+        painter.galley(pos, galley, OVERLAY_COLOR);
+        pos.y += line_height + HUD_LINE_SPACING;
+    }
+}
+
+/// A color bar for Doppler coloring, at the view's top right. `v_max` is in km/s.
+pub fn draw_doppler_legend(ctx: &Context, v_max: f32) {
+    let rect = ctx.available_rect();
+    let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("doppler_legend")));
+    let font = FontId::proportional(13.);
+
+    let origin = rect.right_top() + vec2(-MARGIN - LEGEND_WIDTH, MARGIN);
+    let step_width = LEGEND_WIDTH / LEGEND_STEPS as f32;
+
+    for i in 0..LEGEND_STEPS {
+        let v = v_max * (2. * (i as f32 + 0.5) / LEGEND_STEPS as f32 - 1.);
+        let (r, g, b) = doppler_color(v, v_max);
+        let color = Color32::from_rgb((r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8);
+
+        let min = origin + vec2(i as f32 * step_width, 0.);
+        painter.rect_filled(
+            Rect::from_min_size(min, vec2(step_width, LEGEND_HEIGHT)),
+            0.,
+            color,
+        );
+    }
+
+    let below = origin + vec2(0., LEGEND_HEIGHT + 4.);
+    for (x, text, align) in [
+        (0., format!("-{v_max:.0}"), Align2::LEFT_TOP),
+        (LEGEND_WIDTH / 2., "0 km/s".to_owned(), Align2::CENTER_TOP),
+        (LEGEND_WIDTH, format!("+{v_max:.0}"), Align2::RIGHT_TOP),
+    ] {
+        painter.text(
+            below + vec2(x, 0.),
+            align,
+            text,
+            font.clone(),
+            OVERLAY_COLOR,
+        );
+    }
+}
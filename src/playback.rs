@@ -2,10 +2,9 @@
 //!
 
 use std::{
+    collections::VecDeque,
     fs::File,
-    io,
-    io::{ErrorKind, Read, Write},
-    path::Path,
+    io::{Read, Write},
 };
 
 use barnes_hut::{Cube, Node};
@@ -14,21 +13,30 @@ use bincode::{
     error::{DecodeError, EncodeError},
     Decode, Encode,
 };
-use graphics::{Entity, UP_VEC};
+use graphics::{Entity, RIGHT_VEC, UP_VEC};
 use lin_alg::{
     f32::{Quaternion, Vec3 as Vec3f32},
     f64::Vec3,
 };
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
-    grav_shell::GravShell,
+    body_creation::Component,
+    diagnostics::Diagnostics,
+    grav_shell::{GravShell, MAX_SHELL_R},
     render::{
         ARROW_COLOR, ARROW_SHINYNESS, BODY_COLOR, BODY_SHINYNESS, BODY_SIZE_MAX, BODY_SIZE_MIN,
-        BODY_SIZE_SCALER, MESH_ARROW, MESH_CUBE, MESH_SPHERE, SHELL_COLOR, TREE_COLOR,
+        BODY_SIZE_SCALER, MESH_ARROW, MESH_CUBE, MESH_SPHERE, MESH_TRAIL, SHELL_SHINYNESS,
+        TIDAL_COLOR, TIDAL_OPACITY, TIDAL_SHINYNESS, TRAIL_OPACITY, TRAIL_SHINYNESS, TREE_COLOR,
         TREE_CUBE_SCALE_FACTOR, TREE_SHINYNESS,
     },
+    tidal::TidalHistory,
+    Body,
 };
 
+/// Above this many shells in a snapshot, we draw a subset.
+const SHELLS_DRAWN_MAX: usize = 2_000;
+
 #[derive(Debug, Encode, Decode)]
 /// A compact version
 pub struct GravShellSnapshot {
@@ -55,8 +63,7 @@ pub struct SnapShot {
     pub body_posits: Vec<Vec3f32>,
     // pub V_at_bodies: Vec<Vec3f32>,
     pub body_accs: Vec<Vec3f32>,
-    // todo: Velocity?
-    // pub body_vels: Vec<Vec3f32>,
+    pub body_vels: Vec<Vec3f32>,
     // todo: Determine if you want to store and show these.
     // todo: Store a posit and a velocity for rays A/R.
     // The usize is body id.
@@ -65,25 +72,284 @@ pub struct SnapShot {
     pub shells: Vec<GravShellSnapshot>,
     pub dt: f32,
     pub tree_cubes: Vec<Cube>, // todo: Custom type type f32, as above.
+    /// Each stored body's ID, sorted: Its index in the run's body masses and components. Empty if
+    /// this has all the run's initial bodies, in order. IDs are stable across a run; bodies removed
+    /// during it, e.g. by the superluminal guard, leave gaps, and bodies added, e.g. by mass
+    /// refinement, get new ones.
+    pub body_ids: Vec<u32>,
+    /// Each stored body's mass, in order, if bodies' masses changed during the run, e.g. from mass
+    /// refinement. Empty otherwise; masses are then the run's, by ID.
+    pub body_masses: Vec<f32>,
+    /// If this has a subset of the bodies present at its time, from output decimation.
+    pub subset: bool,
+    /// Energy and angular momentum, if sampled at this snapshot's step.
+    pub diagnostics: Option<Diagnostics>,
+}
+
+impl SnapShot {
+    /// If this snapshot has all bodies present at its time, vice a subset.
+    pub fn is_full(&self) -> bool {
+        !self.subset
+    }
+
+    /// The index of the body at position `k` in this snapshot.
+    pub fn body_id(&self, k: usize) -> usize {
+        self.body_ids.get(k).map_or(k, |id| *id as usize)
+    }
+
+    /// The position of a body in this snapshot, if it's included.
+    pub fn index_of(&self, body: usize) -> Option<usize> {
+        if self.body_ids.is_empty() {
+            (body < self.body_posits.len()).then_some(body)
+        } else {
+            self.body_ids.binary_search(&(body as u32)).ok()
+        }
+    }
+
+    /// The mass of each body in this snapshot. `body_masses` are the run's, by ID; they're only used
+    /// if this snapshot doesn't store its own.
+    pub fn masses(&self, body_masses: &[f32]) -> Vec<f32> {
+        if !self.body_masses.is_empty() {
+            return self.body_masses.clone();
+        }
+
+        (0..self.body_posits.len())
+            .map(|k| {
+                body_masses
+                    .get(self.body_id(k))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Reconstruct bodies, e.g. for use with the `properties` functions. For a snapshot of a subset,
+    /// only those bodies.
+    pub fn bodies(&self, body_masses: &[f32]) -> Vec<Body> {
+        let to_f64 = |v: &Vec3f32| Vec3::new(v.x as f64, v.y as f64, v.z as f64);
+        let masses = self.masses(body_masses);
+
+        self.body_posits
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Body {
+                posit: to_f64(p),
+                vel: self
+                    .body_vels
+                    .get(i)
+                    .map(to_f64)
+                    .unwrap_or(Vec3::new_zero()),
+                accel: self
+                    .body_accs
+                    .get(i)
+                    .map(to_f64)
+                    .unwrap_or(Vec3::new_zero()),
+                mass: masses[i] as f64,
+                // Not stored in snapshots.
+                component: Default::default(),
+            })
+            .collect()
+    }
+}
+
+/// Which bodies to store in snapshots between full ones.
+#[derive(Clone, PartialEq, Debug, Default, Encode, Decode)]
+pub enum SnapshotSubset {
+    #[default]
+    All,
+    /// A random portion of bodies, from 0 to 1. The same bodies in each snapshot.
+    Random(f64),
+    /// Every k-th body.
+    EveryKth(usize),
+    /// Only these bodies, by index.
+    Tracers(Vec<usize>),
+}
+
+impl SnapshotSubset {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::All => "All",
+            Self::Random(_) => "Random",
+            Self::EveryKth(_) => "Every k-th",
+            Self::Tracers(_) => "Tracers",
+        }
+        .to_owned()
+    }
+
+    /// The indices of the bodies to store, sorted. `None` if all. `seed` is for the random subset.
+    pub fn body_ids(&self, num_bodies: usize, seed: Option<u64>) -> Option<Vec<u32>> {
+        let ids: Vec<u32> = match self {
+            Self::All => return None,
+            Self::Random(portion) => {
+                let mut rng = match seed {
+                    Some(seed) => StdRng::seed_from_u64(seed),
+                    None => StdRng::from_os_rng(),
+                };
+                (0..num_bodies as u32)
+                    .filter(|_| rng.random_bool(portion.clamp(0., 1.)))
+                    .collect()
+            }
+            Self::EveryKth(k) => (0..num_bodies as u32).step_by((*k).max(1)).collect(),
+            Self::Tracers(ids) => {
+                let mut result: Vec<u32> = ids
+                    .iter()
+                    .filter(|id| **id < num_bodies)
+                    .map(|id| *id as u32)
+                    .collect();
+                result.sort_unstable();
+                result.dedup();
+                result
+            }
+        };
+        Some(ids)
+    }
+}
+
+/// Output decimation, for large runs: Store only a subset of bodies in most snapshots, and all of
+/// them less frequently. Snapshots are still taken every `snapshot_ratio` steps.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct SnapshotDecimation {
+    pub subset: SnapshotSubset,
+    /// Every this many snapshots stores all bodies, e.g. for analysis. If 0, only the first does.
+    pub full_ratio: usize,
+}
+
+impl Default for SnapshotDecimation {
+    fn default() -> Self {
+        Self {
+            subset: Default::default(),
+            full_ratio: 10,
+        }
+    }
+}
+
+type Color = (f32, f32, f32);
+
+/// What body colors show.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum BodyColorMode {
+    #[default]
+    Plain,
+    /// Log scale.
+    Mass,
+    Speed,
+    /// Log scale; accelerations span orders of magnitude between the center and outskirts.
+    Accel,
+    /// The galaxy component each body was generated for: disk, bulge, gas, or halo.
+    Population,
+}
+
+impl BodyColorMode {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Plain => "Plain",
+            Self::Mass => "Mass",
+            Self::Speed => "|v|",
+            Self::Accel => "|a|",
+            Self::Population => "Population",
+        }
+        .to_owned()
+    }
 }
 
-/// Body masses are separate from the snapshot, since it's invariant.
-pub fn change_snapshot(entities: &mut Vec<Entity>, snapshot: &SnapShot, body_masses: &[f32]) {
+/// Viridis, sampled at even intervals; perceptually uniform, and readable on a dark background.
+const COLOR_MAP: [Color; 5] = [
+    (0.267, 0.005, 0.329),
+    (0.229, 0.322, 0.546),
+    (0.128, 0.567, 0.551),
+    (0.369, 0.789, 0.383),
+    (0.993, 0.906, 0.144),
+];
+
+/// `t` is from 0 to 1.
+pub fn color_map(t: f32) -> Color {
+    let x = t.clamp(0., 1.) * (COLOR_MAP.len() - 1) as f32;
+    let i = (x as usize).min(COLOR_MAP.len() - 2);
+    let (a, b, f) = (COLOR_MAP[i], COLOR_MAP[i + 1], x - i as f32);
+
+    (
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
+pub fn population_color(component: Component) -> Color {
+    match component {
+        Component::Disk => (0.35, 0.6, 1.),
+        Component::Bulge => (1., 0.65, 0.25),
+        Component::Gas => (0.3, 0.9, 0.55),
+        Component::Halo => (0.7, 0.5, 0.9),
+    }
+}
+
+/// Each body's color in this snapshot, for the mode. `components` is indexed by body id; bodies
+/// without one, e.g. from snapshots loaded from a file, show as disk. Scalar modes are normalized
+/// between the snapshot's 1st and 99th percentiles, so a few outliers don't wash out the rest.
+fn body_colors(
+    snapshot: &SnapShot,
+    body_masses: &[f32],
+    components: &[Component],
+    mode: BodyColorMode,
+) -> Vec<Color> {
+    let n = snapshot.body_posits.len();
+    let log = |v: f32| v.max(f32::MIN_POSITIVE).log10();
+
+    let vals: Vec<f32> = match mode {
+        BodyColorMode::Plain => return vec![BODY_COLOR; n],
+        BodyColorMode::Population => {
+            return (0..n)
+                .map(|i| {
+                    let component = components.get(snapshot.body_id(i));
+                    population_color(component.copied().unwrap_or_default())
+                })
+                .collect();
+        }
+        BodyColorMode::Mass => snapshot.masses(body_masses).into_iter().map(log).collect(),
+        BodyColorMode::Speed => (0..n)
+            .map(|i| snapshot.body_vels.get(i).map_or(0., |v| v.magnitude()))
+            .collect(),
+        BodyColorMode::Accel => (0..n)
+            .map(|i| log(snapshot.body_accs.get(i).map_or(0., |a| a.magnitude())))
+            .collect(),
+    };
+
+    if vals.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = vals.clone();
+    sorted.sort_by(f32::total_cmp);
+    let lo = sorted[sorted.len() / 100];
+    let hi = sorted[sorted.len() * 99 / 100];
+    let range = (hi - lo).max(f32::EPSILON);
+
+    vals.iter().map(|v| color_map((v - lo) / range)).collect()
+}
+
+/// Body masses are separate from the snapshot, since it's invariant. `scale` is scene units per
+/// kpc; body markers aren't scaled.
+pub fn change_snapshot(
+    entities: &mut Vec<Entity>,
+    snapshot: &SnapShot,
+    body_masses: &[f32],
+    components: &[Component],
+    color_mode: BodyColorMode,
+    scale: f32,
+) {
     // todo: Shells, acc vecs A/R
     *entities = Vec::with_capacity(snapshot.body_posits.len() + snapshot.tree_cubes.len());
 
+    let colors = body_colors(snapshot, body_masses, components, color_mode);
+    let masses = snapshot.masses(body_masses);
+
     for (i, posit) in snapshot.body_posits.iter().enumerate() {
-        let entity_size = f32::clamp(
-            BODY_SIZE_SCALER * body_masses[i],
-            BODY_SIZE_MIN,
-            BODY_SIZE_MAX,
-        );
+        let entity_size = f32::clamp(BODY_SIZE_SCALER * masses[i], BODY_SIZE_MIN, BODY_SIZE_MAX);
         entities.push(Entity::new(
             MESH_SPHERE,
-            *posit,
+            *posit * scale,
             Quaternion::new_identity(),
             entity_size,
-            BODY_COLOR,
+            colors[i],
             BODY_SHINYNESS,
         ));
 
@@ -104,9 +370,9 @@ pub fn change_snapshot(entities: &mut Vec<Entity>, snapshot: &SnapShot, body_mas
                 cube.center.x as f32,
                 cube.center.y as f32,
                 cube.center.z as f32,
-            ),
+            ) * scale,
             Quaternion::new_identity(),
-            cube.width as f32 * TREE_CUBE_SCALE_FACTOR,
+            cube.width as f32 * TREE_CUBE_SCALE_FACTOR * scale,
             TREE_COLOR,
             TREE_SHINYNESS,
         ));
@@ -122,24 +388,172 @@ pub fn change_snapshot(entities: &mut Vec<Entity>, snapshot: &SnapShot, body_mas
     //         RAY_SHINYNESS,
     //     ));
     // }
+}
 
-    // todo: Draw an actual shell instead of a sphere.
-    // todo: Add back once you sort out transparency.
-    for shell in &snapshot.shells {
-        // let center = Vec3f32::new(shell.center.x, shell.center.y, shell.center.z);
-
-        // let entity = Entity::new(
-        //     2,
-        //     center,
-        //     Quaternion::new_identity(), // todo: A/R?
-        //     shell.radius,
-        //     // todo: Custom colors A/R
-        //     // todo: Color code by radius.
-        //     SHELL_COLOR, // todo: Color-code A/R
-        //     BODY_SHINYNESS,
-        // );
-        //
-        // // entity.opacity = SHELL_OPACITY;
-        // entities.push(entity);
+/// Draw a snapshot's gravity shells as translucent spheres, colored by radius: Young shells are
+/// dark purple, and ones near the maximum radius are yellow. If there are many, we draw an evenly
+/// spaced subset, so overlapping shells don't wash out the view, and the frame rate holds up.
+pub fn add_shells(
+    entities: &mut Vec<Entity>,
+    shells: &[GravShellSnapshot],
+    opacity: f32,
+    scale: f32,
+) {
+    let stride = shells.len().div_ceil(SHELLS_DRAWN_MAX).max(1);
+
+    for shell in shells.iter().step_by(stride) {
+        let mut entity = Entity::new(
+            MESH_SPHERE,
+            shell.center * scale,
+            Quaternion::new_identity(),
+            shell.radius * scale,
+            color_map(shell.radius / MAX_SHELL_R as f32),
+            SHELL_SHINYNESS,
+        );
+        entity.opacity = opacity;
+        entities.push(entity);
     }
 }
+
+/// Overlay the satellite's tidal radius at a snapshot as a translucent sphere, if computed.
+pub fn add_tidal_sphere(
+    entities: &mut Vec<Entity>,
+    history: Option<&TidalHistory>,
+    i: usize,
+    scale: f32,
+) {
+    let Some(Some(est)) = history.and_then(|h| h.estimates.get(i)) else {
+        return;
+    };
+
+    let mut entity = Entity::new(
+        MESH_SPHERE,
+        Vec3f32::from(est.center) * scale,
+        Quaternion::new_identity(),
+        est.r_j as f32 * scale,
+        TIDAL_COLOR,
+        TIDAL_SHINYNESS,
+    );
+    entity.opacity = TIDAL_OPACITY;
+    entities.push(entity);
+}
+
+/// One snapshot's body positions, for trails.
+struct TrailFrame {
+    snapshot: usize,
+    posits: Vec<Vec3f32>,
+    /// As in `SnapShot::body_ids`.
+    body_ids: Vec<u32>,
+}
+
+impl TrailFrame {
+    fn posit(&self, body: usize) -> Option<Vec3f32> {
+        if self.body_ids.is_empty() {
+            self.posits.get(body).copied()
+        } else {
+            let k = self.body_ids.binary_search(&(body as u32)).ok()?;
+            self.posits.get(k).copied()
+        }
+    }
+
+    fn body_id(&self, k: usize) -> usize {
+        self.body_ids.get(k).map_or(k, |id| *id as usize)
+    }
+}
+
+/// Recent body positions, accumulated during playback, for drawing trails. Frames already held are
+/// reused, so stepping forward reads one snapshot.
+#[derive(Default)]
+pub struct TrailHistory {
+    /// Oldest first.
+    frames: VecDeque<TrailFrame>,
+}
+
+impl TrailHistory {
+    /// Hold the `len` snapshots up to and including `i`. `get` reads a snapshot's body positions
+    /// and ids.
+    pub fn update(
+        &mut self,
+        i: usize,
+        len: usize,
+        mut get: impl FnMut(usize) -> Option<(Vec<Vec3f32>, Vec<u32>)>,
+    ) {
+        let Some((posits, body_ids)) = get(i) else {
+            self.frames.clear();
+            return;
+        };
+        // E.g. a rebuild, or a loaded run, replaced the snapshots.
+        if self
+            .frames
+            .iter()
+            .any(|f| f.snapshot == i && f.posits != posits)
+        {
+            self.frames.clear();
+        }
+
+        let start = (i + 1).saturating_sub(len.max(1));
+        let mut frames = VecDeque::with_capacity(i + 1 - start);
+        for j in start..i {
+            let frame = match self.frames.iter().position(|f| f.snapshot == j) {
+                Some(k) => self.frames.remove(k),
+                None => get(j).map(|(posits, body_ids)| TrailFrame {
+                    snapshot: j,
+                    posits,
+                    body_ids,
+                }),
+            };
+            frames.extend(frame);
+        }
+        frames.push_back(TrailFrame {
+            snapshot: i,
+            posits,
+            body_ids,
+        });
+
+        self.frames = frames;
+    }
+}
+
+/// Add trails through each body's positions in the history, fading with age, colored as its
+/// entity. Bodies must be the first entities, in the order of the history's newest snapshot;
+/// hidden ones (scale 0) get no trail. Segments share one mesh, scaled to their length; since
+/// scale is uniform, faster bodies' trails are bolder.
+pub fn add_trails(entities: &mut Vec<Entity>, history: &TrailHistory, scale: f32) {
+    let Some(newest) = history.frames.back() else {
+        return;
+    };
+    let num_segments = history.frames.len() - 1;
+
+    let mut trails = Vec::new();
+    for k in 0..newest.posits.len() {
+        let Some(body_entity) = entities.get(k).filter(|e| e.scale > 0.) else {
+            continue;
+        };
+        let id = newest.body_id(k);
+
+        let pairs = history.frames.iter().zip(history.frames.iter().skip(1));
+        for (age, (older, newer)) in pairs.enumerate() {
+            let (Some(a), Some(b)) = (older.posit(id), newer.posit(id)) else {
+                continue;
+            };
+            let diff = b - a;
+            let len = diff.magnitude();
+            if len <= f32::EPSILON {
+                continue;
+            }
+
+            let mut entity = Entity::new(
+                MESH_TRAIL,
+                (a + b) * 0.5 * scale,
+                Quaternion::from_unit_vecs(RIGHT_VEC, diff / len),
+                len * scale,
+                body_entity.color,
+                TRAIL_SHINYNESS,
+            );
+            entity.opacity = TRAIL_OPACITY * (age + 1) as f32 / num_segments as f32;
+            trails.push(entity);
+        }
+    }
+
+    entities.append(&mut trails);
+}
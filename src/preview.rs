@@ -0,0 +1,134 @@
+//! Fast previews: Given a wall-clock budget, scale down the body count, and if needed the number
+//! of steps, so the build fits in it. Costs are estimated from short benchmark builds at two body
+//! counts, which give the cost per step, and how it scales with the number of bodies, for the
+//! current force model and settings. The budget is also applied as a wall-time stop criterion, as a
+//! backstop; estimates are rough for the shell models, whose cost grows as shells accumulate.
+
+use std::{mem, time::Instant};
+
+use crate::{build_job::BuildJob, run, Config, ForceModel, State};
+
+/// Appended to the galaxy's name, in the HUD, and saved snapshots.
+pub const PREVIEW_LABEL: &str = " (preview)";
+
+const BENCH_STEPS: usize = 10;
+/// Disk and bulge bodies, for the smaller benchmark. The larger uses twice this.
+const BENCH_BODIES: usize = 100;
+/// We reduce steps, vice bodies, below this.
+const MIN_BODIES: usize = 200;
+
+pub struct PreviewPlan {
+    pub num_bodies_disk: usize,
+    pub num_bodies_bulge: usize,
+    pub num_timesteps: usize,
+    /// Seconds.
+    pub time_estimate: f64,
+    /// Seconds of the budget left after benchmarking.
+    pub time_available: f64,
+}
+
+/// The config with this many disk and bulge bodies, split as in the config.
+fn with_num_bodies(cfg: &Config, num_bodies: usize) -> Config {
+    let total = (cfg.num_bodies_disk + cfg.num_bodies_bulge).max(1);
+    let num_bodies_disk = num_bodies * cfg.num_bodies_disk / total;
+
+    Config {
+        num_bodies_disk,
+        num_bodies_bulge: num_bodies - num_bodies_disk,
+        ..cfg.clone()
+    }
+}
+
+/// Seconds per step, with this many bodies.
+fn bench(state: &mut State, force_model: ForceModel, num_bodies: usize) -> f64 {
+    let mut cfg = with_num_bodies(&state.config, num_bodies);
+    cfg.num_timesteps = BENCH_STEPS;
+    cfg.snapshot_ratio = BENCH_STEPS;
+    cfg.stop_criteria = Default::default();
+    cfg.plot_output.enabled = false;
+
+    state.config = cfg;
+    state.refresh_bodies();
+
+    let start = Instant::now();
+    run(state, force_model, None);
+    start.elapsed().as_secs_f64() / BENCH_STEPS as f64
+}
+
+/// Benchmark, and find the largest run that fits in `budget` seconds.
+pub fn plan(state: &mut State, force_model: ForceModel, budget: f64) -> PreviewPlan {
+    let start = Instant::now();
+
+    let cfg_prev = state.config.clone();
+    let stream_prev = state.ui.stream_snapshots;
+    state.ui.stream_snapshots = false;
+    // Benchmark builds' notifications aren't of interest. (They're still printed)
+    let notifications = mem::take(&mut state.ui.notifications);
+
+    let t_1 = bench(state, force_model, BENCH_BODIES);
+    let t_2 = bench(state, force_model, BENCH_BODIES * 2);
+
+    state.config = cfg_prev;
+    state.ui.stream_snapshots = stream_prev;
+    state.ui.notifications = notifications;
+
+    // Cost per step ∝ N^exponent. About 1 for the tree, and 2 for direct sums.
+    let exponent = (t_2 / t_1).log2().clamp(1., 2.);
+    let step_time = |n: usize| t_2 * (n as f64 / (BENCH_BODIES * 2) as f64).powf(exponent);
+    println!(
+        "Preview benchmark: {:.2} ms per step at N = {}; cost ∝ N^{exponent:.2}",
+        t_2 * 1_000.,
+        BENCH_BODIES * 2
+    );
+
+    let budget = (budget - start.elapsed().as_secs_f64()).max(0.);
+    let cfg = &state.config;
+    let num_bodies_full = cfg.num_bodies_disk + cfg.num_bodies_bulge;
+    let num_steps_full = cfg.num_timesteps;
+
+    let mut num_bodies = num_bodies_full;
+    if step_time(num_bodies) * num_steps_full as f64 > budget {
+        let per_step = budget / num_steps_full as f64;
+        num_bodies =
+            ((BENCH_BODIES * 2) as f64 * (per_step / t_2).powf(1. / exponent)).floor() as usize;
+        num_bodies = num_bodies.max(MIN_BODIES.min(num_bodies_full));
+    }
+
+    let num_timesteps = ((budget / step_time(num_bodies)) as usize).clamp(1, num_steps_full.max(1));
+
+    let cfg = with_num_bodies(&state.config, num_bodies);
+    PreviewPlan {
+        num_bodies_disk: cfg.num_bodies_disk,
+        num_bodies_bulge: cfg.num_bodies_bulge,
+        num_timesteps,
+        time_estimate: step_time(num_bodies) * num_timesteps as f64,
+        time_available: budget,
+    }
+}
+
+/// Build a scaled-down run, in the background, that fits in `budget` seconds. The config is
+/// unchanged.
+pub fn build_preview(state: &mut State, force_model: ForceModel, budget: f64) -> PreviewPlan {
+    let plan = plan(state, force_model, budget);
+
+    let cfg_prev = state.config.clone();
+    state.config.num_bodies_disk = plan.num_bodies_disk;
+    state.config.num_bodies_bulge = plan.num_bodies_bulge;
+    state.config.num_timesteps = plan.num_timesteps;
+    state.config.stop_criteria.max_wall_time = Some(
+        cfg_prev
+            .stop_criteria
+            .max_wall_time
+            .map_or(plan.time_available, |t| t.min(plan.time_available)),
+    );
+
+    state.refresh_bodies();
+    state.ui.run_labels.galaxy += PREVIEW_LABEL;
+
+    // The worker keeps the preview config.
+    let job = BuildJob::spawn(state, force_model);
+    state.build_job = Some(job);
+    state.config = cfg_prev;
+
+    plan
+}
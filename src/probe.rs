@@ -0,0 +1,270 @@
+//! Evaluate the gravitational field at arbitrary probe positions, vice only at bodies, for any force
+//! model. For field maps, lensing, and finding Lagrange points. Probes use the same structures as a
+//! build: The tree or direct sum, SCF expansion, QUMOND mesh, and gravity shells.
+
+use barnes_hut::{Cube, Tree};
+use lin_alg::f64::Vec3;
+use rayon::prelude::*;
+
+use crate::{
+    accel,
+    grav_shell::GravShell,
+    properties::{plot_multi, PlotOutput},
+    qumond::QumondGrid,
+    scf::ScfExpansion,
+    units::{G, KPC_MYR_PER_KM_S},
+    Body, Config, ForceModel, BOUNDING_BOX_PAD,
+};
+
+/// Probes aren't bodies; no source is skipped for this ID.
+pub const PROBE_ID: usize = usize::MAX;
+
+/// Integration points for the potential, along the ray from a probe out to the reference radius.
+const N_POTENTIAL_PTS: usize = 128;
+/// The reference radius for the potential, as a multiple of the bodies' extent.
+const R_REF_MULT: f64 = 20.;
+
+/// What a force model evaluates forces from. Structures the model doesn't use are `None`.
+pub struct FieldRefs<'a> {
+    pub config: &'a Config,
+    /// Sources for the direct sum, if there's no tree or SCF expansion.
+    pub bodies: &'a [Body],
+    pub tree: Option<&'a Tree>,
+    pub scf: Option<&'a ScfExpansion>,
+    pub qumond: Option<&'a QumondGrid>,
+    pub shells: &'a [GravShell],
+}
+
+/// Acceleration at a position, under a force model. The body with `id_target` is skipped as a
+/// source; use `PROBE_ID` for positions that aren't bodies.
+pub fn acc_model(force_model: ForceModel, refs: &FieldRefs, posit: Vec3, id_target: usize) -> Vec3 {
+    let cfg = refs.config;
+
+    // Instantaneous acceleration; Newtonian, or with MOND applied pairwise.
+    let acc_instant = |mond| {
+        if let Some(s) = refs.scf {
+            let acc = s.acc(posit);
+            match mond {
+                Some(mond_fn) => accel::apply_mond_net(acc, mond_fn),
+                None => acc,
+            }
+        } else if let Some(tree) = refs.tree {
+            accel::acc_tree(
+                posit,
+                id_target,
+                tree,
+                &cfg.bh_config,
+                mond,
+                cfg.softening_factor_sq,
+            )
+        } else {
+            accel::acc_newton(posit, id_target, refs.bodies, mond, cfg.softening_factor_sq)
+        }
+    };
+
+    let acc_shells = || {
+        accel::calc_acc_shell(
+            refs.shells,
+            posit,
+            id_target,
+            cfg.shell_gauss_c(),
+            cfg.softening_factor_sq,
+            cfg.shell_anisotropy,
+            &cfg.shell_speed,
+            cfg.shell_geometry,
+        )
+    };
+
+    match force_model {
+        ForceModel::Newton => acc_instant(None),
+        ForceModel::Mond(mond_fn) => acc_instant(Some(mond_fn)),
+        ForceModel::MondNet(mond_fn) => accel::apply_mond_net(acc_instant(None), mond_fn),
+        ForceModel::Qumond(mond_fn) => match refs.qumond {
+            Some(grid) => grid.acc_mond(posit, mond_fn),
+            None => Vec3::new_zero(),
+        },
+        ForceModel::GaussShells => acc_shells(),
+        ForceModel::Composite(comp) => comp.combine(acc_shells(), || acc_instant(None)),
+    }
+}
+
+/// The field of a fixed set of bodies and shells, for probing. Builds the structures the force
+/// model needs once, on creation.
+pub struct Field<'a> {
+    config: &'a Config,
+    bodies: &'a [Body],
+    shells: &'a [GravShell],
+    force_model: ForceModel,
+    tree: Option<Tree>,
+    scf: Option<ScfExpansion>,
+    qumond: Option<QumondGrid>,
+    /// Beyond all bodies. kpc.
+    r_ref: f64,
+    /// M☉
+    mass_total: f64,
+}
+
+impl<'a> Field<'a> {
+    pub fn new(
+        bodies: &'a [Body],
+        shells: &'a [GravShell],
+        config: &'a Config,
+        force_model: ForceModel,
+    ) -> Self {
+        let scf = match &config.scf {
+            Some(scf_cfg) if force_model.uses_instantaneous() => ScfExpansion::new(bodies, scf_cfg),
+            _ => None,
+        };
+
+        let tree = if force_model.uses_instantaneous() && !config.skip_tree && scf.is_none() {
+            Cube::from_bodies(bodies, BOUNDING_BOX_PAD, true)
+                .map(|bb| Tree::new(bodies, &bb, &config.bh_config))
+        } else {
+            None
+        };
+
+        let qumond = match force_model {
+            ForceModel::Qumond(mond_fn) => QumondGrid::new(bodies).map(|mut grid| {
+                grid.solve(bodies, mond_fn);
+                grid
+            }),
+            _ => None,
+        };
+
+        let extent = bodies
+            .iter()
+            .map(|b| b.posit.magnitude())
+            .fold(0., f64::max);
+
+        Self {
+            config,
+            bodies,
+            shells,
+            force_model,
+            tree,
+            scf,
+            qumond,
+            r_ref: extent.max(f64::EPSILON) * R_REF_MULT,
+            mass_total: bodies.iter().map(|b| b.mass).sum(),
+        }
+    }
+
+    fn refs(&self) -> FieldRefs<'_> {
+        FieldRefs {
+            config: self.config,
+            bodies: self.bodies,
+            tree: self.tree.as_ref(),
+            scf: self.scf.as_ref(),
+            qumond: self.qumond.as_ref(),
+            shells: self.shells,
+        }
+    }
+
+    /// Acceleration at a probe position. kpc/Myr².
+    pub fn acc(&self, posit: Vec3) -> Vec3 {
+        acc_model(self.force_model, &self.refs(), posit, PROBE_ID)
+    }
+
+    /// Accelerations at many probe positions, in parallel. kpc/Myr².
+    pub fn acc_many(&self, posits: &[Vec3]) -> Vec<Vec3> {
+        let refs = self.refs();
+        posits
+            .par_iter()
+            .map(|p| acc_model(self.force_model, &refs, *p, PROBE_ID))
+            .collect()
+    }
+
+    /// Accelerations of the field's own bodies, by index, each skipped as its own source.
+    /// kpc/Myr².
+    pub fn acc_bodies(&self, ids: &[usize]) -> Vec<Vec3> {
+        let refs = self.refs();
+        ids.par_iter()
+            .map(|i| acc_model(self.force_model, &refs, self.bodies[*i].posit, *i))
+            .collect()
+    }
+
+    /// Potential at a probe position, by integrating the acceleration along the ray from the origin
+    /// through the probe, out to a reference radius well beyond the bodies. There, we take the
+    /// potential to be that of a point mass, -GM/r. Not all force models are conservative, e.g.
+    /// pairwise MOND and shells; for those, this is the potential along that path. kpc²/Myr².
+    pub fn potential(&self, posit: Vec3) -> f64 {
+        let r_probe = posit.magnitude();
+        let dir = if r_probe > f64::EPSILON {
+            posit / r_probe
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+
+        let phi_ref = -G * self.mass_total / self.r_ref;
+        if r_probe >= self.r_ref {
+            return -G * self.mass_total / r_probe;
+        }
+
+        // Log-spaced, so the inner field is resolved. The segment inside `r_min` is negligible.
+        let r_min = r_probe.max(self.r_ref * 1e-5);
+        let ratio = (self.r_ref / r_min).powf(1. / (N_POTENTIAL_PTS - 1) as f64);
+        let radii: Vec<f64> = (0..N_POTENTIAL_PTS)
+            .map(|i| r_min * ratio.powi(i as i32))
+            .collect();
+
+        let a_r: Vec<f64> = radii.iter().map(|r| self.acc(dir * *r).dot(dir)).collect();
+
+        // Φ(r) = Φ(r_ref) + ∫ a · dl, from r to r_ref. Trapezoidal.
+        let integral: f64 = radii
+            .windows(2)
+            .zip(a_r.windows(2))
+            .map(|(r, a)| (r[1] - r[0]) * (a[0] + a[1]) / 2.)
+            .sum();
+
+        phi_ref + integral
+    }
+
+    /// Potentials at many probe positions, in parallel. kpc²/Myr².
+    pub fn potential_many(&self, posits: &[Vec3]) -> Vec<f64> {
+        posits.par_iter().map(|p| self.potential(*p)).collect()
+    }
+}
+
+/// Plot the field along the X axis in the disk plane, from probes: Circular velocity √(r a_r), and
+/// potential.
+pub fn plot_field_profile(out: &PlotOutput, field: &Field, r_max: f64, desc: &str) {
+    const N_PTS: usize = 60;
+
+    let posits: Vec<Vec3> = (1..=N_PTS)
+        .map(|i| Vec3::new(r_max * i as f64 / N_PTS as f64, 0., 0.))
+        .collect();
+
+    let accs = field.acc_many(&posits);
+    let v_circ: Vec<(f64, f64)> = posits
+        .iter()
+        .zip(&accs)
+        .map(|(p, a)| {
+            let a_r = -a.x;
+            (p.x, (p.x * a_r).max(0.).sqrt() / KPC_MYR_PER_KM_S)
+        })
+        .collect();
+
+    let potential: Vec<(f64, f64)> = posits
+        .iter()
+        .zip(field.potential_many(&posits))
+        .map(|(p, phi)| (p.x, phi / KPC_MYR_PER_KM_S.powi(2)))
+        .collect();
+
+    plot_multi(
+        out,
+        &[("v_c from probes", &v_circ)],
+        "r (kpc)",
+        "v (km/s)",
+        &format!("Probed circular velocity of {desc}"),
+        &format!("probe_v_circ_{desc}"),
+    );
+
+    plot_multi(
+        out,
+        &[("Φ", &potential)],
+        "r (kpc)",
+        "Φ (km²/s²)",
+        &format!("Probed potential of {desc}"),
+        &format!("probe_potential_{desc}"),
+    );
+}
@@ -5,15 +5,100 @@
 // todo: You're mixing kpc (mass) with km/s (rotation velocity)
 
 const N_SAMPLE_PTS: usize = 40;
+/// Angles in the disk plane to average over, for component rotation curves.
+const N_AZIMUTHS: usize = 8;
+/// Rotation curve samples further than this many (scaled) median absolute deviations from the
+/// median are rejected as outliers.
+const OUTLIER_MADS: f64 = 3.;
+
+use std::{
+    f64::consts::TAU,
+    fmt,
+    fmt::Formatter,
+    fs, io,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use barnes_hut::{BhConfig, Cube, Tree};
+use bincode::{Decode, Encode};
 
 use lin_alg::{f64::Vec3, linspace, logspace};
 use plotters::{
     element::PathElement,
-    prelude::{BitMapBackend, ChartBuilder, Color, IntoDrawingArea, BLACK, BLUE, WHITE},
+    prelude::{
+        BitMapBackend, ChartBuilder, Color, IntoDrawingArea, RGBColor, BLACK, BLUE, CYAN, GREEN,
+        MAGENTA, RED, WHITE,
+    },
     series::LineSeries,
 };
 
-use crate::{units::KPC_MYR_PER_KM_S, util::volume_sphere, Body};
+use rayon::prelude::*;
+
+use crate::{
+    accel::{self, acc_newton},
+    body_creation::{Component, GalaxyDescrip},
+    units::{G, KPC_MYR_PER_KM_S},
+    util::{interpolate, volume_sphere},
+    Body, BOUNDING_BOX_PAD,
+};
+
+/// Where, and whether, plots are written.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct PlotOutput {
+    /// If false, plotting functions do nothing. E.g. for headless batch runs.
+    pub enabled: bool,
+    pub dir: PathBuf,
+    /// Prefixed to plot filenames, e.g. to identify a batch or experiment. Omitted if empty.
+    pub run_id: String,
+    /// Append a Unix timestamp to plot filenames, so they don't overwrite those from previous runs.
+    pub timestamp: bool,
+}
+
+impl Default for PlotOutput {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: PathBuf::from("plots"),
+            run_id: String::new(),
+            timestamp: false,
+        }
+    }
+}
+
+impl PlotOutput {
+    /// The path to write a plot to, given its base name; `None` if plotting is disabled. Creates
+    /// the output directory A/R.
+    pub fn path(&self, filename: &str) -> Option<PathBuf> {
+        if !self.enabled {
+            return None;
+        }
+
+        let mut name = filename.to_owned();
+        if !self.run_id.is_empty() {
+            name = format!("{}_{name}", self.run_id);
+        }
+        if self.timestamp {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            name = format!("{name}_{secs}");
+        }
+
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            eprintln!("Unable to create the plot directory {:?}: {e}", self.dir);
+        }
+        Some(self.dir.join(format!("{name}.png")))
+    }
+
+    /// For notifications, e.g. "Field plots saved to `plots`."; `None` if plotting is disabled.
+    pub fn saved_msg(&self, what: &str) -> Option<String> {
+        self.enabled
+            .then(|| format!("{what} saved to `{}`.", self.dir.display()))
+    }
+}
 
 fn get_nearby_pts(bodies: &[Body], center: Vec3, r: f64, dr: f64) -> Vec<&Body> {
     // Todo: Consider a fuzzy, weighted dropoff instead of these hard boundaries. Or not;
@@ -25,9 +110,108 @@ fn get_nearby_pts(bodies: &[Body], center: Vec3, r: f64, dr: f64) -> Vec<&Body>
         .collect()
 }
 
-/// Gravitational potential (𝚽). X: r (kpc) Y: 𝚽 (J/kg)
-pub fn gravity_potential(bodies: &[Body], center: Vec3, r_max: f64) -> Vec<(f64, f64)> {
-    Vec::new()
+/// Gravitational potential at a position, summed with the Barnes-Hut tree: Σ -G m / √(d² + ε²),
+/// with distant bodies grouped into nodes. Nodes are opened as for the dynamics; see
+/// `accel::run_bh_softened`. The body with `id_target` is skipped; use `usize::MAX` for positions
+/// that aren't bodies. kpc²/Myr².
+pub fn potential_at(
+    posit: Vec3,
+    id_target: usize,
+    tree: &Tree,
+    bh_config: &BhConfig,
+    softening_factor_sq: f64,
+) -> f64 {
+    // The tree sums vectors; we carry the (scalar) potential in x.
+    let φ_fn = |_acc_dir: Vec3, mass_src: f64, dist: f64| {
+        Vec3::new(
+            -G * mass_src / (dist.powi(2) + softening_factor_sq).sqrt(),
+            0.,
+            0.,
+        )
+    };
+
+    accel::run_bh_softened(
+        posit,
+        id_target,
+        tree,
+        bh_config,
+        softening_factor_sq,
+        &φ_fn,
+    )
+    .x
+}
+
+/// Gravitational potential (𝚽) of the bodies, averaged over azimuth in the disk plane. Newtonian,
+/// summed with the tree. X: R (kpc). Y: 𝚽 (kpc²/Myr²).
+pub fn gravity_potential(
+    bodies: &[Body],
+    center: Vec3,
+    r_max: f64,
+    bh_config: &BhConfig,
+    softening_factor_sq: f64,
+) -> Vec<(f64, f64)> {
+    let Some(bb) = Cube::from_bodies(bodies, BOUNDING_BOX_PAD, false) else {
+        return Vec::new();
+    };
+    let tree = Tree::new(bodies, &bb, bh_config);
+
+    let axis = disk_axis(bodies, center);
+    let ref_vec = if axis.x.abs() < 0.9 {
+        Vec3::new(1., 0., 0.)
+    } else {
+        Vec3::new(0., 1., 0.)
+    };
+    let e_1 = axis.cross(ref_vec).to_normalized();
+    let e_2 = axis.cross(e_1);
+
+    linspace(0., r_max, N_SAMPLE_PTS)
+        .into_par_iter()
+        .map(|r| {
+            let φ = (0..N_AZIMUTHS)
+                .map(|i| {
+                    let θ = TAU * i as f64 / N_AZIMUTHS as f64;
+                    let posit = center + (e_1 * θ.cos() + e_2 * θ.sin()) * r;
+                    potential_at(posit, usize::MAX, &tree, bh_config, softening_factor_sq)
+                })
+                .sum::<f64>()
+                / N_AZIMUTHS as f64;
+            (r, φ)
+        })
+        .collect()
+}
+
+/// Escape velocity, √(-2𝚽), from a potential profile, e.g. from `gravity_potential`. This is the
+/// speed to escape the bodies' gravity alone. X: R (kpc). Y: km/s.
+pub fn escape_velocity(potential: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    potential
+        .iter()
+        .map(|(r, φ)| (*r, (-2. * φ).max(0.).sqrt() / KPC_MYR_PER_KM_S))
+        .collect()
+}
+
+/// Plot a potential profile, e.g. from `gravity_potential`, and the escape velocity from it.
+pub fn plot_potential(out: &PlotOutput, potential: &[(f64, f64)], desc: &str) {
+    let φ: Vec<(f64, f64)> = potential
+        .iter()
+        .map(|(r, φ)| (*r, φ / KPC_MYR_PER_KM_S.powi(2)))
+        .collect();
+
+    plot(
+        out,
+        &φ,
+        "R (kpc)",
+        "Φ (km²/s²)",
+        &format!("Potential of {desc}"),
+        &format!("potential_{desc}"),
+    );
+    plot(
+        out,
+        &escape_velocity(potential),
+        "R (kpc)",
+        "v_esc (km/s)",
+        &format!("Escape velocity of {desc}"),
+        &format!("escape_velocity_{desc}"),
+    );
 }
 
 fn find_r_max(bodies: &[Body], center: Vec3) -> f64 {
@@ -43,6 +227,24 @@ fn find_r_max(bodies: &[Body], center: Vec3) -> f64 {
 
 /// Normalized mass density. X: r (kpc). Y: ρ/ρ_0.
 pub fn mass_density(bodies: &[Body], center: Vec3) -> Vec<(f64, f64)> {
+    let mut result = mass_density_raw(bodies, center);
+
+    if result.is_empty() {
+        eprintln!("Error calculating mass density: Result is empty");
+        return Vec::new();
+    }
+    let rho_0 = result[0].1;
+
+    // Normalize to the central mass density.
+    for r in &mut result {
+        r.1 /= rho_0;
+    }
+
+    result
+}
+
+/// Mass density. X: r (kpc). Y: M☉ / kpc³.
+fn mass_density_raw(bodies: &[Body], center: Vec3) -> Vec<(f64, f64)> {
     let mut result = Vec::with_capacity(N_SAMPLE_PTS);
 
     let r_max = find_r_max(bodies, center);
@@ -69,18 +271,221 @@ pub fn mass_density(bodies: &[Body], center: Vec3) -> Vec<(f64, f64)> {
         }
     }
 
-    if result.is_empty() {
-        eprintln!("Error calculating mass density: Result is empty");
+    result
+}
+
+/// Surface density: Mass in annuli of cylindrical radius in the disk plane, about the center of
+/// mass, over each annulus's area. Pass a single component's bodies, e.g. the disk's, to compare
+/// with the profile they were generated from. Empty annuli are 0. X: R (kpc). Y: M☉ / kpc².
+pub fn surface_density(bodies: &[Body]) -> Vec<(f64, f64)> {
+    let (center, _) = center_of_mass(bodies);
+    let axis = disk_axis(bodies, center);
+
+    let r_cyl: Vec<(f64, f64)> = bodies
+        .iter()
+        .map(|b| {
+            let diff = b.posit - center;
+            ((diff - axis * diff.dot(axis)).magnitude(), b.mass)
+        })
+        .collect();
+
+    let r_max = r_cyl.iter().map(|(r, _)| *r).fold(0., f64::max);
+    if r_max <= 0. {
         return Vec::new();
     }
-    let rho_0 = result[0].1;
+    let dr = r_max / N_SAMPLE_PTS as f64;
 
-    // Normalize to the central mass density.
-    for r in &mut result {
-        r.1 /= rho_0;
+    let mut mass = vec![0.; N_SAMPLE_PTS];
+    for (r, m) in &r_cyl {
+        let i = ((r / dr) as usize).min(N_SAMPLE_PTS - 1);
+        mass[i] += m;
     }
 
-    result
+    mass.iter()
+        .enumerate()
+        .map(|(i, m)| {
+            let (r_inner, r_outer) = (i as f64 * dr, (i + 1) as f64 * dr);
+            let area = TAU / 2. * (r_outer.powi(2) - r_inner.powi(2));
+            ((r_inner + r_outer) / 2., m / area)
+        })
+        .collect()
+}
+
+/// Compare a measured surface density profile, e.g. from `surface_density`, with the target it was
+/// sampled from: The RMS difference in log₁₀ Σ (dex), at measured radii in the target's range.
+/// Bodies are given the component's total mass vice the profile's integral, so the target is
+/// first scaled to the measured mass in that range. Returns the scaled target, and the deviation;
+/// `None` if there are no nonzero points to compare.
+pub fn surface_density_deviation(
+    measured: &[(f64, f64)],
+    target: &[(f64, f64)],
+) -> (Vec<(f64, f64)>, Option<f64>) {
+    if target.len() < 2 {
+        return (target.to_vec(), None);
+    }
+    let r_min = target[0].0;
+    let r_max = target[target.len() - 1].0;
+
+    // Mass in the target's range, from ∫ 2πR Σ dR.
+    let mass = |data: &[(f64, f64)]| -> f64 {
+        data.windows(2)
+            .filter(|w| w[0].0 >= r_min && w[1].0 <= r_max)
+            .map(|w| TAU * (w[1].0 - w[0].0) * (w[0].0 * w[0].1 + w[1].0 * w[1].1) / 2.)
+            .sum()
+    };
+    let mass_target = mass(target);
+    let scale = if mass_target > 0. {
+        mass(measured) / mass_target
+    } else {
+        1.
+    };
+    let scaled: Vec<(f64, f64)> = target.iter().map(|(r, σ)| (*r, σ * scale)).collect();
+
+    let diffs: Vec<f64> = measured
+        .iter()
+        .filter(|(r, σ)| *σ > 0. && *r >= r_min && *r <= r_max)
+        .filter_map(|(r, σ)| {
+            interpolate(&scaled, *r)
+                .filter(|t| *t > 0.)
+                .map(|t| (σ / t).log10())
+        })
+        .collect();
+
+    let deviation = (!diffs.is_empty())
+        .then(|| (diffs.iter().map(|d| d.powi(2)).sum::<f64>() / diffs.len() as f64).sqrt());
+
+    (scaled, deviation)
+}
+
+/// Plot measured and target surface density, as log₁₀ Σ, since it spans decades.
+pub fn plot_surface_density(
+    out: &PlotOutput,
+    measured: &[(f64, f64)],
+    target: &[(f64, f64)],
+    desc: &str,
+) {
+    let log = |data: &[(f64, f64)]| -> Vec<(f64, f64)> {
+        data.iter()
+            .filter(|(_, σ)| *σ > 0.)
+            .map(|(r, σ)| (*r, σ.log10()))
+            .collect()
+    };
+
+    plot_multi(
+        out,
+        &[("Bodies", &log(measured)), ("Input", &log(target))],
+        "R (kpc)",
+        "log₁₀ Σ (M☉/kpc²)",
+        &format!("Disk surface density of {desc}"),
+        &format!("surface_density_{desc}"),
+    );
+}
+
+/// Components with at least one body, in declaration order.
+pub fn components_present(bodies: &[Body]) -> Vec<Component> {
+    [
+        Component::Disk,
+        Component::Bulge,
+        Component::Gas,
+        Component::Halo,
+    ]
+    .into_iter()
+    .filter(|c| bodies.iter().any(|b| b.component == *c))
+    .collect()
+}
+
+/// Mass density of each component, normalized to the central density of all bodies, so components
+/// can be compared. X: r (kpc). Y: ρ/ρ_0.
+pub fn mass_density_by_component(
+    bodies: &[Body],
+    center: Vec3,
+) -> Vec<(Component, Vec<(f64, f64)>)> {
+    let rho_0 = mass_density_raw(bodies, center)
+        .first()
+        .map(|v| v.1)
+        .unwrap_or(1.);
+
+    components_present(bodies)
+        .into_iter()
+        .map(|comp| {
+            let comp_bodies: Vec<Body> = bodies
+                .iter()
+                .filter(|b| b.component == comp)
+                .cloned()
+                .collect();
+
+            let density = mass_density_raw(&comp_bodies, center)
+                .into_iter()
+                .map(|(r, rho)| (r, rho / rho_0))
+                .collect();
+            (comp, density)
+        })
+        .collect()
+}
+
+/// The circular velocity each component contributes: V = √(r a_r), from that component's bodies
+/// alone, averaged over angles in the disk plane. This is how SPARC decomposes observed curves; the
+/// total is their quadrature sum. All curves share radii. X: r (kpc). Y: km/s.
+pub fn component_curves(
+    bodies: &[Body],
+    center: Vec3,
+    softening_factor_sq: f64,
+) -> Vec<(Component, Vec<(f64, f64)>)> {
+    let r_max = find_r_max(bodies, center);
+    let radii = linspace(r_max / N_SAMPLE_PTS as f64, r_max, N_SAMPLE_PTS);
+
+    components_present(bodies)
+        .into_iter()
+        .map(|comp| {
+            let comp_bodies: Vec<Body> = bodies
+                .iter()
+                .filter(|b| b.component == comp)
+                .cloned()
+                .collect();
+
+            let curve = radii
+                .iter()
+                .map(|r| {
+                    let a_r = (0..N_AZIMUTHS)
+                        .map(|i| {
+                            let θ = TAU * i as f64 / N_AZIMUTHS as f64;
+                            let dir = Vec3::new(θ.cos(), θ.sin(), 0.);
+                            // No body has this ID, so none are skipped.
+                            let acc = acc_newton(
+                                center + dir * *r,
+                                usize::MAX,
+                                &comp_bodies,
+                                None,
+                                softening_factor_sq,
+                            );
+                            -acc.dot(dir)
+                        })
+                        .sum::<f64>()
+                        / N_AZIMUTHS as f64;
+
+                    (*r, (r * a_r).max(0.).sqrt() / KPC_MYR_PER_KM_S)
+                })
+                .collect();
+            (comp, curve)
+        })
+        .collect()
+}
+
+/// The quadrature sum of component curves: V² = Σ V_i². Assumes shared radii, as from
+/// `component_curves`.
+pub fn quadrature_sum(curves: &[(Component, Vec<(f64, f64)>)]) -> Vec<(f64, f64)> {
+    let Some((_, first)) = curves.first() else {
+        return Vec::new();
+    };
+
+    first
+        .iter()
+        .enumerate()
+        .map(|(i, (r, _))| {
+            let v_sq: f64 = curves.iter().map(|(_, c)| c[i].1.powi(2)).sum();
+            (*r, v_sq.sqrt())
+        })
+        .collect()
 }
 
 /// Luminosity profile. X: r (kpc). Y: μ (mag arcsec^-2) - Surface brightness profile.
@@ -90,49 +495,406 @@ pub fn luminosity(bodies: &[Body]) -> Vec<(f64, f64)> {
     result
 }
 
-/// Normalized rotation curve. X: r (kpc). Y: V/c, or km/s, or kpc/MLY?
-/// We specify r_max, to avoid calculations involving outliers. But, perhaps should calculate anyway.
+/// The disk's rotation axis: the direction of its angular momentum about `center`, so a tilted
+/// disk is measured in its own plane. +Z if there's no net rotation.
+pub fn disk_axis(bodies: &[Body], center: Vec3) -> Vec3 {
+    let ang_mom: Vec3 = bodies
+        .iter()
+        .map(|b| (b.posit - center).cross(b.vel) * b.mass)
+        .sum();
+
+    if ang_mom.magnitude() < f64::EPSILON {
+        Vec3::new(0., 0., 1.)
+    } else {
+        ang_mom.to_normalized()
+    }
+}
+
+/// Mean of values, after rejecting those more than `OUTLIER_MADS` scaled median absolute
+/// deviations from the median. Robust to e.g. a few bodies on radial or escaping orbits.
+fn mean_clipped(vals: &mut [f64]) -> f64 {
+    let median = |v: &mut [f64]| {
+        v.sort_by(|a, b| a.total_cmp(b));
+        v[v.len() / 2]
+    };
+
+    let med = median(vals);
+    let mut deviations: Vec<f64> = vals.iter().map(|v| (v - med).abs()).collect();
+    // 1.4826 scales the MAD to σ, for a normal distribution.
+    let σ = 1.4826 * median(&mut deviations);
+
+    let kept: Vec<f64> = vals
+        .iter()
+        .copied()
+        .filter(|v| σ == 0. || (v - med).abs() <= OUTLIER_MADS * σ)
+        .collect();
+    kept.iter().sum::<f64>() / kept.len() as f64
+}
+
+/// Rotation curve, as observers report it: The mean azimuthal velocity, v_φ, in annuli of
+/// cylindrical radius in the disk plane. Radial and vertical motion are excluded, and
+/// counter-rotating bodies count against the mean, vice adding to it. X: R (kpc). Y: km/s.
 /// todo: In km/s for now, not V/C.
 pub fn rotation_curve(bodies: &[Body], center: Vec3, c: f64) -> Vec<(f64, f64)> {
     let mut result = Vec::with_capacity(N_SAMPLE_PTS);
 
     let r_max = find_r_max(bodies, center);
     let dr = r_max / N_SAMPLE_PTS as f64;
+    let axis = disk_axis(bodies, center);
 
-    // for i in 0..N_SAMPLE_PTS {
-    for r in linspace(0., r_max, N_SAMPLE_PTS) {
-        // let r = i as f64 * dr;
+    // (R, v_φ) of each body.
+    let cylindrical: Vec<(f64, f64)> = bodies
+        .iter()
+        .map(|b| {
+            let diff = b.posit - center;
+            let in_plane = diff - axis * diff.dot(axis);
+            let r = in_plane.magnitude();
+            if r < f64::EPSILON {
+                return (r, 0.);
+            }
+            let φ_hat = axis.cross(in_plane / r);
+            (r, b.vel.dot(φ_hat))
+        })
+        .collect();
 
-        let nearby_pts: Vec<Vec3> = get_nearby_pts(&bodies, center, r, dr)
-            .into_iter()
-            .map(|b2| b2.vel)
+    for r in linspace(0., r_max, N_SAMPLE_PTS) {
+        let mut v_φ: Vec<f64> = cylindrical
+            .iter()
+            .filter(|(r_body, _)| (r_body - r).abs() <= dr / 2.)
+            .map(|(_, v)| *v)
             .collect();
 
-        if nearby_pts.is_empty() {
+        if v_φ.is_empty() {
             result.push((r, 0.));
         } else {
-            let mut v = 0.;
-            for pt in &nearby_pts {
-                v += pt.magnitude();
+            result.push((r, mean_clipped(&mut v_φ) / KPC_MYR_PER_KM_S));
+        }
+    }
+
+    result
+}
+
+/// Velocity dispersion profile: The standard deviation of each cylindrical velocity component,
+/// [σ_R, σ_φ, σ_z], in annuli in the disk plane, at the same radii as `rotation_curve`. Growth over
+/// a run shows disk heating. Empty annuli are 0. X: R (kpc). Y: km/s.
+pub fn velocity_dispersion(bodies: &[Body], center: Vec3) -> [Vec<(f64, f64)>; 3] {
+    let mut result: [Vec<(f64, f64)>; 3] = Default::default();
+
+    let r_max = find_r_max(bodies, center);
+    let dr = r_max / N_SAMPLE_PTS as f64;
+    let axis = disk_axis(bodies, center);
+
+    // (R, [v_R, v_φ, v_z]) of each body.
+    let cylindrical: Vec<(f64, [f64; 3])> = bodies
+        .iter()
+        .map(|b| {
+            let diff = b.posit - center;
+            let v_z = b.vel.dot(axis);
+            let in_plane = diff - axis * diff.dot(axis);
+            let r = in_plane.magnitude();
+            if r < f64::EPSILON {
+                return (r, [0., 0., v_z]);
             }
+            let r_hat = in_plane / r;
+            let φ_hat = axis.cross(r_hat);
+            (r, [b.vel.dot(r_hat), b.vel.dot(φ_hat), v_z])
+        })
+        .collect();
+
+    for r in linspace(0., r_max, N_SAMPLE_PTS) {
+        let annulus: Vec<[f64; 3]> = cylindrical
+            .iter()
+            .filter(|(r_body, _)| (r_body - r).abs() <= dr / 2.)
+            .map(|(_, v)| *v)
+            .collect();
 
-            // result.push((r, v / (KPC_MYR_PER_KM_S * nearby_pts.len() as f64 * c)));
-            result.push((r, v / (KPC_MYR_PER_KM_S * nearby_pts.len() as f64)));
+        let n = annulus.len() as f64;
+        for (i, profile) in result.iter_mut().enumerate() {
+            if annulus.is_empty() {
+                profile.push((r, 0.));
+                continue;
+            }
+            let mean = annulus.iter().map(|v| v[i]).sum::<f64>() / n;
+            let var = annulus.iter().map(|v| (v[i] - mean).powi(2)).sum::<f64>() / n;
+            profile.push((r, var.sqrt() / KPC_MYR_PER_KM_S));
         }
     }
 
     result
 }
 
-/// Sersic index. X: α. Y: s.
-pub fn sersic(bodies: &[Body]) -> Vec<(f64, f64)> {
-    let mut result = Vec::with_capacity(N_SAMPLE_PTS);
+/// Mean absolute change in v between two rotation curves, relative to mean v. The sample radii depend
+/// on the bodies' extent, so we interpolate the previous curve onto the new radii.
+pub fn rot_curve_change(prev: &[(f64, f64)], curve: &[(f64, f64)]) -> f64 {
+    if prev.len() < 2 || curve.is_empty() {
+        return f64::MAX;
+    }
 
-    result
+    let mut diff = 0.;
+    let mut total = 0.;
+
+    for (r, v) in curve {
+        let v_prev = interpolate(prev, *r).unwrap_or(*v);
+        diff += (v - v_prev).abs();
+        total += v.abs();
+    }
+
+    if total == 0. {
+        return f64::MAX;
+    }
+    diff / total
+}
+
+/// Total kinetic and potential energy. (M☉ kpc² / Myr²). The potential is a direct O(n²) sum, so
+/// sample this sparingly for large body counts.
+pub fn energy(bodies: &[Body], softening_factor_sq: f64) -> (f64, f64) {
+    let kinetic = bodies
+        .iter()
+        .map(|b| 0.5 * b.mass * b.vel.magnitude_squared())
+        .sum();
+
+    let potential = bodies
+        .par_iter()
+        .enumerate()
+        .map(|(i, body_a)| {
+            bodies[i + 1..]
+                .iter()
+                .map(|body_b| {
+                    let dist_sq = (body_a.posit - body_b.posit).magnitude_squared();
+                    -G * body_a.mass * body_b.mass / (dist_sq + softening_factor_sq).sqrt()
+                })
+                .sum::<f64>()
+        })
+        .sum();
+
+    (kinetic, potential)
+}
+
+/// The virial ratio, 2K / |U|, from total kinetic and potential energy, e.g. from `energy()`. 1 in
+/// virial equilibrium. Below, the system is too cold, and collapses; above, too hot, and expands.
+/// `None` if there's no potential energy, e.g. for a single body.
+pub fn virial_ratio(kinetic: f64, potential: f64) -> Option<f64> {
+    (potential != 0.).then(|| 2. * kinetic / potential.abs())
+}
+
+/// Mass-weighted center of mass position (kpc) and velocity (kpc/Myr). An isolated system's COM
+/// velocity is constant, and its position moves uniformly; drift in either means the net force
+/// on the system isn't zero, e.g. from asymmetric tree forces, or a force model that isn't
+/// action-reaction symmetric.
+pub fn center_of_mass(bodies: &[Body]) -> (Vec3, Vec3) {
+    let mass_total: f64 = bodies.iter().map(|b| b.mass).sum();
+    if mass_total <= 0. {
+        return (Vec3::new_zero(), Vec3::new_zero());
+    }
+
+    let posit: Vec3 = bodies.iter().map(|b| b.posit * b.mass).sum();
+    let vel: Vec3 = bodies.iter().map(|b| b.vel * b.mass).sum();
+    (posit / mass_total, vel / mass_total)
+}
+
+/// Estimated two-body relaxation time (Myr): t_relax ≈ N / (8 ln N) t_cross, with the crossing time
+/// from the half-mass radius and RMS speed. (Binney & Tremaine, eq 1.38). Past this, discreteness,
+/// vice the force model, dominates the evolution. `None` if it can't be estimated, e.g. bodies at rest.
+pub fn relaxation_time(bodies: &[Body], center: Vec3) -> Option<f64> {
+    let n = bodies.len();
+    if n < 2 {
+        return None;
+    }
+
+    let mass_total: f64 = bodies.iter().map(|b| b.mass).sum();
+    if mass_total <= 0. {
+        return None;
+    }
+
+    let mut by_dist: Vec<(f64, f64)> = bodies
+        .iter()
+        .map(|b| ((b.posit - center).magnitude(), b.mass))
+        .collect();
+    by_dist.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut mass_enclosed = 0.;
+    let mut r_half = 0.;
+    for (r, mass) in by_dist {
+        mass_enclosed += mass;
+        if mass_enclosed >= mass_total / 2. {
+            r_half = r;
+            break;
+        }
+    }
+
+    let v_rms = (bodies
+        .iter()
+        .map(|b| b.mass * b.vel.magnitude_squared())
+        .sum::<f64>()
+        / mass_total)
+        .sqrt();
+
+    if v_rms < f64::EPSILON || r_half < f64::EPSILON {
+        return None;
+    }
+
+    let t_cross = r_half / v_rms;
+    let n = n as f64;
+    Some(n / (8. * n.ln()) * t_cross)
+}
+
+/// Bar strength: the normalized m=2 Fourier amplitude of the mass distribution in the disk plane,
+/// A₂ = |Σ m e^(2iϕ)| / Σ m. 0 for an axisymmetric disk; typically > 0.2 for a strong bar.
+/// Bodies at the center are excluded, since their angle is undefined.
+pub fn bar_strength(bodies: &[Body], center: Vec3) -> f64 {
+    let mut re = 0.;
+    let mut im = 0.;
+    let mut mass = 0.;
+
+    for body in bodies {
+        let diff = body.posit - center;
+        if diff.x.abs() < f64::EPSILON && diff.y.abs() < f64::EPSILON {
+            continue;
+        }
+
+        let ϕ = diff.y.atan2(diff.x);
+        re += body.mass * (2. * ϕ).cos();
+        im += body.mass * (2. * ϕ).sin();
+        mass += body.mass;
+    }
+
+    if mass == 0. {
+        return 0.;
+    }
+    (re.powi(2) + im.powi(2)).sqrt() / mass
+}
+
+/// A Sérsic profile: I(R) = I_e exp(-b_n ((R/R_e)^(1/n) - 1)). n = 1 is an exponential disk, and
+/// n = 4 a de Vaucouleurs bulge.
+#[derive(Clone, Copy, Debug)]
+pub struct SersicFit {
+    /// Sérsic index.
+    pub n: f64,
+    /// Effective (half-light) radius. kpc.
+    pub r_e: f64,
+    /// Intensity at R_e, in the fit profile's units.
+    pub i_e: f64,
+    /// RMS residual of log₁₀ I. dex.
+    pub rms: f64,
+}
+
+impl SersicFit {
+    pub fn value(&self, r: f64) -> f64 {
+        self.i_e * (-sersic_b(self.n) * ((r / self.r_e).powf(1. / self.n) - 1.)).exp()
+    }
+}
+
+impl fmt::Display for SersicFit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n: {:.2}, R_e: {:.2} kpc ({:.3} dex RMS)",
+            self.n, self.r_e, self.rms
+        )
+    }
+}
+
+/// b_n, such that R_e encloses half the light. (Ciotti & Bertin, 1999)
+fn sersic_b(n: f64) -> f64 {
+    2. * n - 1. / 3. + 4. / (405. * n) + 46. / (25_515. * n.powi(2))
+}
+
+/// Fit a Sérsic profile to a radial one, e.g. surface density, or intensity. We fit log I, so each
+/// point weighs equally regardless of brightness. For given n and R_e, the best I_e is analytic;
+/// we search n and R_e over log-spaced grids, narrowing around the best point. Points at R = 0,
+/// or with no intensity, are skipped. `None` if fewer than 3 remain.
+pub fn sersic_fit(profile: &[(f64, f64)]) -> Option<SersicFit> {
+    const N_MIN: f64 = 0.2;
+    const N_MAX: f64 = 10.;
+    const STEPS: usize = 24;
+    const PASSES: usize = 4;
+
+    let pts: Vec<(f64, f64)> = profile
+        .iter()
+        .filter(|(r, v)| *r > 0. && *v > 0.)
+        .map(|(r, v)| (*r, v.ln()))
+        .collect();
+    if pts.len() < 3 {
+        return None;
+    }
+    let len = pts.len() as f64;
+
+    // (Sum of squared residuals, ln I_e)
+    let cost = |n: f64, r_e: f64| -> (f64, f64) {
+        let b = sersic_b(n);
+        let shape = |r: f64| -b * ((r / r_e).powf(1. / n) - 1.);
+        let ln_i_e = pts.iter().map(|(r, ln_i)| ln_i - shape(*r)).sum::<f64>() / len;
+        let sse = pts
+            .iter()
+            .map(|(r, ln_i)| (ln_i - ln_i_e - shape(*r)).powi(2))
+            .sum();
+        (sse, ln_i_e)
+    };
+
+    let r_min = pts.iter().map(|(r, _)| *r).fold(f64::INFINITY, f64::min);
+    let r_max = pts.iter().map(|(r, _)| *r).fold(0., f64::max);
+
+    let (mut n_lo, mut n_hi) = (N_MIN.ln(), N_MAX.ln());
+    let (mut r_lo, mut r_hi) = ((r_min / 2.).ln(), (r_max * 5.).ln());
+
+    // (sse, ln n, ln R_e, ln I_e)
+    let mut best = (f64::INFINITY, 0., 0., 0.);
+    for _ in 0..PASSES {
+        let (dn, dr) = ((n_hi - n_lo) / STEPS as f64, (r_hi - r_lo) / STEPS as f64);
+
+        for i in 0..=STEPS {
+            for j in 0..=STEPS {
+                let ln_n = n_lo + dn * i as f64;
+                let ln_r = r_lo + dr * j as f64;
+                let (sse, ln_i_e) = cost(ln_n.exp(), ln_r.exp());
+                if sse < best.0 {
+                    best = (sse, ln_n, ln_r, ln_i_e);
+                }
+            }
+        }
+
+        // Narrow to two steps either side of the best.
+        n_lo = (best.1 - 2. * dn).max(N_MIN.ln());
+        n_hi = (best.1 + 2. * dn).min(N_MAX.ln());
+        r_lo = best.2 - 2. * dr;
+        r_hi = best.2 + 2. * dr;
+    }
+
+    Some(SersicFit {
+        n: best.1.exp(),
+        r_e: best.2.exp(),
+        i_e: best.3.exp(),
+        rms: (best.0 / len).sqrt() / 10_f64.ln(),
+    })
+}
+
+/// Sérsic fit to the bodies' surface density. Pass a single component's bodies, e.g. the disk's.
+pub fn sersic(bodies: &[Body]) -> Option<SersicFit> {
+    sersic_fit(&surface_density(bodies))
+}
+
+/// Surface brightness, μ (mag arcsec⁻²), e.g. `GalaxyDescrip::luminosity_disk`, as relative
+/// intensity, e.g. for fitting.
+pub fn intensity_from_mag(profile: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    profile
+        .iter()
+        .map(|(r, μ)| (*r, 10_f64.powf(-0.4 * μ)))
+        .collect()
 }
 
 /// Display a 2d plot of properties, e.g. rotation curve, luminosity etc.
-pub fn plot(data: &[(f64, f64)], x_label: &str, y_label: &str, plot_title: &str, filename: &str) {
+pub fn plot(
+    out: &PlotOutput,
+    data: &[(f64, f64)],
+    x_label: &str,
+    y_label: &str,
+    plot_title: &str,
+    filename: &str,
+) {
+    let Some(path) = out.path(filename) else {
+        return;
+    };
+
     // Find the x and y ranges using PartialOrd
     let x_range = data
         .iter()
@@ -154,8 +916,7 @@ pub fn plot(data: &[(f64, f64)], x_label: &str, y_label: &str, plot_title: &str,
         });
 
     // Create a drawing area for the plot
-    let fname = format!("plots/{filename}.png");
-    let root = BitMapBackend::new(&fname, (800, 600)).into_drawing_area();
+    let root = BitMapBackend::new(&path, (800, 600)).into_drawing_area();
     root.fill(&WHITE).unwrap();
 
     // Create a chart builder
@@ -191,8 +952,99 @@ pub fn plot(data: &[(f64, f64)], x_label: &str, y_label: &str, plot_title: &str,
         .unwrap();
 }
 
-pub fn plot_rotation_curve(data: &[(f64, f64)], desc: &str) {
+/// Like `plot`, but with multiple labeled series on the same axes.
+pub fn plot_multi(
+    out: &PlotOutput,
+    series: &[(&str, &[(f64, f64)])],
+    x_label: &str,
+    y_label: &str,
+    plot_title: &str,
+    filename: &str,
+) {
+    if let Some(path) = out.path(filename) {
+        if let Err(e) = plot_multi_to(&path, series, x_label, y_label, plot_title) {
+            eprintln!("Error plotting {path:?}: {e}");
+        }
+    }
+}
+
+/// Plotting errors, e.g. from writing the image, as `io::Error`, so they propagate with others.
+pub fn plot_err(e: impl fmt::Display) -> io::Error {
+    io::Error::new(ErrorKind::Other, e.to_string())
+}
+
+/// Like `plot_multi`, but writes to an arbitrary path, e.g. a report directory. Returns an error
+/// if the image can't be written, or there's no data.
+pub fn plot_multi_to(
+    path: &Path,
+    series: &[(&str, &[(f64, f64)])],
+    x_label: &str,
+    y_label: &str,
+    plot_title: &str,
+) -> io::Result<()> {
+    const COLORS: [RGBColor; 5] = [BLUE, RED, GREEN, MAGENTA, CYAN];
+
+    let all_pts = series.iter().flat_map(|(_, data)| data.iter());
+
+    let (mut x_min, mut x_max, mut y_min, mut y_max) = (
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+    );
+    for (x, y) in all_pts {
+        x_min = x_min.min(*x);
+        x_max = x_max.max(*x);
+        y_min = y_min.min(*y);
+        y_max = y_max.max(*y);
+    }
+    if !(x_min.is_finite() && x_max.is_finite() && y_min.is_finite() && y_max.is_finite()) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("No data to plot for {plot_title}"),
+        ));
+    }
+
+    let root = BitMapBackend::new(path, (800, 600)).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_err)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(plot_title, ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(30)
+        .build_cartesian_2d(x_min..x_max, y_min..y_max)
+        .map_err(plot_err)?;
+
+    chart
+        .configure_mesh()
+        .x_desc(x_label)
+        .y_desc(y_label)
+        .draw()
+        .map_err(plot_err)?;
+
+    for (i, (label, data)) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        chart
+            .draw_series(LineSeries::new(data.iter().cloned(), color))
+            .map_err(plot_err)?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(plot_err)?;
+
+    root.present().map_err(plot_err)
+}
+
+pub fn plot_rotation_curve(out: &PlotOutput, data: &[(f64, f64)], desc: &str) {
     plot(
+        out,
         data,
         "r (kpc)",
         // "v / c",
@@ -203,8 +1055,102 @@ pub fn plot_rotation_curve(data: &[(f64, f64)], desc: &str) {
     );
 }
 
-pub fn plot_mass_density(data: &[(f64, f64)], desc: &str) {
+/// Plot each component's rotation curve contribution, their quadrature sum, and the measured curve;
+/// each component against its observed contribution, where available; and component densities.
+pub fn plot_components(
+    out: &PlotOutput,
+    bodies: &[Body],
+    descrip: &GalaxyDescrip,
+    softening_factor_sq: f64,
+    desc: &str,
+) {
+    let center = Vec3::new_zero();
+    let curves = component_curves(bodies, center, softening_factor_sq);
+    let total = quadrature_sum(&curves);
+    let measured = rotation_curve(bodies, center, 0.);
+
+    let labels: Vec<String> = curves.iter().map(|(c, _)| c.to_str()).collect();
+    let mut series: Vec<(&str, &[(f64, f64)])> = labels
+        .iter()
+        .zip(&curves)
+        .map(|(l, (_, c))| (l.as_str(), c.as_slice()))
+        .collect();
+    series.push(("Quadrature sum", &total));
+    series.push(("Measured", &measured));
+
+    plot_multi(
+        out,
+        &series,
+        "r (kpc)",
+        "v (km/s)",
+        &format!("Rotation curve components of {desc}"),
+        &format!("rot_components_{desc}"),
+    );
+
+    // Observed contributions are in kpc/Myr.
+    let to_km_s = |curve: &[(f64, f64)]| -> Vec<(f64, f64)> {
+        curve
+            .iter()
+            .map(|(r, v)| (*r, v / KPC_MYR_PER_KM_S))
+            .collect()
+    };
+    let observed: Vec<(Component, Vec<(f64, f64)>)> = [
+        (Component::Disk, to_km_s(&descrip.rotation_curve_disk)),
+        (Component::Bulge, to_km_s(&descrip.rotation_curve_bulge)),
+    ]
+    .into_iter()
+    .filter(|(_, c)| !c.is_empty())
+    .collect();
+
+    let labels: Vec<(String, String)> = observed
+        .iter()
+        .map(|(c, _)| {
+            (
+                format!("{} (sim)", c.to_str()),
+                format!("{} (obs)", c.to_str()),
+            )
+        })
+        .collect();
+    let mut series = Vec::new();
+    for ((comp, obs), (label_sim, label_obs)) in observed.iter().zip(&labels) {
+        if let Some((_, sim)) = curves.iter().find(|(c, _)| c == comp) {
+            series.push((label_sim.as_str(), sim.as_slice()));
+            series.push((label_obs.as_str(), obs.as_slice()));
+        }
+    }
+
+    if !series.is_empty() {
+        plot_multi(
+            out,
+            &series,
+            "r (kpc)",
+            "v (km/s)",
+            &format!("Simulated vs observed components of {desc}"),
+            &format!("rot_components_obs_{desc}"),
+        );
+    }
+
+    let densities = mass_density_by_component(bodies, center);
+    let labels: Vec<String> = densities.iter().map(|(c, _)| c.to_str()).collect();
+    let series: Vec<(&str, &[(f64, f64)])> = labels
+        .iter()
+        .zip(&densities)
+        .map(|(l, (_, d))| (l.as_str(), d.as_slice()))
+        .collect();
+
+    plot_multi(
+        out,
+        &series,
+        "r (kpc)",
+        "ρ / ρ₀",
+        &format!("Mass density components of {desc}"),
+        &format!("mass_components_{desc}"),
+    );
+}
+
+pub fn plot_mass_density(out: &PlotOutput, data: &[(f64, f64)], desc: &str) {
     plot(
+        out,
         data,
         "r (kpc)",
         "ρ / ρ₀",
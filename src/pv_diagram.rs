@@ -0,0 +1,323 @@
+//! Simulated long-slit spectroscopy: A position-velocity (PV) diagram along a slit through the
+//! projected galaxy. Many published rotation curves, e.g. Broeils' NGC 1560 data, were traced from
+//! diagrams like this, so comparing curves measured the same way separates projection and slit
+//! effects from the force model.
+//!
+//! The disk is in the XY plane. We incline it about the X axis (the major axis), then observe along
+//! the line of sight. Slit position angle is measured on the sky from the major axis.
+//!
+//! Optionally, we smear the sky with a gaussian beam before sampling the slit, as a radio telescope
+//! does to HI data. This flattens the inner rise of the traced curve, so compare smeared models with
+//! smeared data. Each body's mass is spread over slit bins by the beam's overlap with them.
+
+use std::path::Path;
+
+use lin_alg::f64::Vec3;
+use plotters::prelude::{
+    BitMapBackend, ChartBuilder, Color, HSLColor, IntoDrawingArea, Rectangle, WHITE,
+};
+
+use crate::{
+    body_creation::GalaxyDescrip,
+    properties::plot_multi_to,
+    units::{ARCSEC_CONV_FACTOR, KPC_MYR_PER_KM_S},
+    Body,
+};
+
+/// FWHM = 2√(2 ln 2) σ
+const FWHM_PER_SIGMA: f64 = 2.354_820_045;
+/// Beam contributions beyond this many σ are neglected.
+const BEAM_CUTOFF_SIGMA: f64 = 4.;
+
+#[derive(Clone, Debug)]
+pub struct Slit {
+    /// Degrees. 90 is edge-on.
+    pub inclination: f64,
+    /// Degrees, on the sky, from the major axis.
+    pub position_angle: f64,
+    /// kpc
+    pub width: f64,
+    /// Full length. kpc.
+    pub length: f64,
+    /// Bins along the slit. Even, so the center is a bin edge, and sides pair up when folding.
+    pub num_pos: usize,
+    pub num_vel: usize,
+    /// Beam FWHM, in arcsec. Beam smearing is disabled if `None`.
+    pub beam_fwhm: Option<f64>,
+}
+
+impl Default for Slit {
+    fn default() -> Self {
+        Self {
+            inclination: 60.,
+            position_angle: 0.,
+            width: 0.5,
+            length: 30.,
+            num_pos: 60,
+            num_vel: 60,
+            beam_fwhm: None,
+        }
+    }
+}
+
+pub struct PvDiagram {
+    /// Bin centers along the slit. kpc.
+    pub positions: Vec<f64>,
+    /// Bin centers. km/s; positive is receding.
+    pub velocities: Vec<f64>,
+    /// Mass in each bin. Outer: position. Inner: velocity. M☉.
+    pub intensity: Vec<Vec<f64>>,
+    pub slit: Slit,
+}
+
+/// Sky position (x, y), and line-of-sight velocity. kpc, and kpc/Myr.
+fn project(posit: Vec3, vel: Vec3, inclination: f64) -> (f64, f64, f64) {
+    let (sin_i, cos_i) = inclination.to_radians().sin_cos();
+
+    let x = posit.x;
+    let y = posit.y * cos_i - posit.z * sin_i;
+    let v_los = vel.y * sin_i + vel.z * cos_i;
+
+    (x, y, v_los)
+}
+
+/// Error function; Abramowitz & Stegun 7.1.26. Max error 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let t = 1. / (1. + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let result = 1. - poly * (-x.powi(2)).exp();
+
+    if x >= 0. {
+        result
+    } else {
+        -result
+    }
+}
+
+/// The portion of a 1D gaussian centered at `center` that falls within [lo, hi].
+fn gaussian_overlap(center: f64, σ: f64, lo: f64, hi: f64) -> f64 {
+    let cdf = |x: f64| 0.5 * (1. + erf((x - center) / (σ * 2_f64.sqrt())));
+    cdf(hi) - cdf(lo)
+}
+
+fn bin_centers(min: f64, max: f64, n: usize) -> Vec<f64> {
+    let width = (max - min) / n as f64;
+    (0..n).map(|i| min + (i as f64 + 0.5) * width).collect()
+}
+
+fn bin_index(val: f64, min: f64, max: f64, n: usize) -> Option<usize> {
+    if val < min || val >= max {
+        return None;
+    }
+    Some((((val - min) / (max - min)) * n as f64) as usize).filter(|i| *i < n)
+}
+
+impl PvDiagram {
+    /// `dist_from_earth` (kpc) converts the beam's angular size to a length.
+    pub fn new(bodies: &[Body], center: Vec3, slit: &Slit, dist_from_earth: f64) -> Self {
+        let (sin_pa, cos_pa) = slit.position_angle.to_radians().sin_cos();
+        let half_len = slit.length / 2.;
+
+        // Beam σ, in kpc.
+        let σ = slit
+            .beam_fwhm
+            .map(|fwhm| fwhm / FWHM_PER_SIGMA * ARCSEC_CONV_FACTOR * dist_from_earth)
+            .filter(|s| *s > 0.);
+        let margin = σ.map(|s| s * BEAM_CUTOFF_SIGMA).unwrap_or_default();
+
+        let samples: Vec<(f64, f64, f64, f64)> = bodies
+            .iter()
+            .filter_map(|b| {
+                let (x, y, v_los) = project(b.posit - center, b.vel, slit.inclination);
+
+                let along = x * cos_pa + y * sin_pa;
+                let across = -x * sin_pa + y * cos_pa;
+
+                (across.abs() <= slit.width / 2. + margin && along.abs() < half_len + margin)
+                    .then_some((along, across, v_los / KPC_MYR_PER_KM_S, b.mass))
+            })
+            .collect();
+
+        let v_max = samples.iter().map(|s| s.2.abs()).fold(0., f64::max).max(1.) * 1.05;
+
+        let mut intensity = vec![vec![0.; slit.num_vel]; slit.num_pos];
+        let bin_width = slit.length / slit.num_pos as f64;
+
+        for (pos, across, v, mass) in &samples {
+            let Some(j) = bin_index(*v, -v_max, v_max, slit.num_vel) else {
+                continue;
+            };
+
+            match σ {
+                Some(σ) => {
+                    let in_slit = gaussian_overlap(*across, σ, -slit.width / 2., slit.width / 2.);
+
+                    // Bins within the cutoff of the body, along the slit.
+                    let first = (((pos - margin + half_len) / bin_width).floor().max(0.)) as usize;
+                    for (i, col) in intensity.iter_mut().enumerate().skip(first) {
+                        let lo = -half_len + i as f64 * bin_width;
+                        if lo > pos + margin {
+                            break;
+                        }
+                        col[j] += mass * in_slit * gaussian_overlap(*pos, σ, lo, lo + bin_width);
+                    }
+                }
+                None => {
+                    if across.abs() <= slit.width / 2. {
+                        if let Some(i) = bin_index(*pos, -half_len, half_len, slit.num_pos) {
+                            intensity[i][j] += mass;
+                        }
+                    }
+                }
+            }
+        }
+
+        Self {
+            positions: bin_centers(-half_len, half_len, slit.num_pos),
+            velocities: bin_centers(-v_max, v_max, slit.num_vel),
+            intensity,
+            slit: slit.clone(),
+        }
+    }
+
+    /// Intensity-weighted mean velocity at each position. `None` for empty bins.
+    fn mean_velocities(&self) -> Vec<Option<f64>> {
+        self.intensity
+            .iter()
+            .map(|col| {
+                let total: f64 = col.iter().sum();
+                (total > 0.).then(|| {
+                    col.iter()
+                        .zip(&self.velocities)
+                        .map(|(m, v)| m * v)
+                        .sum::<f64>()
+                        / total
+                })
+            })
+            .collect()
+    }
+
+    /// The rotation curve traced from the diagram: Intensity-weighted mean velocities, with the two
+    /// sides folded together, and deprojected by 1 / sin(i). Only meaningful along the major axis.
+    /// X: r (kpc). Y: km/s.
+    pub fn rotation_curve(&self) -> Vec<(f64, f64)> {
+        let sin_i = self.slit.inclination.clamp(1., 90.).to_radians().sin();
+        let means = self.mean_velocities();
+        let n = means.len();
+
+        (n / 2..n)
+            .filter_map(|i| {
+                let mirror = n - 1 - i;
+                let v = match (means[i], means[mirror]) {
+                    (Some(a), Some(b)) => (a - b) / 2.,
+                    (Some(a), None) => a,
+                    (None, Some(b)) => -b,
+                    (None, None) => return None,
+                };
+                Some((self.positions[i], v.abs() / sin_i))
+            })
+            .collect()
+    }
+}
+
+/// Plot the diagram as a heat map, with intensity on a log scale.
+pub fn plot_pv(path: &Path, pv: &PvDiagram, title: &str) {
+    let (Some(pos_min), Some(pos_max)) = (pv.positions.first(), pv.positions.last()) else {
+        return;
+    };
+    let (Some(v_min), Some(v_max)) = (pv.velocities.first(), pv.velocities.last()) else {
+        return;
+    };
+    let dx = (pos_max - pos_min) / (pv.positions.len().max(2) - 1) as f64;
+    let dv = (v_max - v_min) / (pv.velocities.len().max(2) - 1) as f64;
+
+    let max_intensity = pv.intensity.iter().flatten().copied().fold(0., f64::max);
+    if max_intensity <= 0. {
+        eprintln!("No bodies in the slit.");
+        return;
+    }
+
+    let root = BitMapBackend::new(path, (800, 600)).into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(
+            pos_min - dx / 2.0..pos_max + dx / 2.,
+            v_min - dv / 2.0..v_max + dv / 2.,
+        )
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .x_desc("Position along slit (kpc)")
+        .y_desc("v_los (km/s)")
+        .draw()
+        .unwrap();
+
+    // Log scale over 3 decades; darker is brighter.
+    let cells = pv.positions.iter().enumerate().flat_map(|(i, x)| {
+        pv.velocities.iter().enumerate().filter_map(move |(j, v)| {
+            let val = pv.intensity[i][j];
+            if val <= 0. {
+                return None;
+            }
+            let level = ((val / max_intensity).log10() / 3. + 1.).clamp(0., 1.);
+            let color = HSLColor(0.65, 0.8, 0.9 - 0.7 * level);
+
+            Some(Rectangle::new(
+                [(x - dx / 2., v - dv / 2.), (x + dx / 2., v + dv / 2.)],
+                color.filled(),
+            ))
+        })
+    });
+
+    chart.draw_series(cells).unwrap();
+}
+
+/// Plot the PV diagram, and its traced rotation curve against the observed one.
+pub fn plot_pv_analysis(
+    pv_path: &Path,
+    curve_path: &Path,
+    pv: &PvDiagram,
+    descrip: &GalaxyDescrip,
+    desc: &str,
+) {
+    let slit = &pv.slit;
+    plot_pv(
+        pv_path,
+        pv,
+        &format!(
+            "PV diagram of {desc}. i: {:.0}° PA: {:.0}° Width: {:.2} kpc Beam: {}",
+            slit.inclination,
+            slit.position_angle,
+            slit.width,
+            slit.beam_fwhm
+                .map(|b| format!("{b:.0}\""))
+                .unwrap_or("None".to_owned())
+        ),
+    );
+
+    let traced = pv.rotation_curve();
+    let observed: Vec<(f64, f64)> = descrip
+        .rotation_curve_disk
+        .iter()
+        .map(|(r, v)| (*r, v / KPC_MYR_PER_KM_S))
+        .collect();
+
+    if let Err(e) = plot_multi_to(
+        curve_path,
+        &[("Observed", &observed), ("Traced from PV", &traced)],
+        "r (kpc)",
+        "v (km/s)",
+        &format!("Rotation curve of {desc}, traced along the slit"),
+    ) {
+        eprintln!("Error plotting {curve_path:?}: {e}");
+    }
+}
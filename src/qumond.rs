@@ -0,0 +1,370 @@
+//! QUMOND (Milgrom, 2010) on a uniform mesh: A field-level MOND baseline. We solve for the Newtonian
+//! potential, ∇²Φ_N = 4πGρ, then for the MOND potential, ∇²Φ = ∇·[ν(|∇Φ_N| / a_0) ∇Φ_N]. The
+//! difference between the two sources is the phantom dark matter density. Both are linear Poisson
+//! problems, so unlike AQUAL, no nonlinear solve is required.
+//!
+//! Mass is assigned, and forces interpolated, with cloud-in-cell. Poisson equations are solved with
+//! red-black SOR, using the isolated (monopole) solution at the boundary. The mesh is fixed for a
+//! run, so each step starts from the previous step's potentials, and converges in few iterations.
+//!
+//! Resolution is coarse compared to the tree; this is a baseline for the outer disk, where MOND
+//! effects dominate, vice the core.
+
+use std::f64::consts::PI;
+
+use lin_alg::f64::Vec3;
+use rand::Rng;
+
+use crate::{
+    accel::MondFn,
+    build,
+    properties::{plot_multi, rotation_curve},
+    units::{A0_MOND, C, G},
+    Body, ForceModel, State,
+};
+
+/// Cells per side.
+pub const GRID_N: usize = 64;
+/// Box half-width, as a multiple of the bodies' initial extent. Leaves room for the boundary to be
+/// approximately monopolar, and for the bodies to expand.
+const BOX_PAD: f64 = 1.5;
+const MAX_ITERS: usize = 3_000;
+/// Convergence: The largest update in a sweep, relative to the largest potential.
+const TOLERANCE: f64 = 1e-6;
+/// Steps when integrating the boundary potential.
+const BOUNDARY_INTEG_STEPS: usize = 64;
+
+/// Cumulative potential from integrating the spherical field outward. Only differences matter.
+fn monopole_potential(r: f64, r_min: f64, mass: f64, mond: Option<MondFn>) -> f64 {
+    let g = |r: f64| {
+        let g_n = G * mass / r.powi(2);
+        match mond {
+            Some(mond_fn) => g_n * mond_fn.nu(g_n / A0_MOND),
+            None => g_n,
+        }
+    };
+
+    match mond {
+        // Exact; avoids integration for the Newtonian case.
+        None => -G * mass / r,
+        // Trapezoidal, in log r, since the field varies over orders of magnitude.
+        Some(_) => {
+            let (ln_0, ln_1) = (r_min.ln(), r.ln());
+            let d = (ln_1 - ln_0) / BOUNDARY_INTEG_STEPS as f64;
+
+            (0..=BOUNDARY_INTEG_STEPS)
+                .map(|i| {
+                    let r_i = (ln_0 + i as f64 * d).exp();
+                    let w = if i == 0 || i == BOUNDARY_INTEG_STEPS {
+                        0.5
+                    } else {
+                        1.
+                    };
+                    w * g(r_i) * r_i * d
+                })
+                .sum()
+        }
+    }
+}
+
+pub struct QumondGrid {
+    /// The center of cell (0, 0, 0).
+    origin: Vec3,
+    /// Cell width. kpc.
+    h: f64,
+    /// Total mass, and center of mass; for the boundary, and for bodies outside the grid.
+    mass: f64,
+    com: Vec3,
+    pub phi_newton: Vec<f64>,
+    pub phi_mond: Vec<f64>,
+    /// M☉ / kpc³
+    pub rho_phantom: Vec<f64>,
+    /// SOR iterations used in the last solve; Newtonian, and MOND.
+    pub iters: (usize, usize),
+    /// The interpolating function the MOND boundary was set up with.
+    mond_boundary: Option<MondFn>,
+}
+
+fn idx(i: usize, j: usize, k: usize) -> usize {
+    (i * GRID_N + j) * GRID_N + k
+}
+
+/// Solve ∇²Φ = `source` in place, holding boundary cells fixed. `phi` is the initial guess.
+/// Returns the number of iterations.
+fn solve_poisson(phi: &mut [f64], source: &[f64], h: f64) -> usize {
+    let n = GRID_N;
+    // Optimal for the model problem.
+    let ω = 2. / (1. + (PI / n as f64).sin());
+    let h_sq = h.powi(2);
+
+    for iter in 0..MAX_ITERS {
+        let mut max_update: f64 = 0.;
+
+        for color in 0..2 {
+            for i in 1..n - 1 {
+                for j in 1..n - 1 {
+                    let k_start = 1 + (i + j + 1 + color) % 2;
+                    for k in (k_start..n - 1).step_by(2) {
+                        let c = idx(i, j, k);
+                        let neighbors = phi[c - n * n]
+                            + phi[c + n * n]
+                            + phi[c - n]
+                            + phi[c + n]
+                            + phi[c - 1]
+                            + phi[c + 1];
+
+                        let gs = (neighbors - h_sq * source[c]) / 6.;
+                        let update = ω * (gs - phi[c]);
+                        phi[c] += update;
+
+                        max_update = max_update.max(update.abs());
+                    }
+                }
+            }
+        }
+
+        let scale = phi.iter().fold(0., |acc: f64, v| acc.max(v.abs()));
+        if max_update <= TOLERANCE * scale {
+            return iter + 1;
+        }
+    }
+
+    eprintln!("QUMOND Poisson solve didn't converge in {MAX_ITERS} iterations");
+    MAX_ITERS
+}
+
+impl QumondGrid {
+    /// Set up the mesh geometry from the bodies' current extent. Returns `None` if there are no bodies.
+    pub fn new(bodies: &[Body]) -> Option<Self> {
+        let mass: f64 = bodies.iter().map(|b| b.mass).sum();
+        if bodies.is_empty() || mass <= 0. {
+            return None;
+        }
+
+        let com = bodies
+            .iter()
+            .fold(Vec3::new_zero(), |acc, b| acc + b.posit * b.mass)
+            / mass;
+
+        let extent = bodies
+            .iter()
+            .map(|b| (b.posit - com).magnitude())
+            .fold(0., f64::max)
+            .max(f64::EPSILON);
+
+        let half_width = extent * BOX_PAD;
+        let h = 2. * half_width / (GRID_N - 1) as f64;
+        let origin = com - Vec3::new(half_width, half_width, half_width);
+
+        let len = GRID_N.pow(3);
+        let mut result = Self {
+            origin,
+            h,
+            mass,
+            com,
+            phi_newton: vec![0.; len],
+            phi_mond: vec![0.; len],
+            rho_phantom: vec![0.; len],
+            iters: (0, 0),
+            mond_boundary: None,
+        };
+
+        // The boundary, and the initial guess for the interior.
+        for i in 0..GRID_N {
+            for j in 0..GRID_N {
+                for k in 0..GRID_N {
+                    let r = (result.cell_posit(i, j, k) - com).magnitude().max(h);
+                    result.phi_newton[idx(i, j, k)] = monopole_potential(r, h, mass, None);
+                }
+            }
+        }
+        result.phi_mond = result.phi_newton.clone();
+
+        Some(result)
+    }
+
+    fn cell_posit(&self, i: usize, j: usize, k: usize) -> Vec3 {
+        self.origin + Vec3::new(i as f64, j as f64, k as f64) * self.h
+    }
+
+    /// Cloud-in-cell weights for a position: The lower cell, and the fractions toward the upper.
+    /// `None` if the stencil leaves the grid.
+    fn cic(&self, posit: Vec3) -> Option<([usize; 3], [f64; 3])> {
+        let u = (posit - self.origin) / self.h;
+        let mut cell = [0; 3];
+        let mut frac = [0.; 3];
+
+        for (d, v) in [u.x, u.y, u.z].into_iter().enumerate() {
+            // Keep a cell of margin, so gradients can use central differences.
+            if !(v >= 1. && v < (GRID_N - 2) as f64) {
+                return None;
+            }
+            cell[d] = v.floor() as usize;
+            frac[d] = v - v.floor();
+        }
+
+        Some((cell, frac))
+    }
+
+    /// Iterate over the 8 cells of a CIC stencil, with their weights.
+    fn stencil(cell: [usize; 3], frac: [f64; 3]) -> impl Iterator<Item = (usize, f64)> {
+        (0..8).map(move |corner| {
+            let mut w = 1.;
+            let mut c = [0; 3];
+            for d in 0..3 {
+                let upper = (corner >> d) & 1 == 1;
+                c[d] = cell[d] + upper as usize;
+                w *= if upper { frac[d] } else { 1. - frac[d] };
+            }
+            (idx(c[0], c[1], c[2]), w)
+        })
+    }
+
+    /// -∇Φ at a cell center, from central differences.
+    fn grad_cell(&self, phi: &[f64], c: usize) -> Vec3 {
+        let n = GRID_N;
+        Vec3::new(
+            phi[c + n * n] - phi[c - n * n],
+            phi[c + n] - phi[c - n],
+            phi[c + 1] - phi[c - 1],
+        ) / (-2. * self.h)
+    }
+
+    /// Solve for both potentials, and the phantom density, from the bodies' current positions.
+    pub fn solve(&mut self, bodies: &[Body], mond_fn: MondFn) {
+        let n = GRID_N;
+        let cell_vol = self.h.powi(3);
+
+        let mut source = vec![0.; n.pow(3)];
+        for body in bodies {
+            // Bodies outside the grid are neglected; the boundary includes them as part of the
+            // monopole.
+            if let Some((cell, frac)) = self.cic(body.posit) {
+                for (c, w) in Self::stencil(cell, frac) {
+                    source[c] += 4. * PI * G * body.mass * w / cell_vol;
+                }
+            }
+        }
+
+        let iters_newton = solve_poisson(&mut self.phi_newton, &source, self.h);
+
+        // ν at each interior cell, from the Newtonian field.
+        let mut nu = vec![1.; n.pow(3)];
+        for i in 1..n - 1 {
+            for j in 1..n - 1 {
+                for k in 1..n - 1 {
+                    let c = idx(i, j, k);
+                    let y = self.grad_cell(&self.phi_newton, c).magnitude() / A0_MOND;
+                    if y > f64::EPSILON {
+                        nu[c] = mond_fn.nu(y);
+                    }
+                }
+            }
+        }
+
+        // ∇·[(ν - 1) ∇Φ_N], with face fluxes and ν averaged to faces. Excludes cells adjacent to the
+        // boundary, where ν isn't defined on both sides of each face.
+        let flux = |a: usize, b: usize| {
+            ((nu[a] + nu[b]) / 2. - 1.) * (self.phi_newton[b] - self.phi_newton[a]) / self.h
+        };
+
+        self.rho_phantom.fill(0.);
+        for i in 2..n - 2 {
+            for j in 2..n - 2 {
+                for k in 2..n - 2 {
+                    let c = idx(i, j, k);
+                    let div = [n * n, n, 1]
+                        .iter()
+                        .map(|s| flux(c, c + s) - flux(c - s, c))
+                        .sum::<f64>()
+                        / self.h;
+
+                    self.rho_phantom[c] = div / (4. * PI * G);
+                    source[c] += div;
+                }
+            }
+        }
+
+        // The MOND boundary is set once; it only depends on the monopole, which is conserved.
+        if self.mond_boundary != Some(mond_fn) {
+            for i in 0..n {
+                for j in 0..n {
+                    for k in 0..n {
+                        let r = (self.cell_posit(i, j, k) - self.com)
+                            .magnitude()
+                            .max(self.h);
+                        self.phi_mond[idx(i, j, k)] =
+                            monopole_potential(r, self.h, self.mass, Some(mond_fn));
+                    }
+                }
+            }
+            self.mond_boundary = Some(mond_fn);
+        }
+
+        let iters_mond = solve_poisson(&mut self.phi_mond, &source, self.h);
+        self.iters = (iters_newton, iters_mond);
+    }
+
+    /// The QUMOND acceleration at a point. `mond_fn` is only used outside the grid; it should match
+    /// the one passed to `solve`.
+    pub fn acc_mond(&self, posit: Vec3, mond_fn: MondFn) -> Vec3 {
+        match self.cic(posit) {
+            Some((cell, frac)) => Self::stencil(cell, frac)
+                .map(|(c, w)| self.grad_cell(&self.phi_mond, c) * w)
+                .fold(Vec3::new_zero(), |acc, v| acc + v),
+            // Outside the grid: The monopole.
+            None => {
+                let diff = self.com - posit;
+                let dist = diff.magnitude().max(self.h);
+                let g_n = G * self.mass / dist.powi(2);
+                diff / dist * g_n * mond_fn.nu(g_n / A0_MOND)
+            }
+        }
+    }
+
+    /// Total phantom dark matter mass within the grid. M☉.
+    pub fn phantom_mass(&self) -> f64 {
+        self.rho_phantom.iter().sum::<f64>() * self.h.powi(3)
+    }
+}
+
+/// Build with Newton, pairwise MOND, MOND on the net field, QUMOND, and the shell model, and plot the
+/// final rotation curves together. All runs use the same seed.
+pub fn compare_models(state: &mut State, mond_fn: MondFn) {
+    let cfg_prev = state.config.clone();
+    state.config.seed = Some(cfg_prev.seed.unwrap_or_else(|| rand::rng().random()));
+
+    let models = [
+        ForceModel::Newton,
+        ForceModel::Mond(mond_fn),
+        ForceModel::MondNet(mond_fn),
+        ForceModel::Qumond(mond_fn),
+        ForceModel::GaussShells,
+    ];
+
+    let mut curves = Vec::new();
+    for model in models {
+        println!("\nBuilding with {}...", model.to_str());
+        build(state, model);
+
+        curves.push(rotation_curve(&state.bodies, Vec3::new_zero(), C));
+    }
+
+    state.config = cfg_prev;
+
+    let labels: Vec<String> = models.iter().map(|m| m.to_str()).collect();
+    let series: Vec<(&str, &[(f64, f64)])> = labels
+        .iter()
+        .zip(&curves)
+        .map(|(l, c)| (l.as_str(), c.as_slice()))
+        .collect();
+
+    plot_multi(
+        &state.config.plot_output,
+        &series,
+        "r (kpc)",
+        "v (km/s)",
+        "Rotation curve by force model",
+        "qumond_compare_rot_curve",
+    );
+}
@@ -1,2 +1,478 @@
 //! Use GR equations (Or approximations?) to model the curvature of gravity rays
 //! from other gravity. Equivalent to light bending.
+//!
+//! Microlensing: Bodies act as point lenses for background sources. In the thin-lens, point-mass
+//! approximation, a lens of mass M has Einstein radius R_E = √(4GM/c² · D_L D_LS / D_S) in the
+//! lens plane, and magnifies a source at impact parameter u (in units of R_E) by
+//! A(u) = (u² + 2) / (u √(u² + 4)).
+//!
+//! Bodies' Einstein radii are tiny compared to the distance they move between snapshots, so we
+//! find each close approach from the body's motion between snapshots, treated as linear, and
+//! describe it by its Paczyński parameters: Peak time, minimum impact parameter, and Einstein
+//! crossing time. Light curves are evaluated from these analytically, at any time resolution.
+//! Lenses are sparse, so we combine events by summing their excess magnification, vice solving
+//! the multi-lens equation. Event rates and durations depend on the lenses' masses and velocities,
+//! so they're an observable for comparing mass models.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fmt::{Formatter, Write as _},
+    fs, io,
+    path::Path,
+};
+
+use lin_alg::{f64::Vec3, linspace};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::{
+    properties::{plot_multi, PlotOutput},
+    units::{C_LIGHT, G},
+    State,
+};
+
+const DAYS_PER_MYR: f64 = 365.25e6;
+/// Source grid cells per side, for finding sources near a lens's path.
+const GRID_CELLS: usize = 64;
+/// Light curves are sampled this many Einstein crossing times either side of each event's peak.
+const EVENT_WINDOW: f64 = 4.;
+/// Events plotted; one per plot color.
+const NUM_PLOTTED: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct MicrolensParams {
+    /// From the observer to the galaxy's center. kpc.
+    pub dist_lens: f64,
+    /// From the galaxy's center to the source plane, along the line of sight. kpc.
+    pub dist_source_behind: f64,
+    /// Unit vector from the observer toward the galaxy. +Z views the disk face-on.
+    pub line_of_sight: Vec3,
+    pub num_sources: usize,
+    /// Sources are placed uniformly within this radius of the line of sight through the galaxy's
+    /// center. kpc.
+    pub source_radius: f64,
+    /// Approaches wider than this, in Einstein radii, aren't events.
+    pub u_max: f64,
+}
+
+impl Default for MicrolensParams {
+    fn default() -> Self {
+        Self {
+            dist_lens: 10_000.,
+            dist_source_behind: 10.,
+            line_of_sight: Vec3::new(0., 0., 1.),
+            num_sources: 10_000,
+            source_radius: 10.,
+            u_max: 3.,
+        }
+    }
+}
+
+/// Point-lens magnification at impact parameter `u`, in Einstein radii.
+pub fn magnification(u: f64) -> f64 {
+    let u = u.max(1e-6);
+    (u.powi(2) + 2.) / (u * (u.powi(2) + 4.).sqrt())
+}
+
+/// Einstein radius in the lens plane. kpc, with mass in M☉, and distances from the observer in
+/// kpc.
+pub fn einstein_radius(mass: f64, dist_lens: f64, dist_source: f64) -> f64 {
+    if dist_lens <= 0. || dist_lens >= dist_source {
+        return 0.;
+    }
+    let d_eff = dist_lens * (dist_source - dist_lens) / dist_source;
+    (4. * G * mass / C_LIGHT.powi(2) * d_eff).sqrt()
+}
+
+/// A body's close approach to a source's line of sight.
+#[derive(Clone, Debug)]
+pub struct LensEvent {
+    pub source: usize,
+    /// Body ID.
+    pub lens: usize,
+    /// M☉
+    pub mass: f64,
+    /// Time of peak magnification. Myr.
+    pub t_0: f64,
+    /// Minimum impact parameter, in Einstein radii.
+    pub u_0: f64,
+    /// Einstein radius crossing time. Myr.
+    pub t_e: f64,
+}
+
+impl LensEvent {
+    /// The Paczyński light curve.
+    pub fn magnification(&self, t: f64) -> f64 {
+        let τ = (t - self.t_0) / self.t_e;
+        magnification((self.u_0.powi(2) + τ.powi(2)).sqrt())
+    }
+}
+
+pub struct Microlensing {
+    /// Positions in the sky plane, relative to the galaxy's center. kpc.
+    pub sources: Vec<(f64, f64)>,
+    /// Sorted by peak time.
+    pub events: Vec<LensEvent>,
+    /// The snapshot times searched. Myr.
+    pub t_start: f64,
+    pub t_end: f64,
+}
+
+impl Microlensing {
+    /// A source's magnification over the searched times: Evenly spaced, and densely around each of
+    /// its events. (t (Myr), A)
+    pub fn light_curve(&self, source: usize, num_pts: usize) -> Vec<(f64, f64)> {
+        let events: Vec<_> = self.events.iter().filter(|e| e.source == source).collect();
+
+        let mut times = linspace(self.t_start, self.t_end, num_pts);
+        for ev in &events {
+            times.extend(linspace(
+                ev.t_0 - EVENT_WINDOW * ev.t_e,
+                ev.t_0 + EVENT_WINDOW * ev.t_e,
+                81,
+            ));
+        }
+        times.sort_by(|a, b| a.total_cmp(b));
+        times.dedup();
+
+        times
+            .into_iter()
+            .map(|t| {
+                let excess: f64 = events.iter().map(|e| e.magnification(t) - 1.).sum();
+                (t, 1. + excess)
+            })
+            .collect()
+    }
+
+    /// Events per source per Myr.
+    pub fn rate(&self) -> f64 {
+        let dt = self.t_end - self.t_start;
+        if dt <= 0. || self.sources.is_empty() {
+            return 0.;
+        }
+        self.events.len() as f64 / (self.sources.len() as f64 * dt)
+    }
+
+    /// One row per event.
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        let mut csv = "source,lens,mass_msun,t_0_myr,u_0,t_e_days,peak_magnification\n".to_owned();
+        for ev in &self.events {
+            writeln!(
+                csv,
+                "{},{},{},{},{},{},{}",
+                ev.source,
+                ev.lens,
+                ev.mass,
+                ev.t_0,
+                ev.u_0,
+                ev.t_e * DAYS_PER_MYR,
+                magnification(ev.u_0)
+            )
+            .unwrap();
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, csv)
+    }
+}
+
+impl fmt::Display for Microlensing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Microlensing: {} events on {} sources over {:.1} Myr",
+            self.events.len(),
+            self.sources.len(),
+            self.t_end - self.t_start,
+        )?;
+        writeln!(f, "Rate: {:.3e} per source per Myr", self.rate())?;
+
+        if !self.events.is_empty() {
+            let mut t_e: Vec<f64> = self.events.iter().map(|e| e.t_e * DAYS_PER_MYR).collect();
+            t_e.sort_by(|a, b| a.total_cmp(b));
+            let peak = self
+                .events
+                .iter()
+                .map(|e| magnification(e.u_0))
+                .fold(1., f64::max);
+
+            writeln!(
+                f,
+                "t_E (days). Median: {:.1}  Min: {:.1}  Max: {:.1}",
+                t_e[t_e.len() / 2],
+                t_e[0],
+                t_e[t_e.len() - 1]
+            )?;
+            writeln!(f, "Highest peak magnification: {peak:.2}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A body's position, and mass.
+type Lens = (Vec3, f64);
+
+/// Finds lensing events from consecutive snapshots, added in time order. Incremental, so
+/// snapshots can be read one at a time, e.g. from a stream.
+pub struct MicrolensSearch {
+    params: MicrolensParams,
+    /// Sky plane basis vectors.
+    e_1: Vec3,
+    e_2: Vec3,
+    sources: Vec<(f64, f64)>,
+    /// Source indices in each grid cell, row-major.
+    grid: Vec<Vec<usize>>,
+    cell_size: f64,
+    prev: Option<(f64, HashMap<usize, Lens>)>,
+    events: Vec<LensEvent>,
+    t_start: f64,
+    /// Events peaking before the first snapshot, or after the last, are kept, so they aren't lost
+    /// at the ends of the run.
+    first_segment: bool,
+}
+
+impl MicrolensSearch {
+    pub fn new(params: MicrolensParams, seed: Option<u64>) -> Self {
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        let los = params.line_of_sight.to_normalized();
+        let ref_vec = if los.x.abs() < 0.9 {
+            Vec3::new(1., 0., 0.)
+        } else {
+            Vec3::new(0., 1., 0.)
+        };
+        let e_1 = los.cross(ref_vec).to_normalized();
+        let e_2 = los.cross(e_1);
+
+        let r = params.source_radius;
+        let sources: Vec<(f64, f64)> = (0..params.num_sources)
+            .map(|_| loop {
+                let (x, y) = (rng.random_range(-r..r), rng.random_range(-r..r));
+                if x.powi(2) + y.powi(2) <= r.powi(2) {
+                    break (x, y);
+                }
+            })
+            .collect();
+
+        let cell_size = 2. * r.max(f64::EPSILON) / GRID_CELLS as f64;
+        let mut grid = vec![Vec::new(); GRID_CELLS * GRID_CELLS];
+        for (i, (x, y)) in sources.iter().enumerate() {
+            let cell = |v: f64| (((v + r) / cell_size) as usize).min(GRID_CELLS - 1);
+            grid[cell(*y) * GRID_CELLS + cell(*x)].push(i);
+        }
+
+        Self {
+            params: MicrolensParams {
+                line_of_sight: los,
+                ..params
+            },
+            e_1,
+            e_2,
+            sources,
+            grid,
+            cell_size,
+            prev: None,
+            events: Vec::new(),
+            t_start: 0.,
+            first_segment: true,
+        }
+    }
+
+    /// Add a snapshot's bodies: (ID, position, mass), and find events between it and the previous.
+    /// `last` keeps events that would peak after it.
+    pub fn add_frame(&mut self, time: f64, bodies: Vec<(usize, Vec3, f64)>, last: bool) {
+        let frame: HashMap<usize, Lens> =
+            bodies.into_iter().map(|(id, p, m)| (id, (p, m))).collect();
+
+        let Some((t_prev, prev)) = self.prev.take() else {
+            self.t_start = time;
+            self.prev = Some((time, frame));
+            return;
+        };
+
+        let dt = time - t_prev;
+        if dt > 0. {
+            let first = self.first_segment;
+            let mut events: Vec<LensEvent> = frame
+                .par_iter()
+                .filter_map(|(id, (p_1, mass))| {
+                    prev.get(id).map(|(p_0, _)| {
+                        self.segment_events(*id, *p_0, *p_1, *mass, t_prev, dt, first, last)
+                    })
+                })
+                .flatten()
+                .collect();
+            self.events.append(&mut events);
+            self.first_segment = false;
+        }
+
+        self.prev = Some((time, frame));
+    }
+
+    /// Close approaches of one body to sources, over its path between two snapshots.
+    #[allow(clippy::too_many_arguments)]
+    fn segment_events(
+        &self,
+        lens: usize,
+        p_0: Vec3,
+        p_1: Vec3,
+        mass: f64,
+        t_0: f64,
+        dt: f64,
+        first: bool,
+        last: bool,
+    ) -> Vec<LensEvent> {
+        let mut result = Vec::new();
+
+        let los = self.params.line_of_sight;
+        let dist_lens = self.params.dist_lens + p_0.dot(los);
+        let dist_source = self.params.dist_lens + self.params.dist_source_behind;
+        let r_e = einstein_radius(mass, dist_lens, dist_source);
+        if r_e <= 0. {
+            return result;
+        }
+
+        let project = |p: Vec3| (p.dot(self.e_1), p.dot(self.e_2));
+        let (x_0, y_0) = project(p_0);
+        let (x_1, y_1) = project(p_1);
+        let (v_x, v_y) = ((x_1 - x_0) / dt, (y_1 - y_0) / dt);
+        let v_sq = v_x.powi(2) + v_y.powi(2);
+        if v_sq < f64::EPSILON.powi(2) {
+            return result;
+        }
+
+        // Grid cells the path, widened by the widest event's impact parameter, crosses.
+        let reach = self.params.u_max * r_e;
+        let r = self.params.source_radius;
+        let cell = |v: f64| ((v + r) / self.cell_size).floor();
+        let range = |a: f64, b: f64| {
+            let lo = cell(a.min(b) - reach).max(0.) as usize;
+            let hi = cell(a.max(b) + reach);
+            if hi < 0. {
+                return 1..0;
+            }
+            lo..(hi as usize).min(GRID_CELLS - 1) + 1
+        };
+
+        for row in range(y_0, y_1) {
+            for col in range(x_0, x_1) {
+                for &source in &self.grid[row * GRID_CELLS + col] {
+                    let (s_x, s_y) = self.sources[source];
+                    let (d_x, d_y) = (x_0 - s_x, y_0 - s_y);
+
+                    // Time of closest approach after the segment's start, on the line through it.
+                    let τ = -(d_x * v_x + d_y * v_y) / v_sq;
+                    let in_segment = (0. ..dt).contains(&τ);
+                    if !(in_segment || (first && τ < 0.) || (last && τ >= dt)) {
+                        continue;
+                    }
+
+                    let b = ((d_x + v_x * τ).powi(2) + (d_y + v_y * τ).powi(2)).sqrt();
+                    let u_0 = b / r_e;
+                    if u_0 > self.params.u_max {
+                        continue;
+                    }
+
+                    result.push(LensEvent {
+                        source,
+                        lens,
+                        mass,
+                        t_0: t_0 + τ,
+                        u_0,
+                        t_e: r_e / v_sq.sqrt(),
+                    });
+                }
+            }
+        }
+        result
+    }
+
+    pub fn finish(self) -> Microlensing {
+        let mut events = self.events;
+        events.sort_by(|a, b| a.t_0.total_cmp(&b.t_0));
+
+        Microlensing {
+            sources: self.sources,
+            events,
+            t_start: self.t_start,
+            t_end: self.prev.map_or(self.t_start, |(t, _)| t),
+        }
+    }
+}
+
+/// Search the run's snapshots for events. The lens distance is the galaxy's, if known.
+pub fn find_events(state: &mut State, mut params: MicrolensParams) -> Microlensing {
+    if state.ui.galaxy_descrip.dist_from_earth > 0. {
+        params.dist_lens = state.ui.galaxy_descrip.dist_from_earth;
+    }
+    let mut search = MicrolensSearch::new(params, state.config.seed);
+
+    let n = state.num_snapshots();
+    for i in 0..n {
+        state.with_snapshot(i, |snap, body_masses| {
+            let bodies = snap
+                .bodies(body_masses)
+                .iter()
+                .enumerate()
+                .map(|(k, b)| (snap.body_id(k), b.posit, b.mass))
+                .collect();
+            search.add_frame(snap.time as f64, bodies, i + 1 == n);
+        });
+    }
+
+    search.finish()
+}
+
+/// Plot the light curves of the highest-magnification events, against time from peak in Einstein
+/// crossing times, so events of different durations are comparable.
+pub fn plot_light_curves(out: &PlotOutput, lensing: &Microlensing, desc: &str) {
+    let mut events: Vec<&LensEvent> = lensing.events.iter().collect();
+    events.sort_by(|a, b| a.u_0.total_cmp(&b.u_0));
+
+    let curves: Vec<(String, Vec<(f64, f64)>)> = events
+        .iter()
+        .take(NUM_PLOTTED)
+        .map(|ev| {
+            let curve = linspace(-EVENT_WINDOW, EVENT_WINDOW, 200)
+                .into_iter()
+                .map(|τ| {
+                    let t = ev.t_0 + τ * ev.t_e;
+                    let excess: f64 = lensing
+                        .events
+                        .iter()
+                        .filter(|e| e.source == ev.source)
+                        .map(|e| e.magnification(t) - 1.)
+                        .sum();
+                    (τ, 1. + excess)
+                })
+                .collect();
+            (
+                format!("Source {}, t_E {:.1} d", ev.source, ev.t_e * DAYS_PER_MYR),
+                curve,
+            )
+        })
+        .collect();
+
+    if curves.is_empty() {
+        return;
+    }
+    let series: Vec<(&str, &[(f64, f64)])> = curves
+        .iter()
+        .map(|(label, c)| (label.as_str(), c.as_slice()))
+        .collect();
+
+    plot_multi(
+        out,
+        &series,
+        "(t - t₀) / t_E",
+        "Magnification",
+        &format!("Microlensing light curves of {desc}"),
+        &format!("microlensing_{desc}"),
+    );
+}
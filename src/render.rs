@@ -3,12 +3,13 @@
 use std::f32::consts::TAU;
 
 use graphics::{
-    event::WindowEvent, Camera, ControlScheme, DeviceEvent, EngineUpdates, GraphicsSettings,
-    InputSettings, LightType, Lighting, Mesh, PointLight, Scene, UiLayout, UiSettings, RIGHT_VEC,
+    event::WindowEvent, Camera, ControlScheme, DeviceEvent, EngineUpdates, Entity,
+    GraphicsSettings, InputSettings, LightType, Lighting, Mesh, PointLight, Scene, UiLayout,
+    UiSettings, FWD_VEC, RIGHT_VEC, UP_VEC,
 };
 use lin_alg::f32::{Quaternion, Vec3};
 
-use crate::{playback::change_snapshot, ui::ui_handler, State};
+use crate::{overlay, playback::change_snapshot, ui::ui_handler, State};
 
 type Color = (f32, f32, f32);
 
@@ -19,6 +20,13 @@ const BACKGROUND_COLOR: Color = (0., 0., 0.);
 
 const RENDER_DIST: f32 = 200.;
 
+/// Camera distance from the origin, along -Z, on startup and after fitting. Scene units.
+pub const CAM_DIST_DEFAULT: f32 = 20.;
+/// Fitting the view puts most bodies within this radius. Scene units.
+const FIT_RADIUS: f32 = 7.;
+/// The portion of bodies within the fit radius. The rest, e.g. a sparse outer halo, may be outside.
+const FIT_PORTION: f32 = 0.9;
+
 pub const BODY_SIZE_SCALER: f32 = 1.0e-9; // We multiply mass by this.
 pub const BODY_SIZE_MIN: f32 = 0.01;
 pub const BODY_SIZE_MAX: f32 = 0.6;
@@ -26,15 +34,24 @@ pub const BODY_SIZE_MAX: f32 = 0.6;
 pub const BODY_COLOR: Color = (1.0, 0.4, 0.4);
 pub const BODY_SHINYNESS: f32 = 2.;
 
-pub const SHELL_COLOR: Color = (1.0, 0.6, 0.2);
 pub const SHELL_SHINYNESS: f32 = 2.;
 
 pub const TREE_COLOR: Color = (0.4, 0.4, 1.0);
 pub const TREE_SHINYNESS: f32 = 1.;
 
+pub const TIDAL_COLOR: Color = (0.3, 0.8, 1.0);
+pub const TIDAL_SHINYNESS: f32 = 1.;
+pub const TIDAL_OPACITY: f32 = 0.15;
+
 pub const ARROW_COLOR: Color = (0.2, 1.0, 0.6);
 pub const ARROW_SHINYNESS: f32 = 1.;
 
+/// Relative to a trail segment's length; see `add_trails`.
+const TRAIL_WIDTH: f32 = 0.03;
+/// Of the newest trail segment; older ones fade.
+pub const TRAIL_OPACITY: f32 = 0.6;
+pub const TRAIL_SHINYNESS: f32 = 0.;
+
 // Allows individual cubes to be distinguished by creating gaps between them.
 pub const TREE_CUBE_SCALE_FACTOR: f32 = 0.85;
 
@@ -42,8 +59,93 @@ pub const TREE_CUBE_SCALE_FACTOR: f32 = 0.85;
 pub const MESH_SPHERE: usize = 0;
 pub const MESH_CUBE: usize = 1;
 pub const MESH_ARROW: usize = 2;
+pub const MESH_GRID_LINE: usize = 3;
+pub const MESH_TRAIL: usize = 4;
+
+/// The default; it's adjustable in the UI.
+pub const SHELL_OPACITY: f32 = 0.05;
+
+/// In emissive mode, the dimmest body's brightness, relative to the brightest.
+const EMISSIVE_BRIGHTNESS_MIN: f32 = 0.15;
+
+/// User-adjustable lighting, applied to the scene.
+#[derive(Clone, Debug)]
+pub struct LightingSettings {
+    pub ambient_intensity: f32,
+    pub point_lights: Vec<PointLight>,
+    /// Render unlit: Each body's color is its own, scaled by its luminosity, vice shaded by the
+    /// lights. Dense regions read as bright, instead of as a jumble of highlights and shadows.
+    pub emissive: bool,
+}
 
-pub const SHELL_OPACITY: f32 = 0.01;
+impl Default for LightingSettings {
+    fn default() -> Self {
+        let light = |position| PointLight {
+            type_: LightType::Omnidirectional,
+            position,
+            diffuse_color: [0.3, 0.4, 0.5, 1.],
+            specular_color: [0.3, 0.4, 0.5, 1.],
+            diffuse_intensity: 1_000.,
+            specular_intensity: 4_000.,
+        };
+
+        Self {
+            ambient_intensity: 0.02,
+            point_lights: vec![
+                // Light from above
+                light(Vec3::new(20., 20., 100.)),
+                // Light from below
+                light(Vec3::new(-20., 20., -100.)),
+            ],
+            emissive: false,
+        }
+    }
+}
+
+impl LightingSettings {
+    /// The engine has no per-entity unlit flag, so emissive mode is full, white ambient light, and
+    /// no point lights. This applies to the whole scene.
+    pub fn scene_lighting(&self) -> Lighting {
+        if self.emissive {
+            return Lighting {
+                ambient_color: [1., 1., 1., 1.],
+                ambient_intensity: 1.,
+                point_lights: Vec::new(),
+            };
+        }
+
+        Lighting {
+            ambient_color: [-1., 1., 1., 0.5],
+            ambient_intensity: self.ambient_intensity,
+            point_lights: self.point_lights.clone(),
+        }
+    }
+}
+
+/// For emissive rendering: Scale body colors by luminosity. We assume a uniform mass-to-light
+/// ratio, so this is by mass, on a log scale between the lightest and heaviest bodies. Bodies must
+/// be the first entities, in the same order as `body_masses`.
+pub fn apply_luminosity(entities: &mut [Entity], body_masses: &[f32]) {
+    let log_masses: Vec<f32> = body_masses
+        .iter()
+        .map(|m| m.max(f32::MIN_POSITIVE).ln())
+        .collect();
+    let min = log_masses.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = log_masses.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+    for (entity, log_mass) in entities.iter_mut().zip(log_masses) {
+        let portion = if max > min {
+            (log_mass - min) / (max - min)
+        } else {
+            1.
+        };
+        let brightness = EMISSIVE_BRIGHTNESS_MIN + (1. - EMISSIVE_BRIGHTNESS_MIN) * portion;
+
+        let (r, g, b) = entity.color;
+        entity.color = (r * brightness, g * brightness, b * brightness);
+        entity.shinyness = 0.;
+    }
+}
 
 fn event_dev_handler(
     _state: &mut State,
@@ -69,14 +171,88 @@ fn render_handler(_state: &mut State, _scene: &mut Scene, _dt: f32) -> EngineUpd
     EngineUpdates::default()
 }
 
+/// Scene units per kpc that fit most bodies of a snapshot in the default view, so large galaxies
+/// fit, and dwarfs don't look tiny.
+pub fn fit_scale(posits: &[Vec3]) -> f32 {
+    let mut radii: Vec<f32> = posits.iter().map(|p| p.magnitude()).collect();
+    radii.sort_by(f32::total_cmp);
+
+    let i = ((radii.len() as f32 * FIT_PORTION) as usize).min(radii.len().saturating_sub(1));
+    match radii.get(i) {
+        Some(r) if *r > 0. => FIT_RADIUS / r,
+        _ => 1.,
+    }
+}
+
+/// Look at the origin along +Z, from the default distance.
+pub fn reset_camera(camera: &mut Camera) {
+    camera.position = Vec3::new(0., 0., -CAM_DIST_DEFAULT);
+    camera.orientation = Quaternion::new_identity();
+}
+
+/// Put the camera at a body, to view the galaxy from inside. If `vel_frame`, look along its
+/// velocity, with up along its angular momentum about the origin; otherwise, keep the orientation,
+/// so the user can look around. Positions are in kpc, and velocities in kpc/Myr.
+pub fn ride_camera(camera: &mut Camera, posit: Vec3, vel: Vec3, vel_frame: bool, world_scale: f32) {
+    camera.position = posit * world_scale;
+
+    if !vel_frame || vel.magnitude() < f32::EPSILON {
+        return;
+    }
+    let fwd = vel.to_normalized();
+
+    let ang_mom = posit.cross(vel);
+    let up_ref = if ang_mom.magnitude() > f32::EPSILON {
+        ang_mom.to_normalized()
+    } else {
+        Vec3::new(0., 0., 1.)
+    };
+    // The reference up, made perpendicular to forward.
+    let up = up_ref - fwd * up_ref.dot(fwd);
+    if up.magnitude() < 1e-6 {
+        camera.orientation = Quaternion::from_unit_vecs(FWD_VEC, fwd);
+        return;
+    }
+
+    let look = Quaternion::from_unit_vecs(FWD_VEC, fwd);
+    // Roll about forward, so up matches.
+    let roll = Quaternion::from_unit_vecs(look.rotate_vec(UP_VEC), up.to_normalized());
+    camera.orientation = roll * look;
+}
+
+/// Keep a body at the center of the view, from the camera, looking at it from the same direction
+/// and distance as last frame. The orientation is kept, so the user can look around it, and the
+/// distance is measured from where it was last frame (`target_prev`), so the user can move closer
+/// or farther. Positions are in kpc; `target_prev` is in scene units.
+pub fn follow_camera(
+    camera: &mut Camera,
+    posit: Vec3,
+    target_prev: Option<Vec3>,
+    world_scale: f32,
+) -> Vec3 {
+    let target = posit * world_scale;
+    let dist = (camera.position - target_prev.unwrap_or(target)).magnitude();
+
+    let fwd = camera.orientation.rotate_vec(FWD_VEC);
+    camera.position = target - fwd * dist;
+
+    target
+}
+
 /// Entry point to our render and event loop.
-pub fn render(state: State) {
+pub fn render(mut state: State) {
+    let snapshot = &state.snapshots[state.ui.snapshot_selected];
+    state.ui.world_scale = fit_scale(&snapshot.body_posits);
+
     // Initialize entities.
     let mut entities = Vec::new();
     change_snapshot(
         &mut entities,
-        &state.snapshots[state.ui.snapshot_selected],
+        snapshot,
         &state.body_masses,
+        &state.body_components,
+        state.ui.color_mode,
+        state.ui.world_scale,
     );
 
     let scene = Scene {
@@ -84,41 +260,20 @@ pub fn render(state: State) {
             Mesh::new_sphere(1., 2),
             Mesh::new_box(1., 1., 1.),
             Mesh::new_arrow(1., 0.05, 8),
+            overlay::grid_line_mesh(state.ui.world_scale),
+            Mesh::new_box(1., TRAIL_WIDTH, TRAIL_WIDTH),
         ],
         entities,
         camera: Camera {
             fov_y: TAU / 8.,
-            position: Vec3::new(0., 0., -20.),
+            position: Vec3::new(0., 0., -CAM_DIST_DEFAULT),
             far: RENDER_DIST,
             near: 0.2, // todo: Adjust A/R
             // orientation: Quaternion::from_axis_angle(RIGHT_VEC, TAU / 16.),
             orientation: Quaternion::from_axis_angle(RIGHT_VEC, 0.),
             ..Default::default()
         },
-        lighting: Lighting {
-            ambient_color: [-1., 1., 1., 0.5],
-            ambient_intensity: 0.02,
-            point_lights: vec![
-                // Light from above
-                PointLight {
-                    type_: LightType::Omnidirectional,
-                    position: Vec3::new(20., 20., 100.),
-                    diffuse_color: [0.3, 0.4, 0.5, 1.],
-                    specular_color: [0.3, 0.4, 0.5, 1.],
-                    diffuse_intensity: 1_000.,
-                    specular_intensity: 4_000.,
-                },
-                // Light from below
-                PointLight {
-                    type_: LightType::Omnidirectional,
-                    position: Vec3::new(-20., 20., -100.),
-                    diffuse_color: [0.3, 0.4, 0.5, 1.],
-                    specular_color: [0.3, 0.4, 0.5, 1.],
-                    diffuse_intensity: 1_000.,
-                    specular_intensity: 4_000.,
-                },
-            ],
-        },
+        lighting: state.ui.lighting.scene_lighting(),
         input_settings: Default::default(),
         background_color: BACKGROUND_COLOR,
         window_size: (WINDOW_SIZE_X, WINDOW_SIZE_Y),
@@ -0,0 +1,224 @@
+//! Generates an HTML summary of a run: The config, galaxy metadata, rotation curve compared to
+//! observations, energy drift, density profiles, and key frames. Each report goes in its own directory,
+//! with its plots alongside it, so it can be archived or shared as a unit.
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lin_alg::f64::Vec3;
+use plotters::prelude::{BitMapBackend, ChartBuilder, Circle, Color, IntoDrawingArea, BLUE, WHITE};
+
+use crate::{
+    diagnostics,
+    integrate::IntegratorKind,
+    obs_uncertainty::plot_rot_curve_overlay,
+    playback::SnapShot,
+    properties::{mass_density, plot_err, plot_multi_to, rotation_curve},
+    units::C,
+    State,
+};
+
+const REPORT_DIR: &str = "reports";
+/// Portions of the way through the run to show positions at.
+const KEY_FRAMES: [f64; 5] = [0., 0.25, 0.5, 0.75, 1.];
+
+/// Body positions projected onto the disk plane.
+fn plot_frame(path: &Path, snap: &SnapShot, extent: f64) -> io::Result<()> {
+    let root = BitMapBackend::new(path, (400, 400)).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_err)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("t = {:.2} Myr", snap.time), ("sans-serif", 16))
+        .margin(10)
+        .x_label_area_size(25)
+        .y_label_area_size(25)
+        .build_cartesian_2d(-extent..extent, -extent..extent)
+        .map_err(plot_err)?;
+
+    chart.configure_mesh().draw().map_err(plot_err)?;
+
+    chart
+        .draw_series(
+            snap.body_posits
+                .iter()
+                .map(|p| Circle::new((p.x as f64, p.y as f64), 1, BLUE.mix(0.6).filled())),
+        )
+        .map_err(plot_err)?;
+
+    root.present().map_err(plot_err)
+}
+
+fn img(html: &mut String, filename: &str, alt: &str) {
+    writeln!(html, "<img src=\"{filename}\" alt=\"{alt}\">").unwrap();
+}
+
+/// Write the report for the most recent build, or the run played back, from memory or a stream.
+/// Returns the path to its HTML file.
+pub fn write_report(state: &mut State) -> io::Result<PathBuf> {
+    let num_snapshots = state.num_snapshots();
+    if num_snapshots == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No snapshots to report on",
+        ));
+    }
+    let snap_err = |i: usize| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unable to read snapshot {i}"),
+        )
+    };
+
+    let galaxy = state.ui.run_labels.galaxy.clone();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let dir = PathBuf::from(REPORT_DIR).join(format!("{}_{timestamp}", galaxy.replace(' ', "_")));
+    fs::create_dir_all(&dir)?;
+
+    let bodies_initial = state
+        .with_snapshot(0, |snap, body_masses| snap.bodies(body_masses))
+        .ok_or_else(|| snap_err(0))?;
+    // Snapshots of a subset of bodies don't have the mass distribution.
+    let i_last = (0..num_snapshots)
+        .rev()
+        .find(|i| state.with_snapshot(*i, |snap, _| snap.is_full()) == Some(true))
+        .unwrap_or_default();
+    let (time_final, bodies_final) = state
+        .with_snapshot(i_last, |snap, body_masses| {
+            (snap.time as f64, snap.bodies(body_masses))
+        })
+        .ok_or_else(|| snap_err(i_last))?;
+
+    // Key frames share an extent, from the initial positions, so they're comparable.
+    let extent = bodies_initial
+        .iter()
+        .map(|b| b.posit.x.abs().max(b.posit.y.abs()))
+        .fold(0., f64::max)
+        .max(1.);
+
+    let mut frame_files = Vec::new();
+    for (i, portion) in KEY_FRAMES.iter().enumerate() {
+        let snap_i = ((num_snapshots - 1) as f64 * portion).round() as usize;
+        let filename = format!("frame_{i}.png");
+        let path = dir.join(&filename);
+        state
+            .with_snapshot(snap_i, |snap, _| plot_frame(&path, snap, extent))
+            .ok_or_else(|| snap_err(snap_i))??;
+        frame_files.push(filename);
+    }
+
+    let descrip = &state.ui.galaxy_descrip;
+
+    // Rotation curve, simulated vs observed.
+    let simulated = rotation_curve(&bodies_final, Vec3::new_zero(), C);
+    plot_rot_curve_overlay(
+        &dir.join("rotation_curve.png"),
+        descrip,
+        &simulated,
+        &format!("Rotation curve of {galaxy}"),
+    )?;
+
+    // Relative energy and angular momentum drift.
+    let (drift, drift_l) = diagnostics::drift(&state.diagnostics);
+    let drift_final = drift.last().map(|d| d.1).unwrap_or_default();
+    let drift_l_final = drift_l.last().map(|d| d.1).unwrap_or_default();
+    // Empty if diagnostics weren't recorded, e.g. with `diagnostics_ratio` 0.
+    let has_drift = !drift.is_empty();
+    if has_drift {
+        plot_multi_to(
+            &dir.join("energy_drift.png"),
+            &[("E / E₀ - 1", &drift), ("|L - L₀| / |L₀|", &drift_l)],
+            "t (Myr)",
+            "Relative drift",
+            "Energy and angular momentum drift",
+        )?;
+    }
+
+    // Density profiles at the start and end of the run.
+    let density_initial = mass_density(&bodies_initial, Vec3::new_zero());
+    let density_final = mass_density(&bodies_final, Vec3::new_zero());
+    plot_multi_to(
+        &dir.join("mass_density.png"),
+        &[("Initial", &density_initial), ("Final", &density_final)],
+        "r (kpc)",
+        "ρ / ρ₀",
+        "Mass density",
+    )?;
+
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )
+    .unwrap();
+    writeln!(html, "<title>Run report: {galaxy}</title>").unwrap();
+    writeln!(
+        html,
+        "<style>body {{ font-family: sans-serif; max-width: 1000px; margin: auto; }} \
+         td {{ padding-right: 20px; }} img {{ max-width: 100%; }}</style>\n</head>\n<body>"
+    )
+    .unwrap();
+
+    writeln!(html, "<h1>{galaxy}</h1>").unwrap();
+    writeln!(html, "<table>").unwrap();
+    let integrator = match state.config.block_timesteps {
+        Some(block) if state.config.integrator == IntegratorKind::Leapfrog => format!(
+            "{}, block timesteps to dt / 2^{}",
+            state.config.integrator.to_str(),
+            block.max_level
+        ),
+        _ => state.config.integrator.to_str(),
+    };
+    let rows = [
+        ("Force model", state.ui.run_labels.force_model.clone()),
+        ("Integrator", integrator),
+        ("Bodies", bodies_final.len().to_string()),
+        ("Snapshots", num_snapshots.to_string()),
+        ("Time simulated", format!("{time_final:.3} Myr")),
+        ("Final energy drift", format!("{:.3e}", drift_final)),
+        (
+            "Final angular momentum drift",
+            format!("{:.3e}", drift_l_final),
+        ),
+    ];
+    for (label, val) in rows.into_iter().chain(descrip.summary()) {
+        writeln!(html, "<tr><td>{label}</td><td>{val}</td></tr>").unwrap();
+    }
+    writeln!(html, "</table>").unwrap();
+
+    writeln!(html, "<h2>Rotation curve</h2>").unwrap();
+    img(&mut html, "rotation_curve.png", "Rotation curve");
+
+    if has_drift {
+        writeln!(html, "<h2>Conservation</h2>").unwrap();
+        img(
+            &mut html,
+            "energy_drift.png",
+            "Energy and angular momentum drift",
+        );
+    }
+
+    writeln!(html, "<h2>Density profile</h2>").unwrap();
+    img(&mut html, "mass_density.png", "Mass density");
+
+    writeln!(html, "<h2>Key frames</h2>\n<div>").unwrap();
+    for filename in &frame_files {
+        img(&mut html, filename, "Key frame");
+    }
+    writeln!(html, "</div>").unwrap();
+
+    writeln!(html, "<h2>Config</h2>\n<pre>{:#?}</pre>", state.config).unwrap();
+    writeln!(html, "</body>\n</html>").unwrap();
+
+    let path = dir.join("index.html");
+    fs::write(&path, html)?;
+
+    Ok(path)
+}
@@ -0,0 +1,81 @@
+//! Light-travel-time-corrected rendering: Draw each body where an observer at the camera would see
+//! it, vice where it is now. A body's retarded time t_r satisfies t - t_r = |x(t_r) - x_obs| / c.
+//! This illustrates the causal model's premise: What we observe is the past, and the farther
+//! away, the older. We use the run's own propagation speed, `C`, so this matches the shells.
+//!
+//! Positions at retarded times are interpolated from earlier snapshots. Where the run doesn't go
+//! back far enough, we use the earliest snapshot.
+
+use lin_alg::f32::Vec3;
+
+use crate::{units::C, State};
+
+/// The retarded position of each body in snapshot `i`, as seen from `observer`, in kpc.
+/// `None` if the snapshot can't be read.
+pub fn retarded_posits(state: &mut State, i: usize, observer: Vec3) -> Option<Vec<Vec3>> {
+    let c = C as f32;
+    let (t_now, ids_now, posits_now) = state.with_snapshot(i, |snap, _| {
+        let ids: Vec<usize> = (0..snap.body_posits.len())
+            .map(|k| snap.body_id(k))
+            .collect();
+        (snap.time, ids, snap.body_posits.clone())
+    })?;
+
+    // Positive once light from the body at time t, and position p, has reached the observer.
+    let arrived = |t: f32, p: Vec3| (t_now - t) - (p - observer).magnitude() / c;
+
+    let mut result: Vec<Option<Vec3>> = vec![None; posits_now.len()];
+    let mut unresolved = posits_now.len();
+
+    // Each body's time and position at the latest snapshot we've checked that has it. Snapshots
+    // may have different subsets of bodies.
+    let mut later: Vec<(f32, Vec3)> = posits_now.into_iter().map(|p| (t_now, p)).collect();
+
+    for j in (0..i).rev() {
+        if unresolved == 0 {
+            break;
+        }
+        let Some((t_earlier, earlier)) = state.with_snapshot(j, |snap, _| {
+            let posits: Vec<Option<Vec3>> = ids_now
+                .iter()
+                .map(|id| snap.index_of(*id).map(|k| snap.body_posits[k]))
+                .collect();
+            (snap.time, posits)
+        }) else {
+            break;
+        };
+
+        for (b, posit) in result.iter_mut().enumerate() {
+            let Some(p_earlier) = earlier[b] else {
+                continue;
+            };
+            if posit.is_some() {
+                continue;
+            }
+            let (t_later, p_later) = later[b];
+
+            let f_later = arrived(t_later, p_later);
+            let f_earlier = arrived(t_earlier, p_earlier);
+            if f_earlier >= 0. {
+                // Light emitted between these snapshots arrives now.
+                let frac = if f_earlier - f_later > 0. {
+                    -f_later / (f_earlier - f_later)
+                } else {
+                    0.
+                };
+                *posit = Some(p_later + (p_earlier - p_later) * frac);
+                unresolved -= 1;
+            }
+            later[b] = (t_earlier, p_earlier);
+        }
+    }
+
+    // Light that left after the run's start hasn't arrived from these; use the earliest we have.
+    Some(
+        result
+            .into_iter()
+            .zip(later)
+            .map(|(p, (_, p_earliest))| p.unwrap_or(p_earliest))
+            .collect(),
+    )
+}
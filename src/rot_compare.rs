@@ -0,0 +1,90 @@
+//! Quantitative comparison of simulated and observed rotation curves: Residuals at the observed
+//! radii, and reduced χ², so force models (e.g. Newton, MOND, and Gauss shells) can be judged
+//! against the data, vice by eye.
+//!
+//! Galaxy descriptions don't carry per-point measurement errors, so each point's σ is a floor,
+//! combined in quadrature with the spread from the galaxy's distance and inclination uncertainty,
+//! if set. χ² values are comparable between runs of the same galaxy, but are only a rough absolute
+//! measure of fit quality.
+
+use crate::{
+    body_creation::GalaxyDescrip, obs_uncertainty::curve_bounds, units::KPC_MYR_PER_KM_S,
+    util::interpolate,
+};
+
+/// Per-point velocity uncertainty, before distance and inclination uncertainty. km/s.
+const V_ERR_FLOOR: f64 = 5.;
+
+/// All in km/s, with radii in kpc.
+pub struct CurveComparison {
+    /// (R, v, σ)
+    pub observed: Vec<(f64, f64, f64)>,
+    /// (R, simulated - observed), at observed radii within the simulated curve's range.
+    pub residuals: Vec<(f64, f64)>,
+    /// `None` if no observed points are in range.
+    pub chi_sq_reduced: Option<f64>,
+}
+
+/// The galaxy's observed disk curve, with each point's σ. In km/s.
+pub fn observed_with_errors(descrip: &GalaxyDescrip) -> Vec<(f64, f64, f64)> {
+    let observed: Vec<(f64, f64)> = descrip
+        .rotation_curve_disk
+        .iter()
+        .map(|(r, v)| (*r, v / KPC_MYR_PER_KM_S))
+        .collect();
+
+    let (lower, upper) = if descrip.uncertainty.is_set() {
+        curve_bounds(&observed, descrip.dist_from_earth, &descrip.uncertainty)
+    } else {
+        (observed.clone(), observed.clone())
+    };
+
+    observed
+        .iter()
+        .zip(lower.iter().zip(&upper))
+        .map(|((r, v), ((_, lo), (_, hi)))| {
+            let spread = (hi - lo).abs() / 2.;
+            (*r, *v, (V_ERR_FLOOR.powi(2) + spread.powi(2)).sqrt())
+        })
+        .collect()
+}
+
+/// Compare a simulated curve, e.g. from `properties::rotation_curve`, with the observed one. Both
+/// are in km/s. We interpolate the simulated curve to each observed radius. Degrees of freedom are
+/// the number of points compared, since a simulated curve has no parameters fit to the data.
+pub fn compare(simulated: &[(f64, f64)], observed: &[(f64, f64, f64)]) -> CurveComparison {
+    // Empty annuli show as 0; don't interpolate through them.
+    let simulated: Vec<_> = simulated
+        .iter()
+        .copied()
+        .filter(|(r, v)| *r == 0. || *v != 0.)
+        .collect();
+
+    let mut residuals = Vec::new();
+    let mut chi_sq = 0.;
+
+    if simulated.len() >= 2 {
+        let r_min = simulated[0].0;
+        let r_max = simulated[simulated.len() - 1].0;
+
+        for (r, v, σ) in observed {
+            if *r < r_min || *r > r_max {
+                continue;
+            }
+            let Some(v_sim) = interpolate(&simulated, *r) else {
+                continue;
+            };
+            let resid = v_sim - v;
+            residuals.push((*r, resid));
+            chi_sq += (resid / σ).powi(2);
+        }
+    }
+
+    let chi_sq_reduced = (!residuals.is_empty()).then(|| chi_sq / residuals.len() as f64);
+
+    CurveComparison {
+        observed: observed.to_vec(),
+        residuals,
+        chi_sq_reduced,
+    }
+}
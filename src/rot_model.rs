@@ -0,0 +1,369 @@
+//! Closed-form rotation curve models, for what-if fitting without an N-body run: An exponential
+//! disk, a Hernquist bulge, an NFW or Burkert halo, and optionally, the MOND transformation of the
+//! baryonic (disk and bulge) curve. Evaluating one takes microseconds, so parameters can be tuned,
+//! or fit to the observed curve, before committing to a full build.
+//!
+//! Components add in quadrature: v² = v_disk² + v_bulge² + v_halo². With MOND, the halo is
+//! omitted, and the baryonic acceleration is boosted: g = ν(g_N / a₀) g_N.
+
+use std::f64::consts::PI;
+
+use crate::{
+    accel::MondFn,
+    body_creation::GalaxyDescrip,
+    cdm::{mass_burkert, mass_nfw},
+    properties::{plot_multi, PlotOutput},
+    units::{A0_MOND, G, KPC_MYR_PER_KM_S},
+};
+
+/// Samples in plotted curves.
+const N_PLOT_PTS: usize = 200;
+/// Fitting stops when the step factor falls below this.
+const FIT_STEP_MIN: f64 = 1.001;
+const FIT_ITERS_MAX: usize = 2_000;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum HaloProfile {
+    None,
+    Nfw,
+    #[default]
+    Burkert,
+}
+
+impl HaloProfile {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::None => "None",
+            Self::Nfw => "NFW",
+            Self::Burkert => "Burkert",
+        }
+        .to_owned()
+    }
+}
+
+/// Masses in M☉, lengths in kpc, and densities in M☉/kpc³. A component with zero mass or density
+/// is omitted.
+#[derive(Clone, Debug)]
+pub struct RotCurveModel {
+    pub disk_mass: f64,
+    /// Exponential scale length.
+    pub disk_scale: f64,
+    pub bulge_mass: f64,
+    /// Hernquist scale radius. The half-mass radius is about 1.8 times this.
+    pub bulge_scale: f64,
+    pub halo: HaloProfile,
+    /// ρ_s for NFW; ρ₀ for Burkert.
+    pub halo_density: f64,
+    /// r_s for NFW; the core radius for Burkert.
+    pub halo_scale: f64,
+    /// If set, replaces the halo.
+    pub mond: Option<MondFn>,
+}
+
+impl Default for RotCurveModel {
+    fn default() -> Self {
+        Self {
+            disk_mass: 5e10,
+            disk_scale: 3.,
+            bulge_mass: 1e10,
+            bulge_scale: 0.5,
+            halo: HaloProfile::Burkert,
+            halo_density: 1e7,
+            halo_scale: 8.,
+            mond: None,
+        }
+    }
+}
+
+/// Exponentially scaled modified Bessel functions: I₀(x)e⁻ˣ, I₁(x)e⁻ˣ, K₀(x)eˣ, and K₁(x)eˣ.
+/// Polynomial approximations from Abramowitz & Stegun, 9.8.1 - 9.8.8. Scaled, so their products
+/// don't overflow at large x.
+fn bessel_scaled(x: f64) -> (f64, f64, f64, f64) {
+    let (i0, i1) = if x <= 3.75 {
+        let t = (x / 3.75).powi(2);
+        let i0 = 1.
+            + t * (3.5156229
+                + t * (3.0899424
+                    + t * (1.2067492 + t * (0.2659732 + t * (0.0360768 + t * 0.0045813)))));
+        let i1 = x
+            * (0.5
+                + t * (0.87890594
+                    + t * (0.51498869
+                        + t * (0.15084934
+                            + t * (0.02658733 + t * (0.00301532 + t * 0.00032411))))));
+        (i0 * (-x).exp(), i1 * (-x).exp())
+    } else {
+        let t = 3.75 / x;
+        let i0 = 0.39894228
+            + t * (0.01328592
+                + t * (0.00225319
+                    + t * (-0.00157565
+                        + t * (0.00916281
+                            + t * (-0.02057706
+                                + t * (0.02635537 + t * (-0.01647633 + t * 0.00392377)))))));
+        let i1 = 0.39894228
+            + t * (-0.03988024
+                + t * (-0.00362018
+                    + t * (0.00163801
+                        + t * (-0.01031555
+                            + t * (0.02282967
+                                + t * (-0.02895312 + t * (0.01787654 - t * 0.00420059)))))));
+        (i0 / x.sqrt(), i1 / x.sqrt())
+    };
+
+    let (k0, k1) = if x <= 2. {
+        let t = (x / 2.).powi(2);
+        // Unscaled I, for the log terms.
+        let (i0_, i1_) = (i0 * x.exp(), i1 * x.exp());
+        let k0 = -(x / 2.).ln() * i0_ - 0.57721566
+            + t * (0.42278420
+                + t * (0.23069756
+                    + t * (0.03488590 + t * (0.00262698 + t * (0.00010750 + t * 0.0000074)))));
+        let k1 = (x / 2.).ln() * i1_
+            + (1.
+                + t * (0.15443144
+                    + t * (-0.67278579
+                        + t * (-0.18156897
+                            + t * (-0.01919402 + t * (-0.00110404 - t * 0.00004686))))))
+                / x;
+        (k0 * x.exp(), k1 * x.exp())
+    } else {
+        let t = 2. / x;
+        let k0 = 1.25331414
+            + t * (-0.07832358
+                + t * (0.02189568
+                    + t * (-0.01062446 + t * (0.00587872 + t * (-0.00251540 + t * 0.00053208)))));
+        let k1 = 1.25331414
+            + t * (0.23498619
+                + t * (-0.03655620
+                    + t * (0.01504268 + t * (-0.00780353 + t * (0.00325614 - t * 0.00068245)))));
+        (k0 / x.sqrt(), k1 / x.sqrt())
+    };
+
+    (i0, i1, k0, k1)
+}
+
+/// Fit Σ = Σ₀ e^(-R/R_d) to a surface density profile, by least squares on ln Σ. kpc.
+pub fn disk_scale_length(density: &[(f64, f64)]) -> Option<f64> {
+    let pts: Vec<(f64, f64)> = density
+        .iter()
+        .filter(|(_, d)| *d > 0.)
+        .map(|(r, d)| (*r, d.ln()))
+        .collect();
+    if pts.len() < 2 {
+        return None;
+    }
+
+    let n = pts.len() as f64;
+    let mean_r = pts.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_ln = pts.iter().map(|p| p.1).sum::<f64>() / n;
+    let cov: f64 = pts.iter().map(|(r, l)| (r - mean_r) * (l - mean_ln)).sum();
+    let var: f64 = pts.iter().map(|(r, _)| (r - mean_r).powi(2)).sum();
+
+    let slope = cov / var;
+    (slope < 0.).then(|| -1. / slope)
+}
+
+impl RotCurveModel {
+    /// Start from a galaxy's disk and bulge masses, and its Burkert halo, if it has one. The disk
+    /// scale length is fit to its surface density profile.
+    pub fn from_galaxy(descrip: &GalaxyDescrip) -> Self {
+        let mut result = Self {
+            disk_mass: descrip.mass_disk,
+            bulge_mass: descrip.mass_bulge,
+            ..Default::default()
+        };
+        if let Some(scale) = disk_scale_length(&descrip.mass_density_disk) {
+            result.disk_scale = scale;
+        }
+
+        let (r_core, rho_0) = descrip.burkert_params;
+        if r_core > 0. && rho_0 > 0. {
+            result.halo = HaloProfile::Burkert;
+            result.halo_scale = r_core;
+            result.halo_density = rho_0;
+        }
+        result
+    }
+
+    /// A thin exponential disk (Freeman, 1970): v² = 4πGΣ₀R_d y² [I₀K₀ - I₁K₁], y = R / 2R_d.
+    /// kpc/Myr.
+    pub fn v_disk(&self, r: f64) -> f64 {
+        if self.disk_mass <= 0. || self.disk_scale <= 0. || r <= 0. {
+            return 0.;
+        }
+        let sigma_0 = self.disk_mass / (2. * PI * self.disk_scale.powi(2));
+        let y = r / (2. * self.disk_scale);
+        let (i0, i1, k0, k1) = bessel_scaled(y);
+
+        let v_sq = 4. * PI * G * sigma_0 * self.disk_scale * y * y * (i0 * k0 - i1 * k1);
+        v_sq.max(0.).sqrt()
+    }
+
+    /// Hernquist (1990): v² = GMr / (r + a)². kpc/Myr.
+    pub fn v_bulge(&self, r: f64) -> f64 {
+        if self.bulge_mass <= 0. || r <= 0. {
+            return 0.;
+        }
+        (G * self.bulge_mass * r).sqrt() / (r + self.bulge_scale.max(0.))
+    }
+
+    /// kpc/Myr. Zero with MOND.
+    pub fn v_halo(&self, r: f64) -> f64 {
+        if self.mond.is_some() || self.halo_density <= 0. || self.halo_scale <= 0. || r <= 0. {
+            return 0.;
+        }
+        let mass = match self.halo {
+            HaloProfile::None => return 0.,
+            HaloProfile::Nfw => mass_nfw(r, self.halo_density, self.halo_scale),
+            HaloProfile::Burkert => mass_burkert(r, self.halo_density, self.halo_scale),
+        };
+        (G * mass / r).max(0.).sqrt()
+    }
+
+    /// Total circular velocity. kpc/Myr.
+    pub fn v_circ(&self, r: f64) -> f64 {
+        let v_baryon_sq = self.v_disk(r).powi(2) + self.v_bulge(r).powi(2);
+
+        match self.mond {
+            Some(mond_fn) if r > 0. => {
+                let g_n = v_baryon_sq / r;
+                if g_n <= 0. {
+                    return 0.;
+                }
+                (mond_fn.nu(g_n / A0_MOND) * g_n * r).sqrt()
+            }
+            _ => (v_baryon_sq + self.v_halo(r).powi(2)).sqrt(),
+        }
+    }
+
+    /// RMS difference from an observed curve, at its radii. Both in kpc/Myr. `None` if the curve
+    /// is empty.
+    pub fn rms_error(&self, observed: &[(f64, f64)]) -> Option<f64> {
+        if observed.is_empty() {
+            return None;
+        }
+        let sum_sq: f64 = observed
+            .iter()
+            .map(|(r, v)| (self.v_circ(*r) - v).powi(2))
+            .sum();
+        Some((sum_sq / observed.len() as f64).sqrt())
+    }
+
+    /// The parameters fitting adjusts: those of the enabled components.
+    fn free_params(&mut self) -> Vec<&mut f64> {
+        let mut result = Vec::new();
+        if self.disk_mass > 0. {
+            result.push(&mut self.disk_mass);
+            result.push(&mut self.disk_scale);
+        }
+        if self.bulge_mass > 0. {
+            result.push(&mut self.bulge_mass);
+            result.push(&mut self.bulge_scale);
+        }
+        if self.mond.is_none() && self.halo != HaloProfile::None && self.halo_density > 0. {
+            result.push(&mut self.halo_density);
+            result.push(&mut self.halo_scale);
+        }
+        result
+    }
+
+    /// Fit the enabled components' parameters to an observed curve (kpc/Myr), minimizing the RMS
+    /// error, by a pattern search in log space: Scale each parameter up and down by a factor,
+    /// keeping improvements, and shrink the factor when none help. Parameters stay positive.
+    /// Returns the final RMS error.
+    pub fn fit(&mut self, observed: &[(f64, f64)]) -> Option<f64> {
+        let mut best = self.rms_error(observed)?;
+        let num_params = self.free_params().len();
+        let mut step: f64 = 2.;
+
+        for _ in 0..FIT_ITERS_MAX {
+            if step < FIT_STEP_MIN {
+                break;
+            }
+
+            let mut improved = false;
+            for i in 0..num_params {
+                for factor in [step, 1. / step] {
+                    let prev = *self.free_params()[i];
+                    *self.free_params()[i] = prev * factor;
+
+                    match self.rms_error(observed) {
+                        Some(err) if err < best => {
+                            best = err;
+                            improved = true;
+                            break;
+                        }
+                        _ => *self.free_params()[i] = prev,
+                    }
+                }
+            }
+
+            if !improved {
+                step = step.sqrt();
+            }
+        }
+
+        Some(best)
+    }
+}
+
+/// The observed curve to compare against: Corrected for asymmetric drift if available. kpc/Myr.
+pub fn observed_curve(descrip: &GalaxyDescrip) -> &[(f64, f64)] {
+    if descrip.rotation_curve_corrected.is_empty() {
+        &descrip.rotation_curve_disk
+    } else {
+        &descrip.rotation_curve_corrected
+    }
+}
+
+/// Plot each component, the total, and the observed curve, in km/s.
+pub fn plot_model(out: &PlotOutput, model: &RotCurveModel, observed: &[(f64, f64)], desc: &str) {
+    let r_max = observed
+        .iter()
+        .map(|(r, _)| *r)
+        .fold(0., f64::max)
+        .max(model.disk_scale * 6.)
+        .max(1.);
+
+    let sample = |f: &dyn Fn(f64) -> f64| -> Vec<(f64, f64)> {
+        (1..=N_PLOT_PTS)
+            .map(|i| {
+                let r = r_max * i as f64 / N_PLOT_PTS as f64;
+                (r, f(r) / KPC_MYR_PER_KM_S)
+            })
+            .collect()
+    };
+
+    let total = sample(&|r| model.v_circ(r));
+    let disk = sample(&|r| model.v_disk(r));
+    let bulge = sample(&|r| model.v_bulge(r));
+    let halo = sample(&|r| model.v_halo(r));
+    let observed: Vec<(f64, f64)> = observed
+        .iter()
+        .map(|(r, v)| (*r, v / KPC_MYR_PER_KM_S))
+        .collect();
+
+    let mut series: Vec<(&str, &[(f64, f64)])> = vec![("Model", &total)];
+    if !observed.is_empty() {
+        series.push(("Observed", &observed));
+    }
+    if model.disk_mass > 0. {
+        series.push(("Disk", &disk));
+    }
+    if model.bulge_mass > 0. {
+        series.push(("Bulge", &bulge));
+    }
+    if halo.iter().any(|(_, v)| *v > 0.) {
+        series.push(("Halo", &halo));
+    }
+
+    plot_multi(
+        out,
+        &series,
+        "r (kpc)",
+        "km/s",
+        &format!("Analytic rotation curve model: {desc}"),
+        &format!("rot_model_{desc}"),
+    );
+}
@@ -0,0 +1,358 @@
+//! A self-consistent field (SCF) force solver, using the Hernquist & Ostriker (1992) basis: Hernquist
+//! model potential-density pairs of increasing radial order, times spherical harmonics. For smooth,
+//! near-equilibrium spheroids, such as halos and bulges, a few dozen terms describe the field, so
+//! forces cost O(N) per step, with no softening, and no tree.
+//!
+//! Disks and other flattened or clumpy systems need many terms; use the tree for those.
+//!
+//! We work in units of the scale radius, `a`, with G = 1, and scale on output. Basis normalization is
+//! computed numerically when the expansion is set up.
+
+use std::f64::consts::{PI, SQRT_2};
+
+use bincode::{Decode, Encode};
+use lin_alg::f64::Vec3;
+use rayon::prelude::*;
+
+use crate::{units::G, Body};
+
+/// Intervals for integrating the normalization. Integrands are smooth in ξ.
+const NORM_INTEG_STEPS: usize = 2_000;
+/// Avoids dividing by zero at the poles and center.
+const SIN_MIN: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, Encode, Decode)]
+pub struct ScfConfig {
+    /// Highest radial order.
+    pub n_max: usize,
+    /// Highest angular order. 0 is spherical.
+    pub l_max: usize,
+}
+
+impl Default for ScfConfig {
+    fn default() -> Self {
+        Self {
+            n_max: 10,
+            l_max: 4,
+        }
+    }
+}
+
+/// Gegenbauer polynomials C_n^(α)(ξ), for n in 0..=n_max.
+fn gegenbauer(n_max: usize, alpha: f64, ξ: f64) -> Vec<f64> {
+    let mut result = vec![1.; n_max + 1];
+    if n_max >= 1 {
+        result[1] = 2. * alpha * ξ;
+    }
+    for n in 2..=n_max {
+        let n_f = n as f64;
+        result[n] = (2. * (n_f + alpha - 1.) * ξ * result[n - 1]
+            - (n_f + 2. * alpha - 2.) * result[n - 2])
+            / n_f;
+    }
+    result
+}
+
+/// Associated Legendre functions P_l^m(x), indexed [l][m], for 0 <= m <= l <= l_max. Without the
+/// Condon-Shortley phase.
+fn legendre(l_max: usize, x: f64) -> Vec<Vec<f64>> {
+    let s = (1. - x.powi(2)).max(0.).sqrt();
+    let mut p = vec![vec![0.; l_max + 1]; l_max + 1];
+
+    for m in 0..=l_max {
+        // P_m^m = (2m - 1)!! sin^m θ
+        p[m][m] = (1..=m).fold(1., |acc, i| acc * (2 * i - 1) as f64 * s);
+        if m < l_max {
+            p[m + 1][m] = x * (2 * m + 1) as f64 * p[m][m];
+        }
+        for l in m + 2..=l_max {
+            p[l][m] = ((2 * l - 1) as f64 * x * p[l - 1][m] - (l + m - 1) as f64 * p[l - 2][m])
+                / (l - m) as f64;
+        }
+    }
+    p
+}
+
+fn factorial(n: usize) -> f64 {
+    (1..=n).fold(1., |acc, i| acc * i as f64)
+}
+
+/// Real spherical harmonic normalization: √((2l + 1) / 4π (l - m)! / (l + m)!), with a factor of √2
+/// for m > 0, so each harmonic is orthonormal over the sphere.
+fn y_norm(l: usize, m: usize) -> f64 {
+    let n = ((2 * l + 1) as f64 / (4. * PI) * factorial(l - m) / factorial(l + m)).sqrt();
+    if m == 0 {
+        n
+    } else {
+        n * SQRT_2
+    }
+}
+
+/// The radial potential basis function, and its derivative WRT r: -r^l / (1 + r)^(2l + 1) C_n(ξ).
+/// `gegen` is C^(2l + 3/2); `gegen_deriv` is C^(2l + 5/2), for the derivative.
+fn radial_potential(r: f64, l: usize, n: usize, gegen: &[f64], gegen_deriv: &[f64]) -> (f64, f64) {
+    let l_f = l as f64;
+    let alpha = 2. * l_f + 1.5;
+
+    let f = r.powi(l as i32) / (1. + r).powi(2 * l as i32 + 1);
+    let df = f * (l_f / r.max(SIN_MIN) - (2. * l_f + 1.) / (1. + r));
+
+    // dC_n^α / dξ = 2α C_(n-1)^(α + 1); dξ / dr = 2 / (1 + r)².
+    let dc = if n == 0 {
+        0.
+    } else {
+        2. * alpha * gegen_deriv[n - 1] * 2. / (1. + r).powi(2)
+    };
+
+    (-f * gegen[n], -(df * gegen[n] + f * dc))
+}
+
+/// The radial density basis function: K_nl / 2π r^l / (r (1 + r)^(2l + 3)) C_n(ξ).
+fn radial_density(r: f64, l: usize, n: usize, c: f64) -> f64 {
+    let k = 0.5 * (n * (n + 4 * l + 3)) as f64 + ((l + 1) * (2 * l + 1)) as f64;
+    k / (2. * PI) * r.powi(l as i32) / (r * (1. + r).powi(2 * l as i32 + 3)) * c
+}
+
+/// ∫ r² ψ_nl ρ_nl dr, for each [n][l]. Integrated in ξ = (r - 1) / (r + 1), with the midpoint rule,
+/// which avoids the endpoints.
+fn normalization(cfg: &ScfConfig) -> Vec<Vec<f64>> {
+    let mut result = vec![vec![0.; cfg.l_max + 1]; cfg.n_max + 1];
+    let dξ = 2. / NORM_INTEG_STEPS as f64;
+
+    for i in 0..NORM_INTEG_STEPS {
+        let ξ = -1. + (i as f64 + 0.5) * dξ;
+        let r = (1. + ξ) / (1. - ξ);
+        let dr_dξ = 2. / (1. - ξ).powi(2);
+
+        for l in 0..=cfg.l_max {
+            let gegen = gegenbauer(cfg.n_max, 2. * l as f64 + 1.5, ξ);
+            for n in 0..=cfg.n_max {
+                let ψ = -r.powi(l as i32) / (1. + r).powi(2 * l as i32 + 1) * gegen[n];
+                let dens = radial_density(r, l, n, gegen[n]);
+                result[n][l] += r.powi(2) * ψ * dens * dr_dξ * dξ;
+            }
+        }
+    }
+    result
+}
+
+/// Real spherical harmonics Y_lm, and their θ and φ derivatives, at one direction. Indexed by
+/// `harmonic_index`.
+struct Harmonics {
+    y: Vec<f64>,
+    dy_dθ: Vec<f64>,
+    /// (∂Y / ∂φ) / sin θ, which is finite at the poles.
+    dy_dphi_sin: Vec<f64>,
+}
+
+/// Harmonics are ordered by l, then m from -l to l.
+fn harmonic_index(l: usize, m: isize) -> usize {
+    l * l + (m + l as isize) as usize
+}
+
+fn harmonics(l_max: usize, θ: f64, phi: f64) -> Harmonics {
+    let (sin_θ, cos_θ) = θ.sin_cos();
+    let sin_θ = sin_θ.max(SIN_MIN);
+    let p = legendre(l_max, cos_θ);
+
+    let len = (l_max + 1).pow(2);
+    let mut result = Harmonics {
+        y: vec![0.; len],
+        dy_dθ: vec![0.; len],
+        dy_dphi_sin: vec![0.; len],
+    };
+
+    for l in 0..=l_max {
+        for m in 0..=l {
+            let norm = y_norm(l, m);
+            let (l_f, m_f) = (l as f64, m as f64);
+
+            // dP_l^m / dθ = (l cos θ P_l^m - (l + m) P_(l-1)^m) / sin θ
+            let p_prev = if l > m { p[l - 1][m] } else { 0. };
+            let dp_dθ = (l_f * cos_θ * p[l][m] - (l_f + m_f) * p_prev) / sin_θ;
+
+            let (sin_mphi, cos_mphi) = (m_f * phi).sin_cos();
+
+            let i = harmonic_index(l, m as isize);
+            result.y[i] = norm * p[l][m] * cos_mphi;
+            result.dy_dθ[i] = norm * dp_dθ * cos_mphi;
+            result.dy_dphi_sin[i] = -norm * m_f * p[l][m] / sin_θ * sin_mphi;
+
+            if m > 0 {
+                let i = harmonic_index(l, -(m as isize));
+                result.y[i] = norm * p[l][m] * sin_mphi;
+                result.dy_dθ[i] = norm * dp_dθ * sin_mphi;
+                result.dy_dphi_sin[i] = norm * m_f * p[l][m] / sin_θ * cos_mphi;
+            }
+        }
+    }
+    result
+}
+
+/// Spherical coordinates of a point relative to the center, in units of the scale radius.
+fn to_spherical(posit: Vec3, center: Vec3, scale_radius: f64) -> (f64, f64, f64) {
+    let d = (posit - center) / scale_radius;
+    let r = d.magnitude();
+    let θ = if r > 0. {
+        (d.z / r).clamp(-1., 1.).acos()
+    } else {
+        0.
+    };
+    (r, θ, d.y.atan2(d.x))
+}
+
+/// An expansion of a set of bodies' field.
+pub struct ScfExpansion {
+    cfg: ScfConfig,
+    /// kpc
+    pub scale_radius: f64,
+    pub center: Vec3,
+    /// [n][harmonic index]. M☉.
+    pub coeffs: Vec<Vec<f64>>,
+    /// [n][l]
+    norm: Vec<Vec<f64>>,
+}
+
+impl ScfExpansion {
+    /// Set up the basis. The scale radius is chosen so a Hernquist model has the same half-mass
+    /// radius as the bodies: r_half = (1 + √2) a. Returns `None` if there are no bodies.
+    pub fn new(bodies: &[Body], cfg: &ScfConfig) -> Option<Self> {
+        let mass: f64 = bodies.iter().map(|b| b.mass).sum();
+        if bodies.is_empty() || mass <= 0. {
+            return None;
+        }
+
+        let center = bodies
+            .iter()
+            .fold(Vec3::new_zero(), |acc, b| acc + b.posit * b.mass)
+            / mass;
+
+        let mut by_r: Vec<(f64, f64)> = bodies
+            .iter()
+            .map(|b| ((b.posit - center).magnitude(), b.mass))
+            .collect();
+        by_r.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut enclosed = 0.;
+        let mut r_half = by_r[by_r.len() - 1].0;
+        for (r, m) in &by_r {
+            enclosed += m;
+            if enclosed >= mass / 2. {
+                r_half = *r;
+                break;
+            }
+        }
+
+        Some(Self {
+            cfg: *cfg,
+            scale_radius: (r_half / (1. + SQRT_2)).max(f64::EPSILON),
+            center,
+            coeffs: vec![vec![0.; (cfg.l_max + 1).pow(2)]; cfg.n_max + 1],
+            norm: normalization(cfg),
+        })
+    }
+
+    /// Compute the expansion coefficients from the bodies' positions. The center is updated to their
+    /// center of mass; the scale radius is kept.
+    pub fn update(&mut self, bodies: &[Body]) {
+        let mass: f64 = bodies.iter().map(|b| b.mass).sum();
+        if mass > 0. {
+            self.center = bodies
+                .iter()
+                .fold(Vec3::new_zero(), |acc, b| acc + b.posit * b.mass)
+                / mass;
+        }
+
+        let cfg = self.cfg;
+        let num_harmonics = (cfg.l_max + 1).pow(2);
+        let zero = || vec![vec![0.; num_harmonics]; cfg.n_max + 1];
+
+        let sums = bodies
+            .par_iter()
+            .map(|body| {
+                let mut result = zero();
+                let (r, θ, phi) = to_spherical(body.posit, self.center, self.scale_radius);
+                let ξ = (r - 1.) / (r + 1.);
+                let harm = harmonics(cfg.l_max, θ, phi);
+
+                for l in 0..=cfg.l_max {
+                    let l_f = l as f64;
+                    let gegen = gegenbauer(cfg.n_max, 2. * l_f + 1.5, ξ);
+                    let f = r.powi(l as i32) / (1. + r).powi(2 * l as i32 + 1);
+
+                    for n in 0..=cfg.n_max {
+                        let ψ = -f * gegen[n];
+                        for m in -(l as isize)..=l as isize {
+                            let i = harmonic_index(l, m);
+                            result[n][i] += body.mass * ψ * harm.y[i];
+                        }
+                    }
+                }
+                result
+            })
+            .reduce(zero, |mut a, b| {
+                for (row_a, row_b) in a.iter_mut().zip(b) {
+                    for (v_a, v_b) in row_a.iter_mut().zip(row_b) {
+                        *v_a += v_b;
+                    }
+                }
+                a
+            });
+
+        for n in 0..=cfg.n_max {
+            for l in 0..=cfg.l_max {
+                for m in -(l as isize)..=l as isize {
+                    let i = harmonic_index(l, m);
+                    self.coeffs[n][i] = sums[n][i] / self.norm[n][l];
+                }
+            }
+        }
+    }
+
+    /// Potential and acceleration at a point. (kpc/Myr)², and kpc/Myr².
+    pub fn potential_acc(&self, posit: Vec3) -> (f64, Vec3) {
+        let cfg = &self.cfg;
+        let (r, θ, phi) = to_spherical(posit, self.center, self.scale_radius);
+        let ξ = (r - 1.) / (r + 1.);
+        let harm = harmonics(cfg.l_max, θ, phi);
+
+        let (mut pot, mut dpot_dr, mut dpot_dθ, mut dpot_dphi_sin) = (0., 0., 0., 0.);
+
+        for l in 0..=cfg.l_max {
+            let l_f = l as f64;
+            let gegen = gegenbauer(cfg.n_max, 2. * l_f + 1.5, ξ);
+            let gegen_deriv = gegenbauer(cfg.n_max, 2. * l_f + 2.5, ξ);
+
+            for n in 0..=cfg.n_max {
+                let (ψ, dψ_dr) = radial_potential(r, l, n, &gegen, &gegen_deriv);
+
+                for m in -(l as isize)..=l as isize {
+                    let i = harmonic_index(l, m);
+                    let a = self.coeffs[n][i];
+
+                    pot += a * ψ * harm.y[i];
+                    dpot_dr += a * dψ_dr * harm.y[i];
+                    dpot_dθ += a * ψ * harm.dy_dθ[i];
+                    dpot_dphi_sin += a * ψ * harm.dy_dphi_sin[i];
+                }
+            }
+        }
+
+        let (sin_θ, cos_θ) = θ.sin_cos();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let r_hat = Vec3::new(sin_θ * cos_phi, sin_θ * sin_phi, cos_θ);
+        let θ_hat = Vec3::new(cos_θ * cos_phi, cos_θ * sin_phi, -sin_θ);
+        let phi_hat = Vec3::new(-sin_phi, cos_phi, 0.);
+
+        let r_safe = r.max(SIN_MIN);
+        let grad =
+            r_hat * dpot_dr + θ_hat * (dpot_dθ / r_safe) + phi_hat * (dpot_dphi_sin / r_safe);
+
+        let a = self.scale_radius;
+        (G / a * pot, grad * (-G / a.powi(2)))
+    }
+
+    pub fn acc(&self, posit: Vec3) -> Vec3 {
+        self.potential_acc(posit).1
+    }
+}
@@ -0,0 +1,230 @@
+//! Shapiro delay: Light crossing a mass distribution is delayed relative to a straight path in
+//! flat space, by Δt = -(2/c³) ∫ Φ dl, in the weak-field limit. We integrate the potential from
+//! probes along parallel sight lines through the galaxy, at a range of impact parameters.
+//!
+//! The potential comes from the selected force model's field, so for the shell model it's from the
+//! retarded shell field, vice the bodies' instantaneous positions. We compute each line's delay
+//! under Newtonian gravity too, with the same bodies; the difference between the two is an
+//! observable that depends on how gravity propagates, vice on the mass distribution alone.
+//!
+//! Each line is integrated over a finite path, centered on its closest approach to the galaxy's
+//! center; the delay diverges logarithmically with path length, so only differences between lines
+//! and models are meaningful.
+
+use std::{
+    fmt,
+    fmt::{Formatter, Write as _},
+    fs, io,
+    path::Path,
+};
+
+use lin_alg::{f64::Vec3, linspace};
+
+use crate::{
+    grav_shell::GravShell,
+    probe::Field,
+    properties::{plot_multi, PlotOutput},
+    units::C_LIGHT,
+    Body, Config, ForceModel,
+};
+
+const DAYS_PER_MYR: f64 = 365.25e6;
+/// Potential samples along each sight line.
+const N_PATH_PTS: usize = 96;
+
+#[derive(Clone, Debug)]
+pub struct ShapiroParams {
+    /// Unit vector along the sight lines. +Z views the disk face-on.
+    pub line_of_sight: Vec3,
+    pub num_lines: usize,
+    /// Lines are offset from the galaxy's center by up to this, either side, in the disk plane.
+    /// kpc.
+    pub b_max: f64,
+    /// Each line is integrated this far either side of its closest approach. kpc.
+    pub half_length: f64,
+}
+
+impl Default for ShapiroParams {
+    fn default() -> Self {
+        Self {
+            line_of_sight: Vec3::new(0., 0., 1.),
+            num_lines: 25,
+            b_max: 20.,
+            half_length: 100.,
+        }
+    }
+}
+
+pub struct SightLine {
+    /// Signed impact parameter, along the offset direction. kpc.
+    pub b: f64,
+    /// Shapiro delay under the selected force model. Days.
+    pub delay: f64,
+    /// Shapiro delay under Newtonian gravity, from the same bodies. Days.
+    pub delay_newton: f64,
+    /// Light travel time along the path, including the delay. Myr.
+    pub time_of_flight: f64,
+}
+
+pub struct ShapiroDelays {
+    pub galaxy: String,
+    pub force_model: ForceModel,
+    pub params: ShapiroParams,
+    pub lines: Vec<SightLine>,
+}
+
+impl ShapiroDelays {
+    /// One row per sight line.
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        let mut csv = "b_kpc,delay_days,delay_newton_days,time_of_flight_myr\n".to_owned();
+        for line in &self.lines {
+            writeln!(
+                csv,
+                "{},{},{},{}",
+                line.b, line.delay, line.delay_newton, line.time_of_flight
+            )
+            .unwrap();
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, csv)
+    }
+}
+
+impl fmt::Display for ShapiroDelays {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Shapiro delay through {}, under {}. Path length: {:.0} kpc",
+            self.galaxy,
+            self.force_model.to_str(),
+            2. * self.params.half_length,
+        )?;
+        writeln!(
+            f,
+            "  {:>8} {:>12} {:>12} {:>10}",
+            "b (kpc)", "Δt (days)", "Newton", "Diff"
+        )?;
+
+        for line in &self.lines {
+            writeln!(
+                f,
+                "  {:>8.2} {:>12.2} {:>12.2} {:>10.3}",
+                line.b,
+                line.delay,
+                line.delay_newton,
+                line.delay - line.delay_newton
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Δt = -(2/c³) ∫ Φ dl, from potentials sampled evenly along a path of length `path_len`.
+/// Trapezoidal. Myr, with the potential in kpc²/Myr².
+fn delay(potentials: &[f64], path_len: f64) -> f64 {
+    if potentials.len() < 2 {
+        return 0.;
+    }
+    let dl = path_len / (potentials.len() - 1) as f64;
+    let integral: f64 = potentials.windows(2).map(|p| (p[0] + p[1]) / 2. * dl).sum();
+
+    -2. * integral / C_LIGHT.powi(3)
+}
+
+/// Compute the delay along each sight line, under the selected force model, and Newtonian
+/// gravity.
+pub fn shapiro_delays(
+    bodies: &[Body],
+    shells: &[GravShell],
+    config: &Config,
+    force_model: ForceModel,
+    params: &ShapiroParams,
+    galaxy: &str,
+) -> ShapiroDelays {
+    let los = params.line_of_sight.to_normalized();
+
+    // Offset lines along the disk plane, perpendicular to the line of sight.
+    let cross = los.cross(Vec3::new(0., 0., 1.));
+    let e_b = if cross.magnitude() < 1e-6 {
+        Vec3::new(1., 0., 0.)
+    } else {
+        cross.to_normalized()
+    };
+
+    let impact_params = linspace(-params.b_max, params.b_max, params.num_lines.max(2));
+    let path = linspace(-params.half_length, params.half_length, N_PATH_PTS);
+
+    let posits: Vec<Vec3> = impact_params
+        .iter()
+        .flat_map(|b| path.iter().map(move |l| e_b * *b + los * *l))
+        .collect();
+
+    let potentials = Field::new(bodies, shells, config, force_model).potential_many(&posits);
+    let potentials_newton = if force_model == ForceModel::Newton {
+        potentials.clone()
+    } else {
+        Field::new(bodies, &[], config, ForceModel::Newton).potential_many(&posits)
+    };
+
+    let path_len = 2. * params.half_length;
+    let lines = impact_params
+        .iter()
+        .zip(
+            potentials
+                .chunks(N_PATH_PTS)
+                .zip(potentials_newton.chunks(N_PATH_PTS)),
+        )
+        .map(|(b, (φ, φ_newton))| {
+            let dt = delay(φ, path_len);
+            SightLine {
+                b: *b,
+                delay: dt * DAYS_PER_MYR,
+                delay_newton: delay(φ_newton, path_len) * DAYS_PER_MYR,
+                time_of_flight: path_len / C_LIGHT + dt,
+            }
+        })
+        .collect();
+
+    ShapiroDelays {
+        galaxy: galaxy.to_owned(),
+        force_model,
+        params: params.clone(),
+        lines,
+    }
+}
+
+/// Plot the delay against impact parameter, under the force model and Newton, and their
+/// difference.
+pub fn plot_delays(out: &PlotOutput, delays: &ShapiroDelays, desc: &str) {
+    let model: Vec<(f64, f64)> = delays.lines.iter().map(|l| (l.b, l.delay)).collect();
+    let newton: Vec<(f64, f64)> = delays.lines.iter().map(|l| (l.b, l.delay_newton)).collect();
+    let diff: Vec<(f64, f64)> = delays
+        .lines
+        .iter()
+        .map(|l| (l.b, l.delay - l.delay_newton))
+        .collect();
+
+    let model_name = delays.force_model.to_str();
+    plot_multi(
+        out,
+        &[(model_name.as_str(), model.as_slice()), ("Newton", &newton)],
+        "b (kpc)",
+        "Δt (days)",
+        &format!("Shapiro delay through {desc}"),
+        &format!("shapiro_delay_{desc}"),
+    );
+
+    if delays.force_model != ForceModel::Newton {
+        plot_multi(
+            out,
+            &[(&format!("{model_name} - Newton"), diff.as_slice())],
+            "b (kpc)",
+            "Δt (days)",
+            &format!("Shapiro delay difference from Newton, {desc}"),
+            &format!("shapiro_delay_diff_{desc}"),
+        );
+    }
+}
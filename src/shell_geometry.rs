@@ -0,0 +1,85 @@
+//! Shell geometry: Spheres, or expanding rings in the disk plane. For thin disks, spherical shells
+//! spend most of their amplitude out of the plane; rings keep it near the plane, with an adjustable
+//! vertical thickness. Ring flux spreads over 2πrh instead of 4πr², so it falls off as 1/r at
+//! distances well beyond the thickness.
+
+use bincode::{Decode, Encode};
+use lin_alg::f64::Vec3;
+use rand::Rng;
+
+use crate::{
+    build,
+    properties::{plot_multi, rotation_curve},
+    units::C,
+    ForceModel, State,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum ShellGeometry {
+    #[default]
+    Sphere,
+    /// An expanding torus in the XY plane. `thickness` is the vertical gaussian width. (kpc)
+    Ring { thickness: f64 },
+}
+
+impl ShellGeometry {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Sphere => "Sphere",
+            Self::Ring { .. } => "Ring",
+        }
+        .to_owned()
+    }
+
+    /// Distance from a point to the shell's surface (the radial gaussian's `x`), and the vertical
+    /// amplitude factor.
+    pub fn surface_dist(&self, posit: Vec3, center: Vec3, radius: f64) -> (f64, f64) {
+        let diff = posit - center;
+
+        match self {
+            Self::Sphere => ((diff.magnitude() - radius).abs(), 1.),
+            Self::Ring { thickness } => {
+                let r_cyl = (diff.x.powi(2) + diff.y.powi(2)).sqrt();
+                let vert = (-diff.z.powi(2) / (2. * thickness.powi(2))).exp();
+                ((r_cyl - radius).abs(), vert)
+            }
+        }
+    }
+
+    /// Flux concentration relative to a sphere, at a given distance from the source. A sphere's
+    /// flux spreads over 4πr²; a ring's over 2πrh. Once the ring is thicker than its radius, it's
+    /// effectively a sphere.
+    pub fn flux_factor(&self, dist: f64) -> f64 {
+        match self {
+            Self::Sphere => 1.,
+            Self::Ring { thickness } => (2. * dist / thickness).max(1.),
+        }
+    }
+}
+
+/// Build with the causal shell model using spherical shells, then rings, and plot the final rotation
+/// curves together. Both runs use the same seed.
+pub fn compare_geometries(state: &mut State, thickness: f64) {
+    let cfg_prev = state.config.clone();
+    state.config.seed = Some(cfg_prev.seed.unwrap_or_else(|| rand::rng().random()));
+
+    let mut curves = Vec::new();
+    for geometry in [ShellGeometry::Sphere, ShellGeometry::Ring { thickness }] {
+        println!("\nBuilding with {} shells...", geometry.to_str());
+        state.config.shell_geometry = geometry;
+        build(state, ForceModel::GaussShells);
+
+        curves.push(rotation_curve(&state.bodies, Vec3::new_zero(), C));
+    }
+
+    state.config = cfg_prev;
+
+    plot_multi(
+        &state.config.plot_output,
+        &[("Sphere", &curves[0]), ("Ring", &curves[1])],
+        "r (kpc)",
+        "v (km/s)",
+        "Rotation curve by shell geometry",
+        "shell_geometry_rot_curve",
+    );
+}
@@ -0,0 +1,163 @@
+//! Prototype of pairwise shell-shell interactions, behind the `shell_interaction` feature. From the
+//! `GravShell` notes: Do aberration-cancelling terms develop as shells propagate, through interaction
+//! with other shells, vice being set at creation from the source body alone?
+//!
+//! Interaction laws implement `ShellInteraction`; each step, every shell is modified by every
+//! overlapping shell from a different source. This is O(n²) in shell count, so keep runs small.
+
+use lin_alg::f64::Vec3;
+use rayon::prelude::*;
+
+use crate::{grav_shell::GravShell, units::C};
+
+/// Change to a shell, per unit time, from interacting with another.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShellDelta {
+    /// Rate of change of the natural log of the shell's amplitude scale.
+    pub ln_amp: f64,
+    /// Rate of change of the shell's center.
+    pub center: Vec3,
+}
+
+impl ShellDelta {
+    fn add(self, other: Self) -> Self {
+        Self {
+            ln_amp: self.ln_amp + other.ln_amp,
+            center: self.center + other.center,
+        }
+    }
+}
+
+pub trait ShellInteraction {
+    fn name(&self) -> String;
+
+    /// The effect of `other` on `shell`. `overlap` is in [0, 1]: 1 where their surfaces intersect,
+    /// falling off as a gaussian of the distance between surfaces.
+    fn interact(&self, shell: &GravShell, other: &GravShell, overlap: f64) -> ShellDelta;
+}
+
+/// Scales amplitude where shells overlap. Positive rates amplify; negative rates damp.
+pub struct OverlapAmplitude {
+    pub rate: f64,
+}
+
+impl ShellInteraction for OverlapAmplitude {
+    fn name(&self) -> String {
+        "Overlap amplitude".to_owned()
+    }
+
+    fn interact(&self, _shell: &GravShell, _other: &GravShell, overlap: f64) -> ShellDelta {
+        ShellDelta {
+            ln_amp: self.rate * overlap,
+            center: Vec3::new_zero(),
+        }
+    }
+}
+
+/// Where shells overlap, drift the shell's center along its source's velocity at emission, towards
+/// where the source would be now if it kept moving. With rate 1 and a single fully-overlapping shell,
+/// this tracks the extrapolated position; i.e. the interaction cancels aberration.
+pub struct CenterDrift {
+    pub rate: f64,
+}
+
+impl ShellInteraction for CenterDrift {
+    fn name(&self) -> String {
+        "Center drift".to_owned()
+    }
+
+    fn interact(&self, shell: &GravShell, _other: &GravShell, overlap: f64) -> ShellDelta {
+        ShellDelta {
+            ln_amp: 0.,
+            center: shell.body_vel * self.rate * overlap,
+        }
+    }
+}
+
+/// Selects the interaction law applied during builds. `Custom` allows experimenting with laws
+/// defined elsewhere, without modifying the build loop.
+#[derive(Default)]
+pub enum InteractionLaw {
+    #[default]
+    Off,
+    OverlapAmplitude(OverlapAmplitude),
+    CenterDrift(CenterDrift),
+    Custom(Box<dyn ShellInteraction + Send + Sync>),
+}
+
+impl InteractionLaw {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Off => "Off".to_owned(),
+            Self::OverlapAmplitude(l) => l.name(),
+            Self::CenterDrift(l) => l.name(),
+            Self::Custom(l) => l.name(),
+        }
+    }
+
+    pub fn as_law(&self) -> Option<&(dyn ShellInteraction + Sync)> {
+        match self {
+            Self::Off => None,
+            Self::OverlapAmplitude(l) => Some(l),
+            Self::CenterDrift(l) => Some(l),
+            Self::Custom(l) => Some(l.as_ref()),
+        }
+    }
+
+    pub fn rate_mut(&mut self) -> Option<&mut f64> {
+        match self {
+            Self::OverlapAmplitude(l) => Some(&mut l.rate),
+            Self::CenterDrift(l) => Some(&mut l.rate),
+            _ => None,
+        }
+    }
+}
+
+/// Overlap of two shells' surfaces, in [0, 1], using the shell gaussian width.
+fn overlap(a: &GravShell, b: &GravShell, gauss_c: f64) -> f64 {
+    let d = (a.center - b.center).magnitude();
+
+    // Distance between the surfaces; 0 if they intersect.
+    let gap = if d > a.radius + b.radius {
+        d - (a.radius + b.radius)
+    } else if d < (a.radius - b.radius).abs() {
+        (a.radius - b.radius).abs() - d
+    } else {
+        0.
+    };
+
+    (-gap.powi(2) / (2. * gauss_c.powi(2))).exp()
+}
+
+/// Apply interactions between all pairs of shells from different sources, for one timestep.
+pub fn apply(shells: &mut [GravShell], law: &(dyn ShellInteraction + Sync), gauss_c: f64, dt: f64) {
+    // Beyond this many widths apart, we treat shells as not overlapping.
+    const MAX_GAP_WIDTHS: f64 = 5.;
+    let min_overlap = (-MAX_GAP_WIDTHS.powi(2) / 2.).exp();
+
+    let deltas: Vec<ShellDelta> = shells
+        .par_iter()
+        .map(|shell| {
+            shells
+                .iter()
+                .filter(|other| other.source_id != shell.source_id)
+                .filter_map(|other| {
+                    let ov = overlap(shell, other, gauss_c);
+                    (ov > min_overlap).then(|| law.interact(shell, other, ov))
+                })
+                .fold(ShellDelta::default(), ShellDelta::add)
+        })
+        .collect();
+
+    for (shell, delta) in shells.iter_mut().zip(deltas) {
+        shell.amp_scale *= (delta.ln_amp * dt).exp();
+
+        // Limit drift to the shell's own expansion speed.
+        let mut drift = delta.center * dt;
+        let drift_max = C * dt;
+        if drift.magnitude() > drift_max {
+            drift = drift.to_normalized() * drift_max;
+        }
+        shell.center += drift;
+    }
+}
@@ -0,0 +1,434 @@
+//! Snapshot streaming: An indexed on-disk format where snapshots are decoded individually, so playback
+//! of runs larger than RAM only needs the snapshots near the current position. `SnapshotCache` keeps
+//! an LRU set of decoded snapshots, and prefetches those around the playback position on a
+//! background thread, so scrubbing stays smooth.
+//!
+//! `SnapshotSink` takes snapshots off the simulation thread during builds: The build sends copies of
+//! the raw body data, and a background thread converts them, and optionally writes them to a stream.
+//!
+//! Layout: magic, format version (u32 LE), index offset (u64 LE), run metadata, snapshots, then the
+//! index: a list of each snapshot's offset. Encoded with bincode. The metadata includes the body
+//! masses, and the config and labels of the run, so it can be replayed and analyzed later. Streams
+//! of other format versions, including those from before we stored one, are rejected: bincode
+//! doesn't describe its layout, so they can't be decoded reliably.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io,
+    io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc,
+    },
+    thread,
+    thread::JoinHandle,
+};
+
+use barnes_hut::Cube;
+use bincode::{config, Decode, Encode};
+use lin_alg::f64::Vec3;
+
+use crate::{
+    diagnostics::Diagnostics,
+    grav_shell::GravShell,
+    overlay::RunLabels,
+    playback::{GravShellSnapshot, SnapShot},
+    Config,
+};
+
+const MAGIC: &[u8; 4] = b"CGsv";
+/// Bump when the encoding of `SnapShot` or `RunMeta` changes.
+const FORMAT_VERSION: u32 = 1;
+/// Streams from before we stored a format version, with and without run metadata.
+const MAGICS_UNVERSIONED: [&[u8; 4]; 2] = [b"CGsm", b"CGss"];
+/// Where the index offset is stored, after the magic and format version.
+const INDEX_OFFSET_POS: u64 = MAGIC.len() as u64 + 4;
+const HEADER_LEN: u64 = INDEX_OFFSET_POS + 8;
+
+/// Decoded snapshots kept in memory.
+pub const CACHE_CAPACITY: usize = 64;
+/// Snapshots on each side of the playback position to prefetch.
+pub const PREFETCH_RADIUS: usize = 8;
+/// Raw snapshots waiting for conversion. If the build gets this far ahead of conversion and
+/// writing, it waits, so raw snapshots don't accumulate in memory.
+const SINK_CAPACITY: usize = 4;
+
+fn to_io_err(e: impl ToString) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+fn encode_to<T: Encode>(writer: &mut impl Write, val: &T) -> io::Result<u64> {
+    let encoded = bincode::encode_to_vec(val, config::standard()).map_err(to_io_err)?;
+    writer.write_all(&encoded)?;
+    Ok(encoded.len() as u64)
+}
+
+fn decode_from<T: Decode<()>>(file: &mut File, start: u64, end: u64) -> io::Result<T> {
+    let mut buf = vec![0; (end - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut buf)?;
+
+    bincode::decode_from_slice(&buf, config::standard())
+        .map(|(v, _len)| v)
+        .map_err(to_io_err)
+}
+
+/// If a file starts with a stream header, of any version.
+pub fn is_stream(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut header = [0; MAGIC.len()];
+
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header == MAGIC || MAGICS_UNVERSIONED.contains(&&header)),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stored with the snapshots. Body masses are here, vice per-snapshot, since they're invariant.
+#[derive(Clone, Default, Encode, Decode)]
+pub struct RunMeta {
+    pub body_masses: Vec<f32>,
+    pub config: Option<Config>,
+    pub labels: Option<RunLabels>,
+}
+
+/// Writes snapshots to a stream file one at a time, so they don't all need to be in memory.
+pub struct SnapshotWriter {
+    file: BufWriter<File>,
+    /// Snapshot start positions, plus the current end.
+    offsets: Vec<u64>,
+}
+
+impl SnapshotWriter {
+    pub fn create(path: &Path, meta: &RunMeta) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        file.write_all(&0_u64.to_le_bytes())?; // Index offset; set in `finish`.
+
+        let len = encode_to(&mut file, meta)?;
+
+        Ok(Self {
+            file,
+            offsets: vec![HEADER_LEN + len],
+        })
+    }
+
+    pub fn push(&mut self, snapshot: &SnapShot) -> io::Result<()> {
+        let len = encode_to(&mut self.file, snapshot)?;
+        let end = self.offsets[self.offsets.len() - 1] + len;
+        self.offsets.push(end);
+        Ok(())
+    }
+
+    /// Write the index. The file isn't readable until this is called.
+    pub fn finish(mut self) -> io::Result<()> {
+        let index_offset = self.offsets[self.offsets.len() - 1];
+        encode_to(&mut self.file, &self.offsets)?;
+
+        let mut file = self.file.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(INDEX_OFFSET_POS))?;
+        file.write_all(&index_offset.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Write all snapshots to a stream file.
+pub fn write_stream(path: &Path, meta: &RunMeta, snapshots: &[SnapShot]) -> io::Result<()> {
+    let mut writer = SnapshotWriter::create(path, meta)?;
+    for snap in snapshots {
+        writer.push(snap)?;
+    }
+    writer.finish()
+}
+
+/// Random access to snapshots in a stream file.
+pub struct SnapshotReader {
+    file: File,
+    /// Start positions of each snapshot, plus the end of the last.
+    offsets: Vec<u64>,
+    pub meta: RunMeta,
+}
+
+impl SnapshotReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+
+        let mut header = [0; HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+        match &header[..MAGIC.len()] {
+            h if h == MAGIC => (),
+            h if MAGICS_UNVERSIONED.iter().any(|m| h == *m) => {
+                return Err(to_io_err(
+                    "This stream is from an older version, whose snapshot format is no longer \
+                     supported. Rebuild the run to replay it.",
+                ))
+            }
+            _ => return Err(to_io_err("Not a snapshot stream")),
+        }
+
+        let version = u32::from_le_bytes(
+            header[MAGIC.len()..INDEX_OFFSET_POS as usize]
+                .try_into()
+                .unwrap(),
+        );
+        if version != FORMAT_VERSION {
+            return Err(to_io_err(format!(
+                "Snapshot format version {version} is not supported; this version reads \
+                 {FORMAT_VERSION}."
+            )));
+        }
+
+        let index_offset =
+            u64::from_le_bytes(header[INDEX_OFFSET_POS as usize..].try_into().unwrap());
+        if index_offset == 0 {
+            return Err(to_io_err("Snapshot stream was not finished"));
+        }
+
+        let file_len = file.metadata()?.len();
+        let offsets: Vec<u64> = decode_from(&mut file, index_offset, file_len)?;
+        if offsets.len() < 2 {
+            return Err(to_io_err("No snapshots in file"));
+        }
+
+        let meta = decode_from(&mut file, HEADER_LEN, offsets[0])?;
+
+        Ok(Self {
+            file,
+            offsets,
+            meta,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn read(&mut self, i: usize) -> io::Result<SnapShot> {
+        if i >= self.len() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "Snapshot index out of range",
+            ));
+        }
+        decode_from(&mut self.file, self.offsets[i], self.offsets[i + 1])
+    }
+}
+
+/// An LRU cache of decoded snapshots from a stream, with background prefetching.
+pub struct SnapshotCache {
+    reader: SnapshotReader,
+    entries: HashMap<usize, Arc<SnapShot>>,
+    /// Least recently used first.
+    lru: VecDeque<usize>,
+    /// Requested from the prefetch thread, but not yet received.
+    pending: HashSet<usize>,
+    prefetch_tx: Sender<usize>,
+    prefetched_rx: Receiver<(usize, SnapShot)>,
+    capacity: usize,
+}
+
+impl SnapshotCache {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let reader = SnapshotReader::open(path)?;
+        // The prefetch thread uses its own handle, so it doesn't contend with synchronous reads.
+        let mut reader_prefetch = SnapshotReader::open(path)?;
+
+        let (prefetch_tx, prefetch_rx) = mpsc::channel::<usize>();
+        let (prefetched_tx, prefetched_rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            // Ends when the cache, and its sender, are dropped.
+            for i in prefetch_rx {
+                match reader_prefetch.read(i) {
+                    Ok(snap) => {
+                        if prefetched_tx.send((i, snap)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => eprintln!("Error prefetching snapshot {i}: {e}"),
+                }
+            }
+        });
+
+        Ok(Self {
+            reader,
+            entries: HashMap::new(),
+            lru: VecDeque::new(),
+            pending: HashSet::new(),
+            prefetch_tx,
+            prefetched_rx,
+            capacity: CACHE_CAPACITY,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.reader.len()
+    }
+
+    pub fn body_masses(&self) -> &[f32] {
+        &self.reader.meta.body_masses
+    }
+
+    pub fn meta(&self) -> &RunMeta {
+        &self.reader.meta
+    }
+
+    fn touch(&mut self, i: usize) {
+        if let Some(pos) = self.lru.iter().position(|v| *v == i) {
+            self.lru.remove(pos);
+        }
+        self.lru.push_back(i);
+    }
+
+    fn insert(&mut self, i: usize, snap: Arc<SnapShot>) {
+        self.entries.insert(i, snap);
+        self.touch(i);
+
+        while self.lru.len() > self.capacity {
+            if let Some(evicted) = self.lru.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    /// Move finished prefetches into the cache.
+    fn receive_prefetched(&mut self) {
+        while let Ok((i, snap)) = self.prefetched_rx.try_recv() {
+            self.pending.remove(&i);
+            if !self.entries.contains_key(&i) {
+                self.insert(i, Arc::new(snap));
+            }
+        }
+    }
+
+    /// Get a snapshot, reading it synchronously if it's not cached, and prefetch its neighbors.
+    pub fn get(&mut self, i: usize) -> io::Result<Arc<SnapShot>> {
+        self.receive_prefetched();
+
+        let result = match self.entries.get(&i) {
+            Some(snap) => {
+                let snap = snap.clone();
+                self.touch(i);
+                snap
+            }
+            None => {
+                let snap = Arc::new(self.reader.read(i)?);
+                self.insert(i, snap.clone());
+                snap
+            }
+        };
+
+        // Nearest first, so they arrive in the order scrubbing is likely to need them.
+        let radius = PREFETCH_RADIUS.min(self.capacity / 2);
+        for dist in 1..=radius {
+            for j in [i.checked_sub(dist), Some(i + dist)].into_iter().flatten() {
+                if j < self.len() && !self.entries.contains_key(&j) && self.pending.insert(j) {
+                    let _ = self.prefetch_tx.send(j);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Body data copied from the simulation, for conversion to a snapshot off the simulation thread.
+pub struct RawSnapshot {
+    pub time: f64,
+    pub dt: f64,
+    pub body_posits: Vec<Vec3>,
+    pub body_accs: Vec<Vec3>,
+    pub body_vels: Vec<Vec3>,
+    pub shells: Vec<GravShell>,
+    pub tree_cubes: Vec<Cube>,
+    /// As in `SnapShot`.
+    pub body_ids: Vec<u32>,
+    pub body_masses: Vec<f32>,
+    pub subset: bool,
+    pub diagnostics: Option<Diagnostics>,
+}
+
+impl RawSnapshot {
+    pub fn convert(self) -> SnapShot {
+        SnapShot {
+            time: self.time as f32,
+            body_posits: self.body_posits.into_iter().map(|p| p.into()).collect(),
+            body_accs: self.body_accs.into_iter().map(|a| a.into()).collect(),
+            body_vels: self.body_vels.into_iter().map(|v| v.into()).collect(),
+            shells: self.shells.iter().map(GravShellSnapshot::new).collect(),
+            dt: self.dt as f32,
+            tree_cubes: self.tree_cubes,
+            body_ids: self.body_ids,
+            body_masses: self.body_masses,
+            subset: self.subset,
+            diagnostics: self.diagnostics,
+        }
+    }
+}
+
+/// Converts, and optionally writes, snapshots on a background thread during a build.
+pub struct SnapshotSink {
+    tx: SyncSender<RawSnapshot>,
+    handle: JoinHandle<io::Result<Vec<SnapShot>>>,
+}
+
+impl SnapshotSink {
+    /// `initial` snapshots are passed through first. If `stream` is set, snapshots are written to
+    /// that path with the given metadata, and not kept in memory. Otherwise, if `live` is set, they
+    /// are sent there as they're converted, e.g. for display during a background build, vice
+    /// collected.
+    pub fn spawn(
+        initial: Vec<SnapShot>,
+        stream: Option<(&Path, &RunMeta)>,
+        live: Option<Sender<SnapShot>>,
+    ) -> io::Result<Self> {
+        let mut writer = match stream {
+            Some((path, meta)) => Some(SnapshotWriter::create(path, meta)?),
+            None => None,
+        };
+
+        let (tx, rx) = mpsc::sync_channel::<RawSnapshot>(SINK_CAPACITY);
+
+        let handle = thread::spawn(move || {
+            let mut snapshots = Vec::new();
+
+            let converted = rx.into_iter().map(RawSnapshot::convert);
+            for snap in initial.into_iter().chain(converted) {
+                match (&mut writer, &live) {
+                    (Some(w), _) => w.push(&snap)?,
+                    // If the receiver is gone, the build was abandoned.
+                    (None, Some(tx)) => {
+                        let _ = tx.send(snap);
+                    }
+                    (None, None) => snapshots.push(snap),
+                }
+            }
+
+            if let Some(w) = writer {
+                w.finish()?;
+            }
+            Ok(snapshots)
+        });
+
+        Ok(Self { tx, handle })
+    }
+
+    pub fn send(&self, snapshot: RawSnapshot) {
+        // If the thread has exited, it was due to a write error, which `finish` reports.
+        let _ = self.tx.send(snapshot);
+    }
+
+    /// Wait for pending snapshots to be processed. Returns the snapshots, if not streaming, or
+    /// sending them live.
+    pub fn finish(self) -> io::Result<Vec<SnapShot>> {
+        drop(self.tx);
+
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::new(ErrorKind::Other, "Snapshot thread panicked")))
+    }
+}
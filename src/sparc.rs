@@ -0,0 +1,252 @@
+//! Parsing of [SPARC](http://astroweb.cwru.edu/SPARC/) files. Surface photometry files populate
+//! galaxy luminosity profiles. Two formats are supported:
+//!
+//! - `.sfb`: Total surface brightness. Columns: radius (arcsec), μ (mag arcsec⁻²), kill flag, error.
+//!   Rows with a nonzero kill flag are excluded, as in SPARC's own fits.
+//! - `.dens`: Bulge-disk decomposition. Columns: radius (kpc), Σ_disk, Σ_bulge (L☉ pc⁻²), at 3.6μm.
+//!
+//! Profiles are stored as surface brightness in mag arcsec⁻², as with the hand-entered NGC 1560 data.
+//! Files in `SPARC_DIR` named after the galaxy, e.g. `NGC3198.sfb`, are loaded automatically.
+//!
+//! Rotation curve files, e.g. `NGC3198_rotmod.dat`, describe a whole galaxy. Each one in `SPARC_DIR`
+//! is added to the galaxy catalog. Columns: radius (kpc), V_obs, its error, and the gas, disk, and
+//! bulge contributions (km/s), then Σ_disk and Σ_bulge (L☉ pc⁻²), at 3.6μm. The distance is in the
+//! header.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    body_creation::{mass_density_from_lum, GalaxyDescrip},
+    units::ARCSEC_CONV_FACTOR,
+    util::scale_x_axis,
+};
+
+pub const SPARC_DIR: &str = "data/sparc";
+
+const ROTMOD_SUFFIX: &str = "_rotmod.dat";
+
+/// Absolute magnitude of the sun at 3.6μm, the SPARC photometry band.
+const M_SUN_3_6: f64 = 3.24;
+/// Converts L☉ pc⁻² to mag arcsec⁻²: μ = M☉ + 21.572 - 2.5 log₁₀(Σ).
+const SB_CONV: f64 = 21.572;
+
+/// Surface brightness profiles. X: arcsec. Y: μ (mag arcsec⁻²).
+#[derive(Clone, Debug, Default)]
+pub struct Photometry {
+    pub disk: Vec<(f64, f64)>,
+    pub bulge: Vec<(f64, f64)>,
+}
+
+impl Photometry {
+    /// Set the galaxy's luminosity profiles, converting radii to kpc using its distance.
+    pub fn apply(&self, descrip: &mut GalaxyDescrip) {
+        let α_conv_factor = ARCSEC_CONV_FACTOR * descrip.dist_from_earth;
+
+        descrip.luminosity_disk = scale_x_axis(&self.disk, α_conv_factor);
+        descrip.luminosity_bulge = scale_x_axis(&self.bulge, α_conv_factor);
+    }
+
+    /// Disk mass density derived from the luminosity profile. See `mass_density_from_lum`.
+    pub fn mass_density_disk(&self, descrip: &GalaxyDescrip) -> Vec<(f64, f64)> {
+        let α_conv_factor = ARCSEC_CONV_FACTOR * descrip.dist_from_earth;
+        let lum = scale_x_axis(&self.disk, α_conv_factor);
+
+        mass_density_from_lum(&lum, descrip.mass_disk, &self.disk)
+    }
+}
+
+/// Parse numeric columns from a row; `None` for headers, comments, and blank lines.
+fn parse_row(line: &str) -> Option<Vec<f64>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    line.split_whitespace()
+        .map(|c| c.parse::<f64>().ok())
+        .collect()
+}
+
+/// L☉ pc⁻² to mag arcsec⁻², at 3.6μm.
+fn sb_to_mag(sb: f64) -> f64 {
+    M_SUN_3_6 + SB_CONV - 2.5 * sb.log10()
+}
+
+/// Parse a `.sfb` file. SPARC doesn't decompose these, so the whole profile is treated as the disk.
+pub fn parse_sfb(text: &str) -> io::Result<Photometry> {
+    let mut disk = Vec::new();
+
+    for row in text.lines().filter_map(parse_row) {
+        if row.len() < 2 {
+            continue;
+        }
+        let killed = row.get(2).map(|k| *k != 0.).unwrap_or(false);
+        if !killed {
+            disk.push((row[0], row[1]));
+        }
+    }
+
+    if disk.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No surface brightness rows found",
+        ));
+    }
+
+    Ok(Photometry {
+        disk,
+        bulge: Vec::new(),
+    })
+}
+
+/// Parse a `.dens` file. Radii are in kpc, so we need the distance (kpc) to convert them to arcsec.
+/// Zero-brightness points, e.g. the bulge beyond its extent, are omitted.
+pub fn parse_dens(text: &str, dist_from_earth: f64) -> io::Result<Photometry> {
+    if dist_from_earth <= 0. {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "A distance is required to convert radii to arcsec",
+        ));
+    }
+    let arcsec_per_kpc = 1. / (ARCSEC_CONV_FACTOR * dist_from_earth);
+
+    let mut result = Photometry::default();
+
+    for row in text.lines().filter_map(parse_row) {
+        if row.len() < 3 {
+            continue;
+        }
+        let r = row[0] * arcsec_per_kpc;
+
+        if row[1] > 0. {
+            result.disk.push((r, sb_to_mag(row[1])));
+        }
+        if row[2] > 0. {
+            result.bulge.push((r, sb_to_mag(row[2])));
+        }
+    }
+
+    if result.disk.is_empty() && result.bulge.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No surface density rows found",
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Load a `.sfb` or `.dens` file, based on its extension.
+pub fn load_photometry(path: &Path, dist_from_earth: f64) -> io::Result<Photometry> {
+    let text = fs::read_to_string(path)?;
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("sfb") => parse_sfb(&text),
+        Some("dens") => parse_dens(&text, dist_from_earth),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Expected a .sfb or .dens file",
+        )),
+    }
+}
+
+/// Find photometry for a galaxy in `SPARC_DIR`. Prefers the bulge-disk decomposition.
+pub fn find_photometry(sparc_name: &str) -> Option<PathBuf> {
+    ["dens", "sfb"]
+        .iter()
+        .map(|ext| PathBuf::from(SPARC_DIR).join(format!("{sparc_name}.{ext}")))
+        .find(|p| p.exists())
+}
+
+/// A SPARC rotation curve (Rotmod) file's data, in its units. Columns are at the radii in `r`.
+#[derive(Clone, Debug, Default)]
+pub struct RotMod {
+    /// Mpc
+    pub dist: f64,
+    /// kpc
+    pub r: Vec<f64>,
+    /// km/s
+    pub v_obs: Vec<f64>,
+    /// km/s
+    pub v_err: Vec<f64>,
+    /// km/s
+    pub v_gas: Vec<f64>,
+    /// km/s, for a mass-to-light ratio of 1.
+    pub v_disk: Vec<f64>,
+    /// km/s, for a mass-to-light ratio of 1.
+    pub v_bulge: Vec<f64>,
+    /// L☉ pc⁻²
+    pub sb_disk: Vec<f64>,
+    /// L☉ pc⁻²
+    pub sb_bulge: Vec<f64>,
+}
+
+/// Parse a Rotmod `.dat` file.
+pub fn parse_rotmod(text: &str) -> io::Result<RotMod> {
+    let mut result = RotMod::default();
+
+    // E.g. "# Distance = 13.80 Mpc"
+    result.dist = text
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix('#'))
+        .filter_map(|l| l.trim().strip_prefix("Distance"))
+        .find_map(|l| {
+            l.trim()
+                .strip_prefix('=')?
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()
+        })
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No distance in the header"))?;
+
+    for row in text.lines().filter_map(parse_row) {
+        if row.len() < 8 {
+            continue;
+        }
+        result.r.push(row[0]);
+        result.v_obs.push(row[1]);
+        result.v_err.push(row[2]);
+        result.v_gas.push(row[3]);
+        result.v_disk.push(row[4]);
+        result.v_bulge.push(row[5]);
+        result.sb_disk.push(row[6]);
+        result.sb_bulge.push(row[7]);
+    }
+
+    if result.r.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No rotation curve rows found",
+        ));
+    }
+
+    Ok(result)
+}
+
+pub fn load_rotmod(path: &Path) -> io::Result<RotMod> {
+    parse_rotmod(&fs::read_to_string(path)?)
+}
+
+/// Find Rotmod files in `dir`. Returns (SPARC name, path) pairs, sorted by name. E.g.
+/// `NGC3198_rotmod.dat` is named `NGC3198`. A missing directory has none.
+pub fn find_rotmods(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut result: Vec<(String, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.strip_suffix(ROTMOD_SUFFIX)?;
+            Some((name.to_owned(), path.clone()))
+        })
+        .collect();
+
+    result.sort_by(|a, b| a.0.cmp(&b.0));
+    result
+}
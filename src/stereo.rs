@@ -0,0 +1,84 @@
+//! Stereoscopic viewing, for judging depth, e.g. of spherical halos and warped disks. The engine
+//! renders from a single camera, so we duplicate the scene instead: Each eye's copy is the scene
+//! rotated about the camera's up axis, through the focal point. This is equivalent to a pair of
+//! toed-in cameras. Side-by-side places the copies in the left and right halves of the view, for
+//! parallel viewing, or VR viewers. Anaglyph overlays them in red and cyan, for glasses.
+
+use graphics::{Camera, Entity, FWD_VEC, RIGHT_VEC};
+use lin_alg::f32::{Quaternion, Vec3};
+
+use crate::render::CAM_DIST_DEFAULT;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum StereoMode {
+    #[default]
+    Off,
+    SideBySide,
+    Anaglyph,
+}
+
+impl StereoMode {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Off => "Off",
+            Self::SideBySide => "Side by side",
+            Self::Anaglyph => "Anaglyph",
+        }
+        .to_owned()
+    }
+}
+
+/// Replace the entities with a copy for each eye. `eye_angle` is the angle between the eyes, as
+/// seen from the focal point. Degrees. The focal point is the origin's depth from the camera.
+pub fn apply_stereo(entities: &mut Vec<Entity>, camera: &Camera, mode: StereoMode, eye_angle: f32) {
+    if mode == StereoMode::Off {
+        return;
+    }
+
+    let fwd = camera.orientation.rotate_vec(FWD_VEC);
+    let right = camera.orientation.rotate_vec(RIGHT_VEC);
+
+    let mut depth = (-camera.position).dot(fwd);
+    if depth <= camera.near {
+        depth = CAM_DIST_DEFAULT;
+    }
+    let pivot = camera.position + fwd * depth;
+
+    // Side by side, each copy is centered in its half of the view.
+    let half_width = depth * (camera.fov_y / 2.).tan() * camera.aspect.max(0.1);
+    let shift = match mode {
+        StereoMode::SideBySide => half_width / 2.,
+        _ => 0.,
+    };
+
+    let half_angle = eye_angle.to_radians() / 2.;
+
+    // (Side, anaglyph color filter) for each eye.
+    let eyes = [
+        (-1., [1., 0., 0.]), // Left: red.
+        (1., [0., 1., 1.]),  // Right: cyan.
+    ];
+
+    let mut result = Vec::with_capacity(entities.len() * 2);
+    for (side, filter) in eyes {
+        let eye_dir = (-fwd * half_angle.cos() + right * side * half_angle.sin()).to_normalized();
+        // Rotates the scene so this eye's view is the camera's.
+        let rotation = Quaternion::from_unit_vecs(eye_dir, -fwd);
+        let offset: Vec3 = right * side * shift;
+
+        for entity in entities.iter() {
+            let mut e = entity.clone();
+            e.position = pivot + rotation.rotate_vec(entity.position - pivot) + offset;
+            e.orientation = rotation * entity.orientation;
+
+            if mode == StereoMode::Anaglyph {
+                let (r, g, b) = entity.color;
+                let lum = (r + g + b) / 3.;
+                e.color = (lum * filter[0], lum * filter[1], lum * filter[2]);
+            }
+            result.push(e);
+        }
+    }
+
+    *entities = result;
+}
@@ -0,0 +1,128 @@
+//! Guards against bodies moving faster than a set fraction of C. This is relevant for the causal
+//! shell model, where a body outrunning its own shells is unphysical, but also catches blowups from
+//! close encounters in other models.
+
+use bincode::{Decode, Encode};
+
+use crate::{grav_shell::GravShell, units::C, Body};
+
+/// Limit on how many flagged bodies we list per step, to keep logs readable.
+const MAX_LOGGED: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Encode, Decode)]
+pub enum SuperluminalAction {
+    Off,
+    /// Log flagged bodies, but don't change them.
+    #[default]
+    Warn,
+    /// Scale flagged bodies' velocity down to the limit, keeping its direction.
+    Clamp,
+    /// Remove flagged bodies from the simulation.
+    Remove,
+}
+
+impl SuperluminalAction {
+    pub fn to_str(&self) -> String {
+        match self {
+            Self::Off => "Off",
+            Self::Warn => "Warn",
+            Self::Clamp => "Clamp",
+            Self::Remove => "Remove",
+        }
+        .to_owned()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Encode, Decode)]
+pub struct SuperluminalGuard {
+    pub action: SuperluminalAction,
+    /// Bodies faster than this portion of C are flagged.
+    pub v_max_portion: f64,
+}
+
+impl Default for SuperluminalGuard {
+    fn default() -> Self {
+        Self {
+            action: Default::default(),
+            v_max_portion: 0.9,
+        }
+    }
+}
+
+impl SuperluminalGuard {
+    /// Check all bodies, and act on those over the limit. Returns the number flagged.
+    ///
+    /// When removing, we also remove the body's entry in `body_ids`, so the remaining bodies keep
+    /// their IDs, and update shell source IDs so they continue to match their bodies. Shells already emitted by a removed body keep
+    /// propagating, but no longer match any body.
+    pub fn apply(
+        &self,
+        bodies: &mut Vec<Body>,
+        body_ids: &mut Vec<u32>,
+        shells: &mut [GravShell],
+        step: usize,
+    ) -> usize {
+        if self.action == SuperluminalAction::Off {
+            return 0;
+        }
+
+        let v_max = self.v_max_portion * C;
+
+        let flagged: Vec<usize> = bodies
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.vel.magnitude() > v_max)
+            .map(|(i, _)| i)
+            .collect();
+
+        if flagged.is_empty() {
+            return 0;
+        }
+
+        let listed: Vec<String> = flagged
+            .iter()
+            .take(MAX_LOGGED)
+            .map(|i| format!("{i} (v/c: {:.3})", bodies[*i].vel.magnitude() / C))
+            .collect();
+        eprintln!(
+            "Step {step}: {} bodies over {} c: {}{}",
+            flagged.len(),
+            self.v_max_portion,
+            listed.join(", "),
+            if flagged.len() > MAX_LOGGED {
+                ", ..."
+            } else {
+                ""
+            }
+        );
+
+        match self.action {
+            SuperluminalAction::Clamp => {
+                for i in &flagged {
+                    let body = &mut bodies[*i];
+                    body.vel = body.vel.to_normalized() * v_max;
+                }
+            }
+            SuperluminalAction::Remove => {
+                // Reverse order, so indices of the remaining flagged bodies stay valid.
+                for i in flagged.iter().rev() {
+                    bodies.remove(*i);
+                    if *i < body_ids.len() {
+                        body_ids.remove(*i);
+                    }
+
+                    for shell in shells.iter_mut() {
+                        if shell.source_id == *i {
+                            shell.source_id = usize::MAX;
+                        } else if shell.source_id > *i && shell.source_id != usize::MAX {
+                            shell.source_id -= 1;
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        flagged.len()
+    }
+}
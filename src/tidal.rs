@@ -0,0 +1,265 @@
+//! The tidal (Jacobi) radius of a satellite in its host's field, per snapshot. Beyond it, the host's
+//! tides outweigh the satellite's own gravity, and bodies are stripped. Following King (1962), for a
+//! satellite at distance R from the host, moving about it with angular speed ω:
+//!
+//! r_J³ = G m / (ω² - ∂²Φ_host / ∂R²)
+//!
+//! For a point-mass host and a circular orbit, this is R (m / 3M)^(1/3). m is the satellite mass
+//! within r_J, so we iterate. The host potential's curvature is from field probes, so this works
+//! under any force model.
+
+use std::{fmt, fmt::Formatter};
+
+use lin_alg::f64::Vec3;
+
+use crate::{
+    lagrange::Binary,
+    probe::Field,
+    properties::{plot_multi, PlotOutput},
+    units::G,
+    Body, Config, ForceModel, State,
+};
+
+const MAX_ITERS: usize = 30;
+/// Finite-difference step for the potential's curvature, as a portion of the distance.
+const CURVATURE_STEP: f64 = 0.01;
+
+#[derive(Clone, Debug)]
+pub struct TidalEstimate {
+    /// The center of mass of the satellite within r_J. kpc.
+    pub center: Vec3,
+    /// kpc
+    pub r_j: f64,
+    /// Satellite mass within r_J. M☉.
+    pub mass_bound: f64,
+    /// From the host's center. kpc.
+    pub dist_host: f64,
+}
+
+/// The satellite's instantaneous tidal radius, if there is a satellite, and the host's tides are
+/// disruptive at its position. Snapshots don't store shell state, so shell-based models use the
+/// Newtonian field.
+pub fn tidal_radius(
+    bodies: &[Body],
+    config: &Config,
+    force_model: ForceModel,
+) -> Option<TidalEstimate> {
+    let binary = Binary::from_bodies(bodies)?;
+    let host_center = binary.primary.posit;
+    let host_vel = binary.primary.vel;
+
+    let host: Vec<Body> = binary
+        .primary
+        .members
+        .iter()
+        .map(|i| bodies[*i].clone())
+        .collect();
+    let force_model = if force_model.uses_shells() {
+        ForceModel::Newton
+    } else {
+        force_model
+    };
+    let field = Field::new(&host, &[], config, force_model);
+
+    let satellite: Vec<&Body> = binary
+        .secondary
+        .members
+        .iter()
+        .map(|i| &bodies[*i])
+        .collect();
+
+    let mut center = binary.secondary.posit;
+    let mut vel = binary.secondary.vel;
+    let mut mass = binary.secondary.mass;
+    let mut r_j = 0.;
+
+    for _ in 0..MAX_ITERS {
+        let offset = center - host_center;
+        let dist = offset.magnitude();
+        if dist <= f64::EPSILON {
+            return None;
+        }
+        let dir = offset / dist;
+
+        let ω_sq = offset.cross(vel - host_vel).magnitude_squared() / dist.powi(4);
+
+        // ∂²Φ/∂R² = -∂a_R/∂R, by central difference.
+        let h = dist * CURVATURE_STEP;
+        let a_r = |r: f64| field.acc(host_center + dir * r).dot(dir);
+        let phi_rr = -(a_r(dist + h) - a_r(dist - h)) / (2. * h);
+
+        let denom = ω_sq - phi_rr;
+        if denom <= 0. {
+            return None;
+        }
+        let r_next = (G * mass / denom).cbrt();
+
+        let bound: Vec<&&Body> = satellite
+            .iter()
+            .filter(|b| (b.posit - center).magnitude() < r_next)
+            .collect();
+        let mass_next: f64 = bound.iter().map(|b| b.mass).sum();
+        if mass_next <= 0. {
+            return None;
+        }
+
+        center = bound
+            .iter()
+            .fold(Vec3::new_zero(), |acc, b| acc + b.posit * b.mass)
+            / mass_next;
+        vel = bound
+            .iter()
+            .fold(Vec3::new_zero(), |acc, b| acc + b.vel * b.mass)
+            / mass_next;
+
+        let converged = (r_next - r_j).abs() < r_next * 1e-4 && mass_next == mass;
+        r_j = r_next;
+        mass = mass_next;
+
+        if converged {
+            break;
+        }
+    }
+
+    Some(TidalEstimate {
+        center,
+        r_j,
+        mass_bound: mass,
+        dist_host: (center - host_center).magnitude(),
+    })
+}
+
+pub struct TidalHistory {
+    /// Myr
+    pub times: Vec<f64>,
+    /// One per snapshot, by index. `None` where there's no satellite, or no finite tidal radius.
+    pub estimates: Vec<Option<TidalEstimate>>,
+}
+
+impl TidalHistory {
+    /// Stripping rate, -dm/dt of the bound mass, between consecutive estimates. X: t (Myr).
+    /// Y: M☉/Myr.
+    pub fn stripping_rate(&self) -> Vec<(f64, f64)> {
+        let pts: Vec<(f64, f64)> = self
+            .times
+            .iter()
+            .zip(&self.estimates)
+            .filter_map(|(t, e)| e.as_ref().map(|e| (*t, e.mass_bound)))
+            .collect();
+
+        pts.windows(2)
+            .filter(|w| w[1].0 > w[0].0)
+            .map(|w| {
+                (
+                    (w[0].0 + w[1].0) / 2.,
+                    -(w[1].1 - w[0].1) / (w[1].0 - w[0].0),
+                )
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for TidalHistory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let found: Vec<(f64, &TidalEstimate)> = self
+            .times
+            .iter()
+            .zip(&self.estimates)
+            .filter_map(|(t, e)| e.as_ref().map(|e| (*t, e)))
+            .collect();
+
+        let (Some((t_0, first)), Some((t_1, last))) = (found.first(), found.last()) else {
+            return writeln!(f, "No satellite tidal radius found in any snapshot.");
+        };
+
+        writeln!(
+            f,
+            "Satellite tidal radius, found in {} of {} snapshots:",
+            found.len(),
+            self.times.len()
+        )?;
+        for (t, e) in [(t_0, first), (t_1, last)] {
+            writeln!(
+                f,
+                "t: {t:.1} Myr  r_J: {:.2} kpc  Bound mass: {:.3e} M☉  Host distance: {:.2} kpc",
+                e.r_j, e.mass_bound, e.dist_host
+            )?;
+        }
+        if t_1 > t_0 {
+            writeln!(
+                f,
+                "Mean stripping rate: {:.3e} M☉/Myr",
+                (first.mass_bound - last.mass_bound) / (t_1 - t_0)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compute the tidal radius at each snapshot of the current run, from memory or a stream.
+pub fn tidal_history(state: &mut State, force_model: ForceModel) -> TidalHistory {
+    let config = state.config.clone();
+
+    let mut times = Vec::new();
+    let mut estimates = Vec::new();
+
+    for i in 0..state.num_snapshots() {
+        let r = state.with_snapshot(i, |snap, body_masses| {
+            // Snapshots of a subset of bodies don't have the mass distribution.
+            let estimate = snap
+                .is_full()
+                .then(|| tidal_radius(&snap.bodies(body_masses), &config, force_model))
+                .flatten();
+            (snap.time as f64, estimate)
+        });
+
+        // Keep entries aligned with snapshot indices, for the viewer, even if one can't be read.
+        let (time, estimate) = r.unwrap_or((f64::NAN, None));
+        times.push(time);
+        estimates.push(estimate);
+    }
+
+    TidalHistory { times, estimates }
+}
+
+/// Plot tidal radius, bound mass, and stripping rate vs time.
+pub fn plot_tidal_history(out: &PlotOutput, history: &TidalHistory) {
+    let series = |f: fn(&TidalEstimate) -> f64| -> Vec<(f64, f64)> {
+        history
+            .times
+            .iter()
+            .zip(&history.estimates)
+            .filter_map(|(t, e)| e.as_ref().map(|e| (*t, f(e))))
+            .collect()
+    };
+
+    plot_multi(
+        out,
+        &[
+            ("r_J", &series(|e| e.r_j)),
+            ("Host distance", &series(|e| e.dist_host)),
+        ],
+        "t (Myr)",
+        "r (kpc)",
+        "Satellite tidal radius",
+        "tidal_radius",
+    );
+
+    plot_multi(
+        out,
+        &[("Bound mass", &series(|e| e.mass_bound))],
+        "t (Myr)",
+        "M (M☉)",
+        "Satellite mass within the tidal radius",
+        "tidal_mass",
+    );
+
+    plot_multi(
+        out,
+        &[("-dm/dt", &history.stripping_rate())],
+        "t (Myr)",
+        "Ṁ (M☉/Myr)",
+        "Satellite stripping rate",
+        "tidal_stripping",
+    );
+}
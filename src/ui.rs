@@ -1,27 +1,259 @@
-use std::{collections::HashMap, path::PathBuf, str::FromStr};
+use std::{
+    collections::HashMap,
+    f64::consts::FRAC_PI_4,
+    fs, io, mem,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use barnes_hut::{Cube, Tree};
-use egui::{Color32, ComboBox, Context, RichText, Slider, TopBottomPanel, Ui};
+use egui::{
+    Button, CollapsingHeader, Color32, ComboBox, Context, DragValue, Grid, ProgressBar, RichText,
+    Slider, TopBottomPanel, Ui, Window,
+};
+use egui_plot::{HLine, Legend, Line, LineStyle, Plot, PlotPoints, Points};
 use graphics::{EngineUpdates, Entity, Scene};
 use lin_alg::{
     f32::{Quaternion, Vec3},
     f64::Vec3 as Vec3F64,
     linspace,
 };
+use rfd::FileDialog;
 
 use crate::{
-    accel::MondFn,
-    build,
+    accel::{self, MondFn},
+    asym_drift,
+    body_creation::{BodySampling, CentralModel, VelocityCheck, VelocityInit},
+    build_background, build_job,
     charge::{plot_field_properties, FieldProperties},
-    galaxy_data::GalaxyModel,
-    playback::{change_snapshot, SnapShot},
-    render::{TREE_COLOR, TREE_CUBE_SCALE_FACTOR, TREE_SHINYNESS},
-    util, ForceModel, State, BOUNDING_BOX_PAD, SAVE_FILE,
+    config_history,
+    config_text::CONFIG_TEXT_FILE,
+    convergence,
+    descrip_editor::DescripParams,
+    diagnostics, doppler, ensemble, export,
+    flythrough::CameraPath,
+    force_law, gem, grav_shell,
+    grav_shell::{ShellAnisotropy, ShellSpeedLaw, MAX_SHELL_R},
+    injection, integrals,
+    integrate::IntegratorKind,
+    lagrange, lagrangian_radii, mass_flux,
+    mass_refinement::MassRefinement,
+    model_compare, obs_uncertainty,
+    orbits::{self, AxisymmetricPotential},
+    overlay, playback,
+    playback::{add_tidal_sphere, change_snapshot, BodyColorMode, SnapshotSubset},
+    preview, probe, properties,
+    properties::{plot, rotation_curve},
+    pv_diagram,
+    pv_diagram::PvDiagram,
+    qumond, ray_bending, render,
+    render::{
+        fit_scale, follow_camera, reset_camera, ride_camera, LightingSettings, MESH_GRID_LINE,
+        TREE_COLOR, TREE_CUBE_SCALE_FACTOR, TREE_SHINYNESS,
+    },
+    report, resume_with_body, retarded, rot_compare,
+    rot_model::{self, HaloProfile, RotCurveModel},
+    shapiro, shell_geometry,
+    shell_geometry::ShellGeometry,
+    snapshot_stream, sparc, stereo,
+    stereo::StereoMode,
+    superluminal::SuperluminalAction,
+    tidal,
+    units::{C, C_LIGHT, KPC_MYR_PER_KM_S},
+    zoom, ComputationDevice, ForceComposition, ForceModel, State, BOUNDING_BOX_PAD, CONFIG_DIR,
+    DEFAULT_SNAPSHOT_FILE,
 };
 
+#[cfg(feature = "cuda")]
+use crate::gpu::GpuPrecision;
+
 pub const ROW_SPACING: f32 = 10.;
 pub const COL_SPACING: f32 = 30.;
 
+/// Load files dropped onto the window. Configs are applied; snapshot files are loaded for playback.
+/// We tell them apart by the config header; files without it that don't decode as snapshots are
+/// tried as configs saved before versioning.
+fn handle_dropped_files(
+    state: &mut State,
+    ctx: &Context,
+    refresh_bodies: &mut bool,
+    reset_snapshot: &mut bool,
+) {
+    let dropped: Vec<PathBuf> = ctx.input(|i| {
+        i.raw
+            .dropped_files
+            .iter()
+            .filter_map(|f| f.path.clone())
+            .collect()
+    });
+
+    for path in dropped {
+        let name = file_name(&path);
+
+        if snapshot_stream::is_stream(&path).unwrap_or(false) {
+            match state.load_snapshots(&path) {
+                Ok(n) => {
+                    state
+                        .ui
+                        .notifications
+                        .success(format!("Loaded {n} snapshots from {name}"));
+                    *reset_snapshot = true;
+                }
+                Err(e) => state
+                    .ui
+                    .notifications
+                    .error(format!("Unable to open {name}: {e}")),
+            }
+            continue;
+        }
+
+        match state.load_config(&path) {
+            Ok(()) => {
+                *refresh_bodies = true;
+                state
+                    .ui
+                    .notifications
+                    .success(format!("Loaded config {name}"));
+            }
+            Err(e) => state
+                .ui
+                .notifications
+                .error(format!("Unable to load {name}: {e}")),
+        }
+    }
+}
+
+/// A file dialog for snapshot files, in the default snapshot directory.
+fn snapshot_dialog() -> FileDialog {
+    let default_path = Path::new(DEFAULT_SNAPSHOT_FILE);
+    let dir = default_path.parent().unwrap_or(Path::new("."));
+    let _ = fs::create_dir_all(dir);
+
+    FileDialog::new()
+        .add_filter("Snapshots", &["grav"])
+        .set_directory(dir)
+        .set_file_name(file_name(default_path))
+}
+
+/// Render each snapshot, as seen from the current camera, to a PNG, and optionally encode them as
+/// an MP4. Returns the video's path, or the frames' directory.
+fn export_playback(state: &mut State, scene: &mut Scene) -> io::Result<PathBuf> {
+    let num_snapshots = state.num_snapshots();
+    if num_snapshots == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No snapshots to export",
+        ));
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let galaxy = state.ui.run_labels.galaxy.replace(' ', "_");
+    let dir = PathBuf::from(export::EXPORT_DIR).join(format!("{galaxy}_{timestamp}"));
+    fs::create_dir_all(&dir)?;
+
+    // We draw each snapshot's entities as they'd be shown, then restore the current ones.
+    let entities_prev = mem::take(&mut scene.entities);
+    let mut engine_updates = EngineUpdates::default();
+    let mut result = Ok(());
+
+    for i in 0..num_snapshots {
+        show_snapshot(state, scene, i, &mut engine_updates);
+
+        let labels = &state.ui.run_labels;
+        let label = format!("{}, {}", labels.galaxy, labels.force_model);
+        let label = state
+            .with_snapshot(i, |snap, _| format!("{label}. t = {:.2} Myr", snap.time))
+            .unwrap_or(label);
+
+        result = export::render_frame(
+            &export::frame_path(&dir, i),
+            &scene.entities,
+            &scene.camera,
+            &state.ui.export,
+            &label,
+        );
+        if result.is_err() {
+            break;
+        }
+    }
+    scene.entities = entities_prev;
+    result?;
+
+    println!("Exported {num_snapshots} frames to {dir:?}");
+    if state.ui.export.mp4 {
+        return export::encode_video(&dir, state.ui.export.fps);
+    }
+    Ok(dir)
+}
+
+/// Offer to apply changes to the config file made outside the program.
+fn config_changed_prompt(state: &mut State, ctx: &Context, refresh_bodies: &mut bool) {
+    if !state.ui.config_watch.poll() {
+        return;
+    }
+
+    let path = state.ui.config_watch.path().to_owned();
+    let name = file_name(&path);
+    let (mut apply, mut ignore) = (false, false);
+
+    Window::new("Config changed")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!("{name} was changed outside the program."));
+            ui.horizontal(|ui| {
+                apply = ui
+                    .button(RichText::new("Apply").color(Color32::GOLD))
+                    .on_hover_text(
+                        "Load the changed config. Unsaved edits are replaced; undo restores them",
+                    )
+                    .clicked();
+                ignore = ui.button("Ignore").clicked();
+            });
+        });
+
+    if apply {
+        match state.load_config_text(&path) {
+            Ok(()) => {
+                *refresh_bodies = true;
+                state
+                    .ui
+                    .notifications
+                    .success(format!("Applied changes to {name}"));
+            }
+            Err(e) => {
+                // E.g. a partially-written file; wait for the next change.
+                state.ui.config_watch.dismiss();
+                state
+                    .ui
+                    .notifications
+                    .error(format!("Unable to load {name}: {e}"));
+            }
+        }
+    } else if ignore {
+        state.ui.config_watch.dismiss();
+    }
+}
+
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// E.g. "1:05:09", or "5:09" if under an hour.
+fn format_duration(d: Duration) -> String {
+    let s = d.as_secs();
+    if s >= 3_600 {
+        format!("{}:{:02}:{:02}", s / 3_600, s % 3_600 / 60, s % 60)
+    } else {
+        format!("{}:{:02}", s / 60, s % 60)
+    }
+}
+
 fn int_field(val: &mut usize, label: &str, redraw_bodies: &mut bool, ui: &mut Ui) {
     ui.label(label);
     let mut val_str = val.to_string();
@@ -39,240 +271,3058 @@ fn int_field(val: &mut usize, label: &str, redraw_bodies: &mut bool, ui: &mut Ui
     }
 }
 
+/// A checkbox to enable an optional value, and a field to edit it when enabled.
+fn optional_field(val: &mut Option<f64>, label: &str, default: f64, speed: f64, ui: &mut Ui) {
+    let mut enabled = val.is_some();
+    if ui.checkbox(&mut enabled, label).changed() {
+        *val = if enabled { Some(default) } else { None };
+    }
+
+    if let Some(v) = val {
+        ui.add(DragValue::new(v).speed(speed).range(0. ..=f64::MAX));
+    }
+}
+
 /// This function draws the (immediate-mode) GUI.
 /// [UI items](https://docs.rs/egui/latest/egui/struct.Ui.html)
-pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> EngineUpdates {
-    let mut engine_updates = EngineUpdates::default();
+/// Rebuild the scene's entities for a snapshot, including overlays, at the current world scale.
+fn show_snapshot(
+    state: &mut State,
+    scene: &mut Scene,
+    i: usize,
+    engine_updates: &mut EngineUpdates,
+) {
+    let scale = state.ui.world_scale;
+    let color_mode = state.ui.color_mode;
+    let components = mem::take(&mut state.body_components);
+    state.with_snapshot(i, |snap, body_masses| {
+        change_snapshot(
+            &mut scene.entities,
+            snap,
+            body_masses,
+            &components,
+            color_mode,
+            scale,
+        )
+    });
+    state.body_components = components;
+    if state.ui.retarded_view && scale > 0. {
+        let observer = scene.camera.position / scale;
+        if let Some(posits) = retarded::retarded_posits(state, i, observer) {
+            for (entity, posit) in scene.entities.iter_mut().zip(posits) {
+                entity.position = posit * scale;
+            }
+        }
+    }
+    if state.ui.doppler_color {
+        let camera_posit = scene.camera.position;
+        let v_max = state.ui.doppler_v_max;
+        state.with_snapshot(i, |snap, body_masses| {
+            doppler::apply_doppler_colors(
+                &mut scene.entities,
+                &snap.body_vels,
+                &snap.masses(body_masses),
+                camera_posit,
+                v_max,
+            )
+        });
+    }
+    if state.ui.lighting.emissive {
+        if let Some(masses) = state.with_snapshot(i, |snap, body_masses| snap.masses(body_masses)) {
+            render::apply_luminosity(&mut scene.entities, &masses);
+        }
+    }
+    // Bodies come first. Hide the one we're riding, so it doesn't block the view.
+    let ride_entity = state
+        .ui
+        .ride_body
+        .and_then(|b| state.with_snapshot(i, |snap, _| snap.index_of(b)).flatten());
+    if let Some(entity) = ride_entity.and_then(|k| scene.entities.get_mut(k)) {
+        entity.scale = 0.;
+    }
+    if state.ui.show_inspector && state.ui.inspector.highlight {
+        let body = state.ui.inspector.body;
+        let k = state
+            .with_snapshot(i, |snap, _| snap.index_of(body))
+            .flatten();
+        if let Some(entity) = k.and_then(|k| scene.entities.get_mut(k)) {
+            entity.color = orbits::HIGHLIGHT_COLOR;
+        }
+    }
+    if state.ui.show_trails {
+        let mut history = mem::take(&mut state.ui.trail_history);
+        history.update(i, state.ui.trail_len, |j| {
+            state.with_snapshot(j, |snap, _| {
+                (snap.body_posits.clone(), snap.body_ids.clone())
+            })
+        });
+        playback::add_trails(&mut scene.entities, &history, scale);
+        state.ui.trail_history = history;
+    }
+    if state.ui.show_rot_curve {
+        let show_escape_vel = state.ui.show_escape_vel;
+        let bh_config = state.config.bh_config.clone();
+        let softening_factor_sq = state.config.softening_factor_sq;
 
-    // This variable prevents mutliple borrow errors.
-    let mut reset_snapshot = false;
-    let mut refresh_bodies = false;
+        (
+            state.ui.rot_curve,
+            state.ui.vel_dispersion,
+            state.ui.potential,
+        ) = state
+            .with_snapshot(i, |snap, body_masses| {
+                let bodies = snap.bodies(body_masses);
+                let center = Vec3F64::new_zero();
+                let rot_curve = rotation_curve(&bodies, center, C);
 
-    TopBottomPanel::top("0").show(ctx, |ui| {
-        let snapshot = if state.snapshots.len() < state.ui.snapshot_selected {
-            state.ui.snapshot_selected = 0;
-            &SnapShot::default()
-        } else {
-            &state.snapshots[state.ui.snapshot_selected]
-        };
+                // The tree sum is slower than the other profiles, so is opt-in.
+                let potential = if show_escape_vel {
+                    let r_max = rot_curve.last().map(|(r, _)| *r).unwrap_or_default();
+                    properties::gravity_potential(
+                        &bodies,
+                        center,
+                        r_max,
+                        &bh_config,
+                        softening_factor_sq,
+                    )
+                } else {
+                    Vec::new()
+                };
 
-        ui.spacing_mut().slider_width = ui.available_width() - 280.;
+                (
+                    rot_curve,
+                    properties::velocity_dispersion(&bodies, center),
+                    potential,
+                )
+            })
+            .unwrap_or_default();
+    }
+    if state.ui.show_shells {
+        let opacity = state.ui.shell_opacity;
+        state.with_snapshot(i, |snap, _| {
+            playback::add_shells(&mut scene.entities, &snap.shells, opacity, scale)
+        });
+    }
+    add_tidal_sphere(
+        &mut scene.entities,
+        state.ui.tidal_history.as_ref(),
+        i,
+        scale,
+    );
 
-        ui.horizontal(|ui| {
-            ui.label("Snap:");
+    if state.ui.show_grid {
+        // The grid line mesh's length depends on scale.
+        scene.meshes[MESH_GRID_LINE] = overlay::grid_line_mesh(scale);
+        engine_updates.meshes = true;
+        overlay::add_grid(&mut scene.entities, scale);
+    }
+    if state.ui.show_axes {
+        overlay::add_axes(&mut scene.entities, scale);
+    }
 
-            let snapshot_prev = state.ui.snapshot_selected;
-            ui.add(Slider::new(
-                &mut state.ui.snapshot_selected,
-                0..=state.snapshots.len() - 1,
-            ));
+    if state.ui.show_injection {
+        injection::add_marker(&mut scene.entities, &state.ui.injection, scale);
+    }
+    if state.ui.show_zoom {
+        zoom::add_region_marker(&mut scene.entities, &state.ui.zoom, scale);
+    }
 
-            if state.ui.snapshot_selected != snapshot_prev {
-                change_snapshot(&mut scene.entities, snapshot, &state.body_masses);
-                engine_updates.entities = true;
-            }
+    stereo::apply_stereo(
+        &mut scene.entities,
+        &scene.camera,
+        state.ui.stereo_mode,
+        state.ui.stereo_angle,
+    );
+    state.ui.entities_camera = Some((scene.camera.position, scene.camera.orientation));
 
-            if !state.snapshots.is_empty() {
-                ui.add_space(COL_SPACING);
-                ui.label(format!(
-                    "t: {:.4}",
-                    &state.snapshots[state.ui.snapshot_selected].time,
-                ));
-                ui.label(format!(
-                    "dt: {:.6}",
-                    &state.snapshots[state.ui.snapshot_selected].dt
-                ));
-            }
-        });
+    engine_updates.entities = true;
+}
 
-        ui.add_space(ROW_SPACING);
+/// Set the world scale to fit a snapshot's bodies, and reset the camera to frame them.
+fn fit_view(state: &mut State, scene: &mut Scene, i: usize) {
+    if let Some(scale) = state.with_snapshot(i, |snap, _| fit_scale(&snap.body_posits)) {
+        state.ui.world_scale = scale;
+    }
+    reset_camera(&mut scene.camera);
+}
 
-        // force_debug(snapshot, ui);
+/// A window for adjusting the ambient level, point lights, and emissive body rendering.
+fn lighting_window(
+    state: &mut State,
+    ctx: &Context,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) {
+    let mut open = state.ui.show_lighting;
+    let mut changed = false;
+    let mut emissive_changed = false;
 
-        ui.horizontal(|ui| {
-            // todo: Prog bar
-            if ui
-                .button(RichText::new("Build").color(Color32::GOLD))
-                .clicked()
-            {
-                build(state, state.ui.force_model);
-            }
+    Window::new("Lighting").open(&mut open).show(ctx, |ui| {
+        let lighting = &mut state.ui.lighting;
 
-            if state.ui.building {
-                ui.heading(RichText::new("Building...").color(Color32::ORANGE));
-            }
+        emissive_changed = ui
+            .checkbox(&mut lighting.emissive, "Emissive bodies")
+            .on_hover_text(
+                "Render unlit, with brightness set by each body's luminosity (mass, for a uniform \
+                mass-to-light ratio) vice shading",
+            )
+            .changed();
 
-            ui.add_space(COL_SPACING);
+        ui.add_enabled_ui(!lighting.emissive, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Ambient:");
+                changed |= ui
+                    .add(Slider::new(&mut lighting.ambient_intensity, 0.0..=1.).logarithmic(true))
+                    .changed();
+            });
 
-            ui.radio_value(&mut state.ui.force_model, ForceModel::Newton, "Newton");
-            ui.radio_value(
-                &mut state.ui.force_model,
-                ForceModel::Mond(MondFn::Simple),
-                "MOND simple",
-            );
-            ui.radio_value(
-                &mut state.ui.force_model,
-                ForceModel::Mond(MondFn::Standard),
-                "MOND",
-            );
-            ui.radio_value(
-                &mut state.ui.force_model,
-                ForceModel::GaussShells,
-                "Causal shells",
-            );
+            let mut remove = None;
+            for (i, light) in lighting.point_lights.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Light {}:", i + 1));
+                    for coord in [
+                        &mut light.position.x,
+                        &mut light.position.y,
+                        &mut light.position.z,
+                    ] {
+                        changed |= ui.add(DragValue::new(coord).speed(1.)).changed();
+                    }
 
-            ui.add_space(COL_SPACING);
+                    ui.label("Diffuse:");
+                    changed |= ui
+                        .add(
+                            Slider::new(&mut light.diffuse_intensity, 0.0..=20_000.)
+                                .logarithmic(true),
+                        )
+                        .changed();
+                    ui.label("Specular:");
+                    changed |= ui
+                        .add(
+                            Slider::new(&mut light.specular_intensity, 0.0..=20_000.)
+                                .logarithmic(true),
+                        )
+                        .changed();
 
-            let mut prev_model = state.ui.galaxy_model;
-            ComboBox::from_id_salt(0)
-                .width(120.)
-                .selected_text(state.ui.galaxy_model.to_str())
-                .show_ui(ui, |ui| {
-                    for model in [
-                        GalaxyModel::Ngc1560,
-                        GalaxyModel::Ngc2685,
-                        GalaxyModel::Ngc2824,
-                        GalaxyModel::Ngc3626,
-                        GalaxyModel::Ugc6176,
-                        GalaxyModel::M31,
-                    ] {
-                        ui.selectable_value(&mut state.ui.galaxy_model, model, model.to_str());
+                    if ui.button("Remove").clicked() {
+                        remove = Some(i);
                     }
                 });
-            if prev_model != state.ui.galaxy_model {
-                state.ui.galaxy_descrip = state.ui.galaxy_model.descrip();
-                refresh_bodies = true;
             }
-
-            ui.add_space(COL_SPACING);
-
-            ui.checkbox(&mut state.ui.add_halo, "Add halo");
-        });
-
-        ui.add_space(ROW_SPACING);
-
-        ui.horizontal(|ui| {
-            ui.label("dt:");
-            ui.add_sized(
-                [60., Ui::available_height(ui)],
-                egui::TextEdit::singleline(&mut state.ui.dt_input),
-            );
-            if ui.button("Save dt").clicked() {
-                if let Ok(v) = state.ui.dt_input.parse() {
-                    state.config.dt = v;
-                }
+            if let Some(i) = remove {
+                lighting.point_lights.remove(i);
+                changed = true;
             }
 
-            ui.label("Steps (x1000):");
-            let mut val = (state.config.num_timesteps / 1_000).to_string();
-            if ui
-                .add_sized(
-                    [40., Ui::available_height(ui)],
-                    egui::TextEdit::singleline(&mut val),
-                )
-                .changed()
-            {
-                if let Ok(v) = val.parse::<usize>() {
-                    state.config.num_timesteps = v * 1_000;
+            ui.horizontal(|ui| {
+                if ui.button("Add light").clicked() {
+                    let light = LightingSettings::default().point_lights[0].clone();
+                    lighting.point_lights.push(light);
+                    changed = true;
                 }
-            }
-
-            ui.label("θ:");
-            ui.add_sized(
-                [40., Ui::available_height(ui)],
-                egui::TextEdit::singleline(&mut state.ui.θ_input),
-            );
-
-            if ui.button("Save θ").clicked() {
-                if let Ok(v) = state.ui.θ_input.parse() {
-                    state.config.bh_config.θ = v;
+                if ui.button("Reset").clicked() {
+                    *lighting = LightingSettings {
+                        emissive: lighting.emissive,
+                        ..Default::default()
+                    };
+                    changed = true;
                 }
-            }
+            });
+        });
+    });
+    state.ui.show_lighting = open;
 
-            ui.label("v scaler:");
-            ui.add_sized(
-                [36., Ui::available_height(ui)],
-                egui::TextEdit::singleline(&mut state.ui.v_scaler_input),
-            );
-            if ui.button("Save v scaler").clicked() {
-                if let Ok(v) = state.ui.v_scaler_input.parse() {
-                    state.config.v_scaler = v;
-                    refresh_bodies = true; // For updated v.
-                }
-            }
+    if changed || emissive_changed {
+        scene.lighting = state.ui.lighting.scene_lighting();
+        engine_updates.lighting = true;
+    }
+    // Body colors depend on it.
+    if emissive_changed {
+        show_snapshot(state, scene, state.ui.snapshot_selected, engine_updates);
+    }
+}
 
-            int_field(
-                &mut state.config.num_bodies_disk,
-                "bodies disk",
-                &mut refresh_bodies,
-                ui,
-            );
+/// If the scene was clicked this frame, the point clicked on the galactic plane. kpc.
+fn pick_click(ctx: &Context, scene: &Scene, world_scale: f32) -> Option<Vec3F64> {
+    if ctx.is_pointer_over_area() || world_scale <= 0. {
+        return None;
+    }
+    let click = ctx.input(|i| {
+        i.pointer
+            .primary_clicked()
+            .then(|| i.pointer.interact_pos())
+            .flatten()
+    })?;
 
-            int_field(
-                &mut state.config.num_bodies_bulge,
-                "bodies bulge",
-                &mut refresh_bodies,
-                ui,
-            );
+    let posit = overlay::pick_plane(&scene.camera, ctx.available_rect(), click)? / world_scale;
+    Some(Vec3F64::new(posit.x as f64, posit.y as f64, 0.))
+}
 
-            // todo: Remove A/R now that cube is in snapshots.
-            if ui.button("Tree").clicked() {
-                // todo: Of current snapshot.
-                let bb = Cube::from_bodies(&state.bodies, BOUNDING_BOX_PAD, true).unwrap();
-                //
-                // for body in &state.bodies {
-                //     println!("Body: {:.4?}", body);
-                // }
+/// The body under a click in the scene, of those drawn from the selected snapshot, by ray-casting
+/// against their markers.
+fn pick_body(state: &State, ctx: &Context, scene: &Scene) -> Option<usize> {
+    if ctx.is_pointer_over_area() {
+        return None;
+    }
+    let click = ctx.input(|i| {
+        i.pointer
+            .primary_clicked()
+            .then(|| i.pointer.interact_pos())
+            .flatten()
+    })?;
 
-                let tree = Tree::new(&state.bodies, &bb, &state.config.bh_config);
+    let selected = state.ui.snapshot_selected;
+    // Bodies are the first entities.
+    let num_bodies = state.with_snapshot(selected, |snap, _| snap.body_posits.len())?;
+    let k = overlay::pick_entity(
+        &scene.camera,
+        ctx.available_rect(),
+        click,
+        &scene.entities,
+        num_bodies,
+    )?;
+    state.with_snapshot(selected, |snap, _| snap.body_id(k))
+}
 
-                // todo: Subdivide the tree based on a target body here A/R.
+/// Clicking a body inspects it, unless a tool is waiting for a click.
+fn select_clicked_body(
+    state: &mut State,
+    ctx: &Context,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) {
+    let tool_picking = (state.ui.show_zoom && state.ui.zoom.picking)
+        || (state.ui.show_injection && state.ui.injection.picking)
+        || (state.ui.show_inspector && state.ui.inspector.picking);
+    if tool_picking {
+        return;
+    }
 
-                scene.entities = scene
-                    .entities
-                    .clone()
-                    .into_iter()
-                    .filter(|s| s.mesh != 1)
-                    .collect();
+    if let Some(body) = pick_body(state, ctx, scene) {
+        let changed = body != state.ui.inspector.body || !state.ui.show_inspector;
+        state.ui.inspector.body = body;
+        state.ui.show_inspector = true;
 
-                let leaves =
-                    tree.leaves(lin_alg::f64::Vec3::new(2., 2., 0.), &state.config.bh_config);
-                println!(
-                    "Leaf count: {}. Full tree len: {}",
-                    leaves.len(),
-                    tree.nodes.len()
-                );
+        // Move or show the highlight.
+        if changed {
+            show_snapshot(state, scene, state.ui.snapshot_selected, engine_updates);
+        }
+    }
+}
 
-                // for leaf in &leaves { // todo te p
-                //     println!("Leaf: {:?}", leaf);
-                // }
+/// A window for resimulating a region at a finer timestep, from the selected snapshot, with the
+/// rest of the run as its boundary.
+fn zoom_window(
+    state: &mut State,
+    ctx: &Context,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) {
+    let mut open = state.ui.show_zoom;
+    let mut changed = false;
+    let mut resimulate = false;
 
-                for leaf in leaves {
-                    let c = leaf.bounding_box.center;
-                    let posit =
-                        // Vec3::new(c.x as f32, c.y as f32, c.z as f32) + Vec3::new(0., 0., 1.5);
-                        Vec3::new(c.x as f32, c.y as f32, c.z as f32);
+    Window::new("Zoom resimulation")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            let region = &mut state.ui.zoom;
 
-                    scene.entities.push(Entity::new(
-                        1,
-                        posit,
-                        Quaternion::new_identity(),
-                        leaf.bounding_box.width as f32 * TREE_CUBE_SCALE_FACTOR,
-                        TREE_COLOR,
-                        TREE_SHINYNESS,
-                    ));
+            ui.horizontal(|ui| {
+                ui.label("Center (kpc):");
+                for coord in [
+                    &mut region.center.x,
+                    &mut region.center.y,
+                    &mut region.center.z,
+                ] {
+                    changed |= ui.add(DragValue::new(coord).speed(0.1)).changed();
                 }
+                ui.toggle_value(&mut region.picking, "Pick")
+                    .on_hover_text("Click in the scene to set the center, on the galactic plane");
+            });
 
-                engine_updates.entities = true;
-            }
+            ui.horizontal(|ui| {
+                ui.label("Radius:");
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut region.radius)
+                            .speed(0.05)
+                            .range(0.01..=100.)
+                            .suffix(" kpc"),
+                    )
+                    .changed();
 
-            ui.add_space(COL_SPACING);
+                ui.label("Duration:");
+                ui.add(
+                    DragValue::new(&mut region.duration)
+                        .speed(0.1)
+                        .range(0.001..=10_000.)
+                        .suffix(" Myr"),
+                );
 
-            ui.checkbox(&mut state.config.skip_tree, "Skip tree");
+                ui.label("dt ÷");
+                ui.add(DragValue::new(&mut region.refinement).range(1..=1_024))
+                    .on_hover_text("The resimulation's dt is the run's, divided by this");
+            });
 
-            ui.checkbox(&mut state.ui.draw_tree, "Draw tree");
+            resimulate = ui
+                .button(RichText::new("Resimulate").color(Color32::GOLD))
+                .on_hover_text(
+                    "Re-run the bodies in the region from the selected snapshot, at the finer dt. \
+                Bodies outside follow their paths from this run. Replaces this run's snapshots",
+                )
+                .clicked();
+        });
+    state.ui.show_zoom = open;
 
-            ui.add_space(COL_SPACING * 2.);
+    if state.ui.zoom.picking {
+        if let Some(posit) = pick_click(ctx, scene, state.ui.world_scale) {
+            state.ui.zoom.center = posit;
+            state.ui.zoom.picking = false;
+            changed = true;
+        }
+    }
+
+    if resimulate {
+        let region = state.ui.zoom.clone();
+        let selected = state.ui.snapshot_selected;
+        match zoom::resimulate(state, state.ui.force_model, selected, &region) {
+            Ok(n) => state
+                .ui
+                .notifications
+                .success(format!("Resimulated {n} bodies in the region")),
+            Err(e) => state
+                .ui
+                .notifications
+                .error(format!("Unable to resimulate: {e}")),
+        }
+    }
+
+    // Move, show, or hide the marker.
+    if changed || resimulate || !open {
+        show_snapshot(state, scene, state.ui.snapshot_selected, engine_updates);
+    }
+}
+
+/// A window for injecting a body at the selected snapshot, and continuing the run with it. The
+/// position can be picked by clicking on the galactic plane.
+fn injection_window(
+    state: &mut State,
+    ctx: &Context,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) {
+    let mut open = state.ui.show_injection;
+    let mut changed = false;
+    let mut inject = false;
+
+    Window::new("Inject body").open(&mut open).show(ctx, |ui| {
+        let injection = &mut state.ui.injection;
+
+        ui.horizontal(|ui| {
+            ui.label("Position (kpc):");
+            for coord in [
+                &mut injection.posit.x,
+                &mut injection.posit.y,
+                &mut injection.posit.z,
+            ] {
+                changed |= ui.add(DragValue::new(coord).speed(0.1)).changed();
+            }
+            ui.toggle_value(&mut injection.picking, "Pick")
+                .on_hover_text("Click in the scene to set the position, on the galactic plane");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Velocity (km/s):");
+            for coord in [
+                &mut injection.vel.x,
+                &mut injection.vel.y,
+                &mut injection.vel.z,
+            ] {
+                ui.add(DragValue::new(coord).speed(1.));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Mass:");
+            ui.add(
+                DragValue::new(&mut injection.mass)
+                    .speed(1e8)
+                    .range(1.0..=1e13)
+                    .custom_formatter(|v, _| format!("{v:.2e}"))
+                    .suffix(" M☉"),
+            );
+        });
+
+        inject = ui
+            .button(RichText::new("Inject and run").color(Color32::GOLD))
+            .on_hover_text(
+                "Add the body at the selected snapshot, and run the configured number of steps \
+                from there. Later snapshots are replaced",
+            )
+            .clicked();
+    });
+    state.ui.show_injection = open;
+
+    if state.ui.injection.picking {
+        if let Some(posit) = pick_click(ctx, scene, state.ui.world_scale) {
+            state.ui.injection.posit = posit;
+            state.ui.injection.picking = false;
+            changed = true;
+        }
+    }
+
+    if inject {
+        let selected = state.ui.snapshot_selected;
+        let body = state.ui.injection.body();
+        resume_with_body(state, state.ui.force_model, selected, body);
+    }
+
+    // Move, show, or hide the marker.
+    if changed || inject || !open {
+        show_snapshot(state, scene, state.ui.snapshot_selected, engine_updates);
+    }
+}
+
+/// A window showing a body's state, and orbital elements, at the selected snapshot, and exporting
+/// elements for all bodies. The body can be selected by clicking it, or picked by clicking near it.
+fn inspector_window(
+    state: &mut State,
+    ctx: &Context,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) {
+    let mut open = state.ui.show_inspector;
+    let selected = state.ui.snapshot_selected;
+    let body_prev = state.ui.inspector.body;
+    let highlight_prev = state.ui.inspector.highlight;
+    let mut export = false;
+
+    let body = state.ui.inspector.body;
+    let info = state
+        .with_snapshot(selected, |snap, body_masses| {
+            let k = snap.index_of(body)?;
+            let bodies = snap.bodies(body_masses);
+            let potential = AxisymmetricPotential::new(&bodies);
+            let (center, _) = properties::center_of_mass(&bodies);
+            Some((
+                bodies[k].clone(),
+                (bodies[k].posit - center).magnitude(),
+                potential.elements(bodies[k].posit, bodies[k].vel),
+            ))
+        })
+        .flatten();
+    let component = state.body_components.get(body).copied();
+    let num_bodies = state.body_masses.len().max(1);
+
+    Window::new("Body inspector")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            let inspector = &mut state.ui.inspector;
+
+            ui.horizontal(|ui| {
+                ui.label("Body:");
+                ui.add(DragValue::new(&mut inspector.body).range(0..=num_bodies - 1));
+                ui.toggle_value(&mut inspector.picking, "Pick")
+                    .on_hover_text(
+                        "Click in the scene to select the body nearest it, on the galactic plane",
+                    );
+                ui.checkbox(&mut inspector.highlight, "Highlight")
+                    .on_hover_text(
+                        "Highlight the body in each snapshot, to follow it during playback",
+                    );
+            });
+
+            let Some((body, r_orbit, elements)) = info else {
+                ui.label("Not in this snapshot");
+                return;
+            };
+            ui.label(format!(
+                "{}Mass: {:.2e} M☉",
+                component.map_or(String::new(), |c| format!("{}. ", c.to_str())),
+                body.mass
+            ));
+
+            let v = body.vel / KPC_MYR_PER_KM_S;
+            ui.label(format!(
+                "Position: ({:.2}, {:.2}, {:.2}) kpc",
+                body.posit.x, body.posit.y, body.posit.z
+            ));
+            ui.label(format!(
+                "Velocity: ({:.1}, {:.1}, {:.1}) km/s   |v|: {:.1} km/s",
+                v.x,
+                v.y,
+                v.z,
+                v.magnitude()
+            ));
+            ui.label(format!(
+                "Acceleration: ({:.2e}, {:.2e}, {:.2e}) kpc/Myr²",
+                body.accel.x, body.accel.y, body.accel.z
+            ));
+            ui.label(format!("Orbital radius: {r_orbit:.2} kpc"))
+                .on_hover_text("Distance from the center of mass");
+            ui.separator();
+
+            match elements {
+                Some(el) => {
+                    ui.label(format!("R: {:.2} kpc   z: {:.3} kpc", el.r, el.z));
+                    ui.label(format!(
+                        "L_z: {:.3} kpc·km/s{}",
+                        el.l_z / KPC_MYR_PER_KM_S,
+                        if el.l_z < 0. { " (retrograde)" } else { "" }
+                    ));
+                    ui.label(format!(
+                        "Guiding radius: {:.2} kpc   κ: {:.1} km/s/kpc",
+                        el.r_guiding,
+                        el.kappa / KPC_MYR_PER_KM_S
+                    ));
+                    ui.label(format!("Eccentricity: {:.3}", el.eccentricity));
+                    ui.label(format!("Vertical amplitude: {:.3} kpc", el.z_amplitude));
+                    ui.label(format!(
+                        "Epicyclic phase: {:.0}°",
+                        el.epicyclic_phase.to_degrees()
+                    ))
+                    .on_hover_text("0° at apocenter, and 180° at pericenter");
+                }
+                None => {
+                    ui.label("Unable to estimate orbital elements for this body");
+                }
+            }
+
+            export = ui
+                .button("Export CSV")
+                .on_hover_text(
+                    "Save the orbital elements of all bodies at this snapshot, e.g. for finding \
+                orbital families",
+                )
+                .clicked();
+        });
+    state.ui.show_inspector = open;
+
+    if state.ui.inspector.picking {
+        if let Some(posit) = pick_click(ctx, scene, state.ui.world_scale) {
+            if let Some(Some(body)) =
+                state.with_snapshot(selected, |snap, _| orbits::nearest_body(snap, posit))
+            {
+                state.ui.inspector.body = body;
+            }
+            state.ui.inspector.picking = false;
+        }
+    }
+
+    if export {
+        let path = Path::new(export::EXPORT_DIR).join(format!("orbits_{selected:05}.csv"));
+        let result = state
+            .with_snapshot(selected, |snap, body_masses| {
+                orbits::save_csv(&path, &orbits::snapshot_elements(snap, body_masses))
+            })
+            .unwrap_or_else(|| {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "Unable to read the snapshot",
+                ))
+            });
+
+        match result {
+            Ok(()) => state
+                .ui
+                .notifications
+                .success(format!("Saved orbital elements to {path:?}")),
+            Err(e) => state
+                .ui
+                .notifications
+                .error(format!("Error saving orbital elements: {e}")),
+        }
+    }
+
+    // Move, show, or hide the highlight.
+    if state.ui.inspector.body != body_prev
+        || state.ui.inspector.highlight != highlight_prev
+        || !open
+    {
+        show_snapshot(state, scene, selected, engine_updates);
+    }
+}
+
+/// A window for an analytic rotation curve model: Set or fit its components, and compare it with
+/// the observed curve, without running a simulation.
+fn rot_model_window(state: &mut State, ctx: &Context) {
+    let mut open = state.ui.show_rot_model;
+    let observed = rot_model::observed_curve(&state.ui.galaxy_descrip).to_vec();
+    let galaxy = state.ui.galaxy_model.to_str();
+
+    Window::new("Rotation curve model")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            let model = &mut state.ui.rot_model;
+
+            let mass = |ui: &mut Ui, label: &str, v: &mut f64| {
+                ui.label(label);
+                ui.add(
+                    DragValue::new(v)
+                        .speed(1e8)
+                        .range(0. ..=1e13)
+                        .custom_formatter(|v, _| format!("{v:.2e}"))
+                        .suffix(" M☉"),
+                );
+            };
+            let length = |ui: &mut Ui, label: &str, v: &mut f64| {
+                ui.label(label);
+                ui.add(
+                    DragValue::new(v)
+                        .speed(0.05)
+                        .range(0.01..=200.)
+                        .suffix(" kpc"),
+                );
+            };
+
+            ui.horizontal(|ui| {
+                mass(ui, "Disk:", &mut model.disk_mass);
+                length(ui, "R_d:", &mut model.disk_scale);
+            });
+            ui.horizontal(|ui| {
+                mass(ui, "Bulge:", &mut model.bulge_mass);
+                length(ui, "a:", &mut model.bulge_scale);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("MOND:");
+                ComboBox::from_id_salt(16)
+                    .width(80.)
+                    .selected_text(model.mond.map_or("Off".to_owned(), |m| m.to_str()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut model.mond, None, "Off");
+                        for mond_fn in [MondFn::Simple, MondFn::Standard] {
+                            ui.selectable_value(&mut model.mond, Some(mond_fn), mond_fn.to_str());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Apply the MOND transformation to the disk and bulge, vice a halo",
+                    );
+            });
+
+            if model.mond.is_none() {
+                ui.horizontal(|ui| {
+                    ui.label("Halo:");
+                    ComboBox::from_id_salt(15)
+                        .width(80.)
+                        .selected_text(model.halo.to_str())
+                        .show_ui(ui, |ui| {
+                            for halo in [HaloProfile::None, HaloProfile::Nfw, HaloProfile::Burkert]
+                            {
+                                ui.selectable_value(&mut model.halo, halo, halo.to_str());
+                            }
+                        });
+
+                    if model.halo != HaloProfile::None {
+                        ui.label("ρ:");
+                        ui.add(
+                            DragValue::new(&mut model.halo_density)
+                                .speed(1e5)
+                                .range(0. ..=1e12)
+                                .custom_formatter(|v, _| format!("{v:.2e}"))
+                                .suffix(" M☉/kpc³"),
+                        );
+                        length(ui, "r:", &mut model.halo_scale);
+                    }
+                });
+            }
+
+            ui.horizontal(|ui| {
+                match model.rms_error(&observed) {
+                    Some(err) => ui.label(format!(
+                        "RMS vs observed: {:.1} km/s",
+                        err / KPC_MYR_PER_KM_S
+                    )),
+                    None => ui.label("No observed curve for this galaxy"),
+                };
+            });
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("From galaxy")
+                    .on_hover_text(
+                        "Set the disk and bulge masses from the selected galaxy, the disk scale \
+                        length from its density profile, and its halo, if it has one",
+                    )
+                    .clicked()
+                {
+                    *model = RotCurveModel::from_galaxy(&state.ui.galaxy_descrip);
+                }
+
+                if ui
+                    .button(RichText::new("Fit").color(Color32::GOLD))
+                    .on_hover_text(
+                        "Fit the parameters of the enabled components to the observed curve. \
+                        Set a mass or density to 0 to leave that component out",
+                    )
+                    .clicked()
+                {
+                    if let Some(err) = model.fit(&observed) {
+                        println!(
+                            "Rotation curve model fit. RMS: {:.2} km/s. {model:?}",
+                            err / KPC_MYR_PER_KM_S
+                        );
+                    }
+                }
+
+                if ui.button("Plot").clicked() {
+                    rot_model::plot_model(&state.config.plot_output, model, &observed, &galaxy);
+                    state
+                        .ui
+                        .notifications
+                        .plots_saved(&state.config.plot_output, "Rotation curve model plot");
+                }
+            });
+        });
+    state.ui.show_rot_model = open;
+}
+
+/// A window for editing the galaxy description's parameters. Applying regenerates bodies; resetting
+/// restores the galaxy's built-in values.
+fn descrip_editor_window(state: &mut State, ctx: &Context, refresh_bodies: &mut bool) {
+    let mut open = state.ui.show_descrip_editor;
+    let applied = DescripParams::new(&state.ui.galaxy_descrip);
+    let mut params = state.ui.descrip_edit.unwrap_or(applied);
+    let mut apply = false;
+    let mut reset = false;
+
+    Window::new("Galaxy parameters")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            let mass = |ui: &mut Ui, label: &str, v: &mut f64| {
+                ui.label(label);
+                ui.add(
+                    DragValue::new(v)
+                        .speed(1e8)
+                        .range(0. ..=1e13)
+                        .custom_formatter(|v, _| format!("{v:.2e}"))
+                        .suffix(" M☉"),
+                );
+            };
+
+            ui.horizontal(|ui| {
+                mass(ui, "Disk:", &mut params.mass_disk);
+                mass(ui, "Bulge:", &mut params.mass_bulge);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("M/L:");
+                ui.add(
+                    DragValue::new(&mut params.mass_to_light_ratio)
+                        .speed(0.05)
+                        .range(0. ..=100.),
+                )
+                .on_hover_text(
+                    "Mass-to-light ratio. Changing it scales the disk's density profile and mass",
+                );
+                ui.label("Eccentricity:");
+                ui.add(
+                    DragValue::new(&mut params.eccentricity)
+                        .speed(0.01)
+                        .range(0. ..=0.99),
+                );
+                ui.label("Thickness:");
+                ui.add(
+                    DragValue::new(&mut params.disk_thickness)
+                        .speed(0.01)
+                        .range(0. ..=5.)
+                        .suffix(" kpc"),
+                )
+                .on_hover_text("Disk bodies are placed uniformly within ±half this of the plane");
+            });
+
+            ui.horizontal(|ui| {
+                let (r_core, rho_0) = &mut params.burkert_params;
+                ui.label("Burkert r:");
+                ui.add(
+                    DragValue::new(r_core)
+                        .speed(0.05)
+                        .range(0. ..=200.)
+                        .suffix(" kpc"),
+                );
+                ui.label("ρ:");
+                ui.add(
+                    DragValue::new(rho_0)
+                        .speed(1e5)
+                        .range(0. ..=1e12)
+                        .custom_formatter(|v, _| format!("{v:.2e}"))
+                        .suffix(" M☉/kpc³"),
+                );
+            })
+            .response
+            .on_hover_text("Dark matter halo core radius and central density. 0 for none");
+
+            ui.horizontal(|ui| {
+                apply = ui
+                    .add_enabled(params != applied, Button::new("Apply"))
+                    .on_hover_text("Set these parameters, and regenerate bodies")
+                    .clicked();
+                reset = ui
+                    .button("Reset")
+                    .on_hover_text("Restore the galaxy's built-in parameters")
+                    .clicked();
+            });
+        });
+    state.ui.show_descrip_editor = open;
+    state.ui.descrip_edit = Some(params);
+
+    if apply {
+        params.apply(&mut state.ui.galaxy_descrip);
+        *refresh_bodies = true;
+    }
+    if reset {
+        state.ui.galaxy_descrip = state.ui.galaxy_model.descrip();
+        state.ui.descrip_edit = None;
+        *refresh_bodies = true;
+    }
+}
+
+/// A window for the cluster's parameters. Closing it returns to building a single galaxy.
+fn cluster_window(state: &mut State, ctx: &Context, refresh_bodies: &mut bool) {
+    let Some(params) = &mut state.ui.cluster else {
+        return;
+    };
+    let mut open = true;
+
+    Window::new("Galaxy cluster")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            let mass = |ui: &mut Ui, label: &str, v: &mut f64| {
+                ui.label(label);
+                ui.add(
+                    DragValue::new(v)
+                        .speed(1e8)
+                        .range(1e6..=1e13)
+                        .custom_formatter(|v, _| format!("{v:.2e}"))
+                        .suffix(" M☉"),
+                );
+            };
+
+            ui.horizontal(|ui| {
+                ui.label("Galaxies:");
+                ui.add(DragValue::new(&mut params.num_galaxies).range(1..=200));
+                ui.label("Radius:");
+                ui.add(
+                    DragValue::new(&mut params.radius)
+                        .speed(10.)
+                        .range(10. ..=10_000.)
+                        .suffix(" kpc"),
+                )
+                .on_hover_text("Galaxies are placed uniformly within a sphere of this radius");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("α:");
+                ui.add(
+                    DragValue::new(&mut params.schechter_alpha)
+                        .speed(0.01)
+                        .range(-2.5..=0.),
+                )
+                .on_hover_text("Schechter mass function slope: dN/dM ∝ (M/M*)^α exp(-M/M*)");
+                mass(ui, "M*:", &mut params.mass_char);
+            });
+
+            ui.horizontal(|ui| {
+                mass(ui, "Min:", &mut params.mass_min);
+                mass(ui, "Max:", &mut params.mass_max);
+            });
+
+            if ui
+                .button("Regenerate")
+                .on_hover_text("Sample new galaxy masses, positions, and velocities")
+                .clicked()
+            {
+                *refresh_bodies = true;
+            }
+        });
+
+    if !open {
+        state.ui.cluster = None;
+        *refresh_bodies = true;
+    }
+}
+
+/// A window plotting the selected snapshot's rotation curve over the observed one, with residuals
+/// and reduced χ², and its velocity dispersion profile. It updates as playback advances, or the
+/// selected snapshot changes.
+fn rot_curve_window(state: &mut State, ctx: &Context) {
+    let mut open = state.ui.show_rot_curve;
+    let descrip = &state.ui.galaxy_descrip;
+    let comparison = rot_compare::compare(
+        &state.ui.rot_curve,
+        &rot_compare::observed_with_errors(descrip),
+    );
+    let bulge: Vec<[f64; 2]> = descrip
+        .rotation_curve_bulge
+        .iter()
+        .map(|(r, v)| [*r, v / KPC_MYR_PER_KM_S])
+        .collect();
+    let escape_vel = properties::escape_velocity(&state.ui.potential);
+
+    Window::new("Rotation curve")
+        .open(&mut open)
+        .default_size([420., 520.])
+        .show(ctx, |ui| {
+            let simulated: PlotPoints = state.ui.rot_curve.iter().map(|(r, v)| [*r, *v]).collect();
+            let observed: PlotPoints = comparison
+                .observed
+                .iter()
+                .map(|(r, v, _)| [*r, *v])
+                .collect();
+            let bound = |sign: f64| -> PlotPoints {
+                comparison
+                    .observed
+                    .iter()
+                    .map(|(r, v, σ)| [*r, v + sign * σ])
+                    .collect()
+            };
+
+            Plot::new("rot_curve")
+                .legend(Legend::default())
+                .x_axis_label("R (kpc)")
+                .y_axis_label("v (km/s)")
+                .include_x(0.)
+                .include_y(0.)
+                .height(220.)
+                .show(ui, |plot_ui| {
+                    plot_ui.line(Line::new(simulated).name("Simulated"));
+                    plot_ui.points(
+                        Points::new(observed)
+                            .radius(2.5)
+                            .color(Color32::GOLD)
+                            .name("Observed"),
+                    );
+                    for sign in [-1., 1.] {
+                        plot_ui.line(
+                            Line::new(bound(sign))
+                                .color(Color32::GOLD)
+                                .style(LineStyle::dashed_loose())
+                                .name("Observed ±1σ"),
+                        );
+                    }
+                    if !bulge.is_empty() {
+                        plot_ui.points(
+                            Points::new(bulge)
+                                .radius(2.)
+                                .color(Color32::LIGHT_RED)
+                                .name("Observed bulge"),
+                        );
+                    }
+                    if !escape_vel.is_empty() {
+                        let pts: PlotPoints = escape_vel.iter().map(|(r, v)| [*r, *v]).collect();
+                        plot_ui.line(
+                            Line::new(pts)
+                                .color(Color32::LIGHT_BLUE)
+                                .style(LineStyle::dashed_dense())
+                                .name("Escape"),
+                        );
+                    }
+                });
+
+            let residuals: PlotPoints =
+                comparison.residuals.iter().map(|(r, d)| [*r, *d]).collect();
+            Plot::new("rot_curve_residuals")
+                .x_axis_label("R (kpc)")
+                .y_axis_label("Δv (km/s)")
+                .include_x(0.)
+                .height(100.)
+                .show(ui, |plot_ui| {
+                    plot_ui.hline(HLine::new(0.).color(Color32::GRAY));
+                    plot_ui.points(
+                        Points::new(residuals)
+                            .radius(2.5)
+                            .name("Simulated - observed"),
+                    );
+                });
+
+            Plot::new("vel_dispersion")
+                .legend(Legend::default())
+                .x_axis_label("R (kpc)")
+                .y_axis_label("σ (km/s)")
+                .include_x(0.)
+                .include_y(0.)
+                .height(120.)
+                .show(ui, |plot_ui| {
+                    for (profile, name) in state.ui.vel_dispersion.iter().zip(["σ_R", "σ_φ", "σ_z"])
+                    {
+                        let pts: PlotPoints = profile.iter().map(|(r, σ)| [*r, *σ]).collect();
+                        plot_ui.line(Line::new(pts).name(name));
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                match comparison.chi_sq_reduced {
+                    Some(chi_sq) => ui
+                        .label(format!(
+                            "χ²_ν: {chi_sq:.2} ({} points)",
+                            comparison.residuals.len()
+                        ))
+                        .on_hover_text(
+                            "Reduced χ² of the simulated curve against the observed disk curve, at \
+                            observed radii within the simulated range. Point errors are a floor, \
+                            combined with distance and inclination uncertainty, if set",
+                        ),
+                    None => ui.label("No observed points in the simulated range"),
+                };
+
+                ui.checkbox(&mut state.ui.show_escape_vel, "Escape v")
+                    .on_hover_text(
+                        "Show the escape velocity, from the bodies' potential, summed with the \
+                        Barnes-Hut tree, and averaged over azimuth in the disk plane",
+                    );
+
+                if ui
+                    .button("Save PNG")
+                    .on_hover_text("Save the comparison to the plot directory")
+                    .clicked()
+                {
+                    let name = format!(
+                        "rot_overlay_{}_{}",
+                        state.ui.run_labels.galaxy, state.ui.snapshot_selected
+                    );
+                    if let Some(path) = state.config.plot_output.path(&name) {
+                        let result = obs_uncertainty::plot_rot_curve_overlay(
+                            &path,
+                            &state.ui.galaxy_descrip,
+                            &state.ui.rot_curve,
+                            &format!("Rotation curve of {}", state.ui.run_labels.galaxy),
+                        );
+                        if !state.ui.potential.is_empty() {
+                            properties::plot_potential(
+                                &state.config.plot_output,
+                                &state.ui.potential,
+                                &name,
+                            );
+                        }
+                        match result {
+                            Ok(()) => state
+                                .ui
+                                .notifications
+                                .plots_saved(&state.config.plot_output, "Rotation curve plot"),
+                            Err(e) => state
+                                .ui
+                                .notifications
+                                .error(format!("Error plotting the rotation curve: {e}")),
+                        }
+                    }
+                }
+            });
+        });
+    state.ui.show_rot_curve = open;
+}
+
+/// A window for recording camera path keyframes, and flying along the path.
+fn camera_path_window(state: &mut State, ctx: &Context, scene: &mut Scene) {
+    let mut open = state.ui.show_camera_path;
+
+    Window::new("Camera path").open(&mut open).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui
+                .button("Key")
+                .on_hover_text("Add a keyframe from the camera, at this snapshot's time")
+                .clicked()
+            {
+                let selected = state.ui.snapshot_selected;
+                if let Some(time) = state.with_snapshot(selected, |snap, _| snap.time) {
+                    let scale = state.ui.world_scale;
+                    state.ui.camera_path.add(time, &scene.camera, scale);
+                }
+            }
+
+            if ui
+                .checkbox(&mut state.ui.path_recording, "Record")
+                .on_hover_text("Add a keyframe at each snapshot while playing, as you fly")
+                .changed()
+                && state.ui.path_recording
+            {
+                state.ui.path_flying = false;
+            }
+            if ui
+                .checkbox(&mut state.ui.path_flying, "Fly")
+                .on_hover_text("Move the camera along the path, during playback and scrubbing")
+                .changed()
+                && state.ui.path_flying
+            {
+                state.ui.path_recording = false;
+                state.ui.ride_body = None;
+                state.ui.follow_body = None;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} keyframes",
+                state.ui.camera_path.keyframes.len()
+            ));
+
+            if ui.button("Clear").clicked() {
+                state.ui.camera_path = Default::default();
+            }
+
+            if ui.button("Save").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("Camera path", &["cam"])
+                    .set_file_name("path.cam")
+                    .save_file()
+                {
+                    match state.ui.camera_path.save(&path) {
+                        Ok(()) => state
+                            .ui
+                            .notifications
+                            .success(format!("Saved camera path to {}", file_name(&path))),
+                        Err(e) => state.ui.notifications.error(format!(
+                            "Unable to save camera path to {}: {e}",
+                            file_name(&path)
+                        )),
+                    }
+                }
+            }
+
+            if ui.button("Load").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("Camera path", &["cam"])
+                    .pick_file()
+                {
+                    match CameraPath::load(&path) {
+                        Ok(camera_path) => {
+                            state.ui.notifications.success(format!(
+                                "Loaded a camera path with {} keyframes from {}",
+                                camera_path.keyframes.len(),
+                                file_name(&path)
+                            ));
+                            state.ui.camera_path = camera_path;
+                        }
+                        Err(e) => state.ui.notifications.error(format!(
+                            "Unable to load a camera path from {}: {e}",
+                            file_name(&path)
+                        )),
+                    }
+                }
+            }
+        });
+
+        if let (Some(first), Some(last)) = (
+            state.ui.camera_path.keyframes.first(),
+            state.ui.camera_path.keyframes.last(),
+        ) {
+            ui.label(format!("t: {:.1} to {:.1} Myr", first.time, last.time));
+        }
+    });
+    state.ui.show_camera_path = open;
+}
+
+/// Advance through snapshots while playing, recording camera path keyframes, or flying along the
+/// path. Call once per frame.
+fn update_playback(
+    state: &mut State,
+    ctx: &Context,
+    scene: &mut Scene,
+    engine_updates: &mut EngineUpdates,
+) {
+    let num_snapshots = state.num_snapshots();
+    if num_snapshots == 0 {
+        return;
+    }
+
+    if state.ui.playing {
+        let dt = ctx.input(|i| i.stable_dt);
+        state.ui.playback_pos += dt * state.ui.playback_speed;
+        // Loop.
+        if state.ui.playback_pos > (num_snapshots - 1) as f32 {
+            state.ui.playback_pos = 0.;
+        }
+
+        let i = state.ui.playback_pos as usize;
+        if i != state.ui.snapshot_selected {
+            state.ui.snapshot_selected = i;
+            show_snapshot(state, scene, i, engine_updates);
+
+            if state.ui.path_recording {
+                if let Some(time) = state.with_snapshot(i, |snap, _| snap.time) {
+                    let scale = state.ui.world_scale;
+                    state.ui.camera_path.add(time, &scene.camera, scale);
+                }
+            }
+        }
+        ctx.request_repaint();
+    }
+
+    if !state.ui.path_flying || state.ui.ride_body.is_some() || state.ui.follow_body.is_some() {
+        return;
+    }
+
+    // Between snapshots while playing, so the camera moves smoothly.
+    let i = state.ui.snapshot_selected;
+    let frac = if state.ui.playing {
+        state.ui.playback_pos - i as f32
+    } else {
+        0.
+    };
+    let t_0 = state.with_snapshot(i, |snap, _| snap.time);
+    let t_1 = state.with_snapshot((i + 1).min(num_snapshots - 1), |snap, _| snap.time);
+    let (Some(t_0), Some(t_1)) = (t_0, t_1) else {
+        return;
+    };
+    let time = t_0 + (t_1 - t_0) * frac;
+
+    let prev = (scene.camera.position, scene.camera.orientation);
+    let scale = state.ui.world_scale;
+    if state.ui.camera_path.apply(time, &mut scene.camera, scale)
+        && prev != (scene.camera.position, scene.camera.orientation)
+    {
+        engine_updates.camera = true;
+    }
+}
+
+pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> EngineUpdates {
+    let mut engine_updates = EngineUpdates::default();
+
+    // This variable prevents mutliple borrow errors.
+    let mut reset_snapshot = false;
+    let mut refresh_bodies = false;
+
+    if state.build_job.is_some() {
+        build_job::poll(state);
+        // Keep polling, without waiting for input.
+        ctx.request_repaint_after(Duration::from_millis(100));
+    }
+
+    TopBottomPanel::top("0").show(ctx, |ui| {
+        let num_snapshots = state.num_snapshots();
+        if state.ui.snapshot_selected >= num_snapshots {
+            state.ui.snapshot_selected = 0;
+        }
+
+        ui.spacing_mut().slider_width = ui.available_width() - 560.;
+
+        ui.horizontal(|ui| {
+            ui.label("Snap:");
+
+            let snapshot_prev = state.ui.snapshot_selected;
+            ui.add(Slider::new(
+                &mut state.ui.snapshot_selected,
+                0..=num_snapshots.saturating_sub(1),
+            ));
+
+            let selected = state.ui.snapshot_selected;
+            if selected != snapshot_prev {
+                state.ui.playback_pos = selected as f32;
+                show_snapshot(state, scene, selected, &mut engine_updates);
+            }
+
+            let play_text = if state.ui.playing { "Pause" } else { "Play" };
+            if ui.button(play_text).clicked() {
+                state.ui.playing = !state.ui.playing;
+                state.ui.playback_pos = selected as f32;
+            }
+            ui.add(
+                DragValue::new(&mut state.ui.playback_speed)
+                    .speed(0.5)
+                    .range(0.1..=200.)
+                    .suffix(" /s"),
+            )
+            .on_hover_text("Playback speed, in snapshots per second");
+
+            if let Some((time, dt)) = state.with_snapshot(selected, |snap, _| (snap.time, snap.dt))
+            {
+                ui.add_space(COL_SPACING);
+                ui.label(format!("t: {time:.4}"));
+                ui.label(format!("dt: {dt:.6}"));
+            }
+
+            // Only snapshots taken on a diagnostic sample have these.
+            let sample = state.with_snapshot(selected, |snap, _| snap.diagnostics).flatten();
+            if let (Some(sample), Some((_, first))) = (sample, state.diagnostics.first()) {
+                ui.add_space(COL_SPACING);
+                let ratio = sample.virial_ratio().unwrap_or_default();
+                let (min, max) = diagnostics::VIRIAL_RATIO_RANGE;
+                let color = if ratio < min || ratio > max {
+                    Color32::ORANGE
+                } else {
+                    Color32::LIGHT_GRAY
+                };
+                ui.label(RichText::new(format!("2K/|U|: {ratio:.2}")).color(color))
+                    .on_hover_text("Virial ratio. 1 in equilibrium");
+
+                let (com_posit, com_vel) = sample.com_drift(first);
+                ui.label(format!("ΔCOM: {com_posit:.2e} kpc")).on_hover_text(format!(
+                    "Center of mass drift since the start of the run. Velocity: {:.2e} km/s",
+                    com_vel / KPC_MYR_PER_KM_S
+                ));
+            }
+
+            ui.add_space(COL_SPACING);
+            let device_color = match state.dev {
+                ComputationDevice::Cpu => Color32::LIGHT_GRAY,
+                #[cfg(feature = "cuda")]
+                ComputationDevice::Gpu(_) => Color32::LIGHT_GREEN,
+            };
+            ui.label(RichText::new(state.dev.to_str()).color(device_color))
+                .on_hover_text("The device computations run on");
+        });
+
+        ui.add_space(ROW_SPACING);
+
+        // force_debug(snapshot, ui);
+
+        ui.horizontal(|ui| {
+            match &state.build_job {
+                Some(job) => {
+                    let text = if job.cancelled() {
+                        "Cancelling...".to_owned()
+                    } else {
+                        let eta = match job.eta() {
+                            Some(eta) => format!(", {} left", format_duration(eta)),
+                            None => String::new(),
+                        };
+                        format!("Step {} / {}{eta}", job.steps_done, job.num_steps)
+                    };
+                    ui.add(
+                        ProgressBar::new(job.portion_done())
+                            .desired_width(240.)
+                            .text(text),
+                    );
+
+                    if ui
+                        .add_enabled(!job.cancelled(), Button::new("Cancel"))
+                        .on_hover_text(
+                            "End the build after the current step. Snapshots taken so far are kept",
+                        )
+                        .clicked()
+                    {
+                        job.cancel();
+                    }
+                }
+                None => {
+                    if ui
+                        .button(RichText::new("Build").color(Color32::GOLD))
+                        .clicked()
+                    {
+                        build_background(state, state.ui.force_model);
+                    }
+
+                    if ui
+                        .button("Preview")
+                        .on_hover_text(
+                            "Build a scaled-down run that finishes within the time budget, with \
+                            fewer bodies, and if needed, steps. Costs are estimated from a quick \
+                            benchmark",
+                        )
+                        .clicked()
+                    {
+                        let plan = preview::build_preview(
+                            state,
+                            state.ui.force_model,
+                            state.ui.preview_budget,
+                        );
+                        state.ui.notifications.info(format!(
+                            "Preview: {} bodies, {} steps. Estimated {:.0} s",
+                            plan.num_bodies_disk + plan.num_bodies_bulge,
+                            plan.num_timesteps,
+                            plan.time_estimate
+                        ));
+                    }
+                    ui.add(
+                        DragValue::new(&mut state.ui.preview_budget)
+                            .range(1.0..=3_600.)
+                            .suffix(" s"),
+                    )
+                    .on_hover_text("Wall-clock budget for previews");
+
+                    if ui
+                        .button("Preview IC")
+                        .on_hover_text(
+                            "Generate bodies, and show them with their rotation curve against the \
+                            observed one, without running the integration",
+                        )
+                        .clicked()
+                    {
+                        state.ui.show_ic_preview = true;
+                        state.ui.fit_view = true;
+                        refresh_bodies = true;
+                    }
+                    if state.ui.show_ic_preview {
+                        ui.checkbox(&mut state.ui.show_ic_preview, "")
+                            .on_hover_text("Show the preview over the first snapshot");
+
+                        if let Some(preview) = &state.ui.ic_preview {
+                            if ui
+                                .button("Σ plot")
+                                .on_hover_text(
+                                    "Plot the disk bodies' surface density against the input \
+                                    profile they're sampled from",
+                                )
+                                .clicked()
+                            {
+                                properties::plot_surface_density(
+                                    &state.config.plot_output,
+                                    &preview.surface_density,
+                                    &preview.surface_density_target,
+                                    &state.ui.galaxy_model.to_str(),
+                                );
+                                state
+                                    .ui
+                                    .notifications
+                                    .plots_saved(&state.config.plot_output, "Surface density plot");
+                            }
+                        }
+                    }
+                }
+            }
+
+            if ui
+                .checkbox(&mut state.ui.show_injection, "Inject")
+                .on_hover_text(
+                    "Add a body, e.g. an intruder mass, at the selected snapshot, and continue the \
+                    run from there",
+                )
+                .changed()
+            {
+                // Show or hide the marker.
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+            if ui
+                .checkbox(&mut state.ui.show_zoom, "Zoom")
+                .on_hover_text(
+                    "Resimulate a region at a finer timestep, from the selected snapshot, e.g. for \
+                    a core interaction",
+                )
+                .changed()
+            {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            if ui
+                .checkbox(&mut state.ui.show_inspector, "Inspect")
+                .on_hover_text(
+                    "Show a body's state and orbital elements: guiding radius, eccentricity, \
+                    vertical amplitude, and epicyclic phase. Click a body to inspect it",
+                )
+                .changed()
+            {
+                // Show or hide the highlight.
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            if ui.button("Ensemble").clicked() {
+                let report =
+                    ensemble::run_ensemble(state, state.ui.force_model, state.ui.ensemble_runs);
+                println!("\n{report}");
+                state.ui.notifications.success(format!(
+                    "Ensemble complete. Bar strength: {:.3} ± {:.3}",
+                    report.bar_mean, report.bar_std
+                ));
+                if !report.aborted.is_empty() {
+                    state.ui.notifications.warning(format!(
+                        "{} of {} ensemble runs aborted, and were excluded. See the log for details.",
+                        report.aborted.len(),
+                        report.num_runs
+                    ));
+                }
+            }
+            ui.add(
+                DragValue::new(&mut state.ui.ensemble_runs)
+                    .range(2..=100)
+                    .suffix(" runs"),
+            );
+
+            if ui
+                .button("Convergence")
+                .on_hover_text("Rerun over a range of dt and body counts, and plot the results")
+                .clicked()
+            {
+                let study = convergence::run_convergence(state, state.ui.force_model);
+                println!("\n{study}");
+                convergence::plot_convergence(&state.config.plot_output, &study);
+                let plots = state.config.plot_output.saved_msg(" Plots");
+                state.ui.notifications.success(format!(
+                    "Convergence study complete.{}",
+                    plots.unwrap_or_default()
+                ));
+                if !study.aborted.is_empty() {
+                    state.ui.notifications.warning(format!(
+                        "{} convergence runs aborted, and were excluded. See the log for details.",
+                        study.aborted.len()
+                    ));
+                }
+            }
+
+            if ui
+                .button("Compare MOND")
+                .on_hover_text(
+                    "Build with Newton, pairwise MOND, net MOND, QUMOND, and shells, and plot the \
+                    rotation curves",
+                )
+                .clicked()
+            {
+                let mond_fn = match state.ui.force_model {
+                    ForceModel::Mond(f) | ForceModel::MondNet(f) | ForceModel::Qumond(f) => f,
+                    _ => MondFn::Standard,
+                };
+                qumond::compare_models(state, mond_fn);
+                state
+                    .ui
+                    .notifications
+                    .plots_saved(&state.config.plot_output, "Force model comparison");
+            }
+
+            if ui
+                .button("Microlensing")
+                .on_hover_text(
+                    "Find microlensing events of background sources by the run's bodies, across \
+                    snapshots. Saves light curves, and an event table",
+                )
+                .clicked()
+            {
+                let lensing = ray_bending::find_events(state, state.ui.microlens.clone());
+                println!("\n{lensing}");
+
+                ray_bending::plot_light_curves(
+                    &state.config.plot_output,
+                    &lensing,
+                    &state.ui.run_labels.galaxy,
+                );
+                let path = Path::new(export::EXPORT_DIR).join("microlensing_events.csv");
+                match lensing.save_csv(&path) {
+                    Ok(()) => state.ui.notifications.success(format!(
+                        "{} microlensing events. Saved to {}",
+                        lensing.events.len(),
+                        path.display()
+                    )),
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error saving microlensing events: {e}")),
+                }
+            }
+            ui.add(
+                DragValue::new(&mut state.ui.microlens.num_sources)
+                    .range(1..=1_000_000)
+                    .suffix(" sources"),
+            );
+            ui.add(
+                DragValue::new(&mut state.ui.microlens.dist_source_behind)
+                    .speed(0.5)
+                    .range(0.1..=1e6)
+                    .prefix("behind: ")
+                    .suffix(" kpc"),
+            )
+            .on_hover_text("The source plane's distance behind the galaxy's center");
+
+            if ui
+                .button("Model χ²")
+                .on_hover_text(
+                    "Build with Newton, both MOND functions, and shells, and tabulate each final \
+                    rotation curve's reduced χ² against the observed one",
+                )
+                .clicked()
+            {
+                let comparison = model_compare::run_model_comparison(state);
+                println!("\n{comparison}");
+
+                let path = Path::new(export::EXPORT_DIR).join("model_chi_sq.csv");
+                match comparison.save_csv(&path) {
+                    Ok(()) => {
+                        let best = comparison
+                            .best()
+                            .map(|f| f.force_model.to_str())
+                            .unwrap_or_else(|| "none".to_owned());
+                        state.ui.notifications.success(format!(
+                            "Model comparison saved to {}. Best fit: {best}",
+                            path.display()
+                        ));
+                    }
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error saving the model comparison: {e}")),
+                }
+                for (force_model, e) in &comparison.aborted {
+                    state.ui.notifications.warning(format!(
+                        "Model comparison: {} aborted, and was excluded. {e}",
+                        force_model.to_str()
+                    ));
+                }
+            }
+
+            if ui
+                .button("Tree error")
+                .on_hover_text(
+                    "Compare tree accelerations of the current bodies against a softened direct sum",
+                )
+                .clicked()
+            {
+                let mond = match state.ui.force_model {
+                    ForceModel::Mond(mond_fn) => Some(mond_fn),
+                    _ => None,
+                };
+                let cfg = &state.config;
+
+                match accel::tree_force_error(
+                    &state.bodies,
+                    &cfg.bh_config,
+                    mond,
+                    cfg.softening_factor_sq,
+                ) {
+                    Some((rms, max)) => {
+                        println!("Tree force error. RMS: {rms:.2e} Max: {max:.2e}");
+                        state.ui.notifications.success(format!(
+                            "Tree force error. RMS: {rms:.2e} Max: {max:.2e}"
+                        ));
+                    }
+                    None => state.ui.notifications.error("No bodies to compare"),
+                }
+            }
+
+            if ui
+                .button("Components")
+                .on_hover_text(
+                    "Plot rotation curve contributions and densities of each component, e.g. disk \
+                    and bulge, and compare them to observed contributions",
+                )
+                .clicked()
+            {
+                properties::plot_components(
+                    &state.config.plot_output,
+                    &state.bodies,
+                    &state.ui.galaxy_descrip,
+                    state.config.softening_factor_sq,
+                    &state.ui.galaxy_model.to_str(),
+                );
+                state
+                    .ui
+                    .notifications
+                    .plots_saved(&state.config.plot_output, "Component plots");
+            }
+
+            if ui
+                .button("Probe field")
+                .on_hover_text(
+                    "Plot the circular velocity and potential along the X axis, from probes under \
+                    the selected force model",
+                )
+                .clicked()
+            {
+                let field = probe::Field::new(
+                    &state.bodies,
+                    &state.shells,
+                    &state.config,
+                    state.ui.force_model,
+                );
+                let r_max = state
+                    .bodies
+                    .iter()
+                    .map(|b| b.posit.magnitude())
+                    .fold(0., f64::max);
+
+                probe::plot_field_profile(
+                    &state.config.plot_output,
+                    &field,
+                    r_max,
+                    &state.ui.galaxy_model.to_str(),
+                );
+                state
+                    .ui
+                    .notifications
+                    .plots_saved(&state.config.plot_output, "Field plots");
+            }
+
+            if ui
+                .button("Shapiro delay")
+                .on_hover_text(
+                    "Compute light travel-time delays along parallel sight lines through the \
+                    potential, from probes under the selected force model, and under Newton",
+                )
+                .clicked()
+            {
+                let delays = shapiro::shapiro_delays(
+                    &state.bodies,
+                    &state.shells,
+                    &state.config,
+                    state.ui.force_model,
+                    &state.ui.shapiro,
+                    &state.ui.run_labels.galaxy,
+                );
+                println!("\n{delays}");
+
+                shapiro::plot_delays(
+                    &state.config.plot_output,
+                    &delays,
+                    &state.ui.run_labels.galaxy,
+                );
+                let path = Path::new(export::EXPORT_DIR).join("shapiro_delay.csv");
+                match delays.save_csv(&path) {
+                    Ok(()) => state
+                        .ui
+                        .notifications
+                        .success(format!("Shapiro delays saved to {}", path.display())),
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error saving Shapiro delays: {e}")),
+                }
+            }
+            ui.add(
+                DragValue::new(&mut state.ui.shapiro.num_lines)
+                    .range(2..=500)
+                    .suffix(" lines"),
+            );
+            ui.add(
+                DragValue::new(&mut state.ui.shapiro.b_max)
+                    .speed(0.5)
+                    .range(0.1..=1e5)
+                    .prefix("b max: ")
+                    .suffix(" kpc"),
+            )
+            .on_hover_text("Sight lines are offset from the galaxy's center by up to this");
+
+            if ui
+                .button("Frame dragging")
+                .on_hover_text(
+                    "Spin the most massive body, e.g. the central mass, and compare the nodal \
+                    precession of inclined test orbits in its gravitomagnetic field with \
+                    Lense–Thirring",
+                )
+                .clicked()
+            {
+                let central_id = state
+                    .bodies
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.mass.total_cmp(&b.mass))
+                    .map(|(i, _)| i);
+
+                if let Some(id) = central_id {
+                    let mut spins = vec![Vec3F64::new_zero(); state.bodies.len()];
+                    spins[id] = Vec3F64::new(0., 0., 1.)
+                        * gem::spin_kerr(state.bodies[id].mass, state.ui.frame_drag_spin, C_LIGHT);
+
+                    let sources = gem::GemSource::from_bodies(&state.bodies, &spins);
+                    let r_max = state
+                        .bodies
+                        .iter()
+                        .map(|b| (b.posit - state.bodies[id].posit).magnitude())
+                        .fold(0., f64::max);
+
+                    let profile = gem::frame_drag_profile(
+                        &sources[id],
+                        &sources,
+                        r_max,
+                        40,
+                        FRAC_PI_4,
+                        C_LIGHT,
+                        state.config.softening_factor_sq,
+                    );
+                    gem::plot_frame_dragging(
+                        &state.config.plot_output,
+                        &profile,
+                        &state.ui.run_labels.galaxy,
+                    );
+                    state
+                        .ui
+                        .notifications
+                        .plots_saved(&state.config.plot_output, "Frame dragging plot");
+                }
+            }
+            ui.add(
+                DragValue::new(&mut state.ui.frame_drag_spin)
+                    .speed(0.01)
+                    .range(0.0..=1.)
+                    .prefix("χ: "),
+            )
+            .on_hover_text("The central mass's dimensionless spin, as for a Kerr black hole");
+
+            if ui
+                .button("Lagrange points")
+                .on_hover_text(
+                    "Find the two dominant mass concentrations, e.g. a galaxy and satellite, and \
+                    plot their Lagrange points and co-rotating effective potential",
+                )
+                .clicked()
+            {
+                let field = probe::Field::new(
+                    &state.bodies,
+                    &state.shells,
+                    &state.config,
+                    state.ui.force_model,
+                );
+
+                if lagrange::plot_lagrange(
+                    &state.config.plot_output,
+                    &field,
+                    &state.bodies,
+                    &state.ui.galaxy_model.to_str(),
+                ) {
+                    state
+                        .ui
+                        .notifications
+                        .plots_saved(&state.config.plot_output, "Lagrange point plots");
+                } else {
+                    state
+                        .ui
+                        .notifications
+                        .warning("No galaxy and satellite pair found.");
+                }
+            }
+
+            if ui
+                .button("E–L_z")
+                .on_hover_text(
+                    "Plot bodies in energy and angular momentum space at the selected snapshot, \
+                    e.g. to find accreted or stripped populations, and resonant trapping",
+                )
+                .clicked()
+            {
+                let selected = state.ui.snapshot_selected;
+                let k = state.ui.elz_clusters;
+                let seed = state.config.seed;
+                let body_components = mem::take(&mut state.body_components);
+
+                let result = state.with_snapshot(selected, |snap, body_masses| {
+                    let bodies = snap.bodies(body_masses);
+                    let integrals = integrals::integrals(&bodies);
+                    let clusters = integrals::cluster(&bodies, &integrals, k, seed);
+                    let components: Vec<_> = if body_components.is_empty() {
+                        Vec::new()
+                    } else {
+                        (0..bodies.len())
+                            .map(|i| {
+                                let comp = body_components.get(snap.body_id(i));
+                                comp.copied().unwrap_or_default()
+                            })
+                            .collect()
+                    };
+                    (integrals, clusters, components, snap.time)
+                });
+                state.body_components = body_components;
+
+                if let Some((integrals, clusters, components, time)) = result {
+                    if !clusters.is_empty() {
+                        println!("\n{}", integrals::cluster_report(&clusters, &components));
+                    }
+                    integrals::plot_elz(
+                        &state.config.plot_output,
+                        &integrals,
+                        &clusters,
+                        &format!("{} t={time:.0} Myr", state.ui.galaxy_model.to_str()),
+                    );
+                    state
+                        .ui
+                        .notifications
+                        .plots_saved(&state.config.plot_output, "E–L_z plot");
+                }
+            }
+            ui.add(DragValue::new(&mut state.ui.elz_clusters).range(0..=12))
+                .on_hover_text("Clusters to group bodies into, by k-means. 0 for none");
+
+            if ui
+                .checkbox(&mut state.ui.show_rot_curve, "Rot curve")
+                .on_hover_text(
+                    "Plot the selected snapshot's rotation curve against the observed one, with \
+                    residuals and reduced χ², updating during playback",
+                )
+                .changed()
+            {
+                // Compute the curve.
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.checkbox(&mut state.ui.show_rot_model, "Rot model")
+                .on_hover_text(
+                    "Fit an analytic rotation curve, with disk, bulge, and halo or MOND, to the \
+                    observed one, without running a simulation",
+                );
+
+            if ui
+                .button("Asym drift")
+                .on_hover_text(
+                    "Plot the asymmetric drift correction from disk dispersions, and compare \
+                    simulated and observed curves with it applied",
+                )
+                .clicked()
+            {
+                asym_drift::plot_drift(
+                    &state.config.plot_output,
+                    &state.bodies,
+                    &state.ui.galaxy_descrip,
+                    state.config.softening_factor_sq,
+                    &state.ui.galaxy_model.to_str(),
+                );
+                state
+                    .ui
+                    .notifications
+                    .plots_saved(&state.config.plot_output, "Asymmetric drift plots");
+            }
+
+            if ui
+                .button("Mass flux")
+                .on_hover_text("Plot net radial mass flux through a set of radii over the run")
+                .clicked()
+            {
+                if let Some(history) = mass_flux::mass_flux_history(state) {
+                    println!("\n{history}");
+                    mass_flux::plot_mass_flux(&state.config.plot_output, &history);
+                    state.ui.notifications.success("Mass flux plotted");
+                }
+            }
+
+            if ui
+                .button("Lagrangian radii")
+                .on_hover_text(
+                    "Plot the radii enclosing 10, 25, 50, 75, and 90% of the disk's mass over the \
+                    run, e.g. to find core collapse or disk spreading",
+                )
+                .clicked()
+            {
+                match lagrangian_radii::lagrangian_radii_history(state) {
+                    Some(history) => {
+                        println!("\n{history}");
+                        lagrangian_radii::plot_lagrangian_radii(
+                            &state.config.plot_output,
+                            &history,
+                            &state.ui.run_labels.galaxy,
+                        );
+                        state
+                            .ui
+                            .notifications
+                            .plots_saved(&state.config.plot_output, "Lagrangian radii");
+                    }
+                    None => state
+                        .ui
+                        .notifications
+                        .warning("No snapshots with all bodies to measure."),
+                }
+            }
+
+            if ui
+                .button("Conservation")
+                .on_hover_text(
+                    "Plot energy and angular momentum drift over the run. Drift comes from the \
+                    integrator, timestep, and softening, or a non-conservative force model",
+                )
+                .clicked()
+            {
+                if state.diagnostics.len() < 2 {
+                    state.ui.notifications.warning(
+                        "Not enough conservation samples; build with a diagnostic interval set",
+                    );
+                } else {
+                    diagnostics::plot_drift(&state.config.plot_output, &state.diagnostics);
+                    state.ui.notifications.success("Conservation plotted");
+                }
+            }
+            ui.label("every")
+                .on_hover_text("Sample energy and angular momentum every this many steps");
+            ui.add(DragValue::new(&mut state.config.diagnostics_ratio).range(0..=100_000));
+
+            if ui
+                .button("Tidal radius")
+                .on_hover_text(
+                    "Estimate a satellite's tidal radius in its host's field at each snapshot. \
+                    Plots it and the bound mass over the run, and shows it in the viewer",
+                )
+                .clicked()
+            {
+                let history = tidal::tidal_history(state, state.ui.force_model);
+                println!("\n{history}");
+                tidal::plot_tidal_history(&state.config.plot_output, &history);
+
+                if history.estimates.iter().any(Option::is_some) {
+                    state.ui.tidal_history = Some(history);
+                    state
+                        .ui
+                        .notifications
+                        .success("Tidal radius plotted, and shown in the viewer");
+                } else {
+                    state.ui.tidal_history = None;
+                    state
+                        .ui
+                        .notifications
+                        .warning("No satellite tidal radius found.");
+                }
+
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            if ui
+                .button("Report")
+                .on_hover_text("Write an HTML summary of the most recent build")
+                .clicked()
+            {
+                match report::write_report(state) {
+                    Ok(path) => state
+                        .ui
+                        .notifications
+                        .success(format!("Saved report to {}", path.display())),
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error writing the report: {e}")),
+                }
+            }
+
+            ui.add_space(COL_SPACING);
+            let mut fixed_seed = state.config.seed.is_some();
+            if ui.checkbox(&mut fixed_seed, "Seed").changed() {
+                state.config.seed = fixed_seed.then_some(0);
+                refresh_bodies = true;
+            }
+            if let Some(seed) = &mut state.config.seed {
+                let resp = ui.add(DragValue::new(seed));
+                if resp.drag_stopped() || resp.lost_focus() {
+                    refresh_bodies = true;
+                }
+            }
+
+            ui.add_space(COL_SPACING);
+
+            ui.radio_value(&mut state.ui.force_model, ForceModel::Newton, "Newton");
+            ui.radio_value(
+                &mut state.ui.force_model,
+                ForceModel::Mond(MondFn::Simple),
+                "MOND simple",
+            );
+            ui.radio_value(
+                &mut state.ui.force_model,
+                ForceModel::Mond(MondFn::Standard),
+                "MOND",
+            );
+            ui.radio_value(
+                &mut state.ui.force_model,
+                ForceModel::MondNet(MondFn::Simple),
+                "MOND net simple",
+            )
+            .on_hover_text("MOND applied to the net Newtonian acceleration, vice per source");
+            ui.radio_value(
+                &mut state.ui.force_model,
+                ForceModel::MondNet(MondFn::Standard),
+                "MOND net",
+            )
+            .on_hover_text("MOND applied to the net Newtonian acceleration, vice per source");
+            ui.radio_value(
+                &mut state.ui.force_model,
+                ForceModel::Qumond(MondFn::Standard),
+                "QUMOND",
+            )
+            .on_hover_text("MOND from the field equations, solved on a mesh");
+            ui.radio_value(
+                &mut state.ui.force_model,
+                ForceModel::GaussShells,
+                "Causal shells",
+            );
+
+            let is_composite = matches!(state.ui.force_model, ForceModel::Composite(_));
+            if ui.radio(is_composite, "Composite").clicked() && !is_composite {
+                state.ui.force_model =
+                    ForceModel::Composite(ForceComposition::ShellsWithMond(MondFn::Simple));
+            }
+
+            if let ForceModel::Composite(comp) = &mut state.ui.force_model {
+                ComboBox::from_id_salt(10)
+                    .width(110.)
+                    .selected_text(comp.to_str())
+                    .show_ui(ui, |ui| {
+                        for option in [
+                            ForceComposition::ShellsWithMond(MondFn::Simple),
+                            ForceComposition::NewtonPlusShells { weight: 1. },
+                        ] {
+                            let selected = comp.to_str() == option.to_str();
+                            if ui.selectable_label(selected, option.to_str()).clicked() && !selected
+                            {
+                                *comp = option;
+                            }
+                        }
+                    });
+
+                match comp {
+                    ForceComposition::ShellsWithMond(mond_fn) => {
+                        ui.radio_value(mond_fn, MondFn::Simple, "Simple");
+                        ui.radio_value(mond_fn, MondFn::Standard, "Standard");
+                    }
+                    ForceComposition::NewtonPlusShells { weight } => {
+                        ui.label("weight:");
+                        ui.add(DragValue::new(weight).speed(0.01));
+                    }
+                }
+            }
+
+            if state.ui.force_model.uses_shells() {
+                let aniso = &mut state.config.shell_anisotropy;
+                let k = aniso.strength_mut().map(|k| *k).unwrap_or(1.);
+
+                ComboBox::from_id_salt(6)
+                    .width(80.)
+                    .selected_text(aniso.to_str())
+                    .show_ui(ui, |ui| {
+                        for option in [
+                            ShellAnisotropy::Isotropic,
+                            ShellAnisotropy::VelocityDipole(k),
+                            ShellAnisotropy::AccelDipole(k),
+                        ] {
+                            let selected = aniso.to_str() == option.to_str();
+                            if ui.selectable_label(selected, option.to_str()).clicked() {
+                                *aniso = option;
+                            }
+                        }
+                    });
+
+                if let Some(k) = aniso.strength_mut() {
+                    ui.label("k:");
+                    ui.add(DragValue::new(k).speed(0.01).range(-10. ..=10.));
+                }
+
+                let speed = &mut state.config.shell_speed;
+                ui.label("C(r):");
+                ComboBox::from_id_salt(7)
+                    .width(80.)
+                    .selected_text(speed.law.to_str())
+                    .show_ui(ui, |ui| {
+                        let selected = matches!(speed.law, ShellSpeedLaw::Constant);
+                        if ui.selectable_label(selected, "Constant").clicked() {
+                            speed.law = ShellSpeedLaw::Constant;
+                        }
+
+                        let selected = matches!(speed.law, ShellSpeedLaw::Falloff { .. });
+                        if ui.selectable_label(selected, "1/(1+r/r0)").clicked() && !selected {
+                            speed.law = ShellSpeedLaw::Falloff { r0: 10. };
+                        }
+
+                        if ui.button("Table from CSV...").clicked() {
+                            if let Some(path) =
+                                FileDialog::new().add_filter("CSV", &["csv"]).pick_file()
+                            {
+                                match fs::read_to_string(&path)
+                                    .ok()
+                                    .and_then(|t| ShellSpeedLaw::table_from_csv(&t))
+                                {
+                                    Some(law) => speed.law = law,
+                                    None => state.ui.notifications.error(format!(
+                                        "Unable to read a speed table from {}",
+                                        file_name(&path)
+                                    )),
+                                }
+                            }
+                            ui.close_menu();
+                        }
+                    });
+
+                if let ShellSpeedLaw::Falloff { r0 } = &mut speed.law {
+                    ui.label("r0:");
+                    ui.add(DragValue::new(r0).speed(0.1).range(0.01..=1_000.));
+                }
+
+                if speed.law != ShellSpeedLaw::Constant {
+                    ui.checkbox(&mut speed.normalize_amplitude, "Norm amp");
+                }
+
+                let geometry = &mut state.config.shell_geometry;
+                let thickness = match geometry {
+                    ShellGeometry::Ring { thickness } => *thickness,
+                    ShellGeometry::Sphere => 0.5,
+                };
+                ComboBox::from_id_salt(9)
+                    .width(60.)
+                    .selected_text(geometry.to_str())
+                    .show_ui(ui, |ui| {
+                        for option in [ShellGeometry::Sphere, ShellGeometry::Ring { thickness }] {
+                            let selected = geometry.to_str() == option.to_str();
+                            if ui.selectable_label(selected, option.to_str()).clicked() {
+                                *geometry = option;
+                            }
+                        }
+                    });
+
+                if let ShellGeometry::Ring { thickness } = geometry {
+                    ui.label("h:");
+                    ui.add(DragValue::new(thickness).speed(0.01).range(0.01..=100.));
+                }
+
+                if ui
+                    .button("Compare geometries")
+                    .on_hover_text("Build with spheres, then rings, and plot both rotation curves")
+                    .clicked()
+                {
+                    shell_geometry::compare_geometries(state, thickness);
+                    state
+                        .ui
+                        .notifications
+                        .plots_saved(&state.config.plot_output, "Geometry comparison");
+                }
+
+                #[cfg(feature = "shell_interaction")]
+                {
+                    use crate::shell_interaction::{CenterDrift, InteractionLaw, OverlapAmplitude};
+
+                    ui.label("Interaction:");
+                    let law = &mut state.shell_interaction;
+                    ComboBox::from_id_salt(8)
+                        .width(110.)
+                        .selected_text(law.to_str())
+                        .show_ui(ui, |ui| {
+                            if ui
+                                .selectable_label(matches!(law, InteractionLaw::Off), "Off")
+                                .clicked()
+                            {
+                                *law = InteractionLaw::Off;
+                            }
+                            if ui
+                                .selectable_label(
+                                    matches!(law, InteractionLaw::OverlapAmplitude(_)),
+                                    "Overlap amplitude",
+                                )
+                                .clicked()
+                            {
+                                *law = InteractionLaw::OverlapAmplitude(OverlapAmplitude {
+                                    rate: -0.1,
+                                });
+                            }
+                            if ui
+                                .selectable_label(
+                                    matches!(law, InteractionLaw::CenterDrift(_)),
+                                    "Center drift",
+                                )
+                                .clicked()
+                            {
+                                *law = InteractionLaw::CenterDrift(CenterDrift { rate: 0.1 });
+                            }
+                        });
+
+                    if let Some(rate) = law.rate_mut() {
+                        ui.label("rate:");
+                        ui.add(DragValue::new(rate).speed(0.01));
+                    }
+                }
+
+                if ui
+                    .button("Force law")
+                    .on_hover_text("Plot the effective force law from a single source, vs Newton")
+                    .clicked()
+                {
+                    match grav_shell::effective_force_law(
+                        &state.config.shell_speed,
+                        state.config.shell_geometry,
+                        state.config.dt,
+                        state.config.shell_creation_ratio,
+                        state.config.shell_gauss_c(),
+                        MAX_SHELL_R / 2.,
+                        40,
+                    ) {
+                        Ok(data) => {
+                            for (r, ratio) in &data {
+                                println!("r: {r:.2} a / a_Newton: {ratio:.4}");
+                            }
+                            plot(
+                                &state.config.plot_output,
+                                &data,
+                                "r (kpc)",
+                                "a / a_Newton",
+                                "Effective shell force law",
+                                "shell_force_law",
+                            );
+                        }
+                        Err(e) => state.ui.notifications.error(format!("Force law: {e}")),
+                    }
+                }
+
+                if ui
+                    .button("Fit force law")
+                    .on_hover_text(
+                        "Run a single source with probe rings to steady state, and fit the \
+                    acceleration to a power law and MOND",
+                    )
+                    .clicked()
+                {
+                    match force_law::extract_force_law(&state.config, 30) {
+                        Ok(fit) => {
+                            println!("\n{fit}");
+                            force_law::plot_force_law(&state.config.plot_output, &fit);
+                            let plot = state.config.plot_output.saved_msg(" Plot");
+                            state.ui.notifications.success(format!(
+                                "Force law: a ∝ r^{:.3}.{}",
+                                fit.exponent,
+                                plot.unwrap_or_default()
+                            ));
+                        }
+                        Err(e) => state.ui.notifications.error(format!("Force law fit: {e}")),
+                    }
+                }
+            }
+
+            ui.add_space(COL_SPACING);
+
+            let prev_model = state.ui.galaxy_model.clone();
+            ComboBox::from_id_salt(0)
+                .width(120.)
+                .selected_text(state.ui.galaxy_model.to_str())
+                .show_ui(ui, |ui| {
+                    for model in state.ui.galaxy_catalog.models() {
+                        ui.selectable_value(
+                            &mut state.ui.galaxy_model,
+                            model.clone(),
+                            model.to_str(),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text(format!(
+                    "Built-in galaxies, and SPARC rotation curve files (e.g. NGC3198_rotmod.dat) \
+                    found in `{}` at startup",
+                    sparc::SPARC_DIR
+                ));
+            if prev_model != state.ui.galaxy_model {
+                state.ui.galaxy_descrip = state.ui.galaxy_model.descrip();
+                state.ui.descrip_edit = None;
+                state.ui.fit_view = true;
+                refresh_bodies = true;
+            }
+
+            if ui
+                .button("Photometry")
+                .on_hover_text("Load a SPARC .sfb or .dens surface photometry file for this galaxy")
+                .clicked()
+            {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("SPARC photometry", &["sfb", "dens"])
+                    .pick_file()
+                {
+                    let desc = &mut state.ui.galaxy_descrip;
+                    match sparc::load_photometry(&path, desc.dist_from_earth) {
+                        Ok(phot) => {
+                            phot.apply(desc);
+                            state.ui.descrip_edit = None;
+                            state.ui.notifications.success(format!(
+                                "Loaded photometry from {}: {} disk, {} bulge points",
+                                file_name(&path),
+                                phot.disk.len(),
+                                phot.bulge.len()
+                            ));
+                        }
+                        Err(e) => state.ui.notifications.error(format!(
+                            "Unable to load photometry from {}: {e}",
+                            file_name(&path)
+                        )),
+                    }
+                }
+            }
+
+            ui.checkbox(&mut state.ui.show_descrip_editor, "Edit")
+                .on_hover_text(
+                    "Edit the galaxy's masses, eccentricity, halo, M/L, and disk thickness",
+                );
+
+            let mut cluster_mode = state.ui.cluster.is_some();
+            if ui
+                .checkbox(&mut cluster_mode, "Cluster")
+                .on_hover_text(
+                    "Build a cluster of scaled copies of this galaxy, with masses from a Schechter \
+                    function, and virial velocities",
+                )
+                .changed()
+            {
+                state.ui.cluster = cluster_mode.then(Default::default);
+                state.ui.fit_view = true;
+                refresh_bodies = true;
+            }
+
+            ui.add_space(COL_SPACING);
+
+            ui.checkbox(&mut state.ui.add_halo, "Add halo");
+
+            ui.add_space(COL_SPACING);
+
+            ui.label("Sampling:");
+            let prev_sampling = state.config.body_sampling;
+            ComboBox::from_id_salt(2)
+                .width(100.)
+                .selected_text(state.config.body_sampling.to_str())
+                .show_ui(ui, |ui| {
+                    for sampling in [BodySampling::Annuli, BodySampling::InverseCdf] {
+                        ui.selectable_value(
+                            &mut state.config.body_sampling,
+                            sampling,
+                            sampling.to_str(),
+                        );
+                    }
+                });
+            if prev_sampling != state.config.body_sampling {
+                refresh_bodies = true;
+            }
+
+            ui.add_space(COL_SPACING);
+
+            ui.label("Core:");
+            let prev_central = state.config.central_model;
+            ComboBox::from_id_salt(1)
+                .width(100.)
+                .selected_text(state.config.central_model.to_str())
+                .show_ui(ui, |ui| {
+                    for model in [
+                        CentralModel::PointMass,
+                        CentralModel::PlummerCore,
+                        CentralModel::Resolved,
+                        CentralModel::Omitted,
+                    ] {
+                        ui.selectable_value(&mut state.config.central_model, model, model.to_str());
+                    }
+                });
+            if prev_central != state.config.central_model {
+                refresh_bodies = true;
+            }
+
+            if state.config.central_model == CentralModel::PlummerCore {
+                int_field(
+                    &mut state.config.num_bodies_core,
+                    "bodies core",
+                    &mut refresh_bodies,
+                    ui,
+                );
+            }
+
+            ui.add_space(COL_SPACING);
+
+            ui.label("v init:");
+            let prev_init = state.config.velocity_init;
+            ComboBox::from_id_salt(4)
+                .width(110.)
+                .selected_text(state.config.velocity_init.to_str())
+                .show_ui(ui, |ui| {
+                    for init in [VelocityInit::Observed, VelocityInit::SelfConsistent] {
+                        ui.selectable_value(&mut state.config.velocity_init, init, init.to_str());
+                    }
+                });
+            if prev_init != state.config.velocity_init {
+                refresh_bodies = true;
+            }
+
+            ui.label("v check:");
+            let prev_check = state.config.velocity_check;
+            ComboBox::from_id_salt(3)
+                .width(80.)
+                .selected_text(state.config.velocity_check.to_str())
+                .show_ui(ui, |ui| {
+                    for check in [
+                        VelocityCheck::Off,
+                        VelocityCheck::Report,
+                        VelocityCheck::Correct,
+                    ] {
+                        ui.selectable_value(
+                            &mut state.config.velocity_check,
+                            check,
+                            check.to_str(),
+                        );
+                    }
+                });
+            if prev_check != state.config.velocity_check {
+                refresh_bodies = true;
+            }
+
+            if state.config.body_sampling == BodySampling::Annuli {
+                ui.add_space(COL_SPACING);
+
+                if ui
+                    .checkbox(&mut state.config.refine_rings, "Refine rings")
+                    .changed()
+                {
+                    refresh_bodies = true;
+                }
+            }
+
+            if state.config.body_sampling == BodySampling::Annuli && state.config.refine_rings {
+                let refinement = &mut state.config.ring_refinement;
+                ui.label("Max mass portion:");
+                let resp = ui.add(
+                    DragValue::new(&mut refinement.max_mass_portion)
+                        .speed(0.001)
+                        .range(0.001..=1.),
+                );
+                if resp.drag_stopped() || resp.lost_focus() {
+                    refresh_bodies = true;
+                }
+
+                ui.label("Max Δρ:");
+                let resp = ui.add(
+                    DragValue::new(&mut refinement.max_density_change)
+                        .speed(0.01)
+                        .range(0.01..=1.),
+                );
+                if resp.drag_stopped() || resp.lost_focus() {
+                    refresh_bodies = true;
+                }
+            }
+        });
+
+        ui.add_space(ROW_SPACING);
+
+        ui.horizontal(|ui| {
+            ui.label("Integrator:");
+            for kind in [
+                IntegratorKind::Rk4,
+                IntegratorKind::Leapfrog,
+                IntegratorKind::Yoshida4,
+                IntegratorKind::Hermite4,
+            ] {
+                ui.radio_value(&mut state.config.integrator, kind, kind.to_str());
+            }
+
+            if state.config.integrator == IntegratorKind::Leapfrog {
+                let mut use_block = state.config.block_timesteps.is_some();
+                if ui
+                    .checkbox(&mut use_block, "Block dt")
+                    .on_hover_text(
+                        "Step each body at a power-of-two fraction of dt, set by its acceleration, \
+                        so the dense core takes small steps, and the outer disk large ones",
+                    )
+                    .changed()
+                {
+                    state.config.block_timesteps = use_block.then(Default::default);
+                }
+
+                if let Some(block) = &mut state.config.block_timesteps {
+                    ui.label("Levels:");
+                    ui.add(DragValue::new(&mut block.max_level).range(1..=12))
+                        .on_hover_text("The shortest step is dt / 2^levels");
+                    ui.label("η:");
+                    ui.add(
+                        DragValue::new(&mut block.η)
+                            .speed(0.001)
+                            .range(0.001..=1.),
+                    )
+                    .on_hover_text("Accuracy parameter. Lower is more accurate, and slower");
+                }
+            }
+
+            ui.add_space(COL_SPACING);
+            ui.label("dt:");
+            ui.add_sized(
+                [60., Ui::available_height(ui)],
+                egui::TextEdit::singleline(&mut state.ui.dt_input),
+            );
+            if ui.button("Save dt").clicked() {
+                if let Ok(v) = state.ui.dt_input.parse() {
+                    state.config.dt = v;
+                }
+            }
+
+            ui.label("Steps (x1000):");
+            let mut val = (state.config.num_timesteps / 1_000).to_string();
+            if ui
+                .add_sized(
+                    [40., Ui::available_height(ui)],
+                    egui::TextEdit::singleline(&mut val),
+                )
+                .changed()
+            {
+                if let Ok(v) = val.parse::<usize>() {
+                    state.config.num_timesteps = v * 1_000;
+                }
+            }
+
+            ui.label("θ:");
+            ui.add_sized(
+                [40., Ui::available_height(ui)],
+                egui::TextEdit::singleline(&mut state.ui.θ_input),
+            );
+
+            if ui.button("Save θ").clicked() {
+                if let Ok(v) = state.ui.θ_input.parse() {
+                    state.config.bh_config.θ = v;
+                }
+            }
+
+            ui.label("v scaler:");
+            ui.add_sized(
+                [36., Ui::available_height(ui)],
+                egui::TextEdit::singleline(&mut state.ui.v_scaler_input),
+            );
+            if ui.button("Save v scaler").clicked() {
+                if let Ok(v) = state.ui.v_scaler_input.parse() {
+                    state.config.v_scaler = v;
+                    refresh_bodies = true; // For updated v.
+                }
+            }
+
+            int_field(
+                &mut state.config.num_bodies_disk,
+                "bodies disk",
+                &mut refresh_bodies,
+                ui,
+            );
+
+            int_field(
+                &mut state.config.num_bodies_bulge,
+                "bodies bulge",
+                &mut refresh_bodies,
+                ui,
+            );
+
+            if let Some(t_relax) = state.ui.relaxation_time {
+                let run_len = state.config.num_timesteps as f64 * state.config.dt;
+                let text = format!("t_relax: {t_relax:.1} Myr");
+
+                if run_len > t_relax {
+                    ui.label(RichText::new(format!("⚠ {text}")).color(Color32::ORANGE))
+                        .on_hover_text(format!(
+                            "The run ({run_len:.1} Myr) is longer than the estimated two-body \
+                             relaxation time; discreteness may dominate the evolution. \
+                             Consider more bodies, or a shorter run."
+                        ));
+                } else {
+                    ui.label(text).on_hover_text(format!(
+                        "Estimated two-body relaxation time. Run length: {run_len:.1} Myr"
+                    ));
+                }
+            }
+
+            // todo: Remove A/R now that cube is in snapshots.
+            if ui.button("Tree").clicked() {
+                // todo: Of current snapshot.
+                let bb = Cube::from_bodies(&state.bodies, BOUNDING_BOX_PAD, true).unwrap();
+                //
+                // for body in &state.bodies {
+                //     println!("Body: {:.4?}", body);
+                // }
+
+                let tree = Tree::new(&state.bodies, &bb, &state.config.bh_config);
+
+                // todo: Subdivide the tree based on a target body here A/R.
+
+                scene.entities = scene
+                    .entities
+                    .clone()
+                    .into_iter()
+                    .filter(|s| s.mesh != 1)
+                    .collect();
+
+                let leaves =
+                    tree.leaves(lin_alg::f64::Vec3::new(2., 2., 0.), &state.config.bh_config);
+                println!(
+                    "Leaf count: {}. Full tree len: {}",
+                    leaves.len(),
+                    tree.nodes.len()
+                );
+
+                // for leaf in &leaves { // todo te p
+                //     println!("Leaf: {:?}", leaf);
+                // }
+
+                for leaf in leaves {
+                    let c = leaf.bounding_box.center;
+                    let posit =
+                        // Vec3::new(c.x as f32, c.y as f32, c.z as f32) + Vec3::new(0., 0., 1.5);
+                        Vec3::new(c.x as f32, c.y as f32, c.z as f32);
+
+                    scene.entities.push(Entity::new(
+                        1,
+                        posit * state.ui.world_scale,
+                        Quaternion::new_identity(),
+                        leaf.bounding_box.width as f32
+                            * TREE_CUBE_SCALE_FACTOR
+                            * state.ui.world_scale,
+                        TREE_COLOR,
+                        TREE_SHINYNESS,
+                    ));
+                }
+
+                engine_updates.entities = true;
+            }
+
+            ui.add_space(COL_SPACING);
+
+            ui.checkbox(&mut state.config.skip_tree, "Skip tree")
+                .on_hover_text(
+                    "Compute forces by direct sum. With the GPU, direct sums for Newton and net \
+                    MOND run on it.",
+                );
+
+            let mut use_scf = state.config.scf.is_some();
+            if ui
+                .checkbox(&mut use_scf, "SCF")
+                .on_hover_text(
+                    "Compute forces from a basis function expansion instead of the tree. For \
+                    near-equilibrium spheroids, e.g. halos.",
+                )
+                .changed()
+            {
+                state.config.scf = use_scf.then(Default::default);
+            }
+            if let Some(scf) = &mut state.config.scf {
+                ui.label("n:");
+                ui.add(DragValue::new(&mut scf.n_max).range(0..=30));
+                ui.label("l:");
+                ui.add(DragValue::new(&mut scf.l_max).range(0..=12));
+            }
+
+            ui.checkbox(&mut state.ui.draw_tree, "Draw tree");
+
+            ui.add_space(COL_SPACING);
+            ui.label("Scale:");
+            if ui
+                .add(
+                    Slider::new(&mut state.ui.world_scale, 0.01..=100.)
+                        .logarithmic(true)
+                        .suffix(" /kpc"),
+                )
+                .on_hover_text("Scene units per kpc")
+                .changed()
+            {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+            if ui
+                .button("Fit")
+                .on_hover_text("Scale and frame the view to fit the bodies")
+                .clicked()
+            {
+                fit_view(state, scene, state.ui.snapshot_selected);
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+                engine_updates.camera = true;
+            }
+            ui.checkbox(&mut state.ui.show_scale_overlay, "Scale bar");
+            ui.checkbox(&mut state.ui.show_lighting, "Lighting")
+                .on_hover_text("Show lighting controls");
+            ui.checkbox(&mut state.ui.show_camera_path, "Camera path")
+                .on_hover_text("Record and play back camera flythroughs");
+            ui.checkbox(&mut state.ui.show_hud, "HUD")
+                .on_hover_text("Show the galaxy, force model, time, and body count over the view");
+
+            let grid_resp = ui
+                .checkbox(&mut state.ui.show_grid, "Grid")
+                .on_hover_text("A 1 kpc grid in the galactic plane. Rings at 5, 10, and 20 kpc");
+            let axes_resp = ui.checkbox(&mut state.ui.show_axes, "Axes");
+            if grid_resp.changed() || axes_resp.changed() {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            ui.label("Stereo:");
+            let stereo_prev = state.ui.stereo_mode;
+            ComboBox::from_id_salt(12)
+                .width(100.)
+                .selected_text(state.ui.stereo_mode.to_str())
+                .show_ui(ui, |ui| {
+                    for mode in [StereoMode::Off, StereoMode::SideBySide, StereoMode::Anaglyph] {
+                        ui.selectable_value(&mut state.ui.stereo_mode, mode, mode.to_str());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Render a view for each eye: Side by side for parallel viewing or VR viewers, \
+                    or anaglyph for red-cyan glasses",
+                );
+
+            let mut angle_changed = false;
+            if state.ui.stereo_mode != StereoMode::Off {
+                angle_changed = ui
+                    .add(
+                        DragValue::new(&mut state.ui.stereo_angle)
+                            .speed(0.1)
+                            .range(0.1..=20.)
+                            .suffix("°"),
+                    )
+                    .on_hover_text("Angle between the eyes, seen from the focal point")
+                    .changed();
+            }
+            if stereo_prev != state.ui.stereo_mode || angle_changed {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            let color_mode_prev = state.ui.color_mode;
+            ui.label("Color:");
+            ComboBox::from_id_salt(14)
+                .width(90.)
+                .selected_text(state.ui.color_mode.to_str())
+                .show_ui(ui, |ui| {
+                    for mode in [
+                        BodyColorMode::Plain,
+                        BodyColorMode::Mass,
+                        BodyColorMode::Speed,
+                        BodyColorMode::Accel,
+                        BodyColorMode::Population,
+                    ] {
+                        ui.selectable_value(&mut state.ui.color_mode, mode, mode.to_str());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Color bodies by mass or acceleration (log scale), speed, or the galaxy \
+                    component they were generated for. Dark purple is low; yellow is high.",
+                );
+            if state.ui.color_mode != color_mode_prev {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            let mut trails_changed = ui
+                .checkbox(&mut state.ui.show_trails, "Trails")
+                .on_hover_text(
+                    "Draw fading trails through each body's positions at recent snapshots, to \
+                    show orbits and the evolution of structure during playback",
+                )
+                .changed();
+            if state.ui.show_trails {
+                trails_changed |= ui
+                    .add(DragValue::new(&mut state.ui.trail_len).range(2..=200))
+                    .on_hover_text("Snapshots per trail")
+                    .changed();
+            }
+            if trails_changed {
+                if !state.ui.show_trails {
+                    state.ui.trail_history = Default::default();
+                }
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            let mut shells_changed = ui
+                .checkbox(&mut state.ui.show_shells, "Shells")
+                .on_hover_text(
+                    "Draw gravity shells as translucent spheres, colored by radius, to show their \
+                    propagation during playback",
+                )
+                .changed();
+            if state.ui.show_shells {
+                shells_changed |= ui
+                    .add(Slider::new(&mut state.ui.shell_opacity, 0.005..=0.5).logarithmic(true))
+                    .on_hover_text("Shell opacity")
+                    .changed();
+            }
+            if shells_changed {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            let mut doppler_changed = ui
+                .checkbox(&mut state.ui.doppler_color, "Doppler")
+                .on_hover_text(
+                    "Color bodies by line-of-sight velocity from the camera: Blue approaching, \
+                    red receding",
+                )
+                .changed();
+            if state.ui.doppler_color {
+                doppler_changed |= ui
+                    .add(
+                        DragValue::new(&mut state.ui.doppler_v_max)
+                            .speed(1.)
+                            .range(1.0..=2_000.)
+                            .suffix(" km/s"),
+                    )
+                    .on_hover_text("Colors saturate at this velocity")
+                    .changed();
+            }
+            if doppler_changed {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            if ui
+                .checkbox(&mut state.ui.retarded_view, "Light travel")
+                .on_hover_text(
+                    "Draw each body where the camera would see it, accounting for light travel \
+                    time, vice where it is at the snapshot's time",
+                )
+                .changed()
+            {
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            let ride_prev = state.ui.ride_body;
+            let mut riding = state.ui.ride_body.is_some();
+            if ui
+                .checkbox(&mut riding, "Ride body")
+                .on_hover_text("Attach the camera to a body, to view playback from inside the galaxy")
+                .changed()
+            {
+                state.ui.ride_body = riding.then_some(0);
+                if !riding {
+                    reset_camera(&mut scene.camera);
+                    engine_updates.camera = true;
+                }
+            }
+            if let Some(body) = &mut state.ui.ride_body {
+                let num_bodies = state.body_masses.len().max(1);
+                ui.add(DragValue::new(body).range(0..=num_bodies - 1));
+                ui.checkbox(&mut state.ui.ride_vel_frame, "Velocity frame")
+                    .on_hover_text("Look along the body's direction of motion");
+            }
+            if ride_prev != state.ui.ride_body {
+                state.ui.follow_body = None;
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+
+            ui.add_space(COL_SPACING);
+            let mut following = state.ui.follow_body.is_some();
+            if ui
+                .checkbox(&mut following, "Follow body")
+                .on_hover_text(
+                    "Keep a body at the center of the view during playback. Starts with the \
+                    inspected body",
+                )
+                .changed()
+            {
+                state.ui.follow_body = following.then_some(state.ui.inspector.body);
+                state.ui.follow_target = None;
+                if following {
+                    state.ui.ride_body = None;
+                    state.ui.path_flying = false;
+                }
+                show_snapshot(state, scene, state.ui.snapshot_selected, &mut engine_updates);
+            }
+            if let Some(body) = &mut state.ui.follow_body {
+                let num_bodies = state.body_masses.len().max(1);
+                if ui
+                    .add(DragValue::new(body).range(0..=num_bodies - 1))
+                    .changed()
+                {
+                    state.ui.follow_target = None;
+                }
+            }
+
+            #[cfg(feature = "cuda")]
+            {
+                let mut fixed = state.config.gpu.block_size.is_some();
+                if ui
+                    .checkbox(&mut fixed, "Block size")
+                    .on_hover_text(
+                        "Override the threads per block for GPU kernels. If unset, we choose \
+                        using the CUDA occupancy API.",
+                    )
+                    .changed()
+                {
+                    state.config.gpu.block_size = fixed.then_some(256);
+                }
+                if let Some(size) = &mut state.config.gpu.block_size {
+                    ui.add(DragValue::new(size).speed(32).range(32..=1024));
+                }
+
+                let mut fixed = state.config.gpu.tile_size.is_some();
+                if ui
+                    .checkbox(&mut fixed, "Tile size")
+                    .on_hover_text(
+                        "Override the max bodies per GPU tile. If unset, tiles are sized to fit in \
+                        free device memory.",
+                    )
+                    .changed()
+                {
+                    state.config.gpu.tile_size = fixed.then_some(1 << 20);
+                }
+                if let Some(size) = &mut state.config.gpu.tile_size {
+                    ui.add(DragValue::new(size).speed(1_000).range(1_024..=usize::MAX));
+                }
+
+                let precision = &mut state.config.gpu.precision;
+                ComboBox::from_id_salt(11)
+                    .width(100.)
+                    .selected_text(precision.to_str())
+                    .show_ui(ui, |ui| {
+                        for p in [GpuPrecision::F32, GpuPrecision::Kahan, GpuPrecision::F64Accum] {
+                            ui.selectable_value(precision, p, p.to_str());
+                        }
+                    })
+                    .response
+                    .on_hover_text("How GPU kernels sum per-body terms");
+            }
+
+            ui.add_space(COL_SPACING * 2.);
 
             if ui.button("Field properties").clicked() {
                 let dx = 0.4;
@@ -288,35 +3338,731 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
                     println!("\nStats at R={r}: {stats}");
                     properties.push((r, stats));
                 }
-                plot_field_properties(&properties);
+                plot_field_properties(&state.config.plot_output, &properties);
             }
 
             if ui
                 .button(RichText::new("Save").color(Color32::GOLD))
                 .clicked()
             {
-                if util::save(&PathBuf::from_str(SAVE_FILE).unwrap(), &state.config).is_err() {
-                    println!("Error saving config.")
+                let path = state.ui.config_path.clone();
+                match state.save_config(&path) {
+                    Ok(()) => state
+                        .ui
+                        .notifications
+                        .success(format!("Saved config {}", file_name(&path))),
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error saving config: {e}")),
+                }
+            }
+
+            if ui.button("Save as").clicked() {
+                let _ = fs::create_dir_all(CONFIG_DIR);
+                if let Some(path) = FileDialog::new()
+                    .add_filter("Config", &["grav"])
+                    .set_directory(CONFIG_DIR)
+                    .save_file()
+                {
+                    match state.save_config(&path) {
+                        Ok(()) => state
+                            .ui
+                            .notifications
+                            .success(format!("Saved config {}", file_name(&path))),
+                        Err(e) => state
+                            .ui
+                            .notifications
+                            .error(format!("Error saving config: {e}")),
+                    }
+                }
+            }
+
+            let mut config_to_load = None;
+
+            if ui.button("Load").clicked() {
+                config_to_load = FileDialog::new()
+                    .add_filter("Config", &["grav"])
+                    .set_directory(CONFIG_DIR)
+                    .pick_file();
+            }
+
+            if ui
+                .button("Edit as text")
+                .on_hover_text(format!(
+                    "Write the config's parameters to {CONFIG_TEXT_FILE}, to edit in a text editor. \
+                    Saved changes there are offered to apply."
+                ))
+                .clicked()
+            {
+                match state.export_config_text(Path::new(CONFIG_TEXT_FILE)) {
+                    Ok(()) => state
+                        .ui
+                        .notifications
+                        .success(format!("Wrote config parameters to {CONFIG_TEXT_FILE}")),
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error writing {CONFIG_TEXT_FILE}: {e}")),
+                }
+            }
+
+            ui.menu_button("Recent", |ui| {
+                if state.ui.recent_configs.is_empty() {
+                    ui.label("No recent configs");
+                }
+                for path in &state.ui.recent_configs {
+                    if ui.button(path.display().to_string()).clicked() {
+                        config_to_load = Some(path.clone());
+                        ui.close_menu();
+                    }
+                }
+            });
+
+            // Undo, redo, and revert to the last build's config.
+            let mut config_switch = None;
+            if ui
+                .add_enabled(state.config_history.can_undo(), Button::new("Undo"))
+                .on_hover_text("Undo the last config change")
+                .clicked()
+            {
+                config_switch = state.config_history.undo(&state.config);
+            }
+            if ui
+                .add_enabled(state.config_history.can_redo(), Button::new("Redo"))
+                .clicked()
+            {
+                config_switch = state.config_history.redo(&state.config);
+            }
+            if ui
+                .add_enabled(
+                    state.config_history.can_revert(&state.config),
+                    Button::new("Revert"),
+                )
+                .on_hover_text("Revert to the config of the last build")
+                .clicked()
+            {
+                config_switch = state.config_history.revert(&state.config);
+            }
+
+            if let Some(cfg) = config_switch {
+                if config_history::affects_bodies(&state.config, &cfg) {
+                    refresh_bodies = true;
+                }
+                state.apply_config(cfg);
+            }
+
+            let out = &mut state.config.plot_output;
+            ui.checkbox(&mut out.enabled, "Plots");
+            if out.enabled {
+                if ui
+                    .button(out.dir.display().to_string())
+                    .on_hover_text("Plot output directory")
+                    .clicked()
+                {
+                    if let Some(dir) = FileDialog::new().set_directory(&out.dir).pick_folder() {
+                        out.dir = dir;
+                    }
+                }
+                ui.label("Run ID:");
+                ui.add(egui::TextEdit::singleline(&mut out.run_id).desired_width(60.));
+                ui.checkbox(&mut out.timestamp, "Timestamp").on_hover_text(
+                    "Add a timestamp to plot names, so runs don't overwrite each other",
+                );
+            }
+
+            ui.add_space(COL_SPACING);
+            ui.checkbox(&mut state.ui.stream_snapshots, "Stream")
+                .on_hover_text(format!(
+                    "Write snapshots to {DEFAULT_SNAPSHOT_FILE} during builds, and play back from \
+                     disk. For runs too large to keep in memory."
+                ));
+
+            ui.add_space(COL_SPACING);
+            ui.label("Snapshot bodies:");
+            let decimation = &mut state.config.snapshot_decimation;
+            ComboBox::from_id_salt(13)
+                .width(90.)
+                .selected_text(decimation.subset.to_str())
+                .show_ui(ui, |ui| {
+                    for option in [
+                        SnapshotSubset::All,
+                        SnapshotSubset::Random(0.1),
+                        SnapshotSubset::EveryKth(10),
+                        SnapshotSubset::Tracers(Vec::new()),
+                    ] {
+                        let selected = decimation.subset.to_str() == option.to_str();
+                        if ui.selectable_label(selected, option.to_str()).clicked() && !selected {
+                            decimation.subset = option;
+                            state.ui.tracers_input = String::new();
+                        }
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Store a subset of bodies in most snapshots, and all of them less often. For \
+                    large runs",
+                );
+
+            match &mut decimation.subset {
+                SnapshotSubset::All => (),
+                SnapshotSubset::Random(portion) => {
+                    ui.add(DragValue::new(portion).speed(0.01).range(0.0..=1.))
+                        .on_hover_text("Portion of bodies");
+                }
+                SnapshotSubset::EveryKth(k) => {
+                    ui.label("k:");
+                    ui.add(DragValue::new(k).range(1..=1_000_000));
+                }
+                SnapshotSubset::Tracers(ids) => {
+                    if ui
+                        .add(
+                            egui::TextEdit::singleline(&mut state.ui.tracers_input)
+                                .desired_width(100.)
+                                .hint_text("0, 12, 40"),
+                        )
+                        .on_hover_text("Indices of tracer bodies, separated by commas")
+                        .changed()
+                    {
+                        *ids = state
+                            .ui
+                            .tracers_input
+                            .split(',')
+                            .filter_map(|id| id.trim().parse().ok())
+                            .collect();
+                    }
+                }
+            }
+
+            if decimation.subset != SnapshotSubset::All {
+                ui.label("Full every:");
+                ui.add(DragValue::new(&mut decimation.full_ratio).range(0..=100_000))
+                    .on_hover_text(
+                        "Every this many snapshots has all bodies, e.g. for analysis. If 0, only \
+                        the first",
+                    );
+            }
+
+            if ui
+                .button("Save snapshots")
+                .on_hover_text(
+                    "Save the build's snapshots, with its config, for replaying later without \
+                    rebuilding",
+                )
+                .clicked()
+            {
+                if state.snapshot_stream.is_some() {
+                    state.ui.notifications.info(format!(
+                        "These snapshots are already on disk, at {DEFAULT_SNAPSHOT_FILE}"
+                    ));
+                } else if let Some(path) = snapshot_dialog().save_file() {
+                    match state.save_snapshots(&path) {
+                        Ok(()) => state.ui.notifications.success(format!(
+                            "Saved {} snapshots to {}",
+                            state.snapshots.len(),
+                            file_name(&path)
+                        )),
+                        Err(e) => state
+                            .ui
+                            .notifications
+                            .error(format!("Error saving snapshots: {e}")),
+                    }
+                }
+            }
+
+            if ui
+                .button("Export video")
+                .on_hover_text(format!(
+                    "Render each snapshot, from the current camera, to a PNG in `{}`, and encode \
+                    them as an MP4 if set. MP4s require ffmpeg",
+                    export::EXPORT_DIR
+                ))
+                .clicked()
+            {
+                match export_playback(state, scene) {
+                    Ok(path) => state
+                        .ui
+                        .notifications
+                        .success(format!("Exported playback to {}", path.display())),
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error exporting playback: {e}")),
+                }
+            }
+            ui.checkbox(&mut state.ui.export.mp4, "MP4");
+            if state.ui.export.mp4 {
+                ui.add(
+                    DragValue::new(&mut state.ui.export.fps)
+                        .range(1..=120)
+                        .suffix(" fps"),
+                );
+            }
+
+            if ui
+                .button("Load snapshots")
+                .on_hover_text(
+                    "Load saved snapshots for playback. Applies the config they were built with, \
+                    if saved",
+                )
+                .clicked()
+            {
+                if let Some(path) = snapshot_dialog().pick_file() {
+                    match state.load_snapshots(&path) {
+                        Ok(n) => {
+                            state.ui.notifications.success(format!(
+                                "Loaded {n} snapshots from {}",
+                                file_name(&path)
+                            ));
+                            reset_snapshot = true;
+                        }
+                        Err(e) => state
+                            .ui
+                            .notifications
+                            .error(format!("Unable to load snapshots: {e}")),
+                    }
+                }
+            }
+
+            if let Some(path) = config_to_load {
+                match state.load_config(&path) {
+                    Ok(()) => refresh_bodies = true,
+                    Err(e) => state
+                        .ui
+                        .notifications
+                        .error(format!("Error loading config: {e}")),
                 }
             }
+
+            ui.label(file_name(&state.ui.config_path));
         });
         ui.add_space(ROW_SPACING);
 
         ui.horizontal(|ui| {
-            let desc = &state.ui.galaxy_descrip;
-            ui.label(format!("Mass: {} ×10⁸ M☉", desc.mass_disk / 1.0e8));
+            let stop = &mut state.config.stop_criteria;
+            ui.label("Stop early:");
+            optional_field(&mut stop.max_wall_time, "Wall time (s)", 60., 1., ui);
             ui.add_space(COL_SPACING);
-            ui.label(format!("M/L: {}", desc.mass_to_light_ratio)); // todo: Remove A/R
+            optional_field(&mut stop.max_sim_time, "Sim time (Myr)", 100., 1., ui);
             ui.add_space(COL_SPACING);
-            ui.label(format!("Dist: {} kpc", desc.dist_from_earth));
+            optional_field(&mut stop.rot_curve_tol, "Rot curve Δ <", 0.01, 0.001, ui);
+
+            if stop.rot_curve_tol.is_some() {
+                ui.label("every");
+                ui.add(
+                    DragValue::new(&mut stop.rot_curve_check_ratio)
+                        .speed(10)
+                        .range(1..=usize::MAX),
+                );
+                ui.label("steps");
+            }
+
             ui.add_space(COL_SPACING);
-            ui.label(format!("Eccentricity: {}", desc.eccentricity));
+
+            let guard = &mut state.config.superluminal;
+            ui.label("v >");
+            ui.add(
+                DragValue::new(&mut guard.v_max_portion)
+                    .speed(0.01)
+                    .range(0.01..=10.)
+                    .suffix(" c"),
+            );
+            ComboBox::from_id_salt(5)
+                .width(70.)
+                .selected_text(guard.action.to_str())
+                .show_ui(ui, |ui| {
+                    for action in [
+                        SuperluminalAction::Off,
+                        SuperluminalAction::Warn,
+                        SuperluminalAction::Clamp,
+                        SuperluminalAction::Remove,
+                    ] {
+                        ui.selectable_value(&mut guard.action, action, action.to_str());
+                    }
+                });
+        });
+        ui.add_space(ROW_SPACING);
+
+        ui.horizontal(|ui| {
+            let mut refine = state.config.mass_refinement.is_some();
+            if ui
+                .checkbox(&mut refine, "Mass refinement")
+                .on_hover_text(
+                    "Split bodies in a range of radii into lighter children at set times, reducing \
+                    noise from few, heavy bodies where the dynamics matter",
+                )
+                .changed()
+            {
+                state.config.mass_refinement = refine.then(MassRefinement::default);
+                state.ui.refine_times_input = state
+                    .config
+                    .mass_refinement
+                    .as_ref()
+                    .map(|r| r.times_text())
+                    .unwrap_or_default();
+            }
+
+            if let Some(refine) = &mut state.config.mass_refinement {
+                ui.label("At (Myr):");
+                if ui
+                    .add(
+                        egui::TextEdit::singleline(&mut state.ui.refine_times_input)
+                            .desired_width(80.)
+                            .hint_text("0, 50"),
+                    )
+                    .on_hover_text("Times to split at, separated by commas. 0 splits the initial bodies")
+                    .changed()
+                {
+                    refine.times = state
+                        .ui
+                        .refine_times_input
+                        .split(',')
+                        .filter_map(|t| t.trim().parse().ok())
+                        .filter(|t: &f64| *t >= 0.)
+                        .collect();
+                    refine.times.sort_by(|a, b| a.total_cmp(b));
+                }
+
+                ui.label("Children:");
+                ui.add(DragValue::new(&mut refine.num_children).range(2..=64));
+
+                ui.label("r:");
+                ui.add(
+                    DragValue::new(&mut refine.r_min)
+                        .speed(0.05)
+                        .range(0.0..=refine.r_max),
+                );
+                ui.label("to");
+                ui.add(
+                    DragValue::new(&mut refine.r_max)
+                        .speed(0.05)
+                        .range(refine.r_min..=MAX_SHELL_R)
+                        .suffix(" kpc"),
+                );
+
+                ui.label("Jitter:");
+                ui.add(
+                    DragValue::new(&mut refine.jitter)
+                        .speed(0.001)
+                        .range(0.0..=1.)
+                        .suffix(" kpc"),
+                )
+                .on_hover_text("Children are placed within this distance of their parent");
+
+                ui.label("Min mass:");
+                ui.add(
+                    DragValue::new(&mut refine.mass_min)
+                        .speed(1.0e5)
+                        .range(0.0..=f64::MAX)
+                        .suffix(" M☉"),
+                )
+                .on_hover_text(
+                    "Lighter bodies aren't split, e.g. children of an earlier split. Bounds the \
+                    body count",
+                );
+            }
+        });
+        ui.add_space(ROW_SPACING);
+
+        CollapsingHeader::new("Galaxy summary")
+            .id_salt("galaxy_summary")
+            .show(ui, |ui| {
+                Grid::new("galaxy_summary_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (label, val) in state.ui.galaxy_descrip.summary() {
+                            ui.label(label);
+                            ui.label(val);
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        ui.horizontal(|ui| {
+            let unc = &mut state.ui.galaxy_descrip.uncertainty;
+            ui.label("Dist σ:");
+            ui.add(
+                DragValue::new(&mut unc.dist_err)
+                    .speed(10.)
+                    .range(0. ..=f64::MAX)
+                    .suffix(" kpc"),
+            );
+            ui.label("Incl:");
+            ui.add(
+                DragValue::new(&mut unc.inclination)
+                    .speed(0.5)
+                    .range(0. ..=90.)
+                    .suffix("°"),
+            );
+            ui.label("σ:");
+            ui.add(
+                DragValue::new(&mut unc.inclination_err)
+                    .speed(0.1)
+                    .range(0. ..=45.)
+                    .suffix("°"),
+            );
+
+            if ui
+                .button("Plot bands")
+                .on_hover_text(
+                    "Plot the current rotation curve against observations, rescaled to the ±1σ \
+                     distance and inclination bounds",
+                )
+                .clicked()
+            {
+                let galaxy = state.ui.galaxy_model.to_str();
+                let simulated = rotation_curve(&state.bodies, Vec3F64::new_zero(), C);
+                if let Some(path) = state
+                    .config
+                    .plot_output
+                    .path(&format!("rot_curve_bands_{galaxy}"))
+                {
+                    if let Err(e) = obs_uncertainty::plot_rot_curve_overlay(
+                        &path,
+                        &state.ui.galaxy_descrip,
+                        &simulated,
+                        &format!("Rotation curve of {galaxy}, with observational uncertainty"),
+                    ) {
+                        state
+                            .ui
+                            .notifications
+                            .error(format!("Error plotting the rotation curve bands: {e}"));
+                    }
+                }
+            }
+
             ui.add_space(COL_SPACING);
+
+            let slit = &mut state.ui.slit;
+            ui.label("Slit i:");
+            ui.add(
+                DragValue::new(&mut slit.inclination)
+                    .speed(0.5)
+                    .range(0. ..=90.)
+                    .suffix("°"),
+            );
+            ui.label("PA:");
+            ui.add(
+                DragValue::new(&mut slit.position_angle)
+                    .speed(0.5)
+                    .range(-90. ..=90.)
+                    .suffix("°"),
+            );
+            ui.label("Width:");
+            ui.add(
+                DragValue::new(&mut slit.width)
+                    .speed(0.01)
+                    .range(0.01..=100.)
+                    .suffix(" kpc"),
+            );
+            ui.label("Length:");
+            ui.add(
+                DragValue::new(&mut slit.length)
+                    .speed(0.1)
+                    .range(0.1..=1_000.)
+                    .suffix(" kpc"),
+            );
+
+            let mut smear = slit.beam_fwhm.is_some();
+            if ui
+                .checkbox(&mut smear, "Beam")
+                .on_hover_text("Smear the sky with a gaussian telescope beam before sampling")
+                .changed()
+            {
+                // A typical HI synthesis beam.
+                slit.beam_fwhm = smear.then_some(15.);
+            }
+            if let Some(fwhm) = &mut slit.beam_fwhm {
+                ui.add(
+                    DragValue::new(fwhm)
+                        .speed(0.5)
+                        .range(0.1..=600.)
+                        .suffix("\""),
+                );
+            }
+
+            if ui
+                .button("PV diagram")
+                .on_hover_text(
+                    "Plot a position-velocity diagram along the slit, and the rotation curve traced \
+                    from it",
+                )
+                .clicked()
+            {
+                let galaxy = state.ui.galaxy_model.to_str();
+                let out = &state.config.plot_output;
+                let pv = PvDiagram::new(
+                    &state.bodies,
+                    Vec3F64::new_zero(),
+                    &state.ui.slit,
+                    state.ui.galaxy_descrip.dist_from_earth,
+                );
+
+                if let (Some(pv_path), Some(curve_path)) = (
+                    out.path(&format!("pv_{galaxy}")),
+                    out.path(&format!("pv_rot_curve_{galaxy}")),
+                ) {
+                    pv_diagram::plot_pv_analysis(
+                        &pv_path,
+                        &curve_path,
+                        &pv,
+                        &state.ui.galaxy_descrip,
+                        &galaxy,
+                    );
+                    state
+                        .ui
+                        .notifications
+                        .success(format!("PV diagram saved to {:?}", out.dir));
+                }
+            }
         });
 
         ui.add_space(ROW_SPACING);
     });
 
+    handle_dropped_files(state, ctx, &mut refresh_bodies, &mut reset_snapshot);
+    config_changed_prompt(state, ctx, &mut refresh_bodies);
+    state.config_history.record(&state.config);
+    state.ui.notifications.draw(ctx);
+
+    if state.ui.show_scale_overlay {
+        overlay::draw_scale_overlay(ctx, &scene.camera, state.ui.world_scale);
+    }
+    update_playback(state, ctx, scene, &mut engine_updates);
+    if state.ui.show_camera_path {
+        camera_path_window(state, ctx, scene);
+    }
+
+    if let Some(body) = state.ui.ride_body {
+        let selected = state.ui.snapshot_selected;
+        let body_state = state.with_snapshot(selected, |snap, _| {
+            let k = snap.index_of(body)?;
+            let posit = snap.body_posits.get(k).copied()?;
+            Some((posit, snap.body_vels.get(k).copied().unwrap_or_default()))
+        });
+
+        match body_state {
+            Some(Some((posit, vel))) => {
+                let prev = (scene.camera.position, scene.camera.orientation);
+                ride_camera(
+                    &mut scene.camera,
+                    posit,
+                    vel,
+                    state.ui.ride_vel_frame,
+                    state.ui.world_scale,
+                );
+                if prev != (scene.camera.position, scene.camera.orientation) {
+                    engine_updates.camera = true;
+                }
+            }
+            // Not in this snapshot, which has a subset of bodies; hold the camera still.
+            Some(None) if body < state.body_masses.len() => (),
+            // E.g. a different run was loaded.
+            _ => state.ui.ride_body = None,
+        }
+    }
+
+    if let Some(body) = state.ui.follow_body {
+        let selected = state.ui.snapshot_selected;
+        let posit = state.with_snapshot(selected, |snap, _| {
+            snap.index_of(body)
+                .and_then(|k| snap.body_posits.get(k).copied())
+        });
+
+        match posit {
+            Some(Some(posit)) => {
+                let prev = scene.camera.position;
+                state.ui.follow_target = Some(follow_camera(
+                    &mut scene.camera,
+                    posit,
+                    state.ui.follow_target,
+                    state.ui.world_scale,
+                ));
+                if prev != scene.camera.position {
+                    engine_updates.camera = true;
+                }
+            }
+            // Not in this snapshot, which has a subset of bodies; hold the camera still.
+            Some(None) if body < state.body_masses.len() => (),
+            _ => {
+                state.ui.follow_body = None;
+                state.ui.follow_target = None;
+            }
+        }
+    }
+
+    if state.ui.show_lighting {
+        lighting_window(state, ctx, scene, &mut engine_updates);
+    }
+    if state.ui.show_injection {
+        injection_window(state, ctx, scene, &mut engine_updates);
+    }
+    if state.ui.show_zoom {
+        zoom_window(state, ctx, scene, &mut engine_updates);
+    }
+    select_clicked_body(state, ctx, scene, &mut engine_updates);
+    if state.ui.show_inspector {
+        inspector_window(state, ctx, scene, &mut engine_updates);
+    }
+    if state.ui.show_rot_model {
+        rot_model_window(state, ctx);
+    }
+    if state.ui.show_rot_curve {
+        rot_curve_window(state, ctx);
+    }
+    if state.ui.show_descrip_editor {
+        descrip_editor_window(state, ctx, &mut refresh_bodies);
+    }
+    if state.ui.cluster.is_some() {
+        cluster_window(state, ctx, &mut refresh_bodies);
+    }
+
+    // Stereo copies, retarded positions, and Doppler colors depend on the camera.
+    if (state.ui.stereo_mode != StereoMode::Off || state.ui.retarded_view || state.ui.doppler_color)
+        && state.ui.entities_camera != Some((scene.camera.position, scene.camera.orientation))
+    {
+        show_snapshot(
+            state,
+            scene,
+            state.ui.snapshot_selected,
+            &mut engine_updates,
+        );
+    }
+
+    if state.ui.show_hud {
+        let selected = state.ui.snapshot_selected;
+        if let Some((time, num_bodies)) =
+            state.with_snapshot(selected, |snap, _| (snap.time, snap.body_posits.len()))
+        {
+            overlay::draw_hud(ctx, &state.ui.run_labels, time, num_bodies);
+        }
+    }
+    if state.ui.doppler_color {
+        overlay::draw_doppler_legend(ctx, state.ui.doppler_v_max);
+    }
+    // The preview describes the initial conditions, so isn't shown for later snapshots.
+    if state.ui.show_ic_preview && state.ui.snapshot_selected == 0 {
+        if let Some(preview) = &state.ui.ic_preview {
+            overlay::draw_curve_inset(
+                ctx,
+                &[
+                    (&preview.curve, Color32::LIGHT_BLUE, "Bodies"),
+                    (&preview.observed, Color32::GOLD, "Observed"),
+                ],
+                "kpc",
+                "km/s",
+                &preview.summary(),
+            );
+        }
+    }
+    overlay::draw_scene_labels(
+        ctx,
+        &scene.camera,
+        state.ui.world_scale,
+        state.ui.show_grid,
+        state.ui.show_axes,
+    );
+
     if refresh_bodies {
         reset_snapshot = true;
         engine_updates.entities = true;
@@ -325,7 +4071,13 @@ pub fn ui_handler(state: &mut State, ctx: &Context, scene: &mut Scene) -> Engine
     }
 
     if reset_snapshot {
-        change_snapshot(&mut scene.entities, &state.snapshots[0], &state.body_masses);
+        if state.ui.fit_view {
+            fit_view(state, scene, 0);
+            engine_updates.camera = true;
+            state.ui.fit_view = false;
+        }
+
+        show_snapshot(state, scene, 0, &mut engine_updates);
     }
 
     engine_updates
@@ -53,6 +53,10 @@ pub const G: f64 =
 
 pub const A0_MOND: f64 = 1.2e-10 * KPC_PER_M / (MYR_PER_S * MYR_PER_S); // 3.87e-3
 
+/// The speed of light. kpc/Myr. For e.g. lensing, vice `C`, the speed gravity propagates at in
+/// the shell models.
+pub const C_LIGHT: f64 = 299_792.458 * KPC_MYR_PER_KM_S;
+
 // Note: Setting this too high is problematic.
 // pub const C: f64 = 306.4; // KPC/Myr
 pub const C: f64 = 5.; // todo: Experimenting
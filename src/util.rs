@@ -1,5 +1,5 @@
 use std::{
-    f64::consts::TAU,
+    f64::consts::{PI, TAU},
     fs::File,
     io,
     io::{ErrorKind, Read, Write},
@@ -132,3 +132,175 @@ pub fn random_unit_vec(rng: &mut ThreadRng) -> Vec3 {
 
     Vec3::new(x, y, z)
 }
+
+/// Deproject the surface density of a spherically-symmetric distribution, e.g. a bulge, to its
+/// volume density, by numerical inverse Abel transform:
+///
+/// ρ(r) = -1/π ∫_r^∞ dΣ/dR / √(R² - r²) dR
+///
+/// `surface_density` is (R, Σ), sorted by R. We estimate dΣ/dR at each point by finite differences,
+/// and take it as linear in R between points; the integral over each segment is then analytic,
+/// which handles the singularity at R = r. Σ is taken as 0 past the last point, so densities near
+/// the outer edge are underestimated if it hasn't fallen off by then.
+///
+/// Returns (r, ρ) at each data radius above 0, in the surface density's units per length; e.g.
+/// M☉/kpc² becomes M☉/kpc³. Negative values, e.g. from noisy data, are clamped to 0.
+pub fn abel_inversion(surface_density: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let n = surface_density.len();
+    if n < 2 {
+        return Vec::new();
+    }
+    let (r_data, sigma): (Vec<f64>, Vec<f64>) = surface_density.iter().copied().unzip();
+
+    // dΣ/dR at each point: Central differences inside, weighted for uneven spacing, and one-sided
+    // at the ends.
+    let mut deriv = Vec::with_capacity(n);
+    for i in 0..n {
+        let d = if i == 0 {
+            (sigma[1] - sigma[0]) / (r_data[1] - r_data[0])
+        } else if i == n - 1 {
+            (sigma[i] - sigma[i - 1]) / (r_data[i] - r_data[i - 1])
+        } else {
+            let h0 = r_data[i] - r_data[i - 1];
+            let h1 = r_data[i + 1] - r_data[i];
+            (sigma[i + 1] * h0.powi(2)
+                - sigma[i - 1] * h1.powi(2)
+                - sigma[i] * (h0.powi(2) - h1.powi(2)))
+                / (h0 * h1 * (h0 + h1))
+        };
+        deriv.push(d);
+    }
+
+    let mut result = Vec::with_capacity(n);
+    for &r in &r_data {
+        if r <= 0. {
+            continue;
+        }
+
+        let mut integral = 0.;
+        for j in 0..n - 1 {
+            let (r0, r1) = (r_data[j], r_data[j + 1]);
+            if r1 <= r {
+                continue;
+            }
+            // dΣ/dR = intercept + slope R on this segment.
+            let slope = (deriv[j + 1] - deriv[j]) / (r1 - r0);
+            let intercept = deriv[j] - slope * r0;
+
+            // ∫ (c + mR) / √(R² - r²) dR = c ln(R + √(R² - r²)) + m √(R² - r²)
+            let antideriv = |x: f64| {
+                let root = (x.powi(2) - r.powi(2)).max(0.).sqrt();
+                intercept * (x + root).ln() + slope * root
+            };
+            integral += antideriv(r1) - antideriv(r0.max(r));
+        }
+
+        result.push((r, (-integral / PI).max(0.)));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::sersic_fit;
+
+    fn assert_close(actual: f64, expected: f64, tol: f64, what: &str) {
+        let err = ((actual - expected) / expected).abs();
+        assert!(
+            err <= tol,
+            "{what}: {actual:e}, expected {expected:e} (relative error {err:e})"
+        );
+    }
+
+    /// (R, f(R)) at even spacing, from 0.
+    fn sample(f: impl Fn(f64) -> f64, dr: f64, r_max: f64) -> Vec<(f64, f64)> {
+        let n = (r_max / dr).round() as usize;
+        (0..=n).map(|i| i as f64 * dr).map(|r| (r, f(r))).collect()
+    }
+
+    /// Trapezoid rule.
+    fn integrate(data: &[(f64, f64)], f: impl Fn(f64, f64) -> f64) -> f64 {
+        data.windows(2)
+            .map(|w| (f(w[0].0, w[0].1) + f(w[1].0, w[1].1)) / 2. * (w[1].0 - w[0].0))
+            .sum()
+    }
+
+    #[test]
+    fn abel_plummer() {
+        let (mass, a): (f64, f64) = (1e10, 0.5);
+        let surface = sample(
+            |r| mass * a.powi(2) / (PI * (a.powi(2) + r.powi(2)).powi(2)),
+            0.025,
+            20.,
+        );
+        let rho_0 = 3. * mass / (2. * TAU * a.powi(3));
+
+        // Stay well inside the data, since it's truncated.
+        for (r, rho) in abel_inversion(&surface)
+            .into_iter()
+            .filter(|(r, _)| *r <= 5.)
+        {
+            let expected = rho_0 * (1. + (r / a).powi(2)).powf(-2.5);
+            assert_close(rho, expected, 0.01, &format!("Plummer ρ at r = {r}"));
+        }
+    }
+
+    /// A Sersic profile with n = 1/2 is a Gaussian, which deprojects to a Gaussian.
+    #[test]
+    fn abel_sersic_half() {
+        let (sigma_0, s): (f64, f64) = (1e9, 1.2);
+        let surface = sample(
+            |r| sigma_0 * (-r.powi(2) / (2. * s.powi(2))).exp(),
+            0.02,
+            10.,
+        );
+
+        for (r, rho) in abel_inversion(&surface)
+            .into_iter()
+            .filter(|(r, _)| *r <= 4.)
+        {
+            let expected = sigma_0 / (TAU.sqrt() * s) * (-r.powi(2) / (2. * s.powi(2))).exp();
+            assert_close(rho, expected, 0.01, &format!("Gaussian ρ at r = {r}"));
+        }
+    }
+
+    /// For Sersic profiles in general there's no closed form, but the deprojection must conserve
+    /// mass.
+    #[test]
+    fn abel_sersic_mass() {
+        let r_e: f64 = 1.;
+        for n in [1., 2., 4.] {
+            // A common approximation.
+            let b = 2. * n - 1. / 3. + 4. / (405. * n);
+            let surface = sample(|r| (-b * ((r / r_e).powf(1. / n) - 1.)).exp(), 0.01, 40.);
+
+            let mass_projected = integrate(&surface, |r, sigma| TAU * r * sigma);
+            let mass = integrate(&abel_inversion(&surface), |r, rho| {
+                2. * TAU * r.powi(2) * rho
+            });
+            assert_close(mass, mass_projected, 0.02, &format!("Sersic n = {n} mass"));
+        }
+    }
+
+    /// The fit recovers n and R_e from exact exponential (n = 1) and de Vaucouleurs (n = 4)
+    /// profiles.
+    #[test]
+    fn sersic_fit_recovers_params() {
+        let i_e = 1e8;
+        for (n, r_e) in [(1., 2.), (4., 1.5)] {
+            // The same b_n as the fit uses. (Ciotti & Bertin, 1999)
+            let b = 2. * n - 1. / 3. + 4. / (405. * n) + 46. / (25_515. * n.powi(2));
+            let profile = sample(
+                |r| i_e * (-b * ((r / r_e).powf(1. / n) - 1.)).exp(),
+                0.05,
+                8. * r_e,
+            );
+
+            let fit = sersic_fit(&profile).unwrap();
+            assert_close(fit.n, n, 0.01, &format!("Sersic n = {n} index"));
+            assert_close(fit.r_e, r_e, 0.01, &format!("Sersic n = {n} R_e"));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Region-of-interest ("zoom") resimulation: Take the bodies in a region at a snapshot of an
+//! existing run, and re-run that region at a finer timestep, for studying localized events, like a
+//! core interaction.
+//!
+//! Bodies outside the region are kept, as the region's boundary conditions: Instead of being
+//! integrated, they follow their paths from the parent run, interpolated between its snapshots. So
+//! their field, including tides, acts on the region as it did in the parent run.
+
+use graphics::Entity;
+use lin_alg::{
+    f32::{Quaternion, Vec3 as Vec3f32},
+    f64::Vec3,
+};
+
+use crate::{build_job, render::MESH_SPHERE, run, Body, BuildStatus, ForceModel, State};
+
+type Color = (f32, f32, f32);
+
+const REGION_COLOR: Color = (0.3, 0.8, 1.);
+const REGION_OPACITY: f32 = 0.15;
+const REGION_SHINYNESS: f32 = 0.;
+
+/// Appended to the galaxy's name, in the HUD.
+const ZOOM_LABEL: &str = " (zoom)";
+
+#[derive(Clone, Debug)]
+pub struct ZoomRegion {
+    /// kpc
+    pub center: Vec3,
+    /// kpc
+    pub radius: f64,
+    /// How long to resimulate for. Limited to the parent run's remaining time. Myr.
+    pub duration: f64,
+    /// The resimulation's dt is the parent's, divided by this.
+    pub refinement: usize,
+    /// If set, the next click in the scene sets the center, on the galactic plane.
+    pub picking: bool,
+}
+
+impl Default for ZoomRegion {
+    fn default() -> Self {
+        Self {
+            center: Vec3::new_zero(),
+            radius: 1.,
+            duration: 5.,
+            refinement: 8,
+            picking: false,
+        }
+    }
+}
+
+/// The paths of the bodies outside the region, from the parent run's snapshots.
+pub struct ZoomBoundary {
+    /// Indices of boundary bodies.
+    ids: Vec<usize>,
+    /// (time, positions, velocities) of boundary bodies, in time order.
+    frames: Vec<(f64, Vec<Vec3>, Vec<Vec3>)>,
+    /// If this changes, e.g. from the superluminal guard removing bodies, indices no longer match.
+    num_bodies: usize,
+}
+
+impl ZoomBoundary {
+    /// Move boundary bodies to their states at `time`. Between snapshots, this uses cubic Hermite
+    /// interpolation from the positions and velocities at each end. Returns false if the body count
+    /// no longer matches.
+    pub fn apply(&self, bodies: &mut [Body], time: f64) -> bool {
+        if bodies.len() != self.num_bodies {
+            return false;
+        }
+
+        let j = self
+            .frames
+            .partition_point(|(t, _, _)| *t <= time)
+            .clamp(1, self.frames.len() - 1);
+        let (t_a, posits_a, vels_a) = &self.frames[j - 1];
+        let (t_b, posits_b, vels_b) = &self.frames[j];
+
+        let h = t_b - t_a;
+        let s = if h > 0. {
+            ((time - t_a) / h).clamp(0., 1.)
+        } else {
+            0.
+        };
+        let (s2, s3) = (s * s, s * s * s);
+
+        // Hermite basis functions, and their derivatives.
+        let (h00, h10, h01, h11) = (
+            2. * s3 - 3. * s2 + 1.,
+            s3 - 2. * s2 + s,
+            -2. * s3 + 3. * s2,
+            s3 - s2,
+        );
+        let (d00, d10, d01, d11) = (
+            6. * s2 - 6. * s,
+            3. * s2 - 4. * s + 1.,
+            -6. * s2 + 6. * s,
+            3. * s2 - 2. * s,
+        );
+
+        for (k, id) in self.ids.iter().enumerate() {
+            let body = &mut bodies[*id];
+            let (pa, va, pb, vb) = (posits_a[k], vels_a[k], posits_b[k], vels_b[k]);
+            body.posit = pa * h00 + va * (h10 * h) + pb * h01 + vb * (h11 * h);
+            body.vel = if h > 0. {
+                pa * (d00 / h) + va * d10 + pb * (d01 / h) + vb * d11
+            } else {
+                va
+            };
+        }
+        true
+    }
+}
+
+/// Mark the region to resimulate.
+pub fn add_region_marker(entities: &mut Vec<Entity>, region: &ZoomRegion, world_scale: f32) {
+    let mut entity = Entity::new(
+        MESH_SPHERE,
+        Vec3f32::from(region.center) * world_scale,
+        Quaternion::new_identity(),
+        region.radius as f32 * world_scale,
+        REGION_COLOR,
+        REGION_SHINYNESS,
+    );
+    entity.opacity = REGION_OPACITY;
+    entities.push(entity);
+}
+
+/// Resimulate the region from snapshot `i`, at the parent's dt divided by the refinement. The
+/// resimulation replaces the run's snapshots. The config's dt and step count are restored
+/// afterwards. Returns the number of bodies in the region; errors if the resimulation aborted.
+pub fn resimulate(
+    state: &mut State,
+    force_model: ForceModel,
+    i: usize,
+    region: &ZoomRegion,
+) -> Result<usize, String> {
+    build_job::stop(state);
+
+    let Some((t_start, mut bodies, body_ids, full)) =
+        state.with_snapshot(i, |snap, body_masses| {
+            let body_ids: Vec<usize> = (0..snap.body_posits.len())
+                .map(|k| snap.body_id(k))
+                .collect();
+            (
+                snap.time as f64,
+                snap.bodies(body_masses),
+                body_ids,
+                snap.is_full(),
+            )
+        })
+    else {
+        return Err(format!("Unable to read snapshot {i}"));
+    };
+    if !full {
+        return Err(format!(
+            "Snapshot {i} only has a subset of bodies; choose one with all of them"
+        ));
+    }
+
+    let (inside, ids): (Vec<usize>, Vec<usize>) = (0..bodies.len())
+        .partition(|b| (bodies[*b].posit - region.center).magnitude() <= region.radius);
+    if inside.is_empty() {
+        return Err("No bodies are in the region".to_owned());
+    }
+
+    // Boundary paths, from the start through the end of the resimulation, or of the parent run.
+    let t_end = t_start + region.duration;
+    let mut frames = Vec::new();
+    for j in i..state.num_snapshots() {
+        // Bodies may have been removed in the parent run, e.g. by the superluminal guard; we find
+        // boundary bodies by ID, and stop once one is missing.
+        let Some(Some(frame)) = state.with_snapshot(j, |snap, body_masses| {
+            let snap_bodies = snap.bodies(body_masses);
+            let indices: Option<Vec<usize>> =
+                ids.iter().map(|b| snap.index_of(body_ids[*b])).collect();
+            indices.map(|indices| {
+                (
+                    snap.time as f64,
+                    indices.iter().map(|k| snap_bodies[*k].posit).collect(),
+                    indices.iter().map(|k| snap_bodies[*k].vel).collect(),
+                )
+            })
+        }) else {
+            break;
+        };
+
+        let time = frame.0;
+        frames.push(frame);
+        if time >= t_end {
+            break;
+        }
+    }
+
+    let Some(t_last) = frames.last().map(|f| f.0).filter(|_| frames.len() >= 2) else {
+        return Err("The run has no snapshots after this one, to take boundaries from".to_owned());
+    };
+    let duration = t_end.min(t_last) - t_start;
+
+    // Snapshots don't store components.
+    for (body, id) in bodies.iter_mut().zip(&body_ids) {
+        if let Some(component) = state.body_components.get(*id) {
+            body.component = *component;
+        }
+    }
+
+    state.zoom_boundary = Some(ZoomBoundary {
+        ids,
+        frames,
+        num_bodies: bodies.len(),
+    });
+
+    state.bodies = bodies;
+    state.reset_body_ids();
+    state.time_elapsed = t_start;
+    state.shells = Vec::new();
+    state.snapshots = Vec::new();
+    state.snapshot_stream = None;
+    state.ui.tidal_history = None;
+    state.diagnostics = Vec::new();
+    state.record_diagnostics();
+    state.take_snapshot(0., Vec::new());
+    state.ui.snapshot_selected = 0;
+
+    if !state.ui.run_labels.galaxy.ends_with(ZOOM_LABEL) {
+        state.ui.run_labels.galaxy += ZOOM_LABEL;
+    }
+
+    let cfg_prev = state.config.clone();
+    state.config.dt = cfg_prev.dt / region.refinement.max(1) as f64;
+    state.config.num_timesteps = ((duration / state.config.dt).round() as usize).max(1);
+
+    println!(
+        "Resimulating {} bodies within {:.2} kpc, for {duration:.3} Myr at dt {}",
+        inside.len(),
+        region.radius,
+        state.config.dt
+    );
+    let status = run(state, force_model, None);
+
+    state.config.dt = cfg_prev.dt;
+    state.config.num_timesteps = cfg_prev.num_timesteps;
+    state.zoom_boundary = None;
+
+    match status {
+        BuildStatus::Done => Ok(inside.len()),
+        BuildStatus::Aborted(e) => Err(e),
+    }
+}